@@ -0,0 +1,196 @@
+//! 允许监视和删除的根目录。
+//!
+//! 应用配置目录下存在 [`ALLOWLIST_FILE`]（JSON 路径数组）时，
+//! 源路径、目标路径和被删除的路径都必须位于其中某个根目录内。
+//! 列表只在启动时读取，前端无法在运行期间修改。文件不存在时不做限制。
+
+use std::{
+    path::{Component, Path, PathBuf},
+    sync::OnceLock,
+};
+
+use anyhow::anyhow;
+use log::{error, info};
+use tauri::Manager;
+
+/// 应用配置目录下的允许列表文件。
+const ALLOWLIST_FILE: &str = "allowed_roots.json";
+
+/// 未配置允许列表时为 `None`。
+static ROOTS: OnceLock<Option<Vec<PathBuf>>> = OnceLock::new();
+
+/// 启动时加载允许列表。
+///
+/// 文件存在但无法解析时拒绝所有路径，避免因配置错误而失去保护。
+pub fn init(app: &tauri::AppHandle) {
+    let roots = match app.path().app_config_dir() {
+        Ok(dir) => load(&dir.join(ALLOWLIST_FILE)),
+        Err(_) => None,
+    };
+    let _ = ROOTS.set(roots);
+}
+
+fn load(file: &Path) -> Option<Vec<PathBuf>> {
+    let bytes = std::fs::read(file).ok()?;
+    match serde_json::from_slice::<Vec<PathBuf>>(&bytes) {
+        Ok(roots) => {
+            let roots: Vec<PathBuf> = roots.iter().map(|root| resolve(root)).collect();
+            info!("已加载允许的根目录: {:?}", roots);
+            Some(roots)
+        }
+        Err(e) => {
+            error!("允许列表 {:?} 无法解析，将拒绝所有路径: {:?}", file, e);
+            Some(Vec::new())
+        }
+    }
+}
+
+/// 解析后的路径是否位于某个根目录内。
+fn contains(roots: &[PathBuf], resolved: &Path) -> bool {
+    roots.iter().any(|root| resolved.starts_with(root))
+}
+
+/// 路径不在允许的根目录内时返回错误，未配置允许列表时总是允许。
+///
+/// 解析路径需要访问文件系统，在阻塞线程中进行，不占用事件循环。
+pub async fn check(path: &Path) -> anyhow::Result<()> {
+    let Some(Some(roots)) = ROOTS.get() else {
+        return Ok(());
+    };
+    let owned = path.to_path_buf();
+    let resolved = tokio::task::spawn_blocking(move || resolve(&owned)).await?;
+    if contains(roots, &resolved) {
+        return Ok(());
+    }
+    Err(anyhow!("路径 '{}' 不在允许的根目录内。", path.display()))
+}
+
+/// 解析为规范路径：最近的存在的上级目录先解析为规范路径，其余部分按字面去掉 `.` 和 `..`，
+/// 防止借此跳出根目录。
+///
+/// 只解析父目录而保留最后一级名称，删除指向根目录外的链接本身不会被拒绝。
+/// 会访问文件系统，异步代码中应在阻塞线程中调用。
+pub(crate) fn resolve(path: &Path) -> PathBuf {
+    let (dir, name) = match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => (parent, Some(name)),
+        _ => (path, None),
+    };
+    let (mut resolved, rest) = dir
+        .ancestors()
+        .find_map(|ancestor| {
+            let canonical = std::fs::canonicalize(ancestor).ok()?;
+            Some((canonical, dir.strip_prefix(ancestor).ok()?))
+        })
+        .unwrap_or((PathBuf::new(), dir));
+    for component in rest.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    if let Some(name) = name {
+        resolved.push(name);
+    }
+    resolved
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn roots(dir: &Path) -> Vec<PathBuf> {
+        vec![resolve(&dir.join("allowed"))]
+    }
+
+    #[test]
+    fn paths_inside_root_are_allowed() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("allowed/sub")).unwrap();
+        let roots = roots(dir.path());
+        for path in [
+            "allowed",
+            "allowed/sub",
+            "allowed/sub/new.txt",
+            "allowed/missing/a",
+        ] {
+            assert!(
+                contains(&roots, &resolve(&dir.path().join(path))),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn paths_outside_root_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("allowed")).unwrap();
+        fs::create_dir_all(dir.path().join("other")).unwrap();
+        let roots = roots(dir.path());
+        for path in ["other", "other/a.txt", "allowed-sibling"] {
+            assert!(
+                !contains(&roots, &resolve(&dir.path().join(path))),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn parent_components_cannot_escape_root() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("allowed/sub")).unwrap();
+        fs::create_dir_all(dir.path().join("other")).unwrap();
+        let roots = roots(dir.path());
+        let escapes = [
+            // 存在的父目录按规范路径解析
+            "allowed/../other/a.txt",
+            "allowed/sub/../../other",
+            // 不存在的父目录按字面去掉 `..`
+            "allowed/missing/../../other/a.txt",
+            "allowed/missing/../../../etc",
+        ];
+        for path in escapes {
+            assert!(
+                !contains(&roots, &resolve(&dir.path().join(path))),
+                "{}",
+                path
+            );
+        }
+        let inside = dir.path().join("allowed/missing/../sub/a.txt");
+        assert!(contains(&roots, &resolve(&inside)));
+    }
+
+    #[test]
+    fn lexical_fallback_drops_current_and_parent_components() {
+        let path = Path::new("/auto-copy-missing/a/./b/../c");
+        assert_eq!(resolve(path), Path::new("/auto-copy-missing/a/c"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn link_in_root_pointing_outside_is_resolved() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("allowed")).unwrap();
+        fs::create_dir_all(dir.path().join("other")).unwrap();
+        std::os::unix::fs::symlink(dir.path().join("other"), dir.path().join("allowed/link"))
+            .unwrap();
+        let roots = roots(dir.path());
+        assert!(!contains(
+            &roots,
+            &resolve(&dir.path().join("allowed/link/a.txt"))
+        ));
+        // 链接本身位于根目录内，可以被删除
+        assert!(contains(&roots, &resolve(&dir.path().join("allowed/link"))));
+    }
+
+    #[tokio::test]
+    async fn check_allows_everything_without_allowlist() {
+        check(Path::new("/anywhere/at/all")).await.unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+//! 导出诊断包：把最近的日志、运行状态快照和后端信息打包成一个 zip 文件，附在问题报告中。
+
+use std::{
+    cmp::Reverse,
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use flate2::{write::DeflateEncoder, Compression};
+use log::info;
+use tauri::Manager;
+
+use crate::{backend_info, diagnostics, log_dir};
+
+/// 诊断包中最多包含的日志文件数，按修改时间从新到旧选取。
+const RECENT_LOGS: usize = 5;
+
+/// 收集诊断信息并写入 `out_path`。
+///
+/// 各项状态只在复制数据期间持有锁，监视运行期间也可以导出。
+/// `redact` 为 `true` 时把所有内容中的用户主目录替换为 `~`。
+pub async fn export(app: &tauri::AppHandle, out_path: &Path, redact: bool) -> anyhow::Result<()> {
+    let state = serde_json::to_string_pretty(&diagnostics::dump(app).await)?;
+    let info = serde_json::to_string_pretty(&backend_info(app).await)?;
+    let home = if redact {
+        Some(app.path().home_dir()?)
+    } else {
+        None
+    };
+    let log_dir = log_dir(app)?;
+    let out_path = out_path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let mut zip = ZipWriter::default();
+        let redact = |text: &str| match &home {
+            Some(home) => redact_home(text, home),
+            None => text.to_string(),
+        };
+        zip.add("state.json", redact(&state).as_bytes())?;
+        zip.add("backend-info.json", redact(&info).as_bytes())?;
+        for log in recent_logs(&log_dir)? {
+            let contents =
+                std::fs::read(&log).with_context(|| format!("无法读取日志 {:?}", log))?;
+            let name = format!(
+                "logs/{}",
+                log.file_name().unwrap_or_default().to_string_lossy()
+            );
+            zip.add(
+                &name,
+                redact(&String::from_utf8_lossy(&contents)).as_bytes(),
+            )?;
+        }
+        std::fs::write(&out_path, zip.finish())
+            .with_context(|| format!("无法写入诊断包 {:?}", out_path))?;
+        info!("已导出诊断包: {:?}", out_path);
+        Ok(())
+    })
+    .await?
+}
+
+/// 日志目录中最近修改的 [`RECENT_LOGS`] 个日志文件，目录不存在时为空。
+fn recent_logs(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut logs: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "log"))
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    logs.sort_by_key(|(modified, _)| Reverse(*modified));
+    Ok(logs
+        .into_iter()
+        .take(RECENT_LOGS)
+        .map(|(_, path)| path)
+        .collect())
+}
+
+/// 替换主目录的原样形式和 JSON、`{:?}` 输出中反斜杠被转义的形式。
+fn redact_home(text: &str, home: &Path) -> String {
+    let home = home.to_string_lossy();
+    if home.is_empty() {
+        return text.to_string();
+    }
+    let escaped = home.replace('\\', "\\\\");
+    text.replace(escaped.as_str(), "~")
+        .replace(home.as_ref(), "~")
+}
+
+/// 只支持 deflate 压缩、不支持 zip64 的最简 zip 写入器，条目不记录修改时间。
+#[derive(Default)]
+struct ZipWriter {
+    data: Vec<u8>,
+    central: Vec<u8>,
+    entries: u16,
+}
+
+/// 1980-01-01 00:00，DOS 日期格式能表示的最早时间。
+const DOS_DATE: u16 = (1 << 5) | 1;
+/// 通用标志位：文件名使用 UTF-8 编码。
+const UTF8_FLAG: u16 = 1 << 11;
+const DEFLATE: u16 = 8;
+const VERSION: u16 = 20;
+
+impl ZipWriter {
+    fn add(&mut self, name: &str, contents: &[u8]) -> anyhow::Result<()> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(contents)?;
+        let compressed = encoder.finish()?;
+        let crc = crc32fast::hash(contents);
+        let offset = u32::try_from(self.data.len()).context("诊断包超过 4 GiB")?;
+        let sizes = [
+            u32::try_from(compressed.len()).context("诊断包超过 4 GiB")?,
+            u32::try_from(contents.len()).context("诊断包超过 4 GiB")?,
+        ];
+        let name_len = u16::try_from(name.len()).context("条目名称过长")?;
+
+        let data = &mut self.data;
+        data.extend(0x0403_4b50u32.to_le_bytes());
+        for field in [VERSION, UTF8_FLAG, DEFLATE, 0, DOS_DATE] {
+            data.extend(field.to_le_bytes());
+        }
+        data.extend(crc.to_le_bytes());
+        data.extend(sizes[0].to_le_bytes());
+        data.extend(sizes[1].to_le_bytes());
+        data.extend(name_len.to_le_bytes());
+        data.extend(0u16.to_le_bytes());
+        data.extend(name.as_bytes());
+        data.extend(&compressed);
+
+        let central = &mut self.central;
+        central.extend(0x0201_4b50u32.to_le_bytes());
+        for field in [VERSION, VERSION, UTF8_FLAG, DEFLATE, 0, DOS_DATE] {
+            central.extend(field.to_le_bytes());
+        }
+        central.extend(crc.to_le_bytes());
+        central.extend(sizes[0].to_le_bytes());
+        central.extend(sizes[1].to_le_bytes());
+        // 文件名长度、扩展字段长度、注释长度、磁盘号、内部属性
+        for field in [name_len, 0, 0, 0, 0] {
+            central.extend(field.to_le_bytes());
+        }
+        central.extend(0u32.to_le_bytes());
+        central.extend(offset.to_le_bytes());
+        central.extend(name.as_bytes());
+
+        self.entries += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        let offset = self.data.len() as u32;
+        let size = self.central.len() as u32;
+        self.data.append(&mut self.central);
+        self.data.extend(0x0605_4b50u32.to_le_bytes());
+        for field in [0, 0, self.entries, self.entries] {
+            self.data.extend(field.to_le_bytes());
+        }
+        self.data.extend(size.to_le_bytes());
+        self.data.extend(offset.to_le_bytes());
+        self.data.extend(0u16.to_le_bytes());
+        self.data
+    }
+}
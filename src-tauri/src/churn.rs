@@ -0,0 +1,185 @@
+//! 合并短时间内反复删除、创建同一路径的事件。
+//!
+//! 一些构建工具通过先删除再创建的方式替换文件，并且可能反复进行。
+//! 逐个处理这些事件会让目标文件被反复删除和重建，事件乱序时还可能最终缺失。
+//! 开启后删除事件不会立即处理：路径在窗口期内没有新的创建、删除或修改事件时，
+//! 再按路径当时的状态决定复制还是删除。
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use notify::{
+    event::{CreateKind, RemoveKind},
+    Event, EventKind,
+};
+
+/// 检查被暂缓的路径是否已稳定的间隔。
+pub const SETTLE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct ChurnGuard {
+    window: Duration,
+    /// 暂缓处理的路径 -> 最近一次事件的时间。
+    held: HashMap<PathBuf, Instant>,
+}
+
+impl ChurnGuard {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            held: HashMap::new(),
+        }
+    }
+
+    /// 事件是否被暂缓。删除事件总会被暂缓，已暂缓路径上的创建和修改事件也会被吸收。
+    pub fn absorb(&mut self, event: &Event) -> bool {
+        let Some(path) = event.paths.first() else {
+            return false;
+        };
+        let held = match event.kind {
+            EventKind::Remove(_) => true,
+            EventKind::Create(_) | EventKind::Modify(_) => self.held.contains_key(path),
+            _ => false,
+        };
+        if held {
+            self.held.insert(path.clone(), Instant::now());
+        }
+        held
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.held.len()
+    }
+
+    /// 取出窗口期内没有新事件的路径，按路径的当前状态生成一个代替它们的事件。
+    pub fn take_settled(&mut self) -> Vec<Event> {
+        let settled: Vec<PathBuf> = self
+            .held
+            .iter()
+            .filter(|(_, at)| at.elapsed() >= self.window)
+            .map(|(path, _)| path.clone())
+            .collect();
+        settled
+            .into_iter()
+            .map(|path| {
+                self.held.remove(&path);
+                settled_event(path)
+            })
+            .collect()
+    }
+}
+
+fn settled_event(path: PathBuf) -> Event {
+    let kind = if is_present(&path) {
+        let kind = if path.is_file() {
+            CreateKind::File
+        } else if path.is_dir() {
+            CreateKind::Folder
+        } else {
+            CreateKind::Any
+        };
+        EventKind::Create(kind)
+    } else {
+        EventKind::Remove(RemoveKind::Any)
+    };
+    Event::new(kind).add_path(path)
+}
+
+fn is_present(path: &Path) -> bool {
+    path.exists() || path.is_symlink()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use notify::event::{DataChange, ModifyKind};
+
+    use super::*;
+    use crate::{
+        config::WatchOptions, file_processor, fs_ops::TokioFs, mem_fs::TestWatch, CopyType,
+    };
+
+    const WINDOW: Duration = Duration::from_millis(50);
+
+    fn event(kind: EventKind, path: &Path) -> Event {
+        Event::new(kind).add_path(path.to_path_buf())
+    }
+
+    fn wait_window() {
+        std::thread::sleep(WINDOW + Duration::from_millis(20));
+    }
+
+    #[test]
+    fn create_for_path_that_is_not_held_passes_through() {
+        let mut guard = ChurnGuard::new(WINDOW);
+        let created = event(EventKind::Create(CreateKind::File), Path::new("/src/a.txt"));
+        assert!(!guard.absorb(&created));
+        assert!(guard.is_empty());
+    }
+
+    #[test]
+    fn held_path_is_not_settled_within_window() {
+        let mut guard = ChurnGuard::new(Duration::from_secs(60));
+        let removed = event(EventKind::Remove(RemoveKind::File), Path::new("/src/a.txt"));
+        assert!(guard.absorb(&removed));
+        assert!(guard.take_settled().is_empty());
+        assert_eq!(guard.len(), 1);
+    }
+
+    #[test]
+    fn path_gone_after_window_settles_into_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        let mut guard = ChurnGuard::new(WINDOW);
+        assert!(guard.absorb(&event(EventKind::Remove(RemoveKind::File), &path)));
+        wait_window();
+        let settled = guard.take_settled();
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0].kind, EventKind::Remove(RemoveKind::Any));
+        assert_eq!(settled[0].paths, vec![path]);
+        assert!(guard.is_empty());
+    }
+
+    #[tokio::test]
+    async fn delete_create_swaps_coalesce_into_one_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("src");
+        let to = dir.path().join("dst");
+        fs::create_dir_all(&from).unwrap();
+        fs::create_dir_all(&to).unwrap();
+        let source = from.join("out.bin");
+        let target = to.join("out.bin");
+        fs::write(&target, "v0").unwrap();
+
+        // 构建工具反复以删除再创建的方式替换文件
+        let mut guard = ChurnGuard::new(WINDOW);
+        for version in ["v1", "v2", "v3"] {
+            let _ = fs::remove_file(&source);
+            assert!(guard.absorb(&event(EventKind::Remove(RemoveKind::File), &source)));
+            fs::write(&source, version).unwrap();
+            assert!(guard.absorb(&event(EventKind::Create(CreateKind::File), &source)));
+            let modified = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+            assert!(guard.absorb(&event(modified, &source)));
+        }
+        assert!(guard.take_settled().is_empty());
+        assert_eq!(fs::read(&target).unwrap(), b"v0");
+
+        wait_window();
+        let settled = guard.take_settled();
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0].kind, EventKind::Create(CreateKind::File));
+
+        let watch = TestWatch::with_fs(TokioFs, &from, &to, WatchOptions::default());
+        file_processor::process(&watch.ctx(), CopyType::Copy, &settled[0])
+            .await
+            .unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"v3");
+    }
+}
@@ -0,0 +1,424 @@
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    filter::Filters,
+    hook::EventHook,
+    pause::PauseOverflow,
+    queue::{self, QueueOverflow},
+    rename::RenameRule,
+    CopyType,
+};
+
+/// 默认的复制缓冲区大小（1 MiB）。
+pub const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+/// 默认的增量复制块大小（64 KiB）。
+pub const DEFAULT_DELTA_BLOCK_SIZE: usize = 64 * 1024;
+
+/// 一个监视的完整配置。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchConfig {
+    pub id: String,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub copy_type: CopyType,
+    #[serde(default)]
+    pub options: WatchOptions,
+}
+
+impl WatchConfig {
+    /// 显示给用户的名称，用于日志、事件和托盘菜单。
+    ///
+    /// 未指定名称时使用源路径的最后一级，没有时使用整个源路径。
+    pub fn name(&self) -> String {
+        match &self.options.name {
+            Some(name) if !name.is_empty() => name.clone(),
+            _ => self
+                .from
+                .file_name()
+                .unwrap_or(self.from.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+}
+
+/// Windows 上链接目录时使用的机制。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirLinkStyle {
+    /// 目录软链接，非管理员用户通常需要开启开发者模式。
+    Symlink,
+    /// 目录联接，不需要额外权限。
+    Junction,
+}
+
+/// 复制模式下如何处理指向目录的软链接。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SymlinkPolicy {
+    /// 复制软链接所指目录中的内容。
+    Follow,
+    /// 在目标中重建指向相同位置的软链接。
+    Preserve,
+    /// 忽略指向目录的软链接。
+    #[default]
+    Skip,
+}
+
+/// 复制模式下何时覆盖已存在的目标文件。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncMode {
+    /// 源文件变化时总是覆盖目标。
+    #[default]
+    Mirror,
+    /// 仅当源文件比目标文件新时才覆盖，保留在目标中手动修改过的文件。
+    Update,
+    /// 仅当源文件与目标文件大小不同时才覆盖，不比较修改时间。
+    ///
+    /// 适合只追加或固定格式的文件；大小不变的修改会被漏掉。
+    SizeOnly,
+}
+
+/// 复制模式下如何对待命名管道、套接字和设备文件。读取它们可能一直阻塞。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpecialFilePolicy {
+    /// 跳过。
+    #[default]
+    Skip,
+    /// 在目标中重建命名管道（仅 Unix），其他类型仍然跳过。
+    Recreate,
+}
+
+/// 复制模式下如何写入已存在的目标文件。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CopyMode {
+    /// 重写整个文件。
+    #[default]
+    Full,
+    /// 分块比较源文件与目标文件，只写入变化的块，适合大文件的少量修改（日志、数据库）。
+    ///
+    /// 块按固定偏移比较，插入或删除内容会使之后的块全部重写。目标不存在时退回完整复制。
+    Delta,
+}
+
+/// 删除目标文件的方式。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeleteMode {
+    /// 直接删除。
+    #[default]
+    Permanent,
+    /// 移到系统回收站，平台不支持或失败时退回直接删除。
+    OsTrash,
+}
+
+/// 移动模式下源文件为只读（Windows 上无法直接删除）时的处理方式。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadOnlySource {
+    /// 保留源文件并记录警告，已复制到目标的文件保持不变。
+    #[default]
+    Keep,
+    /// 清除只读属性后删除源文件。
+    ClearReadOnly,
+}
+
+/// 目标位置被类型不同的条目占用时的处理方式：需要创建目录的位置已被文件占用，
+/// 或需要写入文件的位置已被目录占用（源路径在文件和目录之间改变了类型）。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DirConflict {
+    /// 报告错误，不改动占用的条目。
+    #[default]
+    Error,
+    /// 将占用的条目重命名为 `名称.bak` 后写入。
+    Backup,
+    /// 按 `delete_mode` 删除占用的条目后写入。
+    Remove,
+}
+
+/// 链接模式下目标文件系统不支持软链接时的处理方式。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkFallback {
+    /// 报告错误并发送 `link-unsupported` 事件。
+    #[default]
+    Error,
+    /// 改为复制文件。
+    Copy,
+}
+
+/// 系统的文件监视数量达到上限（Linux 上为 inotify 的 `max_user_watches`）时的处理方式。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WatchLimitFallback {
+    /// 监视启动失败，错误中给出提高上限的方法。
+    #[default]
+    Error,
+    /// 该监视改用轮询模式。
+    Poll,
+}
+
+/// 发现源目录变化的方式。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WatchMode {
+    /// 使用系统的文件系统事件。
+    #[default]
+    Event,
+    /// 定期扫描源目录并与同步索引比较，适用于事件不可靠的网络或虚拟文件系统。
+    Poll,
+    /// 以系统事件为主，同时低频轮询以补上丢失的事件，适用于偶尔丢事件的文件系统。
+    Hybrid,
+}
+
+/// 单个监视的可选配置，前端未提供的字段使用默认值。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct WatchOptions {
+    /// 监视的显示名称。
+    pub name: Option<String>,
+    /// 用户为监视写的备注，不影响监视的行为。
+    pub notes: Option<String>,
+    /// 所属的分组，同组的监视可通过 `start_group`、`stop_group`、`pause_group` 一起操作。
+    pub group: Option<String>,
+    /// 复制文件时读写缓冲区的大小（字节）。
+    pub buffer_size: usize,
+    /// 包含/排除规则。
+    pub filters: Filters,
+    /// 复制后保留源文件的 uid/gid，仅在 Unix 上生效。
+    pub preserve_ownership: bool,
+    /// 复制后将目标文件的权限位设为该值（例如 `0o644`，JSON 中为十进制的 420），
+    /// 不论源文件的权限如何，仅在 Unix 上生效。
+    pub force_mode: Option<u32>,
+    /// 类似 umask 的权限掩码：复制后清除目标文件权限中在掩码里置位的位（例如 `0o022`
+    /// 去掉组和其他用户的写权限，JSON 中为十进制的 18），在 `force_mode` 之后应用，仅在 Unix 上生效。
+    pub permission_mask: Option<u32>,
+    /// 连续这么多秒没有处理任何事件后自动停止监视。
+    pub idle_timeout_secs: Option<u64>,
+    /// 启动监视时先对整个源目录做一次全量同步。
+    pub initial_sync: bool,
+    /// 链接模式下如何链接目录。为空时忽略目录，只链接其中的文件。
+    pub windows_dir_link_style: Option<DirLinkStyle>,
+    /// 复制后将目标文件设为只读，防止误改镜像。
+    pub target_read_only: bool,
+    /// 将所有文件直接放到目标目录下，不保留子目录结构。
+    pub flatten: bool,
+    /// 复制扩展属性（Unix）或备用数据流（Windows）。
+    pub copy_xattrs: bool,
+    /// 源目录本身被删除时清空目标目录。默认保留目标中的内容。
+    pub delete_target_on_source_lost: bool,
+    /// 复制模式下对指向目录的软链接的处理方式。
+    pub symlink_policy: SymlinkPolicy,
+    /// 复制模式下覆盖目标文件的策略。
+    pub sync_mode: SyncMode,
+    /// 复制模式下写入已存在的目标文件的方式。
+    pub copy_mode: CopyMode,
+    /// 复制模式下对命名管道、套接字和设备文件的处理方式。
+    pub special_file_policy: SpecialFilePolicy,
+    /// 增量复制时比较的块大小（字节）。
+    pub delta_block_size: usize,
+    /// 写入目标时对文件名应用的重命名规则。
+    pub rename_rule: Option<RenameRule>,
+    /// 每隔这么多秒执行一次全量对账，修正实时事件遗漏的变化。
+    pub reconcile_interval_secs: Option<u64>,
+    /// 移动模式下删除源文件前先确认目标内容与源文件一致。
+    pub verify_move: bool,
+    /// 移动模式下源文件为只读、无法删除时的处理方式。
+    pub read_only_source: ReadOnlySource,
+    /// 复制的文件先写入暂存目录，连续这么多秒没有新文件后再移动到最终位置。
+    pub staging_quiet_secs: Option<u64>,
+    /// 源中互为硬链接的文件在目标中也创建为硬链接，只复制一次内容。仅在 Unix 上生效。
+    pub preserve_hardlinks: bool,
+    /// 源路径或目标路径不存在时不报错，而是等待它们出现后再启动监视。
+    pub wait_for_paths: bool,
+    /// 删除目标文件的方式。
+    pub delete_mode: DeleteMode,
+    /// 删除事件延迟这么多秒后再同步到目标，期间可用 `undo_delete` 撤销，为空时立即删除。
+    pub delete_delay_secs: Option<u64>,
+    /// 目标中需要目录的位置已被文件占用时的处理方式。
+    pub dir_conflict: DirConflict,
+    /// 缓冲模式暂停时缓冲区已满的处理方式。
+    pub pause_overflow: PauseOverflow,
+    /// 在目标目录中维护记录文件大小、修改时间和摘要的清单文件。
+    pub manifest: bool,
+    /// 每秒最多执行的文件操作数，为空时不限制。
+    pub max_ops_per_sec: Option<u32>,
+    /// 合并同一路径反复删除、创建的窗口（毫秒），为空时逐个处理事件。
+    pub churn_window_ms: Option<u64>,
+    /// 丢弃此窗口（毫秒）内路径和类型完全相同的重复事件，为 0 时不去重。
+    pub dedup_window_ms: u64,
+    /// 只同步修改时间不早于此时刻的文件，自 UNIX 纪元起的毫秒数。可通过 `set_modified_after` 修改。
+    pub modified_after_ms: Option<u64>,
+    pub mode: WatchMode,
+    /// 轮询模式下两次扫描的间隔（秒）。
+    pub poll_interval_secs: u64,
+    /// 混合模式下两次补充扫描的间隔（秒）。扫描需要遍历整个源目录，默认较长。
+    pub hybrid_poll_interval_secs: u64,
+    /// 系统的文件监视数量达到上限时的处理方式。
+    pub watch_limit_fallback: WatchLimitFallback,
+    /// 目标文件系统（如 FAT32、exFAT）不支持软链接时的处理方式。
+    pub link_fallback: LinkFallback,
+    /// 启动监视时把源和目标解析为真实路径（解析软链接和驱动器映射），并用于计算相对路径。
+    /// macOS 上默认开启，因为系统报告的事件路径总是真实路径。
+    pub canonicalize_paths: bool,
+    /// 计算目标路径前从相对路径开头去掉的层数，类似 tar 的 `--strip-components`。
+    /// 层数不多于此值的路径被跳过。
+    pub strip_components: usize,
+    /// 复制模式下先尝试写时复制（reflink），目标文件系统不支持时改为普通复制。
+    pub use_reflink: bool,
+    /// 目标目录中已同步文件的总大小上限（字节）。复制模式和移动模式下超出时先删除最久未同步的目标文件。
+    pub max_target_size: Option<u64>,
+    /// 复制模式下跳过大小为零的文件，避免先创建空占位文件再写入内容的程序触发多余的复制。
+    /// 开启后本就为空或被清空的文件不会同步到目标。
+    pub ignore_empty_files: bool,
+    /// 忽略文件内容和元数据的修改事件，只在创建、重命名和删除时同步，适合写入后不再改变的文件。
+    /// 只影响系统监视的事件，轮询和全量同步仍按文件的变化同步。
+    pub ignore_modify: bool,
+    /// 全量同步完成后将源目录的修改时间应用到对应的目标目录，扁平化时不生效。
+    pub preserve_dir_mtimes: bool,
+    /// 对每个文件事件调用的外部程序，由它决定目标路径或跳过该文件，协议见 [`crate::hook`]。
+    ///
+    /// 钩子以当前用户的权限运行任意程序，只应配置可信的脚本。
+    pub hook: Option<EventHook>,
+    /// 等待事件循环处理的事件数上限，事件风暴期间超出时按 `queue_overflow` 处理。
+    pub max_queued_events: usize,
+    /// 排队的事件达到上限时的处理方式。
+    pub queue_overflow: QueueOverflow,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        Self {
+            name: None,
+            notes: None,
+            group: None,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            filters: Filters::default(),
+            preserve_ownership: false,
+            force_mode: None,
+            permission_mask: None,
+            idle_timeout_secs: None,
+            initial_sync: false,
+            windows_dir_link_style: None,
+            target_read_only: false,
+            flatten: false,
+            copy_xattrs: false,
+            delete_target_on_source_lost: false,
+            symlink_policy: SymlinkPolicy::Skip,
+            sync_mode: SyncMode::Mirror,
+            copy_mode: CopyMode::Full,
+            special_file_policy: SpecialFilePolicy::Skip,
+            delta_block_size: DEFAULT_DELTA_BLOCK_SIZE,
+            rename_rule: None,
+            reconcile_interval_secs: None,
+            verify_move: true,
+            read_only_source: ReadOnlySource::Keep,
+            staging_quiet_secs: None,
+            preserve_hardlinks: false,
+            wait_for_paths: false,
+            delete_mode: DeleteMode::Permanent,
+            delete_delay_secs: None,
+            dir_conflict: DirConflict::Error,
+            pause_overflow: PauseOverflow::Resync,
+            manifest: false,
+            max_ops_per_sec: None,
+            churn_window_ms: None,
+            dedup_window_ms: 50,
+            modified_after_ms: None,
+            mode: WatchMode::Event,
+            poll_interval_secs: 30,
+            hybrid_poll_interval_secs: 600,
+            watch_limit_fallback: WatchLimitFallback::Error,
+            link_fallback: LinkFallback::Error,
+            canonicalize_paths: cfg!(target_os = "macos"),
+            strip_components: 0,
+            use_reflink: false,
+            max_target_size: None,
+            ignore_empty_files: false,
+            ignore_modify: false,
+            preserve_dir_mtimes: false,
+            hook: None,
+            max_queued_events: queue::DEFAULT_CAPACITY,
+            queue_overflow: QueueOverflow::DropNewest,
+        }
+    }
+}
+
+impl WatchOptions {
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_secs.map(Duration::from_secs)
+    }
+
+    pub fn modified_after(&self) -> Option<SystemTime> {
+        self.modified_after_ms
+            .map(|ms| UNIX_EPOCH + Duration::from_millis(ms))
+    }
+
+    /// 轮询或混合模式下的扫描间隔，事件模式下为 `None`。
+    pub fn poll_interval(&self) -> Option<Duration> {
+        let secs = match self.mode {
+            WatchMode::Event => return None,
+            WatchMode::Poll => self.poll_interval_secs,
+            WatchMode::Hybrid => self.hybrid_poll_interval_secs,
+        };
+        Some(Duration::from_secs(secs.max(1)))
+    }
+
+    pub fn churn_window(&self) -> Option<Duration> {
+        self.churn_window_ms.map(Duration::from_millis)
+    }
+
+    pub fn dedup_window(&self) -> Option<Duration> {
+        (self.dedup_window_ms > 0).then(|| Duration::from_millis(self.dedup_window_ms))
+    }
+
+    pub fn delete_delay(&self) -> Option<Duration> {
+        self.delete_delay_secs.map(Duration::from_secs)
+    }
+
+    pub fn staging_quiet(&self) -> Option<Duration> {
+        self.staging_quiet_secs.map(Duration::from_secs)
+    }
+
+    pub fn reconcile_interval(&self) -> Option<Duration> {
+        self.reconcile_interval_secs
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(mode: WatchMode) -> WatchOptions {
+        WatchOptions {
+            mode,
+            poll_interval_secs: 5,
+            hybrid_poll_interval_secs: 300,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn poll_interval_depends_on_mode() {
+        assert_eq!(options(WatchMode::Event).poll_interval(), None);
+        assert_eq!(
+            options(WatchMode::Poll).poll_interval(),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            options(WatchMode::Hybrid).poll_interval(),
+            Some(Duration::from_secs(300))
+        );
+    }
+
+    #[test]
+    fn zero_poll_interval_is_one_second() {
+        let hybrid = WatchOptions {
+            mode: WatchMode::Hybrid,
+            hybrid_poll_interval_secs: 0,
+            ..Default::default()
+        };
+        assert_eq!(hybrid.poll_interval(), Some(Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,172 @@
+//! 按文件内容决定是否同步，例如只同步包含某个标记的文件。
+//!
+//! 只读取文件开头的 `max_bytes` 字节做判断，规则作用于这部分内容。不满足规则的文件被跳过，
+//! 之前已同步到目标的旧版本保持不变。目录和删除不受影响。
+
+use std::{io, path::Path};
+
+use anyhow::Context;
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::fs_ops::FsOps;
+
+/// 内容规则。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum ContentRule {
+    /// 包含该字符串。
+    Contains(String),
+    /// 是有效的 UTF-8 文本。
+    ValidUtf8,
+    /// 匹配该正则表达式。
+    Matches(String),
+}
+
+/// 用户配置的内容规则。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentPredicate {
+    pub rule: ContentRule,
+    /// 最多读取的字节数，默认 64 KiB。
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+}
+
+fn default_max_bytes() -> u64 {
+    64 * 1024
+}
+
+/// 编译后的 [`ContentPredicate`]。
+#[derive(Clone)]
+pub struct ContentFilter {
+    rule: CompiledRule,
+    max_bytes: u64,
+}
+
+#[derive(Clone)]
+enum CompiledRule {
+    Contains(Vec<u8>),
+    ValidUtf8,
+    Matches(Regex),
+}
+
+impl ContentFilter {
+    pub fn new(predicate: &ContentPredicate) -> anyhow::Result<Self> {
+        let rule = match &predicate.rule {
+            ContentRule::Contains(needle) => CompiledRule::Contains(needle.as_bytes().to_vec()),
+            ContentRule::ValidUtf8 => CompiledRule::ValidUtf8,
+            ContentRule::Matches(pattern) => CompiledRule::Matches(
+                Regex::new(pattern).with_context(|| format!("无效的内容规则 '{}'", pattern))?,
+            ),
+        };
+        Ok(Self {
+            rule,
+            max_bytes: predicate.max_bytes,
+        })
+    }
+
+    /// 读取文件开头并判断是否满足规则。
+    pub async fn matches<F: FsOps>(&self, fs: &F, path: &Path) -> io::Result<bool> {
+        let head = fs.read_head(path, self.max_bytes).await?;
+        let truncated = head.len() as u64 >= self.max_bytes;
+        Ok(match &self.rule {
+            CompiledRule::Contains(needle) => {
+                needle.is_empty() || head.windows(needle.len()).any(|window| window == needle)
+            }
+            // 截断处可能正好落在多字节字符中间，此时只要求之前的内容有效
+            CompiledRule::ValidUtf8 => match std::str::from_utf8(&head) {
+                Ok(_) => true,
+                Err(e) => truncated && e.error_len().is_none(),
+            },
+            CompiledRule::Matches(regex) => regex.is_match(&head),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::WatchOptions,
+        file_processor,
+        filter::Filters,
+        mem_fs::{dst, src, MemFs, TestWatch},
+        CopyType,
+    };
+
+    fn filter(rule: ContentRule, max_bytes: u64) -> ContentFilter {
+        ContentFilter::new(&ContentPredicate { rule, max_bytes }).unwrap()
+    }
+
+    async fn matches(filter: &ContentFilter, data: &[u8]) -> bool {
+        let fs = MemFs::new();
+        fs.add_file(src("a.txt"), data);
+        filter.matches(&fs, &src("a.txt")).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn contains_rule_matches_substring() {
+        let filter = filter(ContentRule::Contains("#sync".to_string()), 1024);
+        assert!(matches(&filter, b"title\n#sync\nbody").await);
+        assert!(!matches(&filter, b"title\nbody").await);
+    }
+
+    #[tokio::test]
+    async fn regex_rule_matches_pattern() {
+        let filter = filter(ContentRule::Matches(r"^version = \d+".to_string()), 1024);
+        assert!(matches(&filter, b"version = 3\n").await);
+        assert!(!matches(&filter, b"version = x\n").await);
+    }
+
+    #[tokio::test]
+    async fn utf8_rule_rejects_binary() {
+        let filter = filter(ContentRule::ValidUtf8, 1024);
+        assert!(matches(&filter, "文本".as_bytes()).await);
+        assert!(!matches(&filter, &[0xff, 0xfe, 0x00]).await);
+    }
+
+    #[tokio::test]
+    async fn only_head_of_file_is_checked() {
+        let contains = filter(ContentRule::Contains("marker".to_string()), 8);
+        assert!(!matches(&contains, b"01234567marker").await);
+        // 截断处落在多字节字符中间时仍视为有效文本
+        let utf8 = filter(ContentRule::ValidUtf8, 4);
+        assert!(matches(&utf8, "ab文本".as_bytes()).await);
+    }
+
+    #[test]
+    fn invalid_regex_is_rejected() {
+        let predicate = ContentPredicate {
+            rule: ContentRule::Matches("(".to_string()),
+            max_bytes: 1024,
+        };
+        assert!(ContentFilter::new(&predicate).is_err());
+    }
+
+    #[tokio::test]
+    async fn files_not_matching_content_rule_are_not_copied() {
+        let watch = TestWatch::new(WatchOptions {
+            filters: Filters {
+                content: Some(ContentPredicate {
+                    rule: ContentRule::Contains("#sync".to_string()),
+                    max_bytes: 1024,
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        watch.fs.add_file(src("yes.txt"), "#sync");
+        watch.fs.add_file(src("no.txt"), "other");
+        for name in ["yes.txt", "no.txt"] {
+            let event =
+                notify::Event::new(notify::EventKind::Create(notify::event::CreateKind::File))
+                    .add_path(src(name));
+            file_processor::process(&watch.ctx(), CopyType::Copy, &event)
+                .await
+                .unwrap();
+        }
+        assert_eq!(watch.fs.read(dst("yes.txt")), Some(b"#sync".to_vec()));
+        assert_eq!(watch.fs.read(dst("no.txt")), None);
+    }
+}
@@ -0,0 +1,129 @@
+//! 丢弃短时间内完全相同的重复事件。
+//!
+//! 重叠的监视或某些后端会为同一处变化报告多次相同的事件，逐个处理会重复复制同一文件。
+//! 路径和事件类型都相同、与上一次间隔不超过窗口，且文件的大小和修改时间也未变的事件被视为重复。
+//! 窗口默认很短；比较文件状态保证窗口内的连续写入不会被当作重复而漏掉最后的内容。
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use notify::{Event, EventKind};
+
+use crate::index::IndexEntry;
+
+pub struct EventDedup {
+    window: Duration,
+    /// (事件类型, 路径) -> 最近一次收到的时间和当时第一个路径的文件状态。
+    recent: HashMap<(EventKind, Vec<PathBuf>), (Instant, Option<IndexEntry>)>,
+    last_prune: Instant,
+}
+
+impl EventDedup {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            recent: HashMap::new(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /// 事件是否与窗口内已收到的事件重复，不重复时记录该事件。
+    pub async fn is_duplicate(&mut self, event: &Event) -> bool {
+        let now = Instant::now();
+        if now.duration_since(self.last_prune) >= self.window {
+            let window = self.window;
+            self.recent
+                .retain(|_, (at, _)| now.duration_since(*at) < window);
+            self.last_prune = now;
+        }
+        let entry = match event.paths.first() {
+            Some(path) => file_entry(path).await,
+            None => None,
+        };
+        let key = (event.kind, event.paths.clone());
+        match self.recent.get(&key) {
+            Some((at, seen)) if now.duration_since(*at) < self.window && *seen == entry => true,
+            _ => {
+                self.recent.insert(key, (now, entry));
+                false
+            }
+        }
+    }
+}
+
+/// 文件当前的大小和修改时间，不是文件或无法读取时为 `None`。
+async fn file_entry(path: &std::path::Path) -> Option<IndexEntry> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    if !metadata.is_file() {
+        return None;
+    }
+    IndexEntry::new(metadata.len(), metadata.modified().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{CreateKind, DataChange, ModifyKind};
+
+    use super::*;
+    use crate::{
+        config::WatchOptions,
+        file_processor,
+        mem_fs::{dst, src, Op, TestWatch},
+        CopyType,
+    };
+
+    fn modify(path: PathBuf) -> Event {
+        Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content))).add_path(path)
+    }
+
+    #[tokio::test]
+    async fn duplicates_within_window_are_processed_once() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "data");
+        let mut dedup = EventDedup::new(Duration::from_secs(60));
+        for _ in 0..3 {
+            let event = modify(src("a.txt"));
+            if !dedup.is_duplicate(&event).await {
+                file_processor::process(&watch.ctx(), CopyType::Copy, &event)
+                    .await
+                    .unwrap();
+            }
+        }
+        assert_eq!(watch.fs.ops(), vec![Op::Copy(src("a.txt"), dst("a.txt"))]);
+    }
+
+    #[tokio::test]
+    async fn repeated_event_after_window_is_not_duplicate() {
+        let mut dedup = EventDedup::new(Duration::from_millis(20));
+        let event = modify("/src/a.txt".into());
+        assert!(!dedup.is_duplicate(&event).await);
+        assert!(dedup.is_duplicate(&event).await);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(!dedup.is_duplicate(&event).await);
+    }
+
+    #[tokio::test]
+    async fn different_kind_or_path_is_not_duplicate() {
+        let mut dedup = EventDedup::new(Duration::from_secs(60));
+        assert!(!dedup.is_duplicate(&modify("/src/a.txt".into())).await);
+        assert!(!dedup.is_duplicate(&modify("/src/b.txt".into())).await);
+        let create = Event::new(EventKind::Create(CreateKind::File)).add_path("/src/a.txt".into());
+        assert!(!dedup.is_duplicate(&create).await);
+    }
+
+    #[tokio::test]
+    async fn write_within_window_is_not_duplicate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, "first").unwrap();
+        let mut dedup = EventDedup::new(Duration::from_secs(60));
+        assert!(!dedup.is_duplicate(&modify(path.clone())).await);
+        // 大小改变，即使修改时间的精度不足以区分两次写入
+        std::fs::write(&path, "second write").unwrap();
+        assert!(!dedup.is_duplicate(&modify(path.clone())).await);
+        assert!(dedup.is_duplicate(&modify(path)).await);
+    }
+}
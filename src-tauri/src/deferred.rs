@@ -0,0 +1,71 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// 重试被占用文件的间隔。
+pub const RETRY_INTERVAL: Duration = Duration::from_secs(5);
+/// 文件持续被占用超过此时长后放弃同步。
+pub const MAX_AGE: Duration = Duration::from_secs(10 * 60);
+
+/// 因文件被其他进程占用而暂时无法处理的事件。
+pub struct DeferredEntry {
+    pub event: notify::Event,
+    pub since: Instant,
+}
+
+/// 每个监视独立的延迟队列，按路径去重，只保留最新的事件。
+#[derive(Default)]
+pub struct DeferredQueue {
+    entries: HashMap<PathBuf, DeferredEntry>,
+}
+
+impl DeferredQueue {
+    /// 加入队列。路径已在队列中时更新事件，但保留最初失败的时间。
+    pub fn push(&mut self, path: PathBuf, event: notify::Event) {
+        let since = self
+            .entries
+            .get(&path)
+            .map_or_else(Instant::now, |entry| entry.since);
+        self.entries.insert(path, DeferredEntry { event, since });
+    }
+
+    pub fn requeue(&mut self, path: PathBuf, entry: DeferredEntry) {
+        self.entries.insert(path, entry);
+    }
+
+    pub fn remove(&mut self, path: &Path) -> bool {
+        self.entries.remove(path).is_some()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn drain(&mut self) -> Vec<(PathBuf, DeferredEntry)> {
+        self.entries.drain().collect()
+    }
+}
+
+/// 判断错误是否由文件被占用（共享冲突或锁冲突）引起。
+pub fn is_locked_error(err: &anyhow::Error) -> bool {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(is_locked_io_error)
+}
+
+#[cfg(windows)]
+fn is_locked_io_error(err: &std::io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION = 32, ERROR_LOCK_VIOLATION = 33
+    matches!(err.raw_os_error(), Some(32) | Some(33))
+}
+
+#[cfg(not(windows))]
+fn is_locked_io_error(_err: &std::io::Error) -> bool {
+    false
+}
@@ -0,0 +1,120 @@
+//! 延迟执行删除，留出撤销误删的时间。
+//!
+//! 开启 `delete_delay_secs` 后删除事件不会立即同步到目标，而是先排队，期间可用 `undo_delete`
+//! 撤销该监视所有排队的删除。路径或其下的路径在延迟期间重新出现（例如目录被移走后又移回）时，
+//! 对应的删除自动取消。监视停止时尚未执行的删除被丢弃，目标中的文件保持不变。
+
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use notify::Event;
+
+/// 检查排队的删除是否到期的间隔。
+pub const CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
+/// 按到达顺序排队的删除事件。
+#[derive(Default)]
+pub struct DelayedDeletes {
+    queued: Vec<(Instant, Event)>,
+}
+
+impl DelayedDeletes {
+    pub fn push(&mut self, event: Event) {
+        self.queued.push((Instant::now(), event));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queued.len()
+    }
+
+    /// 取出排队超过 `delay` 的删除，保持原顺序。
+    pub fn take_due(&mut self, delay: Duration) -> Vec<Event> {
+        let due = self
+            .queued
+            .iter()
+            .take_while(|(at, _)| at.elapsed() >= delay)
+            .count();
+        self.queued.drain(..due).map(|(_, event)| event).collect()
+    }
+
+    /// 取消路径本身及其上级目录的删除，返回被取消删除的路径。
+    pub fn cancel(&mut self, path: &Path) -> Vec<PathBuf> {
+        let mut cancelled = Vec::new();
+        self.queued.retain(|(_, event)| match event.paths.first() {
+            Some(queued) if path.starts_with(queued) => {
+                cancelled.push(queued.clone());
+                false
+            }
+            _ => true,
+        });
+        cancelled
+    }
+
+    /// 取出所有排队的删除的路径，用于撤销。
+    pub fn take_all(&mut self) -> Vec<PathBuf> {
+        self.queued
+            .drain(..)
+            .filter_map(|(_, event)| event.paths.into_iter().next())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::{event::RemoveKind, EventKind};
+
+    use super::*;
+
+    fn remove(path: &str) -> Event {
+        Event::new(EventKind::Remove(RemoveKind::Any)).add_path(path.into())
+    }
+
+    #[test]
+    fn deletes_become_due_after_delay_in_order() {
+        let mut deletes = DelayedDeletes::default();
+        deletes.push(remove("/src/a"));
+        deletes.push(remove("/src/b"));
+        assert!(deletes.take_due(Duration::from_secs(60)).is_empty());
+        assert_eq!(deletes.len(), 2);
+        let due = deletes.take_due(Duration::ZERO);
+        assert_eq!(
+            due.iter()
+                .map(|event| event.paths[0].clone())
+                .collect::<Vec<_>>(),
+            [PathBuf::from("/src/a"), PathBuf::from("/src/b")]
+        );
+        assert!(deletes.is_empty());
+    }
+
+    #[test]
+    fn recreated_path_cancels_its_delete() {
+        let mut deletes = DelayedDeletes::default();
+        deletes.push(remove("/src/a.txt"));
+        deletes.push(remove("/src/b.txt"));
+        assert_eq!(
+            deletes.cancel(Path::new("/src/a.txt")),
+            [PathBuf::from("/src/a.txt")]
+        );
+        assert_eq!(deletes.take_all(), [PathBuf::from("/src/b.txt")]);
+    }
+
+    #[test]
+    fn path_reappearing_inside_deleted_dir_cancels_dir_delete() {
+        let mut deletes = DelayedDeletes::default();
+        deletes.push(remove("/src/dir"));
+        deletes.push(remove("/src/dir2"));
+        // 目录被移回后其中的文件先报告创建
+        assert_eq!(
+            deletes.cancel(Path::new("/src/dir/a.txt")),
+            [PathBuf::from("/src/dir")]
+        );
+        assert!(deletes.cancel(Path::new("/src/other.txt")).is_empty());
+        assert_eq!(deletes.len(), 1);
+    }
+}
@@ -0,0 +1,124 @@
+//! 用于远程排查问题的运行状态快照。
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use notify::{RecommendedWatcher, Watcher};
+use serde::Serialize;
+use tauri::Manager;
+
+use crate::{config::WatchConfig, pause::PauseMode, stats::StatsSnapshot, AppState, CopyType};
+
+/// 事件循环内部各队列的长度。
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueDepths {
+    /// 等待事件循环处理的事件数。
+    pub queued: usize,
+    /// 因文件被占用而等待重试的路径数。
+    pub deferred: usize,
+    /// 暂停期间缓存的事件数。
+    pub paused: usize,
+    /// 等待稳定的反复删除、创建的路径数。
+    pub churn: usize,
+    /// 延迟期间等待执行的删除数。
+    pub delayed_deletes: usize,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchDump {
+    pub name: String,
+    pub config: WatchConfig,
+    pub serial: u64,
+    pub created_at_ms: u64,
+    /// 当前生效的复制类型，可能已被 `set_copy_type` 修改。
+    pub copy_type: CopyType,
+    pub stats: StatsSnapshot,
+    pub paused: Option<PauseMode>,
+    pub errors_muted: bool,
+    pub syncing: bool,
+    pub reflink_unsupported: bool,
+    pub same_filesystem: Option<bool>,
+    pub queue_depths: QueueDepths,
+}
+
+/// `dump_state` 返回的快照。
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StateDump {
+    pub version: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub notify_backend: String,
+    /// 监视 id -> 状态。
+    pub watches: BTreeMap<String, WatchDump>,
+    /// 等待路径出现的监视。
+    pub pending: BTreeMap<String, WatchConfig>,
+    /// 分组名称 -> 成员 id。
+    pub groups: BTreeMap<String, BTreeSet<String>>,
+    /// 正在进行的源目录扫描 id。
+    pub scans: BTreeSet<String>,
+    /// 窗口标签 -> 订阅的监视 id。
+    pub subscriptions: BTreeMap<String, BTreeSet<String>>,
+}
+
+/// 收集所有可序列化的运行状态。每个锁只在复制数据期间持有，序列化在释放锁之后进行。
+pub async fn dump(app: &tauri::AppHandle) -> StateDump {
+    let state = app.state::<AppState>();
+    let syncing: BTreeSet<String> = state.syncs.lock().await.keys().cloned().collect();
+    let watches = state
+        .watcher
+        .lock()
+        .await
+        .iter()
+        .map(|(id, watch)| {
+            let dump = WatchDump {
+                name: watch.config.name(),
+                config: watch.config.clone(),
+                serial: watch.serial,
+                created_at_ms: watch.created_at_ms(),
+                copy_type: watch.state.copy_type(),
+                stats: watch.state.stats().snapshot(),
+                paused: watch.state.pause_mode(),
+                errors_muted: watch.state.errors_muted(),
+                syncing: syncing.contains(id),
+                reflink_unsupported: watch.state.reflink_unsupported(),
+                same_filesystem: watch.state.same_filesystem(),
+                queue_depths: watch.state.queue_depths(),
+            };
+            (id.clone(), dump)
+        })
+        .collect();
+    let pending = state
+        .pending
+        .lock()
+        .await
+        .iter()
+        .map(|(id, config)| (id.clone(), config.clone()))
+        .collect();
+    let mut groups: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for (id, config) in state.groups.lock().await.iter() {
+        if let Some(group) = &config.options.group {
+            groups.entry(group.clone()).or_default().insert(id.clone());
+        }
+    }
+    let scans = state.scans.lock().await.keys().cloned().collect();
+    let subscriptions = state
+        .subscriptions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .map(|(label, ids)| (label.clone(), ids.iter().cloned().collect()))
+        .collect();
+    StateDump {
+        version: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        notify_backend: format!("{:?}", RecommendedWatcher::kind()),
+        watches,
+        pending,
+        groups,
+        scans,
+        subscriptions,
+    }
+}
@@ -0,0 +1,92 @@
+//! 每个监视最近的错误记录，便于发现同一文件反复失败之类的规律。
+//!
+//! 只保留最近的 [`MAX_ENTRIES`] 条，更早的记录被丢弃。记录随监视状态创建，重新创建监视时清空。
+
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Serialize;
+
+/// 每个监视最多保留的错误记录数。
+pub const MAX_ENTRIES: usize = 200;
+
+/// 一条错误记录。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorRecord {
+    /// 发生的时间，自 UNIX 纪元起的毫秒数。
+    pub at_ms: u64,
+    pub path: Option<PathBuf>,
+    /// 错误链中第一个 IO 错误的类型（例如 `PermissionDenied`），没有 IO 错误时为空。
+    pub kind: Option<String>,
+    pub message: String,
+}
+
+#[derive(Default)]
+pub struct ErrorHistory {
+    entries: VecDeque<ErrorRecord>,
+}
+
+impl ErrorHistory {
+    pub fn record(&mut self, path: Option<PathBuf>, err: &anyhow::Error) {
+        if self.entries.len() >= MAX_ENTRIES {
+            self.entries.pop_front();
+        }
+        let kind = err
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<std::io::Error>())
+            .map(|e| format!("{:?}", e.kind()));
+        self.entries.push_back(ErrorRecord {
+            at_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis() as u64),
+            path,
+            kind,
+            message: format!("{:#}", err),
+        });
+    }
+
+    /// 最近的 `limit` 条记录，新的在前。
+    pub fn recent(&self, limit: usize) -> Vec<ErrorRecord> {
+        self.entries.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context;
+
+    use super::*;
+
+    #[test]
+    fn history_is_bounded_and_newest_first() {
+        let mut history = ErrorHistory::default();
+        for n in 0..MAX_ENTRIES + 5 {
+            history.record(None, &anyhow::anyhow!("错误 {}", n));
+        }
+        let recent = history.recent(usize::MAX);
+        assert_eq!(recent.len(), MAX_ENTRIES);
+        assert_eq!(recent[0].message, format!("错误 {}", MAX_ENTRIES + 4));
+        // 最早的 5 条已被丢弃
+        assert_eq!(recent[MAX_ENTRIES - 1].message, "错误 5");
+        assert_eq!(history.recent(2).len(), 2);
+    }
+
+    #[test]
+    fn io_error_kind_is_recorded() {
+        let mut history = ErrorHistory::default();
+        let err = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+            .context("复制失败")
+            .unwrap_err();
+        history.record(Some("/src/a.txt".into()), &err);
+        history.record(None, &anyhow::anyhow!("其他错误"));
+        let recent = history.recent(2);
+        assert_eq!(recent[1].kind.as_deref(), Some("PermissionDenied"));
+        assert_eq!(recent[1].path, Some(PathBuf::from("/src/a.txt")));
+        assert!(recent[1].message.starts_with("复制失败: "));
+        assert_eq!(recent[0].kind, None);
+    }
+}
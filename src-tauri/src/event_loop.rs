@@ -0,0 +1,909 @@
+use std::{
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
+
+use log::{debug, error, info, warn};
+use notify::{
+    event::{ModifyKind, RenameMode},
+    Event, EventKind,
+};
+use tauri::Manager;
+use tokio::{
+    select,
+    time::{interval, interval_at, sleep, Instant, MissedTickBehavior, Sleep},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    churn::{self, ChurnGuard},
+    config::{WatchConfig, WatchMode},
+    dedup::EventDedup,
+    deferred::{self, DeferredQueue},
+    delayed_delete,
+    diagnostics::QueueDepths,
+    events::{self, ErrorPayload, IdPayload, PathPayload, PathsPayload, QueueOverflowPayload},
+    evict::TargetCache,
+    file_processor::{self, ProcessContext},
+    file_processor_link::LinkUnsupported,
+    filter::PathFilter,
+    fs_ops::TokioFs,
+    index::IndexEntry,
+    mount,
+    pause::{PauseBuffer, PauseMode, PauseOverflow},
+    progress::ProgressReporter,
+    queue::{EventReceiver, Received},
+    sync, tray,
+    watch_state::WatchState,
+    AppState,
+};
+
+/// 同步索引写回磁盘的间隔。
+const INDEX_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+/// 检查暂存文件是否可以提交的间隔。
+const STAGING_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 单个监视的事件处理循环。
+///
+/// 因文件被占用而失败的事件会进入延迟队列，按固定间隔重试，
+/// 直至成功或超过最长等待时间，期间不阻塞后续事件的处理。
+pub struct EventLoop {
+    app: tauri::AppHandle,
+    config: WatchConfig,
+    serial: u64,
+    filter: PathFilter,
+    state: Arc<WatchState>,
+    deferred: DeferredQueue,
+    paused: PauseBuffer,
+    /// 未配置 `churn_window_ms` 时为 `None`。
+    churn: Option<ChurnGuard>,
+    /// `dedup_window_ms` 为 0 时为 `None`。
+    dedup: Option<EventDedup>,
+    /// 源目录所在的卷已被卸载，期间忽略删除事件。
+    unmounted: bool,
+    shutdown: CancellationToken,
+}
+
+impl EventLoop {
+    pub fn new(
+        app: tauri::AppHandle,
+        config: WatchConfig,
+        serial: u64,
+        filter: PathFilter,
+        state: Arc<WatchState>,
+        shutdown: CancellationToken,
+    ) -> Self {
+        let churn = config.options.churn_window().map(ChurnGuard::new);
+        let dedup = config.options.dedup_window().map(EventDedup::new);
+        Self {
+            app,
+            config,
+            serial,
+            filter,
+            state,
+            deferred: DeferredQueue::default(),
+            paused: PauseBuffer::default(),
+            churn,
+            dedup,
+            unmounted: false,
+            shutdown,
+        }
+    }
+
+    /// 运行事件循环，直到停止令牌被取消，或因空闲超时、源目录丢失自动停止。
+    ///
+    /// 监视器被 drop 导致通道关闭时也会退出，作为停止令牌之外的后备手段。
+    pub async fn run(mut self, mut rx: EventReceiver) {
+        info!(
+            "监视 '{}' ({:?}) 的事件处理循环已启动。",
+            self.config.name(),
+            self.config.from
+        );
+        if self.config.options.initial_sync {
+            self.initial_sync(&mut rx).await;
+        }
+        let mut retry = interval(deferred::RETRY_INTERVAL);
+        retry.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut save_index = interval(INDEX_SAVE_INTERVAL);
+        save_index.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let reconcile_interval = self.config.options.reconcile_interval();
+        // 未配置对账时该计时器永远不会被轮询
+        let reconcile_period = reconcile_interval.unwrap_or(INDEX_SAVE_INTERVAL);
+        let mut reconcile = interval_at(Instant::now() + reconcile_period, reconcile_period);
+        reconcile.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let staging_quiet = self.config.options.staging_quiet();
+        let mut staging = interval(STAGING_CHECK_INTERVAL);
+        staging.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let idle_timeout = self.config.options.idle_timeout();
+        // 未配置空闲超时时该计时器永远不会被轮询
+        let idle = sleep(idle_timeout.unwrap_or(Duration::MAX));
+        tokio::pin!(idle);
+        let mut settle = interval(churn::SETTLE_CHECK_INTERVAL);
+        settle.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let poll_interval = self.config.options.poll_interval();
+        // 事件模式下该计时器永远不会被轮询；混合模式下已执行过初始同步时，第一次扫描推迟一个间隔
+        let poll_period = poll_interval.unwrap_or(INDEX_SAVE_INTERVAL);
+        let poll_start =
+            if self.config.options.mode == WatchMode::Hybrid && self.config.options.initial_sync {
+                Instant::now() + poll_period
+            } else {
+                Instant::now()
+            };
+        let mut poll = interval_at(poll_start, poll_period);
+        poll.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut remount = interval(mount::REMOUNT_CHECK_INTERVAL);
+        remount.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let delete_delay = self.config.options.delete_delay();
+        let mut delayed_deletes = interval(delayed_delete::CHECK_INTERVAL);
+        delayed_deletes.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let state = self.state.clone();
+        let shutdown = self.shutdown.clone();
+        loop {
+            select! {
+                () = shutdown.cancelled() => break,
+                res = rx.recv() => {
+                    self.check_overflow(&rx);
+                    match res {
+                        Some(Received::Event(Ok(event))) if is_source_lost(&self.config.from, &event) => {
+                            self.source_lost().await;
+                            break;
+                        }
+                        Some(Received::Event(Ok(event))) => self.receive(event).await,
+                        Some(Received::Event(Err(_))) if !self.config.from.exists() => {
+                            self.source_lost().await;
+                            break;
+                        }
+                        Some(Received::Event(Err(e))) => {
+                            self.report_error(None, &anyhow::Error::new(e).context("监视路径时出错"))
+                        }
+                        Some(Received::Resync) => self.resync_overflowed().await,
+                        None => break,
+                    }
+                    touch_idle(idle.as_mut(), idle_timeout);
+                }
+                () = state.resumed() => {
+                    if self.replay_paused().await {
+                        touch_idle(idle.as_mut(), idle_timeout);
+                    }
+                }
+                () = state.redirected() => self.apply_redirect().await,
+                () = state.renamed() => self.apply_rename(),
+                _ = settle.tick(), if self.has_unsettled() => {
+                    if self.settle_churn().await {
+                        touch_idle(idle.as_mut(), idle_timeout);
+                    }
+                }
+                _ = remount.tick(), if self.unmounted => self.check_remounted().await,
+                _ = delayed_deletes.tick(), if self.has_delayed_deletes() => {
+                    if self.commit_deletes(delete_delay).await {
+                        touch_idle(idle.as_mut(), idle_timeout);
+                    }
+                }
+                _ = poll.tick(), if poll_interval.is_some() => {
+                    if self.poll_source().await {
+                        touch_idle(idle.as_mut(), idle_timeout);
+                    }
+                }
+                _ = retry.tick(), if !self.deferred.is_empty() => {
+                    if self.retry_deferred().await {
+                        touch_idle(idle.as_mut(), idle_timeout);
+                    }
+                }
+                _ = save_index.tick() => self.state.save().await,
+                _ = reconcile.tick(), if reconcile_interval.is_some() => self.spawn_reconcile(),
+                _ = staging.tick(), if staging_quiet.is_some() => {
+                    self.commit_staged(staging_quiet).await
+                }
+                () = &mut idle, if idle_timeout.is_some() => {
+                    self.auto_stop().await;
+                    break;
+                }
+            }
+            self.publish_queue_depths(&rx);
+        }
+        // 停止前提交所有暂存文件，避免留在暂存目录中
+        self.commit_staged(None).await;
+        self.drop_delayed_deletes();
+        self.state.save().await;
+        info!(
+            "监视 '{}' ({:?}) 的事件处理循环已停止。",
+            self.config.name(),
+            self.config.from
+        );
+    }
+
+    /// 执行初始同步，期间收到的事件先缓存，同步结束后只重放遍历未覆盖的事件。
+    ///
+    /// 事件与遍历同时处理同一文件会造成重复操作，而同步结束后才处理积压的事件又会重复遍历已完成的工作。
+    /// 文件在同步后的当前状态与同步索引一致时，说明遍历已处理过其最新内容，对应的创建和修改事件被丢弃；
+    /// 其余事件（删除、重命名、目录等）按原顺序重放。缓存超出容量时改为再执行一次全量同步。
+    async fn initial_sync(&mut self, rx: &mut EventReceiver) {
+        let mut buffered = PauseBuffer::default();
+        let mut open = true;
+        {
+            let sync = sync::run_full_sync(&self.app, &self.config, &self.filter, &self.state);
+            tokio::pin!(sync);
+            let result = loop {
+                select! {
+                    result = &mut sync => break result,
+                    res = rx.recv(), if open => match res {
+                        Some(Received::Event(Ok(event))) => buffered.push(event, PauseOverflow::Resync),
+                        Some(Received::Resync) => buffered.force_resync(),
+                        Some(Received::Event(Err(e))) => {
+                            let e = anyhow::Error::new(e).context("监视路径时出错");
+                            self.report_error(None, &e);
+                        }
+                        None => open = false,
+                    },
+                }
+            };
+            if let Err(e) = result {
+                error!("路径 {:?} 的初始同步失败: {:?}", self.config.from, e);
+            }
+        }
+        let Some(events) = buffered.take() else {
+            warn!(
+                "监视 '{}' 初始同步期间的事件超出缓冲区容量，改为再执行一次全量同步。",
+                self.config.name()
+            );
+            if let Err(e) =
+                sync::run_full_sync(&self.app, &self.config, &self.filter, &self.state).await
+            {
+                self.report_error(None, &e.context("初始同步后的全量同步失败"));
+            }
+            return;
+        };
+        let total = events.len();
+        let mut replayed = 0;
+        for event in events {
+            if self.covered_by_walk(&event).await {
+                continue;
+            }
+            replayed += 1;
+            self.receive(event).await;
+        }
+        info!(
+            "监视 '{}' 初始同步期间收到 {} 个事件，重放其中 {} 个。",
+            self.config.name(),
+            total,
+            replayed
+        );
+    }
+
+    /// 事件是否只涉及已由全量同步处理过最新内容的文件。
+    async fn covered_by_walk(&self, event: &Event) -> bool {
+        let ctx = ProcessContext {
+            fs: &TokioFs,
+            from: &self.config.from,
+            to: &self.config.to,
+            options: &self.config.options,
+            filter: &self.filter,
+            state: &self.state,
+            progress: None,
+        };
+        ctx.covered_by_sync(event).await
+    }
+
+    /// 安静期已过（`quiet` 为空时立即）将暂存文件移动到最终位置。
+    async fn commit_staged(&self, quiet: Option<Duration>) {
+        let entries = self.state.with_staging(|staging| staging.take(quiet));
+        if entries.is_empty() {
+            return;
+        }
+        let count = entries.len();
+        let ctx = ProcessContext {
+            fs: &TokioFs,
+            from: &self.config.from,
+            to: &self.config.to,
+            options: &self.config.options,
+            filter: &self.filter,
+            state: &self.state,
+            progress: None,
+        };
+        for (target, staged) in entries {
+            if let Err(e) = ctx.commit_staged(&staged, &target).await {
+                self.report_error(Some(target), &e.context("提交暂存文件时出错"));
+            }
+        }
+        info!(
+            "监视 '{}' 已提交 {} 个暂存文件。",
+            self.config.name(),
+            count
+        );
+    }
+
+    /// 轮询模式下扫描源目录，把发现的变化当作普通事件处理，返回是否发现了变化。
+    async fn poll_source(&mut self) -> bool {
+        match sync::poll_changes(&self.config, &self.filter, &self.state).await {
+            Ok(events) => {
+                if events.is_empty() {
+                    return false;
+                }
+                debug!("轮询发现 {} 处变化: {:?}", events.len(), self.config.from);
+                for event in events {
+                    self.receive(event).await;
+                }
+                true
+            }
+            Err(e) => {
+                self.report_error(None, &e.context("轮询源目录时出错"));
+                false
+            }
+        }
+    }
+
+    /// 在后台执行一次对账，不阻塞事件处理。
+    fn spawn_reconcile(&self) {
+        let app = self.app.clone();
+        let config = self.config.clone();
+        let filter = self.filter.clone();
+        let state = self.state.clone();
+        tauri::async_runtime::spawn(async move {
+            sync::reconcile(&app, &config, &filter, &state).await;
+        });
+    }
+
+    /// 空闲超时后从监视表中移除自身。
+    async fn auto_stop(&self) {
+        if self.remove_self().await {
+            info!("监视 '{}' 因空闲超时已自动停止。", self.config.name());
+            self.emit_id("watch-auto-stopped");
+        }
+    }
+
+    /// 源目录被删除后停止监视。
+    ///
+    /// 根目录的删除不会作为普通删除事件同步到目标，
+    /// 只有开启 `delete_target_on_source_lost` 时才清空目标目录。
+    async fn source_lost(&self) {
+        warn!(
+            "监视 '{}' 的源目录 {:?} 已被删除，停止监视。",
+            self.config.name(),
+            self.config.from
+        );
+        if self.config.options.delete_target_on_source_lost {
+            if let Err(e) = self.clear_target().await {
+                self.report_error(
+                    Some(self.config.to.clone()),
+                    &e.context("清空目标目录时出错"),
+                );
+            }
+            self.state.with_index(|index| index.clear());
+        }
+        self.remove_self().await;
+        self.emit_id("watch-source-lost");
+    }
+
+    /// 删除目标目录中的所有内容，保留目标目录本身。
+    async fn clear_target(&self) -> anyhow::Result<()> {
+        let reporter = ProgressReporter {
+            app: &self.app,
+            watch_id: &self.config.id,
+            cancel: Some(&self.shutdown),
+        };
+        let ctx = ProcessContext {
+            fs: &TokioFs,
+            from: &self.config.from,
+            to: &self.config.to,
+            options: &self.config.options,
+            filter: &self.filter,
+            state: &self.state,
+            progress: Some(&reporter),
+        };
+        ctx.clear_target().await
+    }
+
+    async fn remove_self(&self) -> bool {
+        remove_watch(&self.app, &self.config.id, self.serial).await
+    }
+
+    async fn process(&self, event: &Event) -> anyhow::Result<()> {
+        let reporter = ProgressReporter {
+            app: &self.app,
+            watch_id: &self.config.id,
+            cancel: Some(&self.shutdown),
+        };
+        let ctx = ProcessContext {
+            fs: &TokioFs,
+            from: &self.config.from,
+            to: &self.config.to,
+            options: &self.config.options,
+            filter: &self.filter,
+            state: &self.state,
+            progress: Some(&reporter),
+        };
+        file_processor::process(&ctx, self.state.copy_type(), event).await
+    }
+
+    /// 按暂停状态处理新收到的事件：丢弃、缓存，或在重放完缓存的事件后立即处理。
+    async fn receive(&mut self, event: Event) {
+        let duplicate = match &mut self.dedup {
+            Some(dedup) => dedup.is_duplicate(&event).await,
+            None => false,
+        };
+        if duplicate {
+            debug!("丢弃重复的事件: {:?}", event);
+            return;
+        }
+        // 改写目标的通知可能晚于新事件到达，先应用以保证之后的事件写入新目标
+        self.apply_redirect().await;
+        match self.state.pause_mode() {
+            Some(PauseMode::Drop) => debug!("监视已暂停，丢弃事件: {:?}", event),
+            Some(PauseMode::Buffer) => self.paused.push(event, self.config.options.pause_overflow),
+            None => {
+                // 恢复通知可能晚于新事件到达，先重放以保证顺序
+                let _ = self.replay_paused().await;
+                if self
+                    .churn
+                    .as_mut()
+                    .is_some_and(|churn| churn.absorb(&event))
+                {
+                    return;
+                }
+                self.handle_event(event).await;
+            }
+        }
+    }
+
+    /// 队列开始溢出时通知前端，溢出结束时记录丢弃的事件数。
+    fn check_overflow(&self, rx: &EventReceiver) {
+        if rx.take_overflow_started() {
+            warn!(
+                "监视 '{}' 排队的事件超过 {} 个，按 {:?} 处理。",
+                self.config.name(),
+                self.config.options.max_queued_events,
+                rx.overflow()
+            );
+            let payload = QueueOverflowPayload {
+                id: self.config.id.clone(),
+                name: self.config.name(),
+                policy: rx.overflow(),
+            };
+            events::emit(&self.app, &self.config.id, "queue-overflow", payload);
+        }
+        if let Some(dropped) = rx.take_overflow_ended() {
+            info!(
+                "监视 '{}' 的事件队列已恢复，期间丢弃了 {} 个事件。",
+                self.config.name(),
+                dropped
+            );
+        }
+    }
+
+    /// 事件队列溢出后以全量同步代替被丢弃的事件，暂停期间按暂停模式处理。
+    async fn resync_overflowed(&mut self) {
+        match self.state.pause_mode() {
+            Some(PauseMode::Drop) => debug!("监视已暂停，不执行事件队列溢出后的全量同步"),
+            Some(PauseMode::Buffer) => self.paused.force_resync(),
+            None => {
+                if let Err(e) =
+                    sync::run_full_sync(&self.app, &self.config, &self.filter, &self.state).await
+                {
+                    self.report_error(None, &e.context("事件队列溢出后的全量同步失败"));
+                }
+            }
+        }
+    }
+
+    /// 应用 `redirect_target` 设置的新目标目录。
+    ///
+    /// 先把暂存文件提交到原目标，再清除记录了原目标中路径的状态，需要时对新目标执行一次全量同步。
+    async fn apply_redirect(&mut self) {
+        let Some((to, resync)) = self.state.take_redirect() else {
+            return;
+        };
+        self.commit_staged(None).await;
+        let old = std::mem::replace(&mut self.config.to, to);
+        self.state.forget_target_paths();
+        if let Some(cap) = self.config.options.max_target_size {
+            let to = self.config.to.clone();
+            match tokio::task::spawn_blocking(move || TargetCache::scan(&to, cap)).await {
+                Ok(cache) => self.state.set_target_cache(cache),
+                Err(e) => error!("扫描新目标目录失败: {:?}", e),
+            }
+        }
+        info!(
+            "监视 '{}' 的目标目录已从 {:?} 改为 {:?}。",
+            self.config.name(),
+            old,
+            self.config.to
+        );
+        if resync {
+            if let Err(e) =
+                sync::run_full_sync(&self.app, &self.config, &self.filter, &self.state).await
+            {
+                self.report_error(None, &e.context("改写目标后的全量同步失败"));
+            }
+        }
+    }
+
+    /// 应用 `rename_watch` 设置的新 id，之后的事件和日志都使用新 id。
+    fn apply_rename(&mut self) {
+        if let Some(id) = self.state.take_rename() {
+            let old = std::mem::replace(&mut self.config.id, id);
+            info!(
+                "监视 '{}' 的 id 已从 '{}' 改为 '{}'。",
+                self.config.name(),
+                old,
+                self.config.id
+            );
+        }
+    }
+
+    /// 将各队列的长度写入监视状态，供 `dump_state` 读取。
+    fn publish_queue_depths(&self, rx: &EventReceiver) {
+        self.state.set_queue_depths(QueueDepths {
+            queued: rx.len(),
+            deferred: self.deferred.len(),
+            paused: self.paused.len(),
+            churn: self.churn.as_ref().map_or(0, ChurnGuard::len),
+            delayed_deletes: self.state.with_delayed_deletes(|deletes| deletes.len()),
+        });
+    }
+
+    fn has_unsettled(&self) -> bool {
+        self.churn.as_ref().is_some_and(|churn| !churn.is_empty())
+    }
+
+    /// 处理已稳定的暂缓路径，返回是否有路径被处理。
+    async fn settle_churn(&mut self) -> bool {
+        let events = match &mut self.churn {
+            Some(churn) => churn.take_settled(),
+            None => return false,
+        };
+        let settled = !events.is_empty();
+        for event in events {
+            debug!("合并后的事件: {:?}", event);
+            self.handle_event(event).await;
+        }
+        settled
+    }
+
+    /// 按顺序处理暂停期间缓存的事件，缓冲区曾溢出时改为执行一次全量同步。
+    ///
+    /// 返回是否处理了缓存的事件。
+    async fn replay_paused(&mut self) -> bool {
+        if self.paused.is_empty() {
+            return false;
+        }
+        match self.paused.take() {
+            Some(events) => {
+                info!(
+                    "监视 '{}' 已恢复，重放 {} 个暂停期间的事件。",
+                    self.config.name(),
+                    events.len()
+                );
+                for event in events {
+                    self.handle_event(event).await;
+                }
+            }
+            None => {
+                warn!(
+                    "监视 '{}' 暂停期间的事件超出缓冲区容量，改为执行全量同步。",
+                    self.config.name()
+                );
+                if let Err(e) =
+                    sync::run_full_sync(&self.app, &self.config, &self.filter, &self.state).await
+                {
+                    self.report_error(None, &e.context("暂停恢复后的全量同步失败"));
+                }
+            }
+        }
+        true
+    }
+
+    async fn handle_event(&mut self, event: Event) {
+        let Some(path) = event.paths.first().cloned() else {
+            warn!("事件没有路径信息: {:?}", event);
+            return;
+        };
+        if is_removal(&event) && self.check_unmounted() {
+            debug!("源目录所在的卷已卸载，忽略删除事件: {:?}", path);
+            return;
+        }
+        if self.config.options.delete_delay_secs.is_some() {
+            if is_removal(&event) {
+                self.state
+                    .with_delayed_deletes(|deletes| deletes.push(event));
+                self.emit_path("delete-pending", path);
+                return;
+            }
+            self.cancel_delayed_delete(&path);
+        }
+        self.dispatch(event, path).await;
+    }
+
+    /// 处理事件，文件被占用时放入延迟队列稍后重试。
+    async fn dispatch(&mut self, event: Event, path: PathBuf) {
+        match self.process(&event).await {
+            Ok(()) => {
+                self.deferred.remove(&path);
+                self.record_success(&event, &path).await;
+            }
+            Err(e) if deferred::is_locked_error(&e) => {
+                warn!("文件 {:?} 被占用，稍后重试: {:?}", path, e);
+                self.deferred.push(path, event);
+            }
+            Err(e) => self.report_error(Some(path), &e),
+        }
+    }
+
+    /// 执行延迟期已过的删除。暂停期间不执行，恢复后再处理。
+    ///
+    /// 源路径在执行前已重新出现时不再删除目标。返回是否执行了删除。
+    async fn commit_deletes(&mut self, delay: Option<Duration>) -> bool {
+        let Some(delay) = delay else {
+            return false;
+        };
+        if self.state.pause_mode().is_some() {
+            return false;
+        }
+        let mut committed = false;
+        let events = self
+            .state
+            .with_delayed_deletes(|deletes| deletes.take_due(delay));
+        for event in events {
+            let Some(path) = event.paths.first().cloned() else {
+                continue;
+            };
+            if path.exists() || path.is_symlink() {
+                debug!("源路径已重新出现，不再删除: {:?}", path);
+                continue;
+            }
+            if self.check_unmounted() {
+                debug!("源目录所在的卷已卸载，忽略删除事件: {:?}", path);
+                continue;
+            }
+            self.dispatch(event, path.clone()).await;
+            self.emit_path("delete-committed", path);
+            committed = true;
+        }
+        committed
+    }
+
+    fn has_delayed_deletes(&self) -> bool {
+        !self
+            .state
+            .with_delayed_deletes(|deletes| deletes.is_empty())
+    }
+
+    /// 路径重新出现时取消它及其上级目录等待执行的删除。
+    fn cancel_delayed_delete(&self, path: &Path) {
+        let cancelled = self
+            .state
+            .with_delayed_deletes(|deletes| deletes.cancel(path));
+        if cancelled.is_empty() {
+            return;
+        }
+        info!("路径已重新出现，取消等待执行的删除: {:?}", cancelled);
+        let payload = PathsPayload {
+            id: self.config.id.clone(),
+            name: self.config.name(),
+            paths: cancelled,
+        };
+        events::emit(&self.app, &self.config.id, "delete-undone", payload);
+    }
+
+    /// 停止时丢弃尚未执行的删除，目标中的文件保持不变。
+    fn drop_delayed_deletes(&self) {
+        let dropped = self
+            .state
+            .with_delayed_deletes(|deletes| deletes.take_all());
+        if !dropped.is_empty() {
+            warn!(
+                "监视 '{}' 停止时有 {} 个删除尚未执行，已丢弃。",
+                self.config.name(),
+                dropped.len()
+            );
+        }
+    }
+
+    /// 源目录所在的卷是否已被卸载，刚发现卸载时通知前端。
+    fn check_unmounted(&mut self) -> bool {
+        let unmounted = mount::is_unmounted(&TokioFs, &self.config.from, &self.state);
+        if unmounted && !self.unmounted {
+            warn!(
+                "监视 '{}' 的源目录 {:?} 所在的卷已被卸载，暂停同步删除直到重新挂载。",
+                self.config.name(),
+                self.config.from
+            );
+            self.emit_id("source-unmounted");
+        }
+        self.unmounted = unmounted;
+        unmounted
+    }
+
+    /// 卷重新挂载后恢复正常处理，并用一次全量同步补上卸载期间被忽略的变化。
+    async fn check_remounted(&mut self) {
+        if mount::is_unmounted(&TokioFs, &self.config.from, &self.state) {
+            return;
+        }
+        self.unmounted = false;
+        info!(
+            "监视 '{}' 的源目录 {:?} 已重新挂载。",
+            self.config.name(),
+            self.config.from
+        );
+        self.emit_id("source-remounted");
+        if let Err(e) =
+            sync::run_full_sync(&self.app, &self.config, &self.filter, &self.state).await
+        {
+            self.report_error(None, &e.context("重新挂载后的全量同步失败"));
+        }
+    }
+
+    /// 重试被占用的文件，返回是否有文件完成同步。
+    async fn retry_deferred(&mut self) -> bool {
+        let mut synced = false;
+        for (path, entry) in self.deferred.drain() {
+            match self.process(&entry.event).await {
+                Ok(()) => {
+                    info!("被占用的文件已完成同步: {:?}", path);
+                    self.record_success(&entry.event, &path).await;
+                    self.emit_path("deferred-synced", path);
+                    synced = true;
+                }
+                Err(e)
+                    if deferred::is_locked_error(&e)
+                        && entry.since.elapsed() < deferred::MAX_AGE =>
+                {
+                    self.deferred.requeue(path, entry);
+                }
+                Err(e) => {
+                    self.emit_path("deferred-given-up", path.clone());
+                    self.report_error(Some(path), &e.context("放弃同步被占用的文件"));
+                }
+            }
+        }
+        synced
+    }
+
+    /// 事件处理成功后按源路径的当前状态更新同步索引和统计。
+    async fn record_success(&self, event: &Event, path: &Path) {
+        let Ok(relative) = path.strip_prefix(&self.config.from) else {
+            return;
+        };
+        let metadata = tokio::fs::metadata(path)
+            .await
+            .ok()
+            .filter(|metadata| metadata.is_file());
+        let stats = self.state.stats();
+        if matches!(event.kind, EventKind::Remove(_)) {
+            stats.record_deleted();
+        } else if let Some(metadata) = &metadata {
+            stats.record_synced(metadata.len());
+        }
+        let entry =
+            metadata.and_then(|metadata| IndexEntry::new(metadata.len(), metadata.modified().ok()));
+        self.state.with_index(|index| match entry {
+            Some(entry) => index.record(relative.to_path_buf(), entry),
+            None => index.forget(relative),
+        });
+        self.state.record_manifest(relative, path, entry).await;
+    }
+
+    /// 记录错误并通知前端，错误被静音期间只以 debug 级别记录。
+    fn report_error(&self, path: Option<PathBuf>, err: &anyhow::Error) {
+        self.state.stats().record_error();
+        self.state
+            .with_error_history(|history| history.record(path.clone(), err));
+        if self.state.errors_muted() {
+            debug!("(已静音) 路径 {:?}: {:?}", path, err);
+            return;
+        }
+        error!("监视 '{}' 路径 {:?}: {:?}", self.config.name(), path, err);
+        let payload = ErrorPayload {
+            id: self.config.id.clone(),
+            name: self.config.name(),
+            path,
+            message: format!("{:#}", err),
+        };
+        events::emit(&self.app, &self.config.id, "watch-error", payload);
+        if let Some(unsupported) = err.downcast_ref::<LinkUnsupported>() {
+            self.emit_path("link-unsupported", unsupported.target.clone());
+        }
+    }
+
+    fn emit_id(&self, event: &str) {
+        let payload = IdPayload {
+            id: self.config.id.clone(),
+            name: self.config.name(),
+        };
+        events::emit(&self.app, &self.config.id, event, payload);
+    }
+
+    fn emit_path(&self, event: &str, path: PathBuf) {
+        let payload = PathPayload {
+            id: self.config.id.clone(),
+            name: self.config.name(),
+            path,
+        };
+        events::emit(&self.app, &self.config.id, event, payload);
+    }
+}
+
+/// 处理过路径后重新开始计算空闲时间，未配置空闲超时时不做任何事。
+///
+/// 只在确实处理了路径时调用，没有发现变化的轮询不算活动，否则轮询模式的监视永远不会空闲。
+fn touch_idle(idle: Pin<&mut Sleep>, timeout: Option<Duration>) {
+    if let Some(timeout) = timeout {
+        idle.reset(Instant::now() + timeout);
+    }
+}
+
+/// 源目录 `from` 本身是否已被删除。
+fn is_source_lost(from: &Path, event: &Event) -> bool {
+    matches!(event.kind, EventKind::Remove(_))
+        && (event.paths.iter().any(|path| path == from) || !from.exists())
+}
+
+/// 事件是否表示源路径消失：删除或重命名的旧路径。
+fn is_removal(event: &Event) -> bool {
+    matches!(
+        event.kind,
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From))
+    )
+}
+
+/// 从监视表中移除 id 为 `id` 的监视，返回是否确实移除。
+///
+/// 仅当表中仍是序号为 `serial` 的监视时才移除，避免误停同 id 新建的监视。
+pub async fn remove_watch(app: &tauri::AppHandle, id: &str, serial: u64) -> bool {
+    let removed = {
+        let state = app.state::<AppState>();
+        let mut watchers = state.watcher.lock().await;
+        if watchers.get(id).is_some_and(|watch| watch.serial == serial) {
+            watchers.remove(id)
+        } else {
+            None
+        }
+    };
+    // 由事件循环自身调用，不能等待自己退出，只发出停止信号
+    let removed = match removed {
+        Some(watch) => {
+            watch.shutdown.cancel();
+            true
+        }
+        None => false,
+    };
+    if removed {
+        tray::refresh(app).await;
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{CreateKind, RemoveKind};
+
+    use super::*;
+
+    fn remove(path: &Path) -> Event {
+        Event::new(EventKind::Remove(RemoveKind::Folder)).add_path(path.to_path_buf())
+    }
+
+    #[test]
+    fn removing_the_root_loses_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("src");
+        assert!(is_source_lost(&from, &remove(&from)));
+    }
+
+    #[test]
+    fn removing_a_child_keeps_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().to_path_buf();
+        assert!(!is_source_lost(&from, &remove(&from.join("child"))));
+        let create = Event::new(EventKind::Create(CreateKind::Folder)).add_path(from.clone());
+        assert!(!is_source_lost(&from, &create));
+    }
+
+    #[test]
+    fn child_removal_after_root_is_gone_loses_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("src");
+        assert!(is_source_lost(&from, &remove(&from.join("child"))));
+    }
+}
@@ -0,0 +1,105 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use log::error;
+use serde::Serialize;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::{queue::QueueOverflow, AppState};
+
+/// 窗口标签 -> 该窗口订阅的监视 id。没有订阅记录的窗口接收所有监视的事件。
+pub type Subscriptions = Mutex<HashMap<String, HashSet<String>>>;
+
+/// 向关心监视 `id` 的窗口发送事件，失败时只记录日志而不影响后端流程。
+pub fn emit<S: Serialize + Clone>(app: &AppHandle, id: &str, event: &str, payload: S) {
+    let state = app.state::<AppState>();
+    let subscriptions = state
+        .subscriptions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    for label in app.webview_windows().into_keys() {
+        let subscribed = subscriptions.get(&label).is_none_or(|ids| ids.contains(id));
+        if subscribed {
+            if let Err(e) = app.emit_to(label.as_str(), event, payload.clone()) {
+                error!("向窗口 '{}' 发送事件 '{}' 失败: {:?}", label, event, e);
+            }
+        }
+    }
+}
+
+/// 只携带监视 id 和名称的事件负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct IdPayload {
+    pub id: String,
+    pub name: String,
+}
+
+/// 只携带监视 id、名称和单个路径的事件负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PathPayload {
+    pub id: String,
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// 携带监视 id、名称和多个路径的事件负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PathsPayload {
+    pub id: String,
+    pub name: String,
+    pub paths: Vec<PathBuf>,
+}
+
+/// `watch-limit-reached` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchLimitPayload {
+    pub id: String,
+    pub name: String,
+    /// 是否已改用轮询模式，为 `false` 时监视启动失败。
+    pub polling: bool,
+}
+
+/// `queue-overflow` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct QueueOverflowPayload {
+    pub id: String,
+    pub name: String,
+    pub policy: QueueOverflow,
+}
+
+/// `watch-restarted` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RestartPayload {
+    pub id: String,
+    pub name: String,
+    /// 第几次重建，从 1 开始。
+    pub attempt: u32,
+}
+
+/// `watch-renamed` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RenamePayload {
+    pub old_id: String,
+    pub id: String,
+    pub name: String,
+}
+
+/// `watch-error` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorPayload {
+    pub id: String,
+    pub name: String,
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
@@ -0,0 +1,253 @@
+//! 目标目录的总大小上限。
+//!
+//! 开启后目标目录被当作源目录最近变化文件的缓存：复制会使总大小超出上限时，
+//! 先删除最久未同步的目标文件腾出空间，再写入新文件。
+//! 每个已同步文件的大小和同步时间只保存在内存中，启动监视时扫描目标目录重建，
+//! 此时以文件的修改时间作为同步时间（复制写入的文件修改时间即为复制时间）。
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::anyhow;
+use log::warn;
+use serde::Serialize;
+
+use crate::filter::INTERNAL_PREFIX;
+
+/// `target-evicted` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetEvicted {
+    pub watch_id: String,
+    pub path: PathBuf,
+    pub len: u64,
+}
+
+pub struct TargetCache {
+    cap: u64,
+    total: u64,
+    /// 目标文件 -> (大小, 最近一次同步的时间)。
+    files: HashMap<PathBuf, (u64, SystemTime)>,
+}
+
+impl TargetCache {
+    /// 扫描目标目录中已有的文件，应用自己的暂存目录和清单文件不计入。
+    pub fn scan(to: &Path, cap: u64) -> Self {
+        let mut cache = Self {
+            cap,
+            total: 0,
+            files: HashMap::new(),
+        };
+        let mut dirs = vec![to.to_path_buf()];
+        while let Some(dir) = dirs.pop() {
+            let entries = match std::fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    warn!("扫描目标目录 {:?} 失败: {:?}", dir, e);
+                    continue;
+                }
+            };
+            for entry in entries.flatten() {
+                if entry
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.starts_with(INTERNAL_PREFIX))
+                {
+                    continue;
+                }
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    dirs.push(entry.path());
+                } else if metadata.is_file() {
+                    let synced = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    cache.insert(entry.path(), metadata.len(), synced);
+                }
+            }
+        }
+        cache
+    }
+
+    /// 为将要写入 `target` 的 `len` 字节腾出空间，返回需要删除的目标，最久未同步的在前。
+    ///
+    /// 新文件立即按 `len` 计入总大小，避免同时进行的复制都认为空间足够；
+    /// 调用方应在删除返回的目标后对其调用 [`forget`](Self::forget)，复制失败时也应 `forget` 新文件。
+    /// 单个文件已超过上限时返回错误。
+    pub fn make_room(&mut self, target: &Path, len: u64) -> anyhow::Result<Vec<(PathBuf, u64)>> {
+        if len > self.cap {
+            return Err(anyhow!(
+                "文件大小 {} 字节超过目标目录的大小上限 {} 字节: {:?}",
+                len,
+                self.cap,
+                target
+            ));
+        }
+        self.insert(target.to_path_buf(), len, SystemTime::now());
+        if self.total <= self.cap {
+            return Ok(Vec::new());
+        }
+        let mut candidates: Vec<(SystemTime, &PathBuf, u64)> = self
+            .files
+            .iter()
+            .filter(|(path, _)| path.as_path() != target)
+            .map(|(path, (len, synced))| (*synced, path, *len))
+            .collect();
+        candidates.sort();
+        let mut excess = self.total - self.cap;
+        let mut evicted = Vec::new();
+        for (_, path, len) in candidates {
+            if excess == 0 {
+                break;
+            }
+            excess = excess.saturating_sub(len);
+            evicted.push((path.clone(), len));
+        }
+        Ok(evicted)
+    }
+
+    /// 移除路径本身以及其下所有文件的记录。
+    pub fn forget(&mut self, target: &Path) {
+        let mut freed = 0;
+        self.files.retain(|path, (len, _)| {
+            let keep = !path.starts_with(target);
+            if !keep {
+                freed += *len;
+            }
+            keep
+        });
+        self.total -= freed;
+    }
+
+    fn insert(&mut self, target: PathBuf, len: u64, synced: SystemTime) {
+        if let Some((old, _)) = self.files.insert(target, (len, synced)) {
+            self.total -= old;
+        }
+        self.total += len;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    /// 上限为 `cap`、已同步 `files`（路径、大小、同步时间）的缓存。
+    fn cache(cap: u64, files: &[(&str, u64, u64)]) -> TargetCache {
+        let mut cache = TargetCache {
+            cap,
+            total: 0,
+            files: HashMap::new(),
+        };
+        for (path, len, synced) in files {
+            cache.insert(PathBuf::from(path), *len, at(*synced));
+        }
+        cache
+    }
+
+    #[test]
+    fn nothing_is_evicted_while_under_cap() {
+        let mut cache = cache(100, &[("/dst/a", 40, 1)]);
+        assert!(cache.make_room(Path::new("/dst/b"), 60).unwrap().is_empty());
+        assert_eq!(cache.total, 100);
+    }
+
+    #[test]
+    fn least_recently_synced_files_are_evicted_first() {
+        let mut cache = cache(
+            100,
+            &[
+                ("/dst/new", 30, 3),
+                ("/dst/old", 30, 1),
+                ("/dst/mid", 30, 2),
+            ],
+        );
+        let evicted = cache.make_room(Path::new("/dst/x"), 40).unwrap();
+        assert_eq!(evicted, vec![(PathBuf::from("/dst/old"), 30)]);
+        cache.forget(Path::new("/dst/old"));
+
+        // 刚写入的 x 最近同步，排在已有文件之后
+        let evicted = cache.make_room(Path::new("/dst/y"), 50).unwrap();
+        assert_eq!(
+            evicted,
+            vec![
+                (PathBuf::from("/dst/mid"), 30),
+                (PathBuf::from("/dst/new"), 30)
+            ],
+        );
+    }
+
+    #[test]
+    fn new_file_always_fits_after_eviction() {
+        let mut cache = cache(100, &[("/dst/a", 10, 1), ("/dst/b", 80, 2)]);
+        let evicted = cache.make_room(Path::new("/dst/c"), 95).unwrap();
+        for (path, _) in &evicted {
+            cache.forget(path);
+        }
+        assert!(cache.total <= cache.cap);
+        assert_eq!(cache.total, 95);
+    }
+
+    #[test]
+    fn target_being_written_is_never_evicted() {
+        let mut cache = cache(100, &[("/dst/a", 60, 1), ("/dst/b", 40, 2)]);
+        // 重新同步已有文件时按新大小计算，旧大小不重复计入
+        let evicted = cache.make_room(Path::new("/dst/a"), 70).unwrap();
+        assert_eq!(evicted, vec![(PathBuf::from("/dst/b"), 40)]);
+        cache.forget(Path::new("/dst/b"));
+        assert_eq!(cache.total, 70);
+    }
+
+    #[test]
+    fn reserved_space_is_seen_by_concurrent_copies() {
+        let mut cache = cache(100, &[("/dst/old", 50, 1)]);
+        assert!(cache.make_room(Path::new("/dst/a"), 50).unwrap().is_empty());
+        // 第一个复制尚未完成，其预留的空间已计入总大小
+        let evicted = cache.make_room(Path::new("/dst/b"), 50).unwrap();
+        assert_eq!(evicted, vec![(PathBuf::from("/dst/old"), 50)]);
+    }
+
+    #[test]
+    fn file_larger_than_cap_is_rejected_without_reserving() {
+        let mut cache = cache(100, &[("/dst/a", 10, 1)]);
+        assert!(cache.make_room(Path::new("/dst/big"), 101).is_err());
+        assert_eq!(cache.total, 10);
+    }
+
+    #[test]
+    fn forgetting_a_directory_frees_its_files() {
+        let mut cache = cache(
+            100,
+            &[
+                ("/dst/dir/a", 10, 1),
+                ("/dst/dir/b", 20, 1),
+                ("/dst/c", 30, 1),
+            ],
+        );
+        cache.forget(Path::new("/dst/dir"));
+        assert_eq!(cache.total, 30);
+        assert_eq!(cache.files.len(), 1);
+    }
+
+    #[test]
+    fn scan_counts_files_except_internal_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), "aaaa").unwrap();
+        std::fs::write(dir.path().join("sub/b.txt"), "bb").unwrap();
+        let staging = dir.path().join(format!("{}staging", INTERNAL_PREFIX));
+        std::fs::create_dir_all(&staging).unwrap();
+        std::fs::write(staging.join("c.txt"), "cccccc").unwrap();
+        let cache = TargetCache::scan(dir.path(), 100);
+        assert_eq!(cache.total, 6);
+        assert_eq!(cache.files.len(), 2);
+    }
+}
@@ -1,86 +1,1125 @@
 use std::path::{self};
 
-use log::{error, info, warn};
+use anyhow::{anyhow, Context};
+use log::{debug, info, warn};
 
-use crate::{file_processor_copy::CopyProcessor, file_processor_link::LinkProcessor, CopyType};
+use crate::{
+    allowlist,
+    config::{DeleteMode, DirConflict, WatchOptions},
+    file_processor_copy::{self, CopyProcessor},
+    file_processor_link::LinkProcessor,
+    file_processor_monitor::MonitorProcessor,
+    file_processor_move::MoveProcessor,
+    filter::PathFilter,
+    flatten,
+    fs_ops::FsOps,
+    hook::{self, HookDecision},
+    index::IndexEntry,
+    log_dedup::info_collapsed,
+    progress::{DeleteTracker, ProgressReporter},
+    watch_state::WatchState,
+    CopyType,
+};
+
+/// 处理器处理单个事件所需的上下文。
+pub struct ProcessContext<'a, F: FsOps> {
+    pub fs: &'a F,
+    pub from: &'a path::Path,
+    pub to: &'a path::Path,
+    pub options: &'a WatchOptions,
+    pub filter: &'a PathFilter,
+    pub state: &'a WatchState,
+    /// 大文件复制进度的报告方式，为 `None` 时不报告。
+    pub progress: Option<&'a ProgressReporter<'a>>,
+}
+
+impl<F: FsOps> ProcessContext<'_, F> {
+    /// 计算源路径在目标目录中对应的路径，用于写入。
+    ///
+    /// 配置了重命名规则时对文件名应用规则。
+    /// 开启扁平化时所有文件都直接放在目标目录下，同名冲突时分配新名称。
+    /// 钩子为该文件选择过目标时使用钩子的选择。
+    pub fn target_path(&self, path: &path::Path) -> anyhow::Result<path::PathBuf> {
+        self.map_target(path, true)
+    }
+
+    /// 与 [`target_path`](Self::target_path) 相同，但扁平化时不占用新名称，只用于展示。
+    pub fn preview_target(&self, path: &path::Path) -> anyhow::Result<path::PathBuf> {
+        self.map_target(path, false)
+    }
+
+    /// 去掉 `strip_components` 层后的相对路径，剩余层数为零时返回 `None`。
+    fn stripped<'p>(&self, relative_path: &'p path::Path) -> Option<&'p path::Path> {
+        let strip = self.options.strip_components;
+        if strip == 0 {
+            return Some(relative_path);
+        }
+        let mut components = relative_path.components();
+        for _ in 0..strip {
+            components.next()?;
+        }
+        let rest = components.as_path();
+        (!rest.as_os_str().is_empty()).then_some(rest)
+    }
+
+    fn strip<'p>(&self, relative_path: &'p path::Path) -> anyhow::Result<&'p path::Path> {
+        self.stripped(relative_path).ok_or_else(|| {
+            anyhow!(
+                "路径 {:?} 的层数不多于 strip_components ({})",
+                relative_path,
+                self.options.strip_components
+            )
+        })
+    }
+
+    /// 源目录在目标中对应的目录，不应用重命名规则。
+    ///
+    /// 开启扁平化或层数不多于 `strip_components` 时没有对应的目录，返回 `None`。
+    pub fn target_dir(&self, dir: &path::Path) -> Option<path::PathBuf> {
+        if self.options.flatten {
+            return None;
+        }
+        let relative_path = dir.strip_prefix(self.from).ok()?;
+        self.stripped(relative_path)
+            .map(|relative_path| self.to.join(relative_path))
+    }
+
+    /// 路径的层数不多于 `strip_components`，在目标目录中没有对应位置。
+    pub fn is_stripped_away(&self, path: &path::Path) -> bool {
+        path.strip_prefix(self.from)
+            .is_ok_and(|relative_path| self.stripped(relative_path).is_none())
+    }
+
+    fn map_target(&self, path: &path::Path, claim: bool) -> anyhow::Result<path::PathBuf> {
+        if self.options.hook.is_some() {
+            let relative_path = path.strip_prefix(self.from)?;
+            let hooked = self
+                .state
+                .with_hook_targets(|targets| targets.get(relative_path).cloned());
+            if let Some(target) = hooked {
+                return Ok(target);
+            }
+        }
+        self.map_default(path, claim)
+    }
+
+    /// 不考虑钩子时的目标路径。
+    fn map_default(&self, path: &path::Path, claim: bool) -> anyhow::Result<path::PathBuf> {
+        let relative_path = path.strip_prefix(self.from)?;
+        let file_name = relative_path
+            .file_name()
+            .map(|file_name| match self.state.renamer() {
+                Some(renamer) => renamer.apply(file_name),
+                None => file_name.to_os_string(),
+            });
+        if self.options.flatten {
+            let file_name =
+                file_name.ok_or_else(|| anyhow!("路径没有文件名: {:?}", relative_path))?;
+            let name = self.state.with_flat_names(|names| {
+                if claim {
+                    names.assign(relative_path, &file_name)
+                } else {
+                    names.peek(relative_path, &file_name)
+                }
+            });
+            return Ok(self.to.join(name));
+        }
+        let target = self.to.join(self.strip(relative_path)?);
+        Ok(match file_name {
+            Some(file_name) => target.with_file_name(file_name),
+            None => target,
+        })
+    }
+
+    /// 计算删除源路径时需要删除的目标路径。
+    ///
+    /// 配置了重命名规则时优先删除重命名后的目标，不存在时（例如被删除的是目录）使用原名称。
+    /// 开启扁平化时返回本次运行中分配给该路径及其下文件的所有目标，可能为空。
+    /// 配置了钩子时还包括钩子为该路径及其下文件选择的目标。
+    pub fn remove_targets(&self, path: &path::Path) -> anyhow::Result<Vec<path::PathBuf>> {
+        let mut targets = Vec::new();
+        if self.options.hook.is_some() {
+            let relative_path = path.strip_prefix(self.from)?;
+            targets = self
+                .state
+                .with_hook_targets(|targets| targets.release(relative_path));
+        }
+        targets.extend(self.find_remove_targets(path)?);
+        for target in &targets {
+            self.state.with_target_cache(|cache| cache.forget(target));
+        }
+        Ok(targets)
+    }
+
+    fn find_remove_targets(&self, path: &path::Path) -> anyhow::Result<Vec<path::PathBuf>> {
+        let relative_path = path.strip_prefix(self.from)?;
+        if self.options.flatten {
+            let names = self
+                .state
+                .with_flat_names(|names| names.release(relative_path));
+            if names.is_empty() {
+                warn!("扁平化模式下无法确定删除目标，已忽略: {:?}", path);
+            }
+            return Ok(names.into_iter().map(|name| self.to.join(name)).collect());
+        }
+        let target = self.to.join(self.strip(relative_path)?);
+        if let (Some(renamer), Some(file_name)) = (self.state.renamer(), relative_path.file_name())
+        {
+            let renamed = target.with_file_name(renamer.apply(file_name));
+            if renamed != target && (self.fs.exists(&renamed) || self.fs.is_symlink(&renamed)) {
+                return Ok(vec![renamed]);
+            }
+        }
+        Ok(vec![target])
+    }
+
+    /// 配置了钩子时由钩子决定 `path` 的目标，返回 `false` 表示跳过该文件。
+    ///
+    /// 只对普通文件调用钩子，钩子的选择记录下来供之后写入和删除时使用。
+    async fn apply_hook(&self, kind: &str, path: &path::Path) -> anyhow::Result<bool> {
+        let Some(hook) = &self.options.hook else {
+            return Ok(true);
+        };
+        if !self.fs.is_file(path) {
+            return Ok(true);
+        }
+        let relative_path = path.strip_prefix(self.from)?;
+        let default_target = self.map_default(path, false)?;
+        let decision = hook::run(hook, kind, path, &default_target, self.to)
+            .await
+            .context("运行钩子时出错")?;
+        if let HookDecision::Target(target) = &decision {
+            allowlist::check(target).await?;
+        }
+        self.state.with_hook_targets(|targets| match decision {
+            HookDecision::Default => {
+                targets.forget(relative_path);
+                Ok(true)
+            }
+            HookDecision::Skip => {
+                targets.forget(relative_path);
+                info!("钩子跳过了文件: {:?}", path);
+                Ok(false)
+            }
+            HookDecision::Target(target) => {
+                debug!("钩子选择的目标: {:?} -> {:?}", path, target);
+                targets.set(relative_path, target);
+                Ok(true)
+            }
+        })
+    }
+
+    /// 配置了大小上限时，删除最久未同步的目标文件，为写入 `target` 的 `len` 字节腾出空间。
+    pub async fn make_room(&self, target: &path::Path, len: u64) -> anyhow::Result<()> {
+        let Some(evicted) = self
+            .state
+            .with_target_cache(|cache| cache.make_room(target, len))
+            .transpose()?
+        else {
+            return Ok(());
+        };
+        for (path, evicted_len) in evicted {
+            if let Err(e) = self.fs.remove_file(&path).await {
+                if self.fs.exists(&path) {
+                    self.state.with_target_cache(|cache| cache.forget(target));
+                    return Err(
+                        anyhow::Error::new(e).context(format!("腾出空间时无法删除 {:?}", path))
+                    );
+                }
+            }
+            self.state.with_target_cache(|cache| cache.forget(&path));
+            info!("目标目录超出大小上限，已删除最久未同步的文件: {:?}", path);
+            if let Some(progress) = self.progress {
+                progress.evicted(path, evicted_len);
+            }
+        }
+        Ok(())
+    }
+
+    /// 确保目标路径的父目录存在。
+    ///
+    /// 父目录或其上级位置已被文件占用时按 `dir_conflict` 处理。
+    pub async fn ensure_parent_dir(&self, target_path: &path::Path) -> anyhow::Result<()> {
+        let parent = target_path
+            .parent()
+            .ok_or_else(|| anyhow!("无法获取目标路径的父目录: {:?}", target_path))?;
+        if self.fs.is_dir(parent) {
+            return Ok(());
+        }
+        let mut ancestors: Vec<&path::Path> = parent
+            .ancestors()
+            .take_while(|ancestor| ancestor.starts_with(self.to))
+            .collect();
+        ancestors.reverse();
+        let blocking = ancestors.into_iter().find(|ancestor| {
+            (self.fs.exists(ancestor) || self.fs.is_symlink(ancestor)) && !self.fs.is_dir(ancestor)
+        });
+        if let Some(blocking) = blocking {
+            self.clear_type_conflict(blocking, true).await?;
+        }
+        self.fs.create_dir_all(parent).await?;
+        Ok(())
+    }
+
+    /// 目标位置被类型不同的条目占用时按 `dir_conflict` 处理。
+    ///
+    /// `want_dir` 为真时清除占用的文件或软链接，否则清除占用的目录（不含目录链接）。
+    pub async fn clear_type_conflict(
+        &self,
+        target: &path::Path,
+        want_dir: bool,
+    ) -> anyhow::Result<()> {
+        let is_link = self.fs.is_symlink(target);
+        let is_real_dir = self.fs.is_dir(target) && !is_link;
+        let conflict = if want_dir {
+            (self.fs.exists(target) || is_link) && !is_real_dir
+        } else {
+            is_real_dir
+        };
+        if !conflict {
+            return Ok(());
+        }
+        match self.options.dir_conflict {
+            DirConflict::Error if want_dir => {
+                Err(anyhow!("需要创建目录的位置已存在文件: {:?}", target))
+            }
+            DirConflict::Error => Err(anyhow!("需要写入文件的位置已存在目录: {:?}", target)),
+            DirConflict::Backup => {
+                let backup = backup_path(self.fs, target)?;
+                self.fs.rename(target, &backup).await?;
+                warn!("已将类型不同的目标备份为: {:?}", backup);
+                Ok(())
+            }
+            DirConflict::Remove => {
+                self.delete(target).await?;
+                warn!("已删除类型不同的目标: {:?}", target);
+                Ok(())
+            }
+        }
+    }
+
+    /// 将暂存文件 `staged` 移动到最终位置 `target`。
+    pub async fn commit_staged(
+        &self,
+        staged: &path::Path,
+        target: &path::Path,
+    ) -> anyhow::Result<()> {
+        if let Some(parent) = target.parent() {
+            if !self.fs.exists(parent) {
+                self.fs.create_dir_all(parent).await?;
+            }
+        }
+        // Windows 上无法替换只读文件
+        if self.options.target_read_only && self.fs.is_file(target) {
+            self.fs.set_readonly(target, false).await?;
+        }
+        self.fs.rename(staged, target).await?;
+        Ok(())
+    }
+
+    /// 事件是否只涉及已由全量同步处理过最新内容的文件。
+    ///
+    /// 只有创建和内容、元数据修改事件可能被覆盖：事件中每个路径都必须是文件，
+    /// 且当前大小和修改时间与同步索引一致。
+    pub async fn covered_by_sync(&self, event: &notify::Event) -> bool {
+        if !matches!(
+            event.kind,
+            notify::EventKind::Create(_)
+                | notify::EventKind::Modify(
+                    notify::event::ModifyKind::Data(_)
+                        | notify::event::ModifyKind::Metadata(_)
+                        | notify::event::ModifyKind::Any
+                )
+        ) || event.paths.is_empty()
+        {
+            return false;
+        }
+        for path in &event.paths {
+            let Ok(relative) = path.strip_prefix(self.from) else {
+                return false;
+            };
+            let entry = if self.fs.is_file(path) {
+                match (self.fs.len(path).await, self.fs.modified(path).await) {
+                    (Ok(len), Ok(modified)) => IndexEntry::new(len, Some(modified)),
+                    _ => None,
+                }
+            } else {
+                None
+            };
+            let unchanged = entry.is_some_and(|entry| {
+                self.state
+                    .with_index(|index| index.is_unchanged(relative, entry))
+            });
+            if !unchanged {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// 删除目标目录中的所有内容，保留目标目录本身。
+    pub async fn clear_target(&self) -> anyhow::Result<()> {
+        for path in self.fs.read_dir(self.to).await? {
+            if self.options.target_read_only && self.fs.is_file(&path) {
+                self.fs.set_readonly(&path, false).await?;
+            }
+            self.delete(&path).await?;
+        }
+        info!("已清空目标目录: {:?}", self.to);
+        Ok(())
+    }
+
+    /// 按 `delete_mode` 删除目标中的文件、目录或链接，目录按 [`remove_tree`](Self::remove_tree) 分批删除。
+    pub async fn delete(&self, path: &path::Path) -> anyhow::Result<()> {
+        let fs = self.fs;
+        allowlist::check(path).await?;
+        if self.options.delete_mode == DeleteMode::OsTrash
+            && (fs.exists(path) || fs.is_symlink(path))
+        {
+            match fs.trash(path).await {
+                Ok(()) => {
+                    info_collapsed!("已移到回收站", "已移到回收站: {:?}", path);
+                    return Ok(());
+                }
+                Err(e) => warn!("无法移到回收站，改为直接删除: {:?}, {:?}", path, e),
+            }
+        }
+        if fs.is_symlink(path) {
+            remove_entry(fs, path).await?;
+            info_collapsed!("已成功删除软链接", "已成功删除软链接: {:?}", path);
+        } else if fs.is_file(path) {
+            fs.remove_file(path).await?;
+            info_collapsed!("已成功删除文件", "已成功删除文件: {:?}", path);
+        } else if fs.is_dir(path) {
+            self.remove_tree(path).await?;
+            info_collapsed!("已成功删除目录", "已成功删除目录: {:?}", path);
+        } else {
+            return Err(anyhow::anyhow!(
+                "无法删除: {:?}, 不是文件、目录或软链接",
+                path
+            ));
+        }
+        Ok(())
+    }
+
+    /// 逐个删除目录树中的条目，避免一次删除大量文件长时间占用运行时。
+    ///
+    /// 每删除 [`DELETE_BATCH`] 个条目让出一次执行权并发送 `delete-progress` 事件，
+    /// 每批计为一次操作受 `max_ops_per_sec` 限制。进度报告携带的令牌被取消时中止删除，
+    /// 已删除的条目不会恢复。
+    async fn remove_tree(&self, root: &path::Path) -> anyhow::Result<()> {
+        let mut tracker = self.progress.map(|progress| progress.track_delete(root));
+        let mut removed = 0;
+        // 后序遍历：目录中的条目删除后再删除目录本身
+        let mut stack = vec![(root.to_path_buf(), false)];
+        while let Some((path, listed)) = stack.pop() {
+            if !listed && self.fs.is_dir(&path) && !self.fs.is_symlink(&path) {
+                stack.push((path.clone(), true));
+                for entry in self.fs.read_dir(&path).await? {
+                    stack.push((entry, false));
+                }
+                continue;
+            }
+            if listed {
+                self.fs.remove_dir(&path).await?;
+            } else {
+                remove_entry(self.fs, &path).await?;
+            }
+            removed += 1;
+            if removed % DELETE_BATCH == 0 {
+                self.finish_delete_batch(tracker.as_mut(), removed).await?;
+            }
+        }
+        if let Some(tracker) = tracker.as_mut() {
+            tracker.finish(removed);
+        }
+        Ok(())
+    }
+
+    async fn finish_delete_batch(
+        &self,
+        tracker: Option<&mut DeleteTracker<'_>>,
+        removed: u64,
+    ) -> anyhow::Result<()> {
+        if self
+            .progress
+            .is_some_and(|progress| progress.is_cancelled())
+        {
+            return Err(anyhow!("删除目录已取消，已删除 {} 个条目", removed));
+        }
+        if let Some(tracker) = tracker {
+            tracker.update(removed);
+        }
+        tokio::task::yield_now().await;
+        self.state.throttle_op().await;
+        Ok(())
+    }
+}
+
+/// 分批删除目录树时每批删除的条目数。
+const DELETE_BATCH: u64 = 256;
+
+/// 删除文件或链接；目录链接（软链接或联接）需要按目录删除，只删除链接本身。
+async fn remove_entry<F: FsOps>(fs: &F, path: &path::Path) -> anyhow::Result<()> {
+    if fs.is_symlink(path) && fs.is_dir(path) {
+        fs.remove_dir(path).await?;
+    } else {
+        fs.remove_file(path).await?;
+    }
+    Ok(())
+}
+
+/// 为类型不同的占用条目选择一个未被使用的备份路径。
+fn backup_path<F: FsOps>(fs: &F, path: &path::Path) -> anyhow::Result<path::PathBuf> {
+    let mut name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("路径没有文件名: {:?}", path))?
+        .to_os_string();
+    name.push(".bak");
+    let backup = (0..)
+        .map(|n| path.with_file_name(flatten::numbered(&name, n)))
+        .find(|backup| !fs.exists(backup) && !fs.is_symlink(backup))
+        .expect("无限序列中总能找到未占用的名称");
+    Ok(backup)
+}
 
 pub trait FileProcessor {
-    fn deal_create(
+    fn deal_create<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
         kind: notify::event::CreateKind,
         path: &path::Path,
-        from: &path::Path,
-        to: &path::Path,
     ) -> impl std::future::Future<Output = anyhow::Result<()>> + Send;
-    fn deal_modify(
+    fn deal_modify<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
         kind: notify::event::ModifyKind,
         path: &path::Path,
-        from: &path::Path,
-        to: &path::Path,
     ) -> impl std::future::Future<Output = anyhow::Result<()>> + Send;
-    fn deal_remove(
+    fn deal_remove<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
         kind: notify::event::RemoveKind,
         path: &path::Path,
-        from: &path::Path,
-        to: &path::Path,
+    ) -> impl std::future::Future<Output = anyhow::Result<()>> + Send;
+    /// 全量同步时对单个源文件执行的操作。
+    fn sync_file<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
+        path: &path::Path,
     ) -> impl std::future::Future<Output = anyhow::Result<()>> + Send;
 }
 
-pub async fn process(
+/// 将事件分发给对应的处理器。
+///
+/// 处理器返回的错误会附带事件类别的上下文后返回，由调用方决定记录日志还是延迟重试。
+pub async fn process<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
     copy_type: CopyType,
-    event: notify::Event,
-    from: &path::Path,
-    to: &path::Path,
-) {
-    if let Some(path) = event.paths.first() {
-        match event.kind {
-            notify::EventKind::Create(create_kind) => {
-                if let Err(e) = match copy_type {
-                    CopyType::Copy => CopyProcessor::deal_create(create_kind, path, from, to).await,
-                    CopyType::Link => LinkProcessor::deal_create(create_kind, path, from, to).await,
-                } {
-                    error!("处理创建事件时出错: {:?}", e);
-                }
+    event: &notify::Event,
+) -> anyhow::Result<()> {
+    let Some(path) = event.paths.first() else {
+        warn!("事件没有路径信息: {:?}", event);
+        return Ok(());
+    };
+    if ctx.options.ignore_modify
+        && matches!(event.kind, notify::EventKind::Modify(kind)
+            if !matches!(kind, notify::event::ModifyKind::Name(_)))
+    {
+        debug!("已忽略修改事件: {:?}", path);
+        return Ok(());
+    }
+    if let (
+        notify::EventKind::Modify(notify::event::ModifyKind::Name(notify::event::RenameMode::Both)),
+        [from, to],
+        CopyType::Copy,
+    ) = (event.kind, event.paths.as_slice(), copy_type)
+    {
+        if is_included(ctx, from) && is_included(ctx, to) {
+            ctx.state.throttle_op().await;
+            if file_processor_copy::rename_dir(ctx, from, to)
+                .await
+                .context("处理重命名事件时出错")?
+            {
+                return Ok(());
             }
-            notify::EventKind::Modify(modify_kind) => {
-                if let Err(e) = match copy_type {
-                    CopyType::Copy => CopyProcessor::deal_modify(modify_kind, path, from, to).await,
-                    CopyType::Link => LinkProcessor::deal_modify(modify_kind, path, from, to).await,
-                } {
-                    error!("处理修改事件时出错: {:?}", e);
-                }
+        }
+    }
+    if let Ok(relative) = path.strip_prefix(ctx.from) {
+        if !ctx.filter.matches(relative) {
+            debug!("路径被过滤规则排除: {:?}", path);
+            return Ok(());
+        }
+    }
+    if ctx.is_stripped_away(path) {
+        warn!("路径层数不多于 strip_components，已跳过: {:?}", path);
+        return Ok(());
+    }
+    if !matches!(event.kind, notify::EventKind::Remove(_)) && is_too_old(ctx, path).await {
+        debug!("文件的修改时间早于阈值: {:?}", path);
+        return Ok(());
+    }
+    if !matches!(event.kind, notify::EventKind::Remove(_)) && !content_matches(ctx, path).await? {
+        debug!("文件内容不满足内容规则: {:?}", path);
+        return Ok(());
+    }
+    if let Some(kind) = hook_kind(event.kind) {
+        if !ctx.apply_hook(kind, path).await? {
+            return Ok(());
+        }
+    }
+    ctx.state.throttle_op().await;
+    match event.kind {
+        notify::EventKind::Create(create_kind) => match copy_type {
+            CopyType::Copy => CopyProcessor::deal_create(ctx, create_kind, path).await,
+            CopyType::Link => LinkProcessor::deal_create(ctx, create_kind, path).await,
+            CopyType::Move => MoveProcessor::deal_create(ctx, create_kind, path).await,
+            CopyType::Monitor => MonitorProcessor::deal_create(ctx, create_kind, path).await,
+        }
+        .context("处理创建事件时出错"),
+        notify::EventKind::Modify(modify_kind) => match copy_type {
+            CopyType::Copy => CopyProcessor::deal_modify(ctx, modify_kind, path).await,
+            CopyType::Link => LinkProcessor::deal_modify(ctx, modify_kind, path).await,
+            CopyType::Move => MoveProcessor::deal_modify(ctx, modify_kind, path).await,
+            CopyType::Monitor => MonitorProcessor::deal_modify(ctx, modify_kind, path).await,
+        }
+        .context("处理修改事件时出错"),
+        notify::EventKind::Remove(remove_kind) => match copy_type {
+            CopyType::Copy => CopyProcessor::deal_remove(ctx, remove_kind, path).await,
+            CopyType::Link => LinkProcessor::deal_remove(ctx, remove_kind, path).await,
+            CopyType::Move => MoveProcessor::deal_remove(ctx, remove_kind, path).await,
+            CopyType::Monitor => MonitorProcessor::deal_remove(ctx, remove_kind, path).await,
+        }
+        .context("处理删除事件时出错"),
+        default => {
+            info!("未处理的事件类型: {:?}", default);
+            Ok(())
+        }
+    }
+}
+
+/// 需要调用钩子的事件在钩子参数中的名称，删除和只改元数据的事件不调用钩子。
+fn hook_kind(kind: notify::EventKind) -> Option<&'static str> {
+    use notify::event::{ModifyKind, RenameMode};
+    match kind {
+        notify::EventKind::Create(_) => Some("create"),
+        notify::EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Any) => Some("modify"),
+        notify::EventKind::Modify(ModifyKind::Name(RenameMode::To)) => Some("rename"),
+        _ => None,
+    }
+}
+
+/// 路径位于源目录内、未被过滤规则排除，且在目标中有对应位置。
+fn is_included<F: FsOps>(ctx: &ProcessContext<'_, F>, path: &path::Path) -> bool {
+    path.strip_prefix(ctx.from)
+        .is_ok_and(|relative| ctx.filter.matches(relative))
+        && !ctx.is_stripped_away(path)
+}
+
+/// 文件是否通过过滤规则、`modified_after` 和内容规则。
+///
+/// 用于不经过 [`process`] 而直接同步的文件，例如跟随目录软链接时遍历到的文件。
+pub(crate) async fn is_wanted_file<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+) -> anyhow::Result<bool> {
+    if !path
+        .strip_prefix(ctx.from)
+        .is_ok_and(|relative| ctx.filter.matches(relative))
+    {
+        debug!("路径被过滤规则排除: {:?}", path);
+        return Ok(false);
+    }
+    if is_too_old(ctx, path).await {
+        debug!("文件的修改时间早于阈值: {:?}", path);
+        return Ok(false);
+    }
+    if !content_matches(ctx, path).await? {
+        debug!("文件内容不满足内容规则: {:?}", path);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// 路径是否为修改时间早于 `modified_after` 的文件。
+async fn is_too_old<F: FsOps>(ctx: &ProcessContext<'_, F>, path: &path::Path) -> bool {
+    if ctx.state.modified_after().is_none() || !ctx.fs.is_file(path) {
+        return false;
+    }
+    ctx.state.is_too_old(ctx.fs.modified(path).await.ok())
+}
+
+/// 路径不是文件、未配置内容规则或文件内容满足规则时返回 `true`。
+pub async fn content_matches<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+) -> anyhow::Result<bool> {
+    match ctx.filter.content() {
+        Some(content) if ctx.fs.is_file(path) => Ok(content
+            .matches(ctx.fs, path)
+            .await
+            .context("检查文件内容时出错")?),
+        _ => Ok(true),
+    }
+}
+
+/// 按复制类型对单个源文件执行全量同步操作。
+pub async fn sync_file<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    copy_type: CopyType,
+    path: &path::Path,
+) -> anyhow::Result<()> {
+    if ctx.is_stripped_away(path) {
+        warn!("路径层数不多于 strip_components，已跳过: {:?}", path);
+        return Ok(());
+    }
+    if !ctx.apply_hook("sync", path).await? {
+        return Ok(());
+    }
+    ctx.state.throttle_op().await;
+    match copy_type {
+        CopyType::Copy => CopyProcessor::sync_file(ctx, path).await,
+        CopyType::Link => LinkProcessor::sync_file(ctx, path).await,
+        CopyType::Move => MoveProcessor::sync_file(ctx, path).await,
+        CopyType::Monitor => MonitorProcessor::sync_file(ctx, path).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::{
+        config::{DeleteMode, WatchOptions},
+        fs_ops::FsOps,
+        mem_fs::{dst, src, Op, TestWatch, DST},
+        CopyType,
+    };
+
+    #[tokio::test]
+    async fn clearing_target_after_source_root_is_deleted_keeps_target_root() {
+        let watch = TestWatch::new(WatchOptions {
+            delete_target_on_source_lost: true,
+            ..Default::default()
+        });
+        watch.fs.add_file(dst("a.txt"), "a");
+        watch.fs.add_file(dst("dir/b.txt"), "b");
+        watch.fs.add_file(src("a.txt"), "a");
+        watch.ctx().clear_target().await.unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::RemoveFile(dst("a.txt")),
+                Op::RemoveFile(dst("dir/b.txt")),
+                Op::RemoveDir(dst("dir")),
+            ]
+        );
+        assert!(watch.fs.is_dir(Path::new(DST)));
+        assert!(watch.fs.exists(&src("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn clearing_target_clears_read_only_files_first() {
+        let watch = TestWatch::new(WatchOptions {
+            target_read_only: true,
+            ..Default::default()
+        });
+        watch.fs.add_file(dst("a.txt"), "a");
+        watch.fs.set_readonly_flag(dst("a.txt"), true);
+        watch.ctx().clear_target().await.unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::SetReadonly(dst("a.txt"), false),
+                Op::RemoveFile(dst("a.txt")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn os_trash_mode_moves_target_to_trash() {
+        let watch = TestWatch::new(WatchOptions {
+            delete_mode: DeleteMode::OsTrash,
+            ..Default::default()
+        });
+        watch.fs.add_file(dst("a.txt"), "a");
+        watch.ctx().delete(&dst("a.txt")).await.unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::Trash(dst("a.txt"))]);
+        assert!(!watch.fs.exists(&dst("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn os_trash_mode_falls_back_to_permanent_delete() {
+        let watch = TestWatch::new(WatchOptions {
+            delete_mode: DeleteMode::OsTrash,
+            ..Default::default()
+        });
+        watch.fs.add_file(dst("a.txt"), "a");
+        watch.fs.fail("trash", std::io::ErrorKind::Unsupported);
+        watch.ctx().delete(&dst("a.txt")).await.unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::RemoveFile(dst("a.txt"))]);
+        assert!(!watch.fs.exists(&dst("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn permanent_mode_does_not_use_trash() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(dst("dir/a.txt"), "a");
+        watch.ctx().delete(&dst("dir")).await.unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![Op::RemoveFile(dst("dir/a.txt")), Op::RemoveDir(dst("dir"))]
+        );
+        assert!(!watch.fs.exists(&dst("dir")));
+    }
+
+    #[tokio::test]
+    async fn temp_files_are_not_processed() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("~$doc.docx"), "lock");
+        watch.fs.add_file(src("file.crdownload"), "part");
+        for path in [src("~$doc.docx"), src("file.crdownload")] {
+            let event =
+                notify::Event::new(notify::EventKind::Create(notify::event::CreateKind::File))
+                    .add_path(path);
+            super::process(&watch.ctx(), CopyType::Copy, &event)
+                .await
+                .unwrap();
+        }
+        assert!(watch.fs.ops().is_empty());
+    }
+
+    #[tokio::test]
+    async fn processing_is_paced_by_max_ops_per_sec() {
+        let watch = TestWatch::new(WatchOptions {
+            max_ops_per_sec: Some(20),
+            ..Default::default()
+        });
+        let start = std::time::Instant::now();
+        for n in 0..25 {
+            let path = src(&format!("{}.txt", n));
+            watch.fs.add_file(&path, "data");
+            let event =
+                notify::Event::new(notify::EventKind::Create(notify::event::CreateKind::File))
+                    .add_path(path);
+            super::process(&watch.ctx(), CopyType::Copy, &event)
+                .await
+                .unwrap();
+        }
+        // 前 20 个操作使用积累的令牌，其余 5 个约需 250ms
+        assert!(start.elapsed() >= std::time::Duration::from_millis(200));
+        assert_eq!(watch.fs.read(dst("24.txt")), Some(b"data".to_vec()));
+    }
+
+    fn create_event(path: std::path::PathBuf) -> notify::Event {
+        notify::Event::new(notify::EventKind::Create(notify::event::CreateKind::File))
+            .add_path(path)
+    }
+
+    #[tokio::test]
+    async fn only_files_modified_after_cutoff_are_copied() {
+        let watch = TestWatch::new(WatchOptions {
+            modified_after_ms: Some(2_000_000_000_000),
+            ..Default::default()
+        });
+        watch
+            .state
+            .set_modified_after(watch.options.modified_after());
+        let cutoff = watch.options.modified_after().unwrap();
+        let second = std::time::Duration::from_secs(1);
+        watch.fs.add_file(src("old.txt"), "old");
+        watch.fs.set_modified(src("old.txt"), cutoff - second);
+        watch.fs.add_file(src("new.txt"), "new");
+        watch.fs.set_modified(src("new.txt"), cutoff + second);
+        watch.fs.add_file(src("exact.txt"), "exact");
+        watch.fs.set_modified(src("exact.txt"), cutoff);
+        for name in ["old.txt", "new.txt", "exact.txt"] {
+            super::process(&watch.ctx(), CopyType::Copy, &create_event(src(name)))
+                .await
+                .unwrap();
+        }
+        assert_eq!(watch.fs.read(dst("old.txt")), None);
+        assert_eq!(watch.fs.read(dst("new.txt")), Some(b"new".to_vec()));
+        assert_eq!(watch.fs.read(dst("exact.txt")), Some(b"exact".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn clearing_cutoff_at_runtime_copies_older_files_again() {
+        let watch = TestWatch::new(WatchOptions::default());
+        let cutoff = std::time::UNIX_EPOCH + std::time::Duration::from_secs(2_000_000_000);
+        watch.fs.add_file(src("old.txt"), "old");
+        watch
+            .fs
+            .set_modified(src("old.txt"), cutoff - std::time::Duration::from_secs(1));
+        watch.state.set_modified_after(Some(cutoff));
+        super::process(&watch.ctx(), CopyType::Copy, &create_event(src("old.txt")))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read(dst("old.txt")), None);
+
+        watch.state.set_modified_after(None);
+        super::process(&watch.ctx(), CopyType::Copy, &create_event(src("old.txt")))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read(dst("old.txt")), Some(b"old".to_vec()));
+    }
+
+    /// 模拟全量同步已处理 `names`：复制到目标并记入同步索引。
+    async fn walked(watch: &TestWatch, names: &[&str]) {
+        for name in names {
+            let path = src(name);
+            super::sync_file(&watch.ctx(), CopyType::Copy, &path)
+                .await
+                .unwrap();
+            let len = watch.fs.len(&path).await.unwrap();
+            let modified = watch.fs.modified(&path).await.ok();
+            let entry = crate::index::IndexEntry::new(len, modified).unwrap();
+            watch
+                .state
+                .with_index(|index| index.record((*name).into(), entry));
+        }
+    }
+
+    #[tokio::test]
+    async fn events_for_files_already_walked_are_covered() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "a");
+        watch.fs.add_file(src("dir/b.txt"), "b");
+        walked(&watch, &["a.txt", "dir/b.txt"]).await;
+        let ctx = watch.ctx();
+        assert!(ctx.covered_by_sync(&create_event(src("a.txt"))).await);
+        let modify = notify::Event::new(notify::EventKind::Modify(
+            notify::event::ModifyKind::Data(notify::event::DataChange::Content),
+        ))
+        .add_path(src("dir/b.txt"));
+        assert!(ctx.covered_by_sync(&modify).await);
+    }
+
+    #[tokio::test]
+    async fn events_not_handled_by_walk_are_not_covered() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "a");
+        watch.fs.add_dir(src("dir"));
+        walked(&watch, &["a.txt"]).await;
+        let ctx = watch.ctx();
+        let remove = notify::Event::new(notify::EventKind::Remove(notify::event::RemoveKind::File))
+            .add_path(src("a.txt"));
+        let rename = notify::Event::new(notify::EventKind::Modify(
+            notify::event::ModifyKind::Name(notify::event::RenameMode::To),
+        ))
+        .add_path(src("a.txt"));
+        let folder =
+            notify::Event::new(notify::EventKind::Create(notify::event::CreateKind::Folder))
+                .add_path(src("dir"));
+        let unknown = create_event(src("new.txt"));
+        let outside = create_event(dst("a.txt"));
+        let partly = create_event(src("a.txt")).add_path(src("new.txt"));
+        for event in [remove, rename, folder, unknown, outside, partly] {
+            assert!(!ctx.covered_by_sync(&event).await, "{:?}", event);
+        }
+    }
+
+    #[tokio::test]
+    async fn file_modified_during_initial_sync_is_replayed() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "a");
+        watch.fs.add_file(src("b.txt"), "b1");
+        watch.fs.add_file(src("gone.txt"), "gone");
+        // 遍历开始时收到的事件被缓存
+        let mut buffered = vec![create_event(src("a.txt")), create_event(src("b.txt"))];
+        walked(&watch, &["a.txt", "b.txt", "gone.txt"]).await;
+        // 遍历处理过后源目录继续变化
+        watch.fs.add_file(src("b.txt"), "b2");
+        buffered.push(create_event(src("b.txt")));
+        watch.fs.remove_file(&src("gone.txt")).await.unwrap();
+        buffered.push(
+            notify::Event::new(notify::EventKind::Remove(notify::event::RemoveKind::File))
+                .add_path(src("gone.txt")),
+        );
+        let ops_before = watch.fs.ops().len();
+
+        let ctx = watch.ctx();
+        let mut replayed = Vec::new();
+        for event in buffered {
+            if !ctx.covered_by_sync(&event).await {
+                replayed.push(event);
             }
-            notify::EventKind::Remove(remove_kind) => {
-                if let Err(e) = match copy_type {
-                    CopyType::Copy => CopyProcessor::deal_remove(remove_kind, path, from, to).await,
-                    CopyType::Link => LinkProcessor::deal_remove(remove_kind, path, from, to).await,
-                } {
-                    error!("处理删除事件时出错: {:?}", e);
-                }
+        }
+        assert_eq!(replayed.len(), 3);
+        for event in &replayed {
+            super::process(&ctx, CopyType::Copy, event).await.unwrap();
+        }
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"a".to_vec()));
+        assert_eq!(watch.fs.read(dst("b.txt")), Some(b"b2".to_vec()));
+        assert_eq!(watch.fs.read(dst("gone.txt")), None);
+        // a.txt 没有被重复复制
+        assert!(!watch.fs.ops()[ops_before..]
+            .iter()
+            .any(|op| matches!(op, Op::Copy(from, _) if *from == src("a.txt"))));
+    }
+
+    fn strip_watch(strip_components: usize) -> TestWatch {
+        let watch = TestWatch::new(WatchOptions {
+            strip_components,
+            ..Default::default()
+        });
+        watch.fs.add_file(src("src/app/main.rs"), "fn main() {}");
+        watch
+    }
+
+    #[tokio::test]
+    async fn strip_zero_keeps_full_relative_path() {
+        let watch = strip_watch(0);
+        let ctx = watch.ctx();
+        assert_eq!(
+            ctx.target_path(&src("src/app/main.rs")).unwrap(),
+            dst("src/app/main.rs")
+        );
+        super::process(&ctx, CopyType::Copy, &create_event(src("src/app/main.rs")))
+            .await
+            .unwrap();
+        assert!(watch.fs.exists(&dst("src/app/main.rs")));
+    }
+
+    #[tokio::test]
+    async fn strip_one_drops_leading_component() {
+        let watch = strip_watch(1);
+        let ctx = watch.ctx();
+        assert_eq!(
+            ctx.target_path(&src("src/app/main.rs")).unwrap(),
+            dst("app/main.rs")
+        );
+        assert_eq!(ctx.target_dir(&src("src/app")), Some(dst("app")));
+        assert_eq!(ctx.target_dir(&src("src")), None);
+        super::process(&ctx, CopyType::Copy, &create_event(src("src/app/main.rs")))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.read(dst("app/main.rs")),
+            Some(b"fn main() {}".to_vec())
+        );
+        assert!(!watch.fs.exists(&dst("src")));
+
+        let remove = notify::Event::new(notify::EventKind::Remove(notify::event::RemoveKind::File))
+            .add_path(src("src/app/main.rs"));
+        watch.fs.remove_file(&src("src/app/main.rs")).await.unwrap();
+        super::process(&ctx, CopyType::Copy, &remove).await.unwrap();
+        assert!(!watch.fs.exists(&dst("app/main.rs")));
+    }
+
+    #[tokio::test]
+    async fn strip_at_or_beyond_depth_skips_file() {
+        for strip in [3, 5] {
+            let watch = strip_watch(strip);
+            let ctx = watch.ctx();
+            assert!(ctx.is_stripped_away(&src("src/app/main.rs")));
+            assert!(ctx.target_path(&src("src/app/main.rs")).is_err());
+            super::process(&ctx, CopyType::Copy, &create_event(src("src/app/main.rs")))
+                .await
+                .unwrap();
+            super::sync_file(&ctx, CopyType::Copy, &src("src/app/main.rs"))
+                .await
+                .unwrap();
+            assert!(watch.fs.ops().is_empty(), "strip {}", strip);
+        }
+    }
+
+    /// 在目标中创建 `dirs` 个子目录，每个子目录包含 `files` 个文件。
+    fn add_tree(watch: &TestWatch, dirs: usize, files: usize) {
+        for d in 0..dirs {
+            for f in 0..files {
+                watch
+                    .fs
+                    .add_file(dst(&format!("big/{}/{}.txt", d, f)), "data");
             }
-            default => {
-                info!("未处理的事件类型: {:?}", default);
+        }
+    }
+
+    #[tokio::test]
+    async fn large_directory_is_deleted_in_batches_children_first() {
+        let watch = TestWatch::new(WatchOptions::default());
+        add_tree(&watch, 6, 100);
+        watch.ctx().delete(&dst("big")).await.unwrap();
+        assert!(!watch.fs.exists(&dst("big")));
+
+        let ops = watch.fs.ops();
+        // 600 个文件、6 个子目录和根目录各删除一次
+        assert_eq!(ops.len(), 607);
+        assert_eq!(ops.last(), Some(&Op::RemoveDir(dst("big"))));
+        for (i, op) in ops.iter().enumerate() {
+            if let Op::RemoveDir(dir) = op {
+                assert!(
+                    ops[i + 1..].iter().all(|later| match later {
+                        Op::RemoveFile(file) => !file.starts_with(dir),
+                        _ => true,
+                    }),
+                    "{:?} 在其中的文件之前被删除",
+                    dir
+                );
             }
         }
-    } else {
-        warn!("事件没有路径信息: {:?}", event);
     }
-}
 
-pub async fn delete(path: &path::Path) -> anyhow::Result<()> {
-    if path.is_symlink() {
-        std::fs::remove_file(path)?;
-        info!("已成功删除软链接: {:?}", path);
-    } else if path.is_file() {
-        std::fs::remove_file(path)?;
-        info!("已成功删除文件: {:?}", path);
-    } else if path.is_dir() {
-        std::fs::remove_dir_all(path)?;
-        info!("已成功删除目录: {:?}", path);
-    } else {
-        return Err(anyhow::anyhow!(
-            "无法删除: {:?}, 不是文件、目录或软链接",
-            path
-        ));
+    #[tokio::test]
+    async fn each_delete_batch_counts_as_one_op() {
+        let watch = TestWatch::new(WatchOptions {
+            max_ops_per_sec: Some(2),
+            ..Default::default()
+        });
+        // 800 个条目分为 3 批，前 2 批使用积累的令牌，第 3 批约需等待 500ms
+        add_tree(&watch, 8, 99);
+        let start = std::time::Instant::now();
+        watch.ctx().delete(&dst("big")).await.unwrap();
+        assert!(start.elapsed() >= std::time::Duration::from_millis(400));
+        assert!(!watch.fs.exists(&dst("big")));
+    }
+
+    #[tokio::test]
+    async fn ignore_modify_skips_modify_events_only() {
+        let watch = TestWatch::new(WatchOptions {
+            ignore_modify: true,
+            ..Default::default()
+        });
+        watch.fs.add_file(src("a.txt"), "new");
+        watch.fs.add_file(dst("a.txt"), "old");
+        for kind in [
+            notify::event::ModifyKind::Data(notify::event::DataChange::Content),
+            notify::event::ModifyKind::Metadata(notify::event::MetadataKind::Any),
+            notify::event::ModifyKind::Any,
+        ] {
+            let event = notify::Event::new(notify::EventKind::Modify(kind)).add_path(src("a.txt"));
+            super::process(&watch.ctx(), CopyType::Copy, &event)
+                .await
+                .unwrap();
+        }
+        assert!(watch.fs.ops().is_empty());
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"old".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn ignore_modify_still_syncs_creates_renames_and_removes() {
+        let watch = TestWatch::new(WatchOptions {
+            ignore_modify: true,
+            ..Default::default()
+        });
+        watch.fs.add_file(src("a.txt"), "a");
+        super::process(&watch.ctx(), CopyType::Copy, &create_event(src("a.txt")))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"a".to_vec()));
+
+        watch.fs.add_file(src("b.txt"), "b");
+        let rename = notify::Event::new(notify::EventKind::Modify(
+            notify::event::ModifyKind::Name(notify::event::RenameMode::To),
+        ))
+        .add_path(src("b.txt"));
+        super::process(&watch.ctx(), CopyType::Copy, &rename)
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read(dst("b.txt")), Some(b"b".to_vec()));
+
+        let remove = notify::Event::new(notify::EventKind::Remove(notify::event::RemoveKind::File))
+            .add_path(src("gone.txt"));
+        watch.fs.add_file(dst("gone.txt"), "old");
+        super::process(&watch.ctx(), CopyType::Copy, &remove)
+            .await
+            .unwrap();
+        assert!(!watch.fs.exists(&dst("gone.txt")));
     }
-    Ok(())
 }
@@ -1,59 +1,68 @@
-use std::path;
+use std::{ops::ControlFlow, path};
 
 use anyhow::anyhow;
-use log::info;
+use log::{debug, info, warn};
+use tokio_util::sync::CancellationToken;
 
-use crate::file_processor::{self, FileProcessor};
+use crate::{
+    config::{CopyMode, SpecialFilePolicy, SymlinkPolicy, SyncMode},
+    file_processor::{self, FileProcessor, ProcessContext},
+    filter::{Filters, PathFilter},
+    fs_ops::{self, FsOps},
+    log_dedup::info_collapsed,
+    staging::Staging,
+    walk, CopyType,
+};
 
 pub struct CopyProcessor;
 impl FileProcessor for CopyProcessor {
-    async fn deal_create(
+    async fn deal_create<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
         kind: notify::event::CreateKind,
-        path: &std::path::Path,
-        from: &std::path::Path,
-        to: &std::path::Path,
+        path: &path::Path,
     ) -> anyhow::Result<()> {
+        if is_dir_symlink(ctx, path) {
+            return copy_dir_symlink(ctx, path).await;
+        }
         match kind {
-            notify::event::CreateKind::File => copy(path, from, to).await,
-            notify::event::CreateKind::Folder => {
-                info!("无处理，创建了一个目录: {:?}", path);
-                Ok(())
-            }
+            notify::event::CreateKind::File => copy(ctx, path).await,
+            notify::event::CreateKind::Folder => create_dir(ctx, path).await,
             notify::event::CreateKind::Other => {
                 info!("无处理，创建了其他类型的文件或目录:  {:?}", path);
                 Ok(())
             }
             notify::event::CreateKind::Any => {
-                if path.is_file() {
-                    copy(path, from, to).await
+                if ctx.fs.is_file(path) {
+                    copy(ctx, path).await
                 } else {
-                    info!("无处理，创建了一个目录: {:?}", path);
-                    Ok(())
+                    create_dir(ctx, path).await
                 }
             }
         }
     }
 
-    async fn deal_modify(
+    async fn deal_modify<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
         kind: notify::event::ModifyKind,
         path: &path::Path,
-        from: &path::Path,
-        to: &path::Path,
     ) -> anyhow::Result<()> {
         match kind {
             notify::event::ModifyKind::Data(data) => {
-                info!("数据被修改: {:?}, {:?}", data, path);
-                copy(path, from, to).await
+                info_collapsed!("数据被修改", "数据被修改: {:?}, {:?}", data, path);
+                copy(ctx, path).await
             }
             notify::event::ModifyKind::Metadata(metadata) => {
-                info!("元数据被修改: {:?}, {:?}", metadata, path);
+                info_collapsed!("元数据被修改", "元数据被修改: {:?}, {:?}", metadata, path);
                 Ok(())
             }
             notify::event::ModifyKind::Name(rename) => {
                 info!("文件名被修改: {:?}, {:?}", rename, path);
                 match rename {
-                    notify::event::RenameMode::To => copy(path, from, to).await,
-                    notify::event::RenameMode::From => delete(path, from, to).await,
+                    notify::event::RenameMode::To if is_dir_symlink(ctx, path) => {
+                        copy_dir_symlink(ctx, path).await
+                    }
+                    notify::event::RenameMode::To => copy(ctx, path).await,
+                    notify::event::RenameMode::From => delete(ctx, path).await,
                     default => {
                         info!("未处理的重命名模式: {:?}", default);
                         Ok(())
@@ -66,37 +75,1571 @@ impl FileProcessor for CopyProcessor {
             }
             notify::event::ModifyKind::Any => {
                 info!("任意类型的修改: {:?}", path);
-                copy(path, from, to).await
+                copy(ctx, path).await
             }
         }
     }
 
-    async fn deal_remove(
+    async fn deal_remove<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
         _kind: notify::event::RemoveKind,
         path: &path::Path,
-        from: &path::Path,
-        to: &path::Path,
     ) -> anyhow::Result<()> {
-        delete(path, from, to).await
+        delete(ctx, path).await
+    }
+
+    async fn sync_file<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
+        path: &path::Path,
+    ) -> anyhow::Result<()> {
+        copy(ctx, path).await
     }
 }
 
-async fn copy(path: &path::Path, from: &path::Path, to: &path::Path) -> anyhow::Result<()> {
-    let relative_path = path.strip_prefix(from)?;
-    let target_path = to.join(relative_path);
-    if let Some(parent) = target_path.parent() {
-        if !parent.exists() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
+/// 目录本身不复制，其中的文件复制时会自动创建父目录。
+///
+/// 只有源路径由文件变为目录、目标中还留着同名文件时，才清除该文件并创建目录。
+async fn create_dir<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+) -> anyhow::Result<()> {
+    let target_path = ctx.target_path(path)?;
+    if !ctx.fs.is_file(&target_path) && !ctx.fs.is_symlink(&target_path) {
+        info!("无处理，创建了一个目录: {:?}", path);
+        return Ok(());
+    }
+    ctx.clear_type_conflict(&target_path, true).await?;
+    ctx.fs.create_dir_all(&target_path).await?;
+    info!("源路径已变为目录，已在目标中创建目录: {:?}", target_path);
+    Ok(())
+}
+
+/// 源目录被重命名且事件同时带有新旧路径时，将目标中的整个子树一次重命名到新位置。
+///
+/// 部分后端只为目录报告这一个事件，不为其中的文件逐个报告。新路径不是目录、旧路径在目标中
+/// 没有对应的目录，或目录在目标中的位置不由源路径决定（扁平化、钩子）时返回 `false`，
+/// 由调用方按普通的重命名事件处理。
+pub(crate) async fn rename_dir<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    from: &path::Path,
+    to: &path::Path,
+) -> anyhow::Result<bool> {
+    if ctx.options.hook.is_some() || !ctx.fs.is_dir(to) || ctx.fs.is_symlink(to) {
+        return Ok(false);
+    }
+    let (Some(old_target), Some(new_target)) = (ctx.target_dir(from), ctx.target_dir(to)) else {
+        return Ok(false);
+    };
+    if !ctx.fs.is_dir(&old_target) || ctx.fs.is_symlink(&old_target) {
+        return Ok(false);
+    }
+    if ctx.fs.exists(&new_target) || ctx.fs.is_symlink(&new_target) {
+        debug!("目标中已存在新目录，不整体重命名: {:?}", new_target);
+        return Ok(false);
+    }
+    crate::allowlist::check(&old_target).await?;
+    crate::allowlist::check(&new_target).await?;
+    ctx.ensure_parent_dir(&new_target).await?;
+    ctx.fs.rename(&old_target, &new_target).await?;
+    ctx.state
+        .with_target_cache(|cache| cache.forget(&old_target));
+    info!("已重命名目标目录: {:?} 到 {:?}", old_target, new_target);
+    Ok(true)
+}
+
+async fn copy<F: FsOps>(ctx: &ProcessContext<'_, F>, path: &path::Path) -> anyhow::Result<()> {
+    if ctx.fs.exists(path) && !ctx.fs.is_file(path) && !ctx.fs.is_dir(path) {
+        return copy_special(ctx, path).await;
+    }
+    if ctx.options.ignore_empty_files
+        && ctx.fs.is_file(path)
+        && ctx.fs.len(path).await.is_ok_and(|len| len == 0)
+    {
+        debug!("跳过空文件: {:?}", path);
+        return Ok(());
+    }
+    copy_to_target(ctx, path).await.map(|_| ())
+}
+
+/// 源路径是命名管道、套接字或设备文件，读取它可能一直阻塞，按 `special_file_policy` 跳过或重建。
+async fn copy_special<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+) -> anyhow::Result<()> {
+    if ctx.options.special_file_policy == SpecialFilePolicy::Skip {
+        info_collapsed!("跳过特殊文件", "跳过命名管道、套接字或设备文件: {:?}", path);
+        return Ok(());
+    }
+    if !fs_ops::is_fifo(path) {
+        info_collapsed!("跳过特殊文件", "只能重建命名管道，已跳过: {:?}", path);
+        return Ok(());
+    }
+    let target_path = ctx.target_path(path)?;
+    if fs_ops::is_fifo(&target_path) {
+        return Ok(());
+    }
+    ctx.ensure_parent_dir(&target_path).await?;
+    ctx.clear_type_conflict(&target_path, false).await?;
+    if ctx.fs.exists(&target_path) || ctx.fs.is_symlink(&target_path) {
+        ctx.fs.remove_file(&target_path).await?;
+    }
+    ctx.fs.create_fifo(path, &target_path).await?;
+    info!("已在目标中重建命名管道: {:?}", target_path);
+    Ok(())
+}
+
+/// 复制文件并返回实际写入的路径。
+///
+/// 开启暂存时写入暂存目录并登记待提交，否则直接写入目标路径。
+pub(crate) async fn copy_to_target<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+) -> anyhow::Result<path::PathBuf> {
+    transfer(ctx, path, false).await.map(|(written, _)| written)
+}
+
+/// 与 [`copy_to_target`] 相同，但源和目标位于同一文件系统时直接重命名源文件。
+///
+/// 返回实际写入的路径，以及源文件是否已被移走。
+pub(crate) async fn move_to_target<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+) -> anyhow::Result<(path::PathBuf, bool)> {
+    transfer(ctx, path, true).await
+}
+
+async fn transfer<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+    allow_rename: bool,
+) -> anyhow::Result<(path::PathBuf, bool)> {
+    let target_path = ctx.target_path(path)?;
+    let staged = ctx.options.staging_quiet_secs.is_some();
+    let write_path = if staged {
+        Staging::staging_path(ctx.to, &target_path)?
     } else {
-        return Err(anyhow!("无法获取目标路径的父目录: {:?}", target_path));
+        target_path.clone()
+    };
+    ctx.ensure_parent_dir(&write_path).await?;
+    // 切换复制类型后目标中可能残留指向源文件的链接，直接写入会覆盖源文件
+    if ctx.fs.is_symlink(&target_path) {
+        ctx.fs.remove_file(&target_path).await?;
+        info!("已删除旧链接: {:?}", target_path);
+    }
+    // 源路径由目录变为文件时，目标中可能还留着同名目录
+    ctx.clear_type_conflict(&target_path, false).await?;
+    if ctx.fs.is_file(&target_path) && !should_overwrite(ctx, path, &target_path).await? {
+        return Ok((target_path, false));
+    }
+    // 只读的目标文件需要先清除只读属性才能覆盖
+    if ctx.options.target_read_only && ctx.fs.is_file(&write_path) {
+        ctx.fs.set_readonly(&write_path, false).await?;
     }
-    tokio::fs::copy(path, &target_path).await?;
-    info!("已成功复制文件: {:?} 到 {:?}", path, target_path);
+    let linked = ctx.options.preserve_hardlinks && link_to_copied(ctx, path, &write_path).await?;
+    let mut renamed = false;
+    if !linked {
+        let len = ctx.fs.len(path).await?;
+        ctx.make_room(&target_path, len).await?;
+        // 同一文件系统内重命名保留了内容和所有元数据
+        renamed = allow_rename
+            && ctx.state.same_filesystem() == Some(true)
+            && try_rename(ctx, path, &write_path).await;
+        if !renamed {
+            if let Err(e) = copy_file(ctx, path, &write_path, len).await {
+                ctx.state
+                    .with_target_cache(|cache| cache.forget(&target_path));
+                return Err(e);
+            }
+            if ctx.options.copy_xattrs {
+                crate::metadata::copy_xattrs(path, &write_path).await?;
+            }
+            #[cfg(unix)]
+            if ctx.options.preserve_ownership {
+                crate::metadata::preserve_ownership(path, &write_path).await?;
+            }
+        }
+        #[cfg(unix)]
+        if let Some(mode) = ctx.options.force_mode {
+            crate::metadata::force_mode(&write_path, mode).await?;
+        }
+        #[cfg(unix)]
+        if let Some(mask) = ctx.options.permission_mask {
+            crate::metadata::mask_mode(&write_path, mask).await?;
+        }
+        if ctx.options.target_read_only {
+            ctx.fs.set_readonly(&write_path, true).await?;
+        }
+    }
+    if staged {
+        ctx.state
+            .with_staging(|staging| staging.add(target_path, write_path.clone()));
+    }
+    if !renamed {
+        info_collapsed!(
+            "已成功复制文件",
+            "已成功复制文件: {:?} 到 {:?}",
+            path,
+            write_path
+        );
+    }
+    Ok((write_path, renamed))
+}
+
+/// 尝试将源文件重命名到目标，失败时返回 `false` 由调用方改为复制。
+async fn try_rename<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+    write_path: &path::Path,
+) -> bool {
+    match ctx.fs.rename(path, write_path).await {
+        Ok(()) => true,
+        Err(e) => {
+            debug!("重命名失败，改为复制: {:?}, {:?}", path, e);
+            false
+        }
+    }
+}
+
+/// 写入文件内容，先尝试写时复制（如已开启），不可用时普通复制并报告进度。
+///
+/// 开启增量复制且目标文件已存在时只写入变化的块。
+async fn copy_file<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+    write_path: &path::Path,
+    len: u64,
+) -> anyhow::Result<()> {
+    if ctx.options.use_reflink && try_reflink(ctx, path, write_path).await {
+        return Ok(());
+    }
+    let mut tracker = ctx.progress.map(|reporter| reporter.track(path, len));
+    let mut progress = |copied| match &mut tracker {
+        Some(tracker) => tracker.update(copied),
+        None => ControlFlow::Continue(()),
+    };
+    if ctx.options.copy_mode == CopyMode::Delta
+        && ctx.fs.is_file(write_path)
+        && !ctx.fs.is_symlink(write_path)
+    {
+        let written = ctx
+            .fs
+            .delta_copy(
+                path,
+                write_path,
+                ctx.options.delta_block_size,
+                &mut progress,
+            )
+            .await?;
+        debug!(
+            "增量复制: {:?}, 写入 {} / {} 字节",
+            write_path, written, len
+        );
+        return Ok(());
+    }
+    ctx.fs
+        .copy(path, write_path, ctx.options.buffer_size, &mut progress)
+        .await?;
     Ok(())
 }
-async fn delete(path: &path::Path, from: &path::Path, to: &path::Path) -> anyhow::Result<()> {
-    let relative_path = path.strip_prefix(from)?;
-    let target_path = to.join(relative_path);
-    file_processor::delete(&target_path).await
+
+/// 尝试以写时复制方式克隆文件，失败时返回 `false` 由调用方改为普通复制。
+///
+/// 目标不支持写时复制时记录在监视状态中，之后该监视不再尝试。
+async fn try_reflink<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+    write_path: &path::Path,
+) -> bool {
+    if ctx.state.reflink_unsupported() {
+        return false;
+    }
+    match ctx.fs.reflink(path, write_path).await {
+        Ok(()) => true,
+        Err(e) if fs_ops::is_reflink_unsupported(&e) => {
+            info!(
+                "目标不支持写时复制，改为普通复制: {:?}, {:?}",
+                write_path, e
+            );
+            ctx.state.set_reflink_unsupported();
+            false
+        }
+        Err(e) => {
+            debug!("写时复制失败，改为普通复制: {:?}, {:?}", write_path, e);
+            false
+        }
+    }
+}
+
+/// 源文件与已复制过的文件互为硬链接时，在目标中创建指向已有副本的硬链接。
+///
+/// 返回是否已创建硬链接。同一 inode 第一次出现时记录本次的写入路径，供之后的文件链接。
+async fn link_to_copied<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+    write_path: &path::Path,
+) -> anyhow::Result<bool> {
+    let Some(inode) = crate::metadata::shared_inode(path).await? else {
+        return Ok(false);
+    };
+    let copied = ctx.state.with_hardlinks(|links| {
+        links
+            .entry(inode)
+            .or_insert_with(|| write_path.to_path_buf())
+            .clone()
+    });
+    // 目标已存在时正常复制：若它已是硬链接，写入会同时更新所有链接
+    if copied == write_path || ctx.fs.exists(write_path) || !ctx.fs.is_file(&copied) {
+        return Ok(false);
+    }
+    ctx.fs.hard_link(&copied, write_path).await?;
+    info!("已创建硬链接: {:?} 到 {:?}", write_path, copied);
+    Ok(true)
+}
+
+/// 按 `sync_mode` 判断是否覆盖已存在的目标文件。
+async fn should_overwrite<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+    target_path: &path::Path,
+) -> anyhow::Result<bool> {
+    match ctx.options.sync_mode {
+        SyncMode::Mirror => Ok(true),
+        SyncMode::Update => {
+            if ctx.fs.modified(target_path).await? >= ctx.fs.modified(path).await? {
+                info_collapsed!(
+                    "目标文件不比源文件旧",
+                    "目标文件不比源文件旧，跳过: {:?}",
+                    target_path
+                );
+                return Ok(false);
+            }
+            Ok(true)
+        }
+        SyncMode::SizeOnly => {
+            if ctx.fs.len(target_path).await? == ctx.fs.len(path).await? {
+                info_collapsed!(
+                    "目标文件大小未变",
+                    "目标文件与源文件大小相同，跳过: {:?}",
+                    target_path
+                );
+                return Ok(false);
+            }
+            Ok(true)
+        }
+    }
+}
+
+/// 软链接是否指向自身所在的目录或其上级目录，跟随这样的链接会形成环。
+async fn points_to_ancestor(link: &path::Path) -> bool {
+    let Some(parent) = link.parent() else {
+        return false;
+    };
+    match (
+        tokio::fs::canonicalize(link).await,
+        tokio::fs::canonicalize(parent).await,
+    ) {
+        (Ok(resolved), Ok(parent)) => parent.starts_with(resolved),
+        _ => false,
+    }
+}
+
+fn is_dir_symlink<F: FsOps>(ctx: &ProcessContext<'_, F>, path: &path::Path) -> bool {
+    ctx.fs.is_symlink(path) && ctx.fs.is_dir(path)
+}
+
+/// 按 `symlink_policy` 处理源中指向目录的软链接。
+async fn copy_dir_symlink<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &path::Path,
+) -> anyhow::Result<()> {
+    match ctx.options.symlink_policy {
+        SymlinkPolicy::Skip => {
+            info!("忽略指向目录的软链接: {:?}", path);
+            Ok(())
+        }
+        SymlinkPolicy::Preserve => {
+            let original = ctx.fs.read_link(path).await?;
+            let target_path = ctx.target_path(path)?;
+            ctx.ensure_parent_dir(&target_path).await?;
+            if ctx.fs.is_symlink(&target_path) {
+                ctx.fs.remove_dir(&target_path).await?;
+            } else if ctx.fs.exists(&target_path) {
+                return Err(anyhow!(
+                    "目标路径已存在且不是链接，无法重建软链接: {:?}",
+                    target_path
+                ));
+            }
+            ctx.fs.symlink_dir(&original, &target_path).await?;
+            info!("已重建目录软链接: {:?} 指向 {:?}", target_path, original);
+            Ok(())
+        }
+        SymlinkPolicy::Follow => {
+            if points_to_ancestor(path).await {
+                warn!("软链接指向其所在目录或上级目录，跳过以避免循环: {:?}", path);
+                return Ok(());
+            }
+            // 遍历时只收集文件，过滤规则按相对源目录的路径另行检查
+            let all = PathFilter::new(&Filters {
+                temp_files: Vec::new(),
+                ..Default::default()
+            })?;
+            let files = walk::walk_files(path, &all, &CancellationToken::new()).await?;
+            // 遍历到的文件与普通事件一样经过过滤规则、修改时间、内容规则和钩子的检查
+            for file in files {
+                if file_processor::is_wanted_file(ctx, &file.path).await? {
+                    file_processor::sync_file(ctx, CopyType::Copy, &file.path).await?;
+                }
+            }
+            info!("已复制软链接所指目录的内容: {:?}", path);
+            Ok(())
+        }
+    }
+}
+
+async fn delete<F: FsOps>(ctx: &ProcessContext<'_, F>, path: &path::Path) -> anyhow::Result<()> {
+    for target_path in ctx.remove_targets(path)? {
+        // 尚未提交的暂存文件不再需要
+        if let Some(staged) = ctx
+            .state
+            .with_staging(|staging| staging.discard(&target_path))
+        {
+            if ctx.options.target_read_only {
+                let _ = ctx.fs.set_readonly(&staged, false).await;
+            }
+            let _ = ctx.fs.remove_file(&staged).await;
+        }
+        if !ctx.fs.exists(&target_path) && !ctx.fs.is_symlink(&target_path) {
+            continue;
+        }
+        // Windows 上无法直接删除只读文件
+        if ctx.options.target_read_only && ctx.fs.is_file(&target_path) {
+            ctx.fs.set_readonly(&target_path, false).await?;
+        }
+        ctx.delete(&target_path).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind, RenameMode};
+
+    use super::*;
+    use crate::{
+        config::{DirConflict, WatchOptions},
+        mem_fs::{dst, src, Op, TestWatch},
+    };
+
+    #[tokio::test]
+    async fn create_copies_file_after_creating_parent() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("sub/a.txt"), "hello");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("sub/a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::CreateDirAll(dst("sub")),
+                Op::Copy(src("sub/a.txt"), dst("sub/a.txt")),
+            ]
+        );
+        assert_eq!(watch.fs.read(dst("sub/a.txt")), Some(b"hello".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn modify_overwrites_existing_target() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(dst("a.txt"), "old");
+        watch.fs.add_file(src("a.txt"), "new");
+        let kind = ModifyKind::Data(DataChange::Content);
+        CopyProcessor::deal_modify(&watch.ctx(), kind, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::Copy(src("a.txt"), dst("a.txt"))]);
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"new".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn read_only_target_is_cleared_before_overwrite() {
+        let options = WatchOptions {
+            target_read_only: true,
+            ..Default::default()
+        };
+        let watch = TestWatch::new(options);
+        watch.fs.add_file(dst("a.txt"), "old");
+        watch.fs.set_readonly_flag(dst("a.txt"), true);
+        watch.fs.add_file(src("a.txt"), "new");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::SetReadonly(dst("a.txt"), false),
+                Op::Copy(src("a.txt"), dst("a.txt")),
+                Op::SetReadonly(dst("a.txt"), true),
+            ]
+        );
+        assert!(watch.fs.readonly(dst("a.txt")));
+    }
+
+    fn read_only_options() -> WatchOptions {
+        WatchOptions {
+            target_read_only: true,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn copied_target_is_marked_read_only() {
+        let watch = TestWatch::new(read_only_options());
+        watch.fs.add_file(src("a.txt"), "data");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::Copy(src("a.txt"), dst("a.txt")),
+                Op::SetReadonly(dst("a.txt"), true),
+            ]
+        );
+        assert!(watch.fs.readonly(dst("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn read_only_target_follows_source_changes() {
+        let watch = TestWatch::new(read_only_options());
+        watch.fs.add_file(src("a.txt"), "v1");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        watch.fs.add_file(src("a.txt"), "v2");
+        let kind = ModifyKind::Data(DataChange::Content);
+        CopyProcessor::deal_modify(&watch.ctx(), kind, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"v2".to_vec()));
+        assert!(watch.fs.readonly(dst("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn read_only_target_can_be_deleted() {
+        let watch = TestWatch::new(read_only_options());
+        watch.fs.add_file(dst("a.txt"), "data");
+        watch.fs.set_readonly_flag(dst("a.txt"), true);
+        CopyProcessor::deal_remove(&watch.ctx(), RemoveKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::SetReadonly(dst("a.txt"), false),
+                Op::RemoveFile(dst("a.txt")),
+            ]
+        );
+        assert!(!watch.fs.exists(&dst("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn read_only_target_updates_on_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("src"), dir.path().join("dst"));
+        std::fs::create_dir_all(&from).unwrap();
+        std::fs::create_dir_all(&to).unwrap();
+        let watch = TestWatch::with_fs(fs_ops::TokioFs, &from, &to, read_only_options());
+        let (source, target) = (from.join("a.txt"), to.join("a.txt"));
+        for content in ["v1", "v2"] {
+            std::fs::write(&source, content).unwrap();
+            CopyProcessor::sync_file(&watch.ctx(), &source)
+                .await
+                .unwrap();
+            assert_eq!(std::fs::read_to_string(&target).unwrap(), content);
+            assert!(std::fs::metadata(&target).unwrap().permissions().readonly());
+        }
+        CopyProcessor::deal_remove(&watch.ctx(), RemoveKind::File, &source)
+            .await
+            .unwrap();
+        assert!(!target.exists());
+    }
+
+    fn flatten_options() -> WatchOptions {
+        WatchOptions {
+            flatten: true,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn flatten_renames_colliding_files() {
+        let watch = TestWatch::new(flatten_options());
+        watch.fs.add_file(src("a/app.log"), "a");
+        watch.fs.add_file(src("b/app.log"), "b");
+        for path in [src("a/app.log"), src("b/app.log")] {
+            CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &path)
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::Copy(src("a/app.log"), dst("app.log")),
+                Op::Copy(src("b/app.log"), dst("app (1).log")),
+            ]
+        );
+        assert_eq!(watch.fs.read(dst("app.log")), Some(b"a".to_vec()));
+        assert_eq!(watch.fs.read(dst("app (1).log")), Some(b"b".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn flatten_deletes_the_name_assigned_to_the_source() {
+        let watch = TestWatch::new(flatten_options());
+        watch.fs.add_file(src("a/app.log"), "a");
+        watch.fs.add_file(src("b/app.log"), "b");
+        for path in [src("a/app.log"), src("b/app.log")] {
+            CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &path)
+                .await
+                .unwrap();
+        }
+        CopyProcessor::deal_remove(&watch.ctx(), RemoveKind::File, &src("b/app.log"))
+            .await
+            .unwrap();
+        assert!(watch.fs.exists(&dst("app.log")));
+        assert!(!watch.fs.exists(&dst("app (1).log")));
+    }
+
+    #[tokio::test]
+    async fn flatten_ignores_deletes_of_unknown_sources() {
+        let watch = TestWatch::new(flatten_options());
+        watch.fs.add_file(dst("app.log"), "old");
+        CopyProcessor::deal_remove(&watch.ctx(), RemoveKind::File, &src("a/app.log"))
+            .await
+            .unwrap();
+        assert!(watch.fs.ops().is_empty());
+    }
+
+    /// 在临时目录中创建 `data` 目录和指向它的源目录软链接 `src/link`。
+    struct LinkedDir {
+        _dir: tempfile::TempDir,
+        data: path::PathBuf,
+        from: path::PathBuf,
+        to: path::PathBuf,
+    }
+
+    impl LinkedDir {
+        fn new(files: &[(&str, &str)]) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let (data, from, to) = (
+                dir.path().join("data"),
+                dir.path().join("src"),
+                dir.path().join("dst"),
+            );
+            for dir in [&data, &from, &to] {
+                std::fs::create_dir_all(dir).unwrap();
+            }
+            for (name, content) in files {
+                std::fs::write(data.join(name), content).unwrap();
+            }
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&data, from.join("link")).unwrap();
+            #[cfg(windows)]
+            junction::create(&data, from.join("link")).unwrap();
+            Self {
+                _dir: dir,
+                data,
+                from,
+                to,
+            }
+        }
+
+        fn watch(&self, options: WatchOptions) -> TestWatch<fs_ops::TokioFs> {
+            TestWatch::with_fs(fs_ops::TokioFs, &self.from, &self.to, options)
+        }
+    }
+
+    fn symlink_options(symlink_policy: SymlinkPolicy) -> WatchOptions {
+        WatchOptions {
+            symlink_policy,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn skipped_dir_symlink_copies_nothing() {
+        let linked = LinkedDir::new(&[("a.txt", "a")]);
+        let watch = linked.watch(symlink_options(SymlinkPolicy::Skip));
+        let link = linked.from.join("link");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::Any, &link)
+            .await
+            .unwrap();
+        assert!(!linked.to.join("link").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn preserved_dir_symlink_points_to_the_same_directory() {
+        let linked = LinkedDir::new(&[("a.txt", "a")]);
+        let watch = linked.watch(symlink_options(SymlinkPolicy::Preserve));
+        let link = linked.from.join("link");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::Any, &link)
+            .await
+            .unwrap();
+        let target = linked.to.join("link");
+        assert!(target.is_symlink());
+        assert_eq!(std::fs::read_link(&target).unwrap(), linked.data);
+    }
+
+    #[tokio::test]
+    async fn followed_dir_symlink_copies_contents() {
+        let linked = LinkedDir::new(&[("a.txt", "a"), ("b.txt", "b")]);
+        let watch = linked.watch(symlink_options(SymlinkPolicy::Follow));
+        let link = linked.from.join("link");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::Any, &link)
+            .await
+            .unwrap();
+        let target = linked.to.join("link");
+        assert!(!target.is_symlink());
+        assert_eq!(std::fs::read_to_string(target.join("a.txt")).unwrap(), "a");
+        assert_eq!(std::fs::read_to_string(target.join("b.txt")).unwrap(), "b");
+    }
+
+    #[tokio::test]
+    async fn followed_files_pass_the_same_checks_as_events() {
+        let linked = LinkedDir::new(&[
+            ("keep.txt", "mirror me"),
+            ("skip.tmp", "mirror me"),
+            ("old.txt", "mirror me"),
+            ("other.txt", "something else"),
+        ]);
+        let old = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        std::fs::File::options()
+            .write(true)
+            .open(linked.data.join("old.txt"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        let mut options = symlink_options(SymlinkPolicy::Follow);
+        options.filters.exclude = vec!["**/*.tmp".to_string()];
+        options.filters.content = Some(crate::content_filter::ContentPredicate {
+            rule: crate::content_filter::ContentRule::Contains("mirror".to_string()),
+            max_bytes: 1024,
+        });
+        let watch = linked.watch(options);
+        watch
+            .state
+            .set_modified_after(Some(old + std::time::Duration::from_secs(1)));
+        let link = linked.from.join("link");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::Any, &link)
+            .await
+            .unwrap();
+        let target = linked.to.join("link");
+        assert!(target.join("keep.txt").exists());
+        for skipped in ["skip.tmp", "old.txt", "other.txt"] {
+            assert!(!target.join(skipped).exists(), "{} 不应被复制", skipped);
+        }
+    }
+
+    #[tokio::test]
+    async fn dir_symlink_to_ancestor_is_not_followed() {
+        let linked = LinkedDir::new(&[]);
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&linked.from, linked.from.join("loop")).unwrap();
+        #[cfg(windows)]
+        junction::create(&linked.from, linked.from.join("loop")).unwrap();
+        let watch = linked.watch(symlink_options(SymlinkPolicy::Follow));
+        let link = linked.from.join("loop");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::Any, &link)
+            .await
+            .unwrap();
+        assert!(!linked.to.join("loop").exists());
+    }
+
+    /// 在 `sync_mode` 下以给定的修改时间（秒）复制已存在目标的源文件，返回目标的内容。
+    async fn sync_existing(sync_mode: SyncMode, source_secs: u64, target_secs: u64) -> String {
+        let watch = TestWatch::new(WatchOptions {
+            sync_mode,
+            ..Default::default()
+        });
+        let at = |secs| std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        watch.fs.add_file(src("a.txt"), "source");
+        watch.fs.set_modified(src("a.txt"), at(source_secs));
+        watch.fs.add_file(dst("a.txt"), "target");
+        watch.fs.set_modified(dst("a.txt"), at(target_secs));
+        CopyProcessor::sync_file(&watch.ctx(), &src("a.txt"))
+            .await
+            .unwrap();
+        String::from_utf8(watch.fs.read(dst("a.txt")).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn update_overwrites_older_target() {
+        assert_eq!(sync_existing(SyncMode::Update, 2000, 1000).await, "source");
+    }
+
+    #[tokio::test]
+    async fn update_keeps_newer_target() {
+        assert_eq!(sync_existing(SyncMode::Update, 1000, 2000).await, "target");
+    }
+
+    #[tokio::test]
+    async fn update_keeps_target_with_equal_mtime() {
+        assert_eq!(sync_existing(SyncMode::Update, 1000, 1000).await, "target");
+    }
+
+    #[tokio::test]
+    async fn mirror_overwrites_newer_target() {
+        assert_eq!(sync_existing(SyncMode::Mirror, 1000, 2000).await, "source");
+    }
+
+    #[tokio::test]
+    async fn size_only_overwrites_target_of_different_size() {
+        let watch = TestWatch::new(WatchOptions {
+            sync_mode: SyncMode::SizeOnly,
+            ..Default::default()
+        });
+        watch.fs.add_file(dst("a.txt"), "old");
+        watch.fs.add_file(src("a.txt"), "longer");
+        CopyProcessor::sync_file(&watch.ctx(), &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"longer".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn size_only_skips_same_size_edit() {
+        let watch = TestWatch::new(WatchOptions {
+            sync_mode: SyncMode::SizeOnly,
+            ..Default::default()
+        });
+        watch.fs.add_file(dst("a.txt"), "abc");
+        watch.fs.add_file(src("a.txt"), "xyz");
+        CopyProcessor::sync_file(&watch.ctx(), &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(watch.fs.ops().is_empty());
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"abc".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn size_only_ignores_mtime() {
+        assert_eq!(
+            sync_existing(SyncMode::SizeOnly, 2000, 1000).await,
+            "target"
+        );
+        assert_eq!(
+            sync_existing(SyncMode::SizeOnly, 1000, 2000).await,
+            "target"
+        );
+    }
+
+    #[tokio::test]
+    async fn update_copies_missing_target() {
+        let watch = TestWatch::new(WatchOptions {
+            sync_mode: SyncMode::Update,
+            ..Default::default()
+        });
+        watch.fs.add_file(src("a.txt"), "source");
+        CopyProcessor::sync_file(&watch.ctx(), &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"source".to_vec()));
+    }
+
+    fn rename_md_options() -> WatchOptions {
+        WatchOptions {
+            rename_rule: Some(crate::rename::RenameRule {
+                from: r"\.md$".to_string(),
+                to: ".html".to_string(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn rename_rule_applies_to_target_name() {
+        let watch = TestWatch::new(rename_md_options());
+        watch.fs.add_file(src("docs/a.md"), "# a");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("docs/a.md"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::CreateDirAll(dst("docs")),
+                Op::Copy(src("docs/a.md"), dst("docs/a.html")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn rename_rule_applies_to_deletes() {
+        let watch = TestWatch::new(rename_md_options());
+        watch.fs.add_file(dst("docs/a.html"), "# a");
+        CopyProcessor::deal_remove(&watch.ctx(), RemoveKind::File, &src("docs/a.md"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::RemoveFile(dst("docs/a.html"))]);
+    }
+
+    #[tokio::test]
+    async fn staged_file_appears_only_after_commit() {
+        let watch = TestWatch::new(WatchOptions {
+            staging_quiet_secs: Some(1),
+            ..Default::default()
+        });
+        let staged = dst(".auto-copy-staging/sub/a.txt");
+        watch.fs.add_file(src("sub/a.txt"), "data");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("sub/a.txt"))
+            .await
+            .unwrap();
+        assert!(watch.fs.exists(&staged));
+        assert!(!watch.fs.exists(&dst("sub/a.txt")));
+
+        let quiet = Some(std::time::Duration::from_secs(3600));
+        assert!(watch
+            .state
+            .with_staging(|staging| staging.take(quiet))
+            .is_empty());
+        let entries = watch
+            .state
+            .with_staging(|staging| staging.take(Some(std::time::Duration::ZERO)));
+        assert_eq!(entries, vec![(dst("sub/a.txt"), staged.clone())]);
+        for (target, staged) in entries {
+            watch.ctx().commit_staged(&staged, &target).await.unwrap();
+        }
+        assert_eq!(watch.fs.read(dst("sub/a.txt")), Some(b"data".to_vec()));
+        assert!(!watch.fs.exists(&staged));
+    }
+
+    #[tokio::test]
+    async fn deleting_uncommitted_file_discards_staged_copy() {
+        let watch = TestWatch::new(WatchOptions {
+            staging_quiet_secs: Some(1),
+            ..Default::default()
+        });
+        let staged = dst(".auto-copy-staging/a.txt");
+        watch.fs.add_file(src("a.txt"), "data");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        CopyProcessor::deal_remove(&watch.ctx(), RemoveKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(!watch.fs.exists(&staged));
+        assert!(watch
+            .state
+            .with_staging(|staging| staging.take(None))
+            .is_empty());
+    }
+
+    #[cfg(unix)]
+    fn temp_watch(options: WatchOptions) -> (tempfile::TempDir, TestWatch<fs_ops::TokioFs>) {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("src"), dir.path().join("dst"));
+        std::fs::create_dir_all(&from).unwrap();
+        std::fs::create_dir_all(&to).unwrap();
+        let watch = TestWatch::with_fs(fs_ops::TokioFs, from, to, options);
+        (dir, watch)
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn hardlinked_sources_share_an_inode_in_target() {
+        use std::os::unix::fs::MetadataExt;
+
+        let (_dir, watch) = temp_watch(WatchOptions {
+            preserve_hardlinks: true,
+            ..Default::default()
+        });
+        let (a, b) = (watch.from.join("a.txt"), watch.from.join("sub/b.txt"));
+        std::fs::create_dir_all(b.parent().unwrap()).unwrap();
+        std::fs::write(&a, "shared").unwrap();
+        std::fs::hard_link(&a, &b).unwrap();
+        for path in [&a, &b] {
+            CopyProcessor::sync_file(&watch.ctx(), path).await.unwrap();
+        }
+        let inode = |path: path::PathBuf| std::fs::metadata(path).unwrap().ino();
+        assert_eq!(
+            inode(watch.to.join("a.txt")),
+            inode(watch.to.join("sub/b.txt"))
+        );
+        assert_eq!(
+            std::fs::read_to_string(watch.to.join("sub/b.txt")).unwrap(),
+            "shared"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn hardlinks_are_copied_separately_when_disabled() {
+        use std::os::unix::fs::MetadataExt;
+
+        let (_dir, watch) = temp_watch(WatchOptions::default());
+        let (a, b) = (watch.from.join("a.txt"), watch.from.join("b.txt"));
+        std::fs::write(&a, "shared").unwrap();
+        std::fs::hard_link(&a, &b).unwrap();
+        for path in [&a, &b] {
+            CopyProcessor::sync_file(&watch.ctx(), path).await.unwrap();
+        }
+        let inode = |path: path::PathBuf| std::fs::metadata(path).unwrap().ino();
+        assert_ne!(inode(watch.to.join("a.txt")), inode(watch.to.join("b.txt")));
+    }
+
+    #[tokio::test]
+    async fn metadata_change_does_nothing() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "data");
+        let kind = ModifyKind::Metadata(notify::event::MetadataKind::Permissions);
+        CopyProcessor::deal_modify(&watch.ctx(), kind, &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(watch.fs.ops().is_empty());
+    }
+
+    #[tokio::test]
+    async fn remove_deletes_target_file() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(dst("a.txt"), "data");
+        CopyProcessor::deal_remove(&watch.ctx(), RemoveKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::RemoveFile(dst("a.txt"))]);
+        assert!(!watch.fs.exists(&dst("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn rename_from_deletes_target() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(dst("a.txt"), "data");
+        let kind = ModifyKind::Name(RenameMode::From);
+        CopyProcessor::deal_modify(&watch.ctx(), kind, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::RemoveFile(dst("a.txt"))]);
+    }
+
+    #[tokio::test]
+    async fn removing_directory_removes_contents_first() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(dst("dir/a.txt"), "data");
+        CopyProcessor::deal_remove(&watch.ctx(), RemoveKind::Folder, &src("dir"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![Op::RemoveFile(dst("dir/a.txt")), Op::RemoveDir(dst("dir"))]
+        );
+        assert!(!watch.fs.exists(&dst("dir")));
+    }
+
+    #[tokio::test]
+    async fn removing_missing_target_does_nothing() {
+        let watch = TestWatch::new(WatchOptions::default());
+        CopyProcessor::deal_remove(&watch.ctx(), RemoveKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(watch.fs.ops().is_empty());
+    }
+
+    async fn copy_into_blocked_dir(dir_conflict: DirConflict) -> (TestWatch, anyhow::Result<()>) {
+        let watch = TestWatch::new(WatchOptions {
+            dir_conflict,
+            ..Default::default()
+        });
+        watch.fs.add_file(dst("sub"), "file");
+        watch.fs.add_file(src("sub/a.txt"), "a");
+        let result =
+            CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("sub/a.txt")).await;
+        (watch, result)
+    }
+
+    #[tokio::test]
+    async fn file_blocking_parent_dir_is_an_error_by_default() {
+        let (watch, result) = copy_into_blocked_dir(DirConflict::Error).await;
+        assert!(result.is_err());
+        assert_eq!(watch.fs.read(dst("sub")), Some(b"file".to_vec()));
+        assert!(watch.fs.ops().is_empty());
+    }
+
+    #[tokio::test]
+    async fn file_blocking_parent_dir_is_backed_up() {
+        let (watch, result) = copy_into_blocked_dir(DirConflict::Backup).await;
+        result.unwrap();
+        assert_eq!(watch.fs.read(dst("sub.bak")), Some(b"file".to_vec()));
+        assert_eq!(watch.fs.read(dst("sub/a.txt")), Some(b"a".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn file_blocking_nested_parent_dir_is_removed() {
+        let watch = TestWatch::new(WatchOptions {
+            dir_conflict: DirConflict::Remove,
+            ..Default::default()
+        });
+        watch.fs.add_file(dst("sub"), "file");
+        watch.fs.add_file(src("sub/deep/a.txt"), "a");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("sub/deep/a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::RemoveFile(dst("sub")),
+                Op::CreateDirAll(dst("sub/deep")),
+                Op::Copy(src("sub/deep/a.txt"), dst("sub/deep/a.txt")),
+            ]
+        );
+        assert_eq!(watch.fs.read(dst("sub/deep/a.txt")), Some(b"a".to_vec()));
+    }
+
+    fn reflink_options() -> WatchOptions {
+        WatchOptions {
+            use_reflink: true,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn reflink_is_used_when_enabled() {
+        let watch = TestWatch::new(reflink_options());
+        watch.fs.add_file(src("a.txt"), "data");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![Op::Reflink(src("a.txt"), dst("a.txt"))]
+        );
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"data".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn reflink_is_not_tried_by_default() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "data");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::Copy(src("a.txt"), dst("a.txt"))]);
+    }
+
+    #[tokio::test]
+    async fn unsupported_reflink_falls_back_and_is_not_retried() {
+        let watch = TestWatch::new(reflink_options());
+        watch.fs.add_file(src("a.txt"), "a");
+        watch.fs.add_file(src("b.txt"), "b");
+        watch.fs.fail("reflink", std::io::ErrorKind::Unsupported);
+        for name in ["a.txt", "b.txt"] {
+            CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src(name))
+                .await
+                .unwrap();
+        }
+        assert!(watch.state.reflink_unsupported());
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::Copy(src("a.txt"), dst("a.txt")),
+                Op::Copy(src("b.txt"), dst("b.txt")),
+            ]
+        );
+        assert_eq!(watch.fs.read(dst("b.txt")), Some(b"b".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn other_reflink_errors_fall_back_without_disabling_reflink() {
+        let watch = TestWatch::new(reflink_options());
+        watch.fs.add_file(src("a.txt"), "a");
+        watch
+            .fs
+            .fail("reflink", std::io::ErrorKind::PermissionDenied);
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(!watch.state.reflink_unsupported());
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"a".to_vec()));
+    }
+
+    fn conflict_watch(dir_conflict: DirConflict) -> TestWatch {
+        TestWatch::new(WatchOptions {
+            dir_conflict,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn file_turned_directory_replaces_target_file() {
+        let watch = conflict_watch(DirConflict::Remove);
+        watch.fs.add_file(dst("x"), "file");
+        watch.fs.add_dir(src("x"));
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::Folder, &src("x"))
+            .await
+            .unwrap();
+        assert!(watch.fs.is_dir(&dst("x")));
+        assert_eq!(
+            watch.fs.ops(),
+            vec![Op::RemoveFile(dst("x")), Op::CreateDirAll(dst("x"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn file_turned_directory_backs_up_target_file() {
+        let watch = conflict_watch(DirConflict::Backup);
+        watch.fs.add_file(dst("x"), "file");
+        watch.fs.add_dir(src("x"));
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::Folder, &src("x"))
+            .await
+            .unwrap();
+        assert!(watch.fs.is_dir(&dst("x")));
+        assert_eq!(watch.fs.read(dst("x.bak")), Some(b"file".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn file_turned_directory_is_an_error_by_default() {
+        let watch = conflict_watch(DirConflict::Error);
+        watch.fs.add_file(dst("x"), "file");
+        watch.fs.add_dir(src("x"));
+        let result = CopyProcessor::deal_create(&watch.ctx(), CreateKind::Folder, &src("x")).await;
+        assert!(result.is_err());
+        assert_eq!(watch.fs.read(dst("x")), Some(b"file".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn directory_turned_file_replaces_target_directory() {
+        let watch = conflict_watch(DirConflict::Remove);
+        watch.fs.add_file(dst("x/old.txt"), "old");
+        watch.fs.add_file(src("x"), "file");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("x"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read(dst("x")), Some(b"file".to_vec()));
+        assert!(!watch.fs.exists(&dst("x/old.txt")));
+    }
+
+    #[tokio::test]
+    async fn directory_turned_file_backs_up_target_directory() {
+        let watch = conflict_watch(DirConflict::Backup);
+        watch.fs.add_file(dst("x/old.txt"), "old");
+        watch.fs.add_file(src("x"), "file");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("x"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read(dst("x")), Some(b"file".to_vec()));
+        assert_eq!(watch.fs.read(dst("x.bak/old.txt")), Some(b"old".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn directory_turned_file_is_an_error_by_default() {
+        let watch = conflict_watch(DirConflict::Error);
+        watch.fs.add_file(dst("x/old.txt"), "old");
+        watch.fs.add_file(src("x"), "file");
+        let result = CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("x")).await;
+        assert!(result.is_err());
+        assert_eq!(watch.fs.read(dst("x/old.txt")), Some(b"old".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn copying_past_size_cap_evicts_least_recently_synced() {
+        let watch = TestWatch::new(WatchOptions {
+            max_target_size: Some(10),
+            ..Default::default()
+        });
+        watch
+            .state
+            .set_target_cache(crate::evict::TargetCache::scan(&watch.to, 10));
+        for name in ["a.txt", "b.txt", "c.txt"] {
+            watch.fs.add_file(src(name), "1234");
+            CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src(name))
+                .await
+                .unwrap();
+        }
+        assert_eq!(watch.fs.read(dst("a.txt")), None);
+        assert_eq!(watch.fs.read(dst("b.txt")), Some(b"1234".to_vec()));
+        assert_eq!(watch.fs.read(dst("c.txt")), Some(b"1234".to_vec()));
+        let removed = watch
+            .fs
+            .ops()
+            .iter()
+            .position(|op| *op == Op::RemoveFile(dst("a.txt")));
+        let copied = watch
+            .fs
+            .ops()
+            .iter()
+            .position(|op| *op == Op::Copy(src("c.txt"), dst("c.txt")));
+        assert!(removed < copied, "先腾出空间再写入新文件");
+    }
+
+    fn ignore_empty_options(ignore_empty_files: bool) -> WatchOptions {
+        WatchOptions {
+            ignore_empty_files,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_file_is_skipped_when_ignored() {
+        let watch = TestWatch::new(ignore_empty_options(true));
+        watch.fs.add_file(src("empty.txt"), "");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("empty.txt"))
+            .await
+            .unwrap();
+        assert!(watch.fs.ops().is_empty());
+        assert!(!watch.fs.exists(&dst("empty.txt")));
+    }
+
+    #[tokio::test]
+    async fn non_empty_file_is_copied_when_ignoring_empty_files() {
+        let watch = TestWatch::new(ignore_empty_options(true));
+        watch.fs.add_file(src("empty.txt"), "");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("empty.txt"))
+            .await
+            .unwrap();
+        // 占位文件随后写入了内容
+        watch.fs.add_file(src("empty.txt"), "content");
+        let kind = ModifyKind::Data(DataChange::Content);
+        CopyProcessor::deal_modify(&watch.ctx(), kind, &src("empty.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![Op::Copy(src("empty.txt"), dst("empty.txt"))]
+        );
+        assert_eq!(watch.fs.read(dst("empty.txt")), Some(b"content".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn empty_file_is_copied_by_default() {
+        let watch = TestWatch::new(ignore_empty_options(false));
+        watch.fs.add_file(src("empty.txt"), "");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("empty.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read(dst("empty.txt")), Some(Vec::new()));
+    }
+
+    fn rename_both(from: &str, to: &str) -> notify::Event {
+        notify::Event::new(notify::EventKind::Modify(ModifyKind::Name(
+            RenameMode::Both,
+        )))
+        .add_path(src(from))
+        .add_path(src(to))
+    }
+
+    #[tokio::test]
+    async fn renaming_populated_directory_renames_target_subtree() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("new/a.txt"), "a");
+        watch.fs.add_file(src("new/sub/b.txt"), "b");
+        watch.fs.add_file(dst("old/a.txt"), "a");
+        watch.fs.add_file(dst("old/sub/b.txt"), "b");
+        crate::file_processor::process(&watch.ctx(), CopyType::Copy, &rename_both("old", "new"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::Rename(dst("old"), dst("new"))]);
+        assert!(!watch.fs.exists(&dst("old")));
+        assert_eq!(watch.fs.read(dst("new/a.txt")), Some(b"a".to_vec()));
+        assert_eq!(watch.fs.read(dst("new/sub/b.txt")), Some(b"b".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn directory_rename_is_not_merged_into_existing_target() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("new/a.txt"), "a");
+        watch.fs.add_file(dst("old/a.txt"), "a");
+        watch.fs.add_file(dst("new/b.txt"), "b");
+        crate::file_processor::process(&watch.ctx(), CopyType::Copy, &rename_both("old", "new"))
+            .await
+            .unwrap();
+        assert!(!watch.fs.ops().iter().any(|op| matches!(op, Op::Rename(..))));
+        assert_eq!(watch.fs.read(dst("new/b.txt")), Some(b"b".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn flattened_directory_rename_is_not_applied_to_target() {
+        let watch = TestWatch::new(WatchOptions {
+            flatten: true,
+            ..Default::default()
+        });
+        watch.fs.add_file(src("new/a.txt"), "a");
+        watch.fs.add_dir(dst("old"));
+        crate::file_processor::process(&watch.ctx(), CopyType::Copy, &rename_both("old", "new"))
+            .await
+            .unwrap();
+        assert!(watch.fs.is_dir(&dst("old")));
+        assert!(!watch.fs.exists(&dst("new")));
+    }
+
+    #[tokio::test]
+    async fn delta_mode_updates_existing_target_in_place() {
+        let watch = TestWatch::new(WatchOptions {
+            copy_mode: CopyMode::Delta,
+            ..Default::default()
+        });
+        watch.fs.add_file(dst("a.txt"), "old data");
+        watch.fs.add_file(src("a.txt"), "new data");
+        let kind = ModifyKind::Data(DataChange::Content);
+        CopyProcessor::deal_modify(&watch.ctx(), kind, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![Op::DeltaCopy(src("a.txt"), dst("a.txt"))]
+        );
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"new data".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn delta_mode_falls_back_to_full_copy_without_target() {
+        let watch = TestWatch::new(WatchOptions {
+            copy_mode: CopyMode::Delta,
+            ..Default::default()
+        });
+        watch.fs.add_file(src("a.txt"), "data");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::Copy(src("a.txt"), dst("a.txt"))]);
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"data".to_vec()));
+    }
+
+    /// 在源目录中创建命名管道，以源目录的权限为模板。
+    #[cfg(unix)]
+    async fn add_fifo(watch: &TestWatch<fs_ops::TokioFs>, name: &str) -> path::PathBuf {
+        let pipe = watch.from.join(name);
+        watch.fs.create_fifo(&watch.from, &pipe).await.unwrap();
+        assert!(fs_ops::is_fifo(&pipe));
+        pipe
+    }
+
+    /// 处理命名管道的创建事件，读取管道会一直阻塞，因此限定时间。
+    #[cfg(unix)]
+    async fn create_with_timeout(watch: &TestWatch<fs_ops::TokioFs>, path: &path::Path) {
+        let ctx = watch.ctx();
+        let create = CopyProcessor::deal_create(&ctx, CreateKind::File, path);
+        tokio::time::timeout(std::time::Duration::from_secs(5), create)
+            .await
+            .expect("处理命名管道时阻塞")
+            .unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn fifo_is_skipped_by_default() {
+        let (_dir, watch) = temp_watch(WatchOptions::default());
+        let pipe = add_fifo(&watch, "pipe").await;
+        create_with_timeout(&watch, &pipe).await;
+        assert!(!watch.to.join("pipe").exists());
+
+        let file = watch.from.join("a.txt");
+        std::fs::write(&file, "data").unwrap();
+        create_with_timeout(&watch, &file).await;
+        assert_eq!(
+            std::fs::read_to_string(watch.to.join("a.txt")).unwrap(),
+            "data"
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn fifo_is_recreated_in_target_when_enabled() {
+        let (_dir, watch) = temp_watch(WatchOptions {
+            special_file_policy: SpecialFilePolicy::Recreate,
+            ..Default::default()
+        });
+        let pipe = add_fifo(&watch, "pipe").await;
+        std::fs::write(watch.to.join("pipe"), "stale").unwrap();
+        create_with_timeout(&watch, &pipe).await;
+        assert!(fs_ops::is_fifo(&watch.to.join("pipe")));
+        // 目标中已是命名管道时不再重建
+        create_with_timeout(&watch, &pipe).await;
+        assert!(fs_ops::is_fifo(&watch.to.join("pipe")));
+    }
+
+    #[tokio::test]
+    async fn events_after_redirect_go_to_new_target() {
+        let mut watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "a");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+
+        // 与事件循环应用改写请求的方式相同
+        watch.fs.add_dir("/other");
+        watch.state.redirect("/other".into(), false);
+        let (to, _) = watch.state.take_redirect().unwrap();
+        watch.to = to;
+        watch.state.forget_target_paths();
+
+        watch.fs.add_file(src("b.txt"), "b");
+        CopyProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("b.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read("/other/b.txt"), Some(b"b".to_vec()));
+        assert!(!watch.fs.exists(&dst("b.txt")));
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"a".to_vec()));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn forced_mode_overrides_source_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (_dir, watch) = temp_watch(WatchOptions {
+            force_mode: Some(0o644),
+            ..Default::default()
+        });
+        let mode = |path: path::PathBuf| std::fs::metadata(path).unwrap().permissions().mode();
+        for (name, source_mode) in [("a.sh", 0o755), ("b.txt", 0o600)] {
+            let path = watch.from.join(name);
+            std::fs::write(&path, "data").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(source_mode)).unwrap();
+            CopyProcessor::sync_file(&watch.ctx(), &path).await.unwrap();
+            assert_eq!(mode(watch.to.join(name)) & 0o7777, 0o644, "{}", name);
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn source_permissions_are_kept_without_forced_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let (_dir, watch) = temp_watch(WatchOptions::default());
+        let path = watch.from.join("a.sh");
+        std::fs::write(&path, "data").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o700)).unwrap();
+        CopyProcessor::sync_file(&watch.ctx(), &path).await.unwrap();
+        let mode = std::fs::metadata(watch.to.join("a.sh"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o7777, 0o700);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn permission_mask_clears_bits_after_forced_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mode = |path: path::PathBuf| std::fs::metadata(path).unwrap().permissions().mode();
+        for (force_mode, source_mode, expected) in [
+            (None, 0o777, 0o755),
+            (None, 0o640, 0o640),
+            (Some(0o666), 0o600, 0o644),
+        ] {
+            let (_dir, watch) = temp_watch(WatchOptions {
+                force_mode,
+                permission_mask: Some(0o022),
+                ..Default::default()
+            });
+            let path = watch.from.join("a.txt");
+            std::fs::write(&path, "data").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(source_mode)).unwrap();
+            CopyProcessor::sync_file(&watch.ctx(), &path).await.unwrap();
+            assert_eq!(
+                mode(watch.to.join("a.txt")) & 0o7777,
+                expected,
+                "{:o}",
+                source_mode
+            );
+        }
+    }
 }
@@ -1,21 +1,28 @@
+use std::{fmt, path::PathBuf};
+
 use anyhow::anyhow;
-use log::{error, info};
+use log::{error, info, warn};
 
-use crate::file_processor::{self, FileProcessor};
+use crate::{
+    config::{DirLinkStyle, LinkFallback},
+    file_processor::{FileProcessor, ProcessContext},
+    file_processor_copy,
+    fs_ops::FsOps,
+    log_dedup::info_collapsed,
+};
 
 pub struct LinkProcessor;
 
 impl FileProcessor for LinkProcessor {
-    async fn deal_create(
+    async fn deal_create<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
         kind: notify::event::CreateKind,
         path: &std::path::Path,
-        from: &std::path::Path,
-        to: &std::path::Path,
     ) -> anyhow::Result<()> {
         match kind {
             notify::event::CreateKind::Any
             | notify::event::CreateKind::File
-            | notify::event::CreateKind::Folder => create_link(path, from, to).await,
+            | notify::event::CreateKind::Folder => create_link(ctx, path).await,
 
             notify::event::CreateKind::Other => {
                 // 处理其他类型的创建
@@ -25,18 +32,17 @@ impl FileProcessor for LinkProcessor {
         }
     }
 
-    async fn deal_modify(
+    async fn deal_modify<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
         kind: notify::event::ModifyKind,
         path: &std::path::Path,
-        from: &std::path::Path,
-        to: &std::path::Path,
     ) -> anyhow::Result<()> {
         match kind {
             notify::event::ModifyKind::Name(rename_mode) => {
                 info!("文件名被修改: {:?}, {:?}", rename_mode, path);
                 match rename_mode {
-                    notify::event::RenameMode::To => create_link(path, from, to).await,
-                    notify::event::RenameMode::From => delete(path, from, to).await,
+                    notify::event::RenameMode::To => create_link(ctx, path).await,
+                    notify::event::RenameMode::From => delete(ctx, path).await,
                     _ => {
                         info!("未处理的重命名模式: {:?}", rename_mode);
                         Ok(())
@@ -50,74 +56,513 @@ impl FileProcessor for LinkProcessor {
         }
     }
 
-    async fn deal_remove(
+    async fn deal_remove<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
         _kind: notify::event::RemoveKind,
         path: &std::path::Path,
-        from: &std::path::Path,
-        to: &std::path::Path,
     ) -> anyhow::Result<()> {
-        delete(path, from, to).await
+        delete(ctx, path).await
+    }
+
+    async fn sync_file<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
+        path: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        create_link(ctx, path).await
     }
 }
 
-async fn create_link(
+async fn create_link<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
     path: &std::path::Path,
-    from: &std::path::Path,
-    to: &std::path::Path,
 ) -> anyhow::Result<()> {
-    if path.is_dir() {
-        info!("忽略目录链接: {:?}", path);
-        return Ok(());
+    if ctx.fs.is_dir(path) {
+        return match ctx.options.windows_dir_link_style {
+            Some(style) => create_dir_link(ctx, path, style).await,
+            None => {
+                info!("忽略目录链接: {:?}", path);
+                Ok(())
+            }
+        };
     }
 
-    let relative_path = path.strip_prefix(from)?;
-    let target_path = to.join(relative_path);
-    // 确保目标路径的父目录存在
-    if let Some(parent) = target_path.parent() {
-        if !parent.exists() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
-    } else {
-        return Err(anyhow!("无法获取目标路径的父目录: {:?}", target_path));
+    let target_path = ctx.target_path(path)?;
+    if is_inside_dir_link(ctx, &target_path) {
+        info!("目标位于已链接的目录中，无需处理: {:?}", target_path);
+        return Ok(());
     }
+    ctx.ensure_parent_dir(&target_path).await?;
 
     // 检查目标路径是否已存在链接，不相同则删除
-    if target_path.is_symlink() {
-        match tokio::fs::read_link(&target_path).await {
+    if ctx.fs.is_symlink(&target_path) {
+        match ctx.fs.read_link(&target_path).await {
             Ok(old_link) => {
                 if old_link == path {
                     info!("链接已存在且指向相同的路径: {:?}", target_path);
                     return Ok(());
                 } else {
-                    tokio::fs::remove_file(&target_path).await?;
+                    ctx.fs.remove_file(&target_path).await?;
                     info!("已删除旧链接: {:?}", target_path);
                 }
             }
             Err(e) => {
                 error!("读取链接失败: {:?}", e);
-                tokio::fs::remove_file(&target_path).await?;
+                ctx.fs.remove_file(&target_path).await?;
                 info!("已删除旧链接: {:?}", target_path);
             }
         }
     }
     // 删除已存在普通文件
-    else if target_path.is_file() {
-        tokio::fs::remove_file(&target_path).await?;
+    else if ctx.fs.is_file(&target_path) {
+        ctx.fs.remove_file(&target_path).await?;
         info!("已删除旧文件: {:?}", target_path);
+    } else {
+        // 源路径由目录变为文件时，目标中可能还留着同名目录
+        ctx.clear_type_conflict(&target_path, false).await?;
     }
 
-    tokio::fs::symlink_file(path, &target_path).await?;
-    info!("已成功创建文件链接: {:?} 到 {:?}", path, target_path);
+    match ctx.fs.symlink_file(path, &target_path).await {
+        Ok(()) => {}
+        Err(e) if is_unsupported_error(&e) => {
+            return link_unsupported(ctx, path, target_path, e).await;
+        }
+        Err(e) => return Err(e.into()),
+    }
+    info_collapsed!(
+        "已成功创建文件链接",
+        "已成功创建文件链接: {:?} 到 {:?}",
+        path,
+        target_path
+    );
+
+    Ok(())
+}
 
+async fn delete<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &std::path::Path,
+) -> anyhow::Result<()> {
+    for target_path in ctx.remove_targets(path)? {
+        ctx.delete(&target_path).await?;
+    }
     Ok(())
 }
 
-async fn delete(
+/// 为源目录在目标中创建目录链接。
+///
+/// 使用软链接时若因权限不足失败，会回退为目录联接。
+async fn create_dir_link<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
     path: &std::path::Path,
-    from: &std::path::Path,
-    to: &std::path::Path,
+    style: DirLinkStyle,
 ) -> anyhow::Result<()> {
-    let relative_path = path.strip_prefix(from)?;
-    let target_path = to.join(relative_path);
-    file_processor::delete(&target_path).await
+    let target_path = ctx.target_path(path)?;
+    if is_inside_dir_link(ctx, &target_path) {
+        info!("目标位于已链接的目录中，无需处理: {:?}", target_path);
+        return Ok(());
+    }
+    ctx.ensure_parent_dir(&target_path).await?;
+
+    if ctx.fs.is_symlink(&target_path) {
+        if ctx.fs.read_link(&target_path).await.ok().as_deref() == Some(path) {
+            info!("目录链接已存在且指向相同的路径: {:?}", target_path);
+            return Ok(());
+        }
+        ctx.fs.remove_dir(&target_path).await?;
+        info!("已删除旧目录链接: {:?}", target_path);
+    } else if ctx.fs.is_file(&target_path) {
+        // 源路径由文件变为目录
+        ctx.clear_type_conflict(&target_path, true).await?;
+    } else if ctx.fs.exists(&target_path) {
+        return Err(anyhow!(
+            "目标路径已存在且不是链接，无法创建目录链接: {:?}",
+            target_path
+        ));
+    }
+
+    let used = match style {
+        DirLinkStyle::Junction => {
+            ctx.fs.junction(path, &target_path).await?;
+            DirLinkStyle::Junction
+        }
+        DirLinkStyle::Symlink => match ctx.fs.symlink_dir(path, &target_path).await {
+            Ok(()) => DirLinkStyle::Symlink,
+            Err(e) if is_privilege_error(&e) => {
+                warn!("没有创建目录软链接的权限，改用目录联接: {:?}", target_path);
+                ctx.fs.junction(path, &target_path).await?;
+                DirLinkStyle::Junction
+            }
+            Err(e) => return Err(e.into()),
+        },
+    };
+    info!(
+        "已成功创建目录链接({:?}): {:?} 到 {:?}",
+        used, path, target_path
+    );
+    Ok(())
+}
+
+/// 目标文件系统不支持软链接时返回的错误，事件循环据此发送 `link-unsupported` 事件。
+#[derive(Debug)]
+pub struct LinkUnsupported {
+    pub target: PathBuf,
+}
+
+impl fmt::Display for LinkUnsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "目标文件系统不支持软链接，无法创建 {:?}，可改用复制模式",
+            self.target
+        )
+    }
+}
+
+impl std::error::Error for LinkUnsupported {}
+
+/// 按 `link_fallback` 处理无法创建的文件链接。
+async fn link_unsupported<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &std::path::Path,
+    target_path: PathBuf,
+    err: std::io::Error,
+) -> anyhow::Result<()> {
+    match ctx.options.link_fallback {
+        LinkFallback::Copy => {
+            warn!("目标文件系统不支持软链接，改为复制: {:?}", target_path);
+            file_processor_copy::copy_to_target(ctx, path).await?;
+            Ok(())
+        }
+        LinkFallback::Error => Err(anyhow::Error::new(err).context(LinkUnsupported {
+            target: target_path,
+        })),
+    }
+}
+
+/// 目标路径的某个上级目录（在 `to` 之内）是否已经是目录链接。
+///
+/// 这种情况下再在其中创建链接会写进源目录本身。
+fn is_inside_dir_link<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    target_path: &std::path::Path,
+) -> bool {
+    target_path
+        .ancestors()
+        .skip(1)
+        .take_while(|ancestor| *ancestor != ctx.to)
+        .any(|ancestor| ctx.fs.is_symlink(ancestor))
+}
+
+/// 是否为文件系统不支持软链接导致的错误。
+fn is_unsupported_error(err: &std::io::Error) -> bool {
+    // Windows: ERROR_INVALID_FUNCTION = 1, ERROR_NOT_SUPPORTED = 50
+    // Linux: vfat 返回 EPERM = 1，其他文件系统返回 EOPNOTSUPP = 95；macOS: ENOTSUP = 45
+    let codes: &[i32] = if cfg!(windows) {
+        &[1, 50]
+    } else if cfg!(target_os = "macos") {
+        &[1, 45]
+    } else {
+        &[1, 95]
+    };
+    err.kind() == std::io::ErrorKind::Unsupported
+        || err.raw_os_error().is_some_and(|code| codes.contains(&code))
+}
+
+#[cfg(windows)]
+fn is_privilege_error(err: &std::io::Error) -> bool {
+    // ERROR_PRIVILEGE_NOT_HELD
+    err.raw_os_error() == Some(1314)
+}
+
+#[cfg(not(windows))]
+fn is_privilege_error(err: &std::io::Error) -> bool {
+    err.kind() == std::io::ErrorKind::PermissionDenied
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{CreateKind, RemoveKind};
+
+    use super::*;
+    use crate::{
+        config::WatchOptions,
+        mem_fs::{dst, src, Op, TestWatch},
+    };
+
+    #[tokio::test]
+    async fn create_links_target_to_source() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("sub/a.txt"), "hello");
+        LinkProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("sub/a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::CreateDirAll(dst("sub")),
+                Op::SymlinkFile(src("sub/a.txt"), dst("sub/a.txt")),
+            ]
+        );
+        assert_eq!(watch.fs.read(dst("sub/a.txt")), Some(b"hello".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn existing_link_to_same_source_is_kept() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "hello");
+        watch.fs.add_symlink(dst("a.txt"), src("a.txt"), false);
+        LinkProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(watch.fs.ops().is_empty());
+    }
+
+    #[tokio::test]
+    async fn existing_file_is_replaced_by_link() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "hello");
+        watch.fs.add_file(dst("a.txt"), "copied");
+        LinkProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::RemoveFile(dst("a.txt")),
+                Op::SymlinkFile(src("a.txt"), dst("a.txt")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn unsupported_link_is_reported() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "hello");
+        watch
+            .fs
+            .fail("symlink_file", std::io::ErrorKind::Unsupported);
+        let err = LinkProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<LinkUnsupported>().is_some());
+        assert!(watch.fs.ops().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unsupported_link_falls_back_to_copy() {
+        let options = WatchOptions {
+            link_fallback: LinkFallback::Copy,
+            ..Default::default()
+        };
+        let watch = TestWatch::new(options);
+        watch.fs.add_file(src("a.txt"), "hello");
+        watch
+            .fs
+            .fail("symlink_file", std::io::ErrorKind::Unsupported);
+        LinkProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::Copy(src("a.txt"), dst("a.txt"))]);
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"hello".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn unsupported_os_error_names_target_and_suggests_copy_mode() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "hello");
+        let code = if cfg!(windows) {
+            50
+        } else if cfg!(target_os = "macos") {
+            45
+        } else {
+            95
+        };
+        watch.fs.fail_os("symlink_file", code);
+        let err = LinkProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap_err()
+            .context("处理事件时出错");
+        let unsupported = err.downcast_ref::<LinkUnsupported>().unwrap();
+        assert_eq!(unsupported.target, dst("a.txt"));
+        assert!(format!("{:#}", err).contains("可改用复制模式"));
+    }
+
+    #[tokio::test]
+    async fn other_link_errors_are_not_reported_as_unsupported() {
+        let watch = TestWatch::new(WatchOptions {
+            link_fallback: LinkFallback::Copy,
+            ..Default::default()
+        });
+        watch.fs.add_file(src("a.txt"), "hello");
+        watch
+            .fs
+            .fail("symlink_file", std::io::ErrorKind::PermissionDenied);
+        let err = LinkProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<LinkUnsupported>().is_none());
+        assert_eq!(watch.fs.read(dst("a.txt")), None);
+    }
+
+    #[test]
+    fn unsupported_error_classes() {
+        let unsupported = std::io::Error::from(std::io::ErrorKind::Unsupported);
+        assert!(is_unsupported_error(&unsupported));
+        let not_found = std::io::Error::from(std::io::ErrorKind::NotFound);
+        assert!(!is_unsupported_error(&not_found));
+        // vfat 和 Windows 的 ERROR_INVALID_FUNCTION 都是 1
+        assert!(is_unsupported_error(&std::io::Error::from_raw_os_error(1)));
+        assert!(!is_unsupported_error(&std::io::Error::from_raw_os_error(2)));
+    }
+
+    #[tokio::test]
+    async fn directories_are_not_linked_by_default() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_dir(src("dir"));
+        LinkProcessor::deal_create(&watch.ctx(), CreateKind::Folder, &src("dir"))
+            .await
+            .unwrap();
+        assert!(watch.fs.ops().is_empty());
+    }
+
+    fn dir_link_options(style: DirLinkStyle) -> WatchOptions {
+        WatchOptions {
+            windows_dir_link_style: Some(style),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn directory_is_linked_with_junction() {
+        let watch = TestWatch::new(dir_link_options(DirLinkStyle::Junction));
+        watch.fs.add_file(src("dir/a.txt"), "hello");
+        LinkProcessor::deal_create(&watch.ctx(), CreateKind::Folder, &src("dir"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::Junction(src("dir"), dst("dir"))]);
+        assert_eq!(watch.fs.read(dst("dir/a.txt")), Some(b"hello".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn directory_symlink_without_privilege_falls_back_to_junction() {
+        let watch = TestWatch::new(dir_link_options(DirLinkStyle::Symlink));
+        watch.fs.add_dir(src("dir"));
+        #[cfg(windows)]
+        watch.fs.fail_os("symlink_dir", 1314);
+        #[cfg(not(windows))]
+        watch
+            .fs
+            .fail("symlink_dir", std::io::ErrorKind::PermissionDenied);
+        LinkProcessor::deal_create(&watch.ctx(), CreateKind::Folder, &src("dir"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::Junction(src("dir"), dst("dir"))]);
+    }
+
+    #[tokio::test]
+    async fn files_inside_linked_directory_are_skipped() {
+        let watch = TestWatch::new(dir_link_options(DirLinkStyle::Junction));
+        watch.fs.add_file(src("dir/a.txt"), "hello");
+        watch.fs.add_symlink(dst("dir"), src("dir"), true);
+        LinkProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("dir/a.txt"))
+            .await
+            .unwrap();
+        assert!(watch.fs.ops().is_empty());
+    }
+
+    #[tokio::test]
+    async fn removing_directory_removes_only_the_link() {
+        let watch = TestWatch::new(dir_link_options(DirLinkStyle::Junction));
+        watch.fs.add_file(src("dir/a.txt"), "hello");
+        watch.fs.add_symlink(dst("dir"), src("dir"), true);
+        LinkProcessor::deal_remove(&watch.ctx(), RemoveKind::Folder, &src("dir"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::RemoveDir(dst("dir"))]);
+        assert!(watch.fs.exists(&src("dir/a.txt")));
+    }
+
+    #[cfg(windows)]
+    #[tokio::test]
+    async fn junction_is_created_and_removed_on_disk() {
+        use crate::fs_ops::TokioFs;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("src"), dir.path().join("dst"));
+        std::fs::create_dir_all(from.join("dir")).unwrap();
+        std::fs::create_dir_all(&to).unwrap();
+        std::fs::write(from.join("dir/a.txt"), "hello").unwrap();
+        let watch = TestWatch::with_fs(
+            TokioFs,
+            &from,
+            &to,
+            dir_link_options(DirLinkStyle::Junction),
+        );
+
+        let (source, target) = (from.join("dir"), to.join("dir"));
+        LinkProcessor::deal_create(&watch.ctx(), CreateKind::Folder, &source)
+            .await
+            .unwrap();
+        assert!(junction::exists(&target).unwrap());
+        assert_eq!(std::fs::read(target.join("a.txt")).unwrap(), b"hello");
+
+        LinkProcessor::deal_remove(&watch.ctx(), RemoveKind::Folder, &source)
+            .await
+            .unwrap();
+        assert!(!target.exists() && !target.is_symlink());
+        assert!(from.join("dir/a.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn remove_deletes_link() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_symlink(dst("a.txt"), src("a.txt"), false);
+        LinkProcessor::deal_remove(&watch.ctx(), RemoveKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::RemoveFile(dst("a.txt"))]);
+        assert!(!watch.fs.is_symlink(&dst("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn directory_turned_file_is_linked_after_removing_directory() {
+        let watch = TestWatch::new(WatchOptions {
+            dir_conflict: crate::config::DirConflict::Remove,
+            ..Default::default()
+        });
+        watch.fs.add_file(dst("x/old.txt"), "old");
+        watch.fs.add_file(src("x"), "file");
+        LinkProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("x"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::RemoveFile(dst("x/old.txt")),
+                Op::RemoveDir(dst("x")),
+                Op::SymlinkFile(src("x"), dst("x")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn file_turned_directory_is_linked_after_removing_file() {
+        let watch = TestWatch::new(WatchOptions {
+            dir_conflict: crate::config::DirConflict::Remove,
+            ..dir_link_options(DirLinkStyle::Junction)
+        });
+        watch.fs.add_file(dst("dir"), "file");
+        watch.fs.add_file(src("dir/a.txt"), "a");
+        LinkProcessor::deal_create(&watch.ctx(), CreateKind::Folder, &src("dir"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::RemoveFile(dst("dir")),
+                Op::Junction(src("dir"), dst("dir"))
+            ]
+        );
+        assert_eq!(watch.fs.read(dst("dir/a.txt")), Some(b"a".to_vec()));
+    }
 }
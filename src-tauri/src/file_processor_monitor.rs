@@ -0,0 +1,279 @@
+//! 只校验不同步的监视：比较源文件与目标中已有的文件，报告差异而不写入目标。
+//!
+//! 每个差异发送一次 `drift-detected` 事件。实时事件和全量同步（包括定期对账）都只做比较，
+//! 全量同步结束时还会报告目标中没有对应源文件的多余文件。
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    events,
+    file_processor::{FileProcessor, ProcessContext},
+    fs_ops::FsOps,
+    walk,
+};
+
+/// 目标与源不一致的方式。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum DriftKind {
+    /// 源文件在目标中不存在。
+    Missing,
+    /// 目标中的文件没有对应的源文件。
+    Extra,
+    /// 目标文件的类型、大小或内容与源文件不同。
+    Differing,
+}
+
+/// `drift-detected` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DriftPayload {
+    pub watch_id: String,
+    /// 源路径，多余的目标文件没有源路径。
+    pub path: Option<PathBuf>,
+    pub target: PathBuf,
+    pub kind: DriftKind,
+}
+
+pub struct MonitorProcessor;
+
+impl FileProcessor for MonitorProcessor {
+    async fn deal_create<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
+        _kind: notify::event::CreateKind,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        compare(ctx, path).await
+    }
+
+    async fn deal_modify<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
+        kind: notify::event::ModifyKind,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        match kind {
+            notify::event::ModifyKind::Name(notify::event::RenameMode::From) => {
+                removed(ctx, path).await
+            }
+            notify::event::ModifyKind::Metadata(_) => Ok(()),
+            _ => compare(ctx, path).await,
+        }
+    }
+
+    async fn deal_remove<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
+        _kind: notify::event::RemoveKind,
+        path: &Path,
+    ) -> anyhow::Result<()> {
+        removed(ctx, path).await
+    }
+
+    async fn sync_file<F: FsOps>(ctx: &ProcessContext<'_, F>, path: &Path) -> anyhow::Result<()> {
+        compare(ctx, path).await
+    }
+}
+
+/// 比较源路径与其目标，不一致时报告。
+async fn compare<F: FsOps>(ctx: &ProcessContext<'_, F>, path: &Path) -> anyhow::Result<()> {
+    if let Some((target, kind)) = drift(ctx, path).await? {
+        report(ctx, Some(path), target, kind);
+    }
+    Ok(())
+}
+
+/// 源路径的目标及其与源不一致的方式，一致或源路径已不存在时返回 `None`。
+async fn drift<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &Path,
+) -> anyhow::Result<Option<(PathBuf, DriftKind)>> {
+    let target = ctx.preview_target(path)?;
+    let fs = ctx.fs;
+    let drift = if fs.is_dir(path) {
+        (!fs.is_dir(&target)).then_some(DriftKind::Missing)
+    } else if fs.is_file(path) {
+        if !fs.exists(&target) {
+            Some(DriftKind::Missing)
+        } else if !fs.is_file(&target) || fs.len(path).await? != fs.len(&target).await? {
+            Some(DriftKind::Differing)
+        } else {
+            (fs.digest(path).await? != fs.digest(&target).await?).then_some(DriftKind::Differing)
+        }
+    } else {
+        None
+    };
+    Ok(drift.map(|kind| (target, kind)))
+}
+
+/// 源路径被删除后目标仍存在时报告多余的目标。
+async fn removed<F: FsOps>(ctx: &ProcessContext<'_, F>, path: &Path) -> anyhow::Result<()> {
+    for target in remaining_targets(ctx, path)? {
+        report(ctx, Some(path), target, DriftKind::Extra);
+    }
+    Ok(())
+}
+
+/// 已删除的源路径在目标中仍然存在的对应路径。
+fn remaining_targets<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: &Path,
+) -> anyhow::Result<Vec<PathBuf>> {
+    Ok(ctx
+        .remove_targets(path)?
+        .into_iter()
+        .filter(|target| ctx.fs.exists(target) || ctx.fs.is_symlink(target))
+        .collect())
+}
+
+fn report<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    path: Option<&Path>,
+    target: PathBuf,
+    kind: DriftKind,
+) {
+    info!("目标与源不一致 ({:?}): {:?}", kind, target);
+    if let Some(progress) = ctx.progress {
+        let payload = DriftPayload {
+            watch_id: progress.watch_id.to_string(),
+            path: path.map(Path::to_path_buf),
+            target,
+            kind,
+        };
+        events::emit(progress.app, progress.watch_id, "drift-detected", payload);
+    }
+}
+
+/// 报告目标目录中不属于 `expected` 的文件，`expected` 为所有源文件对应的目标。
+///
+/// 目标中的路径同样按过滤规则筛选，因此内部文件（清单、暂存文件等）不会被视为多余。
+pub async fn report_extras<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    expected: &HashSet<PathBuf>,
+    cancel: &CancellationToken,
+) {
+    match extra_files(ctx, expected, cancel).await {
+        Ok(extras) => {
+            for extra in extras {
+                report(ctx, None, extra, DriftKind::Extra);
+            }
+        }
+        Err(e) => warn!("无法遍历目标目录 {:?}: {:?}", ctx.to, e),
+    }
+}
+
+/// 目标目录中按过滤规则应当同步、但不属于 `expected` 的文件。
+async fn extra_files<F: FsOps>(
+    ctx: &ProcessContext<'_, F>,
+    expected: &HashSet<PathBuf>,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<PathBuf>> {
+    Ok(walk::walk_files(ctx.to, ctx.filter, cancel)
+        .await?
+        .into_iter()
+        .map(|file| file.path)
+        .filter(|path| !expected.contains(path))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{CreateKind, DataChange, ModifyKind, RemoveKind};
+
+    use super::*;
+    use crate::{
+        config::WatchOptions,
+        fs_ops::TokioFs,
+        mem_fs::{dst, src, TestWatch},
+    };
+
+    #[tokio::test]
+    async fn drift_is_detected_for_missing_differing_and_matching_files() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("missing.txt"), "a");
+        watch.fs.add_file(src("size.txt"), "long");
+        watch.fs.add_file(dst("size.txt"), "short data");
+        watch.fs.add_file(src("content.txt"), "abcd");
+        watch.fs.add_file(dst("content.txt"), "abce");
+        watch.fs.add_file(src("same.txt"), "same");
+        watch.fs.add_file(dst("same.txt"), "same");
+        watch.fs.add_dir(src("dir"));
+        watch.fs.add_file(dst("dir"), "not a dir");
+        let ctx = watch.ctx();
+        for (name, expected) in [
+            ("missing.txt", Some(DriftKind::Missing)),
+            ("size.txt", Some(DriftKind::Differing)),
+            ("content.txt", Some(DriftKind::Differing)),
+            ("same.txt", None),
+            ("dir", Some(DriftKind::Missing)),
+            ("gone.txt", None),
+        ] {
+            let found = drift(&ctx, &src(name)).await.unwrap();
+            assert_eq!(found.as_ref().map(|(_, kind)| *kind), expected, "{}", name);
+            if let Some((target, _)) = found {
+                assert_eq!(target, dst(name));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn removed_source_reports_remaining_target() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(dst("a.txt"), "a");
+        let ctx = watch.ctx();
+        assert_eq!(
+            remaining_targets(&ctx, &src("a.txt")).unwrap(),
+            vec![dst("a.txt")]
+        );
+        assert!(remaining_targets(&ctx, &src("b.txt")).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn events_never_write_to_target() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("new.txt"), "new");
+        watch.fs.add_file(src("changed.txt"), "new");
+        watch.fs.add_file(dst("changed.txt"), "old");
+        watch.fs.add_file(dst("removed.txt"), "old");
+        let ctx = watch.ctx();
+        MonitorProcessor::deal_create(&ctx, CreateKind::File, &src("new.txt"))
+            .await
+            .unwrap();
+        let kind = ModifyKind::Data(DataChange::Content);
+        MonitorProcessor::deal_modify(&ctx, kind, &src("changed.txt"))
+            .await
+            .unwrap();
+        MonitorProcessor::deal_remove(&ctx, RemoveKind::File, &src("removed.txt"))
+            .await
+            .unwrap();
+        MonitorProcessor::sync_file(&ctx, &src("new.txt"))
+            .await
+            .unwrap();
+        assert!(watch.fs.ops().is_empty());
+        assert!(!watch.fs.exists(&dst("new.txt")));
+        assert_eq!(watch.fs.read(dst("changed.txt")), Some(b"old".to_vec()));
+        assert_eq!(watch.fs.read(dst("removed.txt")), Some(b"old".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn target_files_without_source_are_extra() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("src"), dir.path().join("dst"));
+        std::fs::create_dir_all(&from).unwrap();
+        std::fs::create_dir_all(to.join("sub")).unwrap();
+        for name in ["a.txt", "sub/b.txt", ".auto-copy-manifest.json"] {
+            std::fs::write(to.join(name), name).unwrap();
+        }
+        let watch = TestWatch::with_fs(TokioFs, from, to.clone(), WatchOptions::default());
+        let expected = HashSet::from([to.join("a.txt")]);
+        let extras = extra_files(&watch.ctx(), &expected, &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(extras, vec![to.join("sub/b.txt")]);
+    }
+}
@@ -0,0 +1,360 @@
+use std::{io, path};
+
+use anyhow::anyhow;
+use log::{info, warn};
+
+use crate::{
+    config::ReadOnlySource,
+    file_processor::{FileProcessor, ProcessContext},
+    file_processor_copy,
+    fs_ops::FsOps,
+    log_dedup::info_collapsed,
+};
+
+/// 移动模式：文件复制到目标后删除源文件。
+///
+/// 源文件的删除由本处理器自己产生，因此删除事件不会传播到目标。
+pub struct MoveProcessor;
+
+impl FileProcessor for MoveProcessor {
+    async fn deal_create<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
+        kind: notify::event::CreateKind,
+        path: &path::Path,
+    ) -> anyhow::Result<()> {
+        match kind {
+            notify::event::CreateKind::File | notify::event::CreateKind::Any => {
+                move_file(ctx, path).await
+            }
+            _ => {
+                info!("无处理，创建了一个目录或其他类型的文件: {:?}", path);
+                Ok(())
+            }
+        }
+    }
+
+    async fn deal_modify<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
+        kind: notify::event::ModifyKind,
+        path: &path::Path,
+    ) -> anyhow::Result<()> {
+        match kind {
+            notify::event::ModifyKind::Data(_)
+            | notify::event::ModifyKind::Any
+            | notify::event::ModifyKind::Name(notify::event::RenameMode::To) => {
+                move_file(ctx, path).await
+            }
+            _ => {
+                info!("修改事件未处理: {:?}, {:?}", kind, path);
+                Ok(())
+            }
+        }
+    }
+
+    async fn deal_remove<F: FsOps>(
+        _ctx: &ProcessContext<'_, F>,
+        _kind: notify::event::RemoveKind,
+        path: &path::Path,
+    ) -> anyhow::Result<()> {
+        info_collapsed!("移动模式下忽略删除", "移动模式下忽略删除: {:?}", path);
+        Ok(())
+    }
+
+    async fn sync_file<F: FsOps>(
+        ctx: &ProcessContext<'_, F>,
+        path: &path::Path,
+    ) -> anyhow::Result<()> {
+        move_file(ctx, path).await
+    }
+}
+
+/// 源和目标位于同一文件系统时直接重命名；否则复制文件，
+/// 开启 `verify_move` 时确认目标与源文件内容一致后再删除源文件。
+///
+/// 校验失败时保留源文件并返回错误。源文件为只读而无法删除时按 `read_only_source` 处理。
+async fn move_file<F: FsOps>(ctx: &ProcessContext<'_, F>, path: &path::Path) -> anyhow::Result<()> {
+    if !ctx.fs.is_file(path) {
+        // 同一文件的多个事件中，之前的事件已经将其移走
+        return Ok(());
+    }
+    // 开启暂存时校验的是暂存目录中的副本
+    let (target_path, renamed) = file_processor_copy::move_to_target(ctx, path).await?;
+    if renamed {
+        info_collapsed!(
+            "已成功移动文件",
+            "已成功移动文件: {:?} 到 {:?}",
+            path,
+            target_path
+        );
+        return Ok(());
+    }
+    if ctx.options.verify_move {
+        let source = ctx.fs.digest(path).await?;
+        let target = ctx.fs.digest(&target_path).await?;
+        if source != target {
+            return Err(anyhow!(
+                "目标文件 {:?} 与源文件内容不一致，已保留源文件: {:?}",
+                target_path,
+                path
+            ));
+        }
+    }
+    match ctx.fs.remove_file(path).await {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied && ctx.fs.is_readonly(path) => {
+            match ctx.options.read_only_source {
+                ReadOnlySource::Keep => {
+                    warn!("源文件为只读，已复制到目标但保留源文件: {:?}", path);
+                    return Ok(());
+                }
+                ReadOnlySource::ClearReadOnly => {
+                    ctx.fs.set_readonly(path, false).await?;
+                    ctx.fs.remove_file(path).await?;
+                }
+            }
+        }
+        Err(e) => return Err(e.into()),
+    }
+    info_collapsed!(
+        "已成功移动文件",
+        "已成功移动文件: {:?} 到 {:?}",
+        path,
+        target_path
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{CreateKind, RemoveKind};
+
+    use super::*;
+    use crate::{
+        config::WatchOptions,
+        mem_fs::{dst, src, Op, TestWatch},
+    };
+
+    #[tokio::test]
+    async fn create_copies_then_removes_source() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("sub/a.txt"), "hello");
+        MoveProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("sub/a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::CreateDirAll(dst("sub")),
+                Op::Copy(src("sub/a.txt"), dst("sub/a.txt")),
+                Op::RemoveFile(src("sub/a.txt")),
+            ]
+        );
+        assert_eq!(watch.fs.read(dst("sub/a.txt")), Some(b"hello".to_vec()));
+        assert!(!watch.fs.exists(&src("sub/a.txt")));
+    }
+
+    #[tokio::test]
+    async fn read_only_source_is_kept() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "hello");
+        watch.fs.set_readonly_flag(src("a.txt"), true);
+        MoveProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::Copy(src("a.txt"), dst("a.txt")),
+                Op::RemoveFile(src("a.txt")),
+            ]
+        );
+        assert!(watch.fs.exists(&src("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn read_only_source_is_cleared_and_removed() {
+        let options = WatchOptions {
+            read_only_source: ReadOnlySource::ClearReadOnly,
+            ..Default::default()
+        };
+        let watch = TestWatch::new(options);
+        watch.fs.add_file(src("a.txt"), "hello");
+        watch.fs.set_readonly_flag(src("a.txt"), true);
+        MoveProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::Copy(src("a.txt"), dst("a.txt")),
+                Op::RemoveFile(src("a.txt")),
+                Op::SetReadonly(src("a.txt"), false),
+                Op::RemoveFile(src("a.txt")),
+            ]
+        );
+        assert!(!watch.fs.exists(&src("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn verified_move_deletes_source() {
+        let watch = TestWatch::new(WatchOptions {
+            verify_move: true,
+            ..Default::default()
+        });
+        watch.fs.add_file(src("a.txt"), "hello");
+        MoveProcessor::sync_file(&watch.ctx(), &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(!watch.fs.exists(&src("a.txt")));
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"hello".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn failed_verification_keeps_source() {
+        let watch = TestWatch::new(WatchOptions {
+            verify_move: true,
+            ..Default::default()
+        });
+        watch.fs.add_file(src("a.txt"), "hello");
+        watch.fs.short_copies();
+        let err = MoveProcessor::sync_file(&watch.ctx(), &src("a.txt"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("已保留源文件"));
+        assert_eq!(watch.fs.ops(), vec![Op::Copy(src("a.txt"), dst("a.txt"))]);
+        assert_eq!(watch.fs.read(src("a.txt")), Some(b"hello".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn unverified_move_deletes_source_after_short_copy() {
+        let watch = TestWatch::new(WatchOptions {
+            verify_move: false,
+            ..Default::default()
+        });
+        watch.fs.add_file(src("a.txt"), "hello");
+        watch.fs.short_copies();
+        MoveProcessor::sync_file(&watch.ctx(), &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(!watch.fs.exists(&src("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn already_moved_source_is_ignored() {
+        let watch = TestWatch::new(WatchOptions::default());
+        MoveProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(watch.fs.ops().is_empty());
+    }
+
+    #[tokio::test]
+    async fn remove_is_not_propagated() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(dst("a.txt"), "moved");
+        MoveProcessor::deal_remove(&watch.ctx(), RemoveKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(watch.fs.ops().is_empty());
+        assert!(watch.fs.exists(&dst("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn same_filesystem_move_renames_source() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.state.set_same_filesystem(true);
+        watch.fs.add_file(src("a.txt"), "hello");
+        MoveProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.ops(), vec![Op::Rename(src("a.txt"), dst("a.txt"))]);
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"hello".to_vec()));
+        assert!(!watch.fs.exists(&src("a.txt")));
+    }
+
+    #[tokio::test]
+    async fn cross_filesystem_move_copies_then_removes() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.state.set_same_filesystem(false);
+        watch.fs.add_file(src("a.txt"), "hello");
+        MoveProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(
+            watch.fs.ops(),
+            vec![
+                Op::Copy(src("a.txt"), dst("a.txt")),
+                Op::RemoveFile(src("a.txt")),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_rename_falls_back_to_copy() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.state.set_same_filesystem(true);
+        watch.fs.add_file(src("a.txt"), "hello");
+        watch.fs.fail("rename", std::io::ErrorKind::CrossesDevices);
+        MoveProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap();
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"hello".to_vec()));
+        assert!(!watch.fs.exists(&src("a.txt")));
+        assert!(watch
+            .fs
+            .ops()
+            .contains(&Op::Copy(src("a.txt"), dst("a.txt"))));
+    }
+
+    #[tokio::test]
+    async fn permission_error_on_writable_source_is_reported() {
+        let watch = TestWatch::new(WatchOptions {
+            read_only_source: ReadOnlySource::ClearReadOnly,
+            ..Default::default()
+        });
+        watch.fs.add_file(src("a.txt"), "hello");
+        watch
+            .fs
+            .fail("remove_file", io::ErrorKind::PermissionDenied);
+        let err = MoveProcessor::deal_create(&watch.ctx(), CreateKind::File, &src("a.txt"))
+            .await
+            .unwrap_err();
+        assert!(err
+            .downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::PermissionDenied));
+        assert!(!watch
+            .fs
+            .ops()
+            .iter()
+            .any(|op| matches!(op, Op::SetReadonly(..))));
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"hello".to_vec()));
+    }
+
+    #[cfg(windows)]
+    #[tokio::test]
+    async fn read_only_source_on_disk_is_kept_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("src"), dir.path().join("dst"));
+        std::fs::create_dir_all(&from).unwrap();
+        std::fs::create_dir_all(&to).unwrap();
+        let path = from.join("a.txt");
+        std::fs::write(&path, "hello").unwrap();
+        let mut permissions = std::fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&path, permissions).unwrap();
+
+        let watch = TestWatch::with_fs(
+            crate::fs_ops::TokioFs,
+            from,
+            to.clone(),
+            WatchOptions::default(),
+        );
+        MoveProcessor::deal_create(&watch.ctx(), CreateKind::File, &path)
+            .await
+            .unwrap();
+        assert!(path.exists());
+        assert_eq!(std::fs::read(to.join("a.txt")).unwrap(), b"hello");
+        // 清除只读属性，以便删除临时目录
+        watch.fs.set_readonly(&path, false).await.unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+use std::path::Path;
+
+use anyhow::Context;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::content_filter::{ContentFilter, ContentPredicate};
+
+/// 暂存目录、清单文件等应用内部文件名称的共同前缀。
+pub(crate) const INTERNAL_PREFIX: &str = ".auto-copy-";
+
+/// 编辑器、下载工具等常见的临时文件名，按文件名匹配。
+const DEFAULT_TEMP_FILES: &[&str] = &[
+    ".goutputstream-*",
+    "~$*",
+    ".~lock.*#",
+    ".#*",
+    "#*#",
+    "*~",
+    "*.swp",
+    "*.swx",
+    "*.tmp",
+    "*.crdownload",
+    "*.part",
+    "*.partial",
+    "*.download",
+];
+
+/// 用户配置的包含/排除规则，使用相对于源目录的 glob 模式。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Filters {
+    /// 为空时包含所有文件。
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// 不同步的临时文件，只匹配文件名。默认包含常见的编辑器和下载临时文件，设为空列表可关闭。
+    pub temp_files: Vec<String>,
+    /// 按文件开头的内容筛选文件，为空时不检查内容。
+    pub content: Option<ContentPredicate>,
+}
+
+impl Default for Filters {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            temp_files: DEFAULT_TEMP_FILES.iter().map(|s| s.to_string()).collect(),
+            content: None,
+        }
+    }
+}
+
+/// 编译后的 [`Filters`]。
+#[derive(Clone)]
+pub struct PathFilter {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+    temp_files: GlobSet,
+    content: Option<ContentFilter>,
+}
+
+impl PathFilter {
+    pub fn new(filters: &Filters) -> anyhow::Result<Self> {
+        let include = if filters.include.is_empty() {
+            None
+        } else {
+            Some(build_set(&filters.include)?)
+        };
+        Ok(Self {
+            include,
+            exclude: build_set(&filters.exclude)?,
+            temp_files: build_set(&filters.temp_files)?,
+            content: filters
+                .content
+                .as_ref()
+                .map(ContentFilter::new)
+                .transpose()?,
+        })
+    }
+
+    /// 路径是否被排除规则命中，用于在遍历时剪掉整个目录。
+    ///
+    /// 应用自己在目标目录中创建的暂存目录和清单文件总是被排除，
+    /// 以免目标位于源目录内时被当作源文件处理。
+    pub fn is_excluded(&self, relative: &Path) -> bool {
+        let internal = relative.components().any(|component| {
+            component
+                .as_os_str()
+                .to_str()
+                .is_some_and(|name| name.starts_with(INTERNAL_PREFIX))
+        });
+        internal || self.exclude.is_match(relative)
+    }
+
+    /// 文件名是否匹配临时文件规则。
+    pub fn is_temp_file(&self, relative: &Path) -> bool {
+        relative
+            .file_name()
+            .is_some_and(|name| self.temp_files.is_match(name))
+    }
+
+    /// 配置了内容规则时返回编译后的规则。
+    pub fn content(&self) -> Option<&ContentFilter> {
+        self.content.as_ref()
+    }
+
+    /// 文件是否应当被同步。
+    pub fn matches(&self, relative: &Path) -> bool {
+        !self.is_excluded(relative)
+            && !self.is_temp_file(relative)
+            && self
+                .include
+                .as_ref()
+                .is_none_or(|include| include.is_match(relative))
+    }
+}
+
+fn build_set(patterns: &[String]) -> anyhow::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).with_context(|| format!("无效的匹配规则 '{}'", pattern))?);
+    }
+    Ok(builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(filters: Filters) -> PathFilter {
+        PathFilter::new(&filters).unwrap()
+    }
+
+    #[test]
+    fn common_editor_and_download_temp_files_are_skipped() {
+        let filter = filter(Filters::default());
+        for name in [
+            ".goutputstream-AB12CD",
+            "~$report.docx",
+            ".~lock.report.odt#",
+            ".#notes.txt",
+            "#notes.txt#",
+            "notes.txt~",
+            ".notes.txt.swp",
+            ".notes.txt.swx",
+            "data.tmp",
+            "video.mp4.crdownload",
+            "video.mp4.part",
+            "archive.zip.partial",
+            "image.png.download",
+        ] {
+            assert!(filter.is_temp_file(Path::new(name)), "{}", name);
+            assert!(!filter.matches(&Path::new("sub").join(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn ordinary_files_are_not_temp_files() {
+        let filter = filter(Filters::default());
+        for name in [
+            "report.docx",
+            "notes.txt",
+            "party.mp4",
+            "tmp/a.txt",
+            "a.tmp/b.txt",
+        ] {
+            assert!(!filter.is_temp_file(Path::new(name)), "{}", name);
+            assert!(filter.matches(Path::new(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    fn temp_file_list_can_be_overridden() {
+        let custom = filter(Filters {
+            temp_files: vec!["*.bak".to_string()],
+            ..Default::default()
+        });
+        assert!(!custom.matches(Path::new("a.bak")));
+        assert!(custom.matches(Path::new("a.tmp")));
+
+        let disabled = filter(Filters {
+            temp_files: Vec::new(),
+            ..Default::default()
+        });
+        assert!(disabled.matches(Path::new("a.tmp")));
+        assert!(disabled.matches(Path::new("~$report.docx")));
+    }
+
+    #[test]
+    fn temp_files_are_checked_separately_from_user_globs() {
+        let filter = filter(Filters {
+            include: vec!["**/*.tmp".to_string()],
+            ..Default::default()
+        });
+        assert!(!filter.matches(Path::new("a.tmp")));
+        assert!(!filter.is_excluded(Path::new("a.tmp")));
+    }
+}
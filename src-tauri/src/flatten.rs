@@ -0,0 +1,135 @@
+//! 扁平化目标目录时的文件名分配。
+//!
+//! 扁平化后不同子目录中的同名文件会落到同一个目标文件名上，
+//! 后出现的源文件会得到 `名称 (n).扩展名` 形式的新名称。
+//! 分配结果只保存在内存中：删除源文件时只删除本次运行中分配给它的目标文件；
+//! 对本次运行中未见过的源文件，删除无法确定对应的目标，因此不会传播。
+//! 重启后目标中已有的同名文件视为自己上次的产物，直接覆盖。
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+};
+
+#[derive(Default)]
+pub struct FlatNames {
+    /// 相对源路径 -> 目标文件名
+    by_source: HashMap<PathBuf, OsString>,
+    claimed: HashSet<OsString>,
+}
+
+impl FlatNames {
+    /// 返回分配给源文件的目标文件名，首次出现时以 `file_name` 为基础分配一个未被占用的名称。
+    pub fn assign(&mut self, relative: &Path, file_name: &OsStr) -> OsString {
+        if let Some(name) = self.by_source.get(relative) {
+            return name.clone();
+        }
+        let name = (0..)
+            .map(|n| numbered(file_name, n))
+            .find(|name| !self.claimed.contains(name))
+            .expect("无限序列中总能找到未占用的名称");
+        self.claimed.insert(name.clone());
+        self.by_source.insert(relative.to_path_buf(), name.clone());
+        name
+    }
+
+    /// 返回 [`assign`](Self::assign) 会分配的名称，但不占用该名称。
+    pub fn peek(&self, relative: &Path, file_name: &OsStr) -> OsString {
+        if let Some(name) = self.by_source.get(relative) {
+            return name.clone();
+        }
+        (0..)
+            .map(|n| numbered(file_name, n))
+            .find(|name| !self.claimed.contains(name))
+            .expect("无限序列中总能找到未占用的名称")
+    }
+
+    /// 释放源路径本身及其下所有文件的名称，返回这些名称。
+    pub fn release(&mut self, relative: &Path) -> Vec<OsString> {
+        let released: Vec<PathBuf> = self
+            .by_source
+            .keys()
+            .filter(|source| source.starts_with(relative))
+            .cloned()
+            .collect();
+        released
+            .into_iter()
+            .filter_map(|source| self.by_source.remove(&source))
+            .inspect(|name| {
+                self.claimed.remove(name);
+            })
+            .collect()
+    }
+}
+
+/// `n` 为 0 时返回原名，否则在扩展名前插入 ` (n)`。
+pub(crate) fn numbered(file_name: &OsStr, n: usize) -> OsString {
+    if n == 0 {
+        return file_name.to_os_string();
+    }
+    let path = Path::new(file_name);
+    let mut name = path.file_stem().unwrap_or(file_name).to_os_string();
+    name.push(format!(" ({})", n));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbered_inserts_counter_before_extension() {
+        let name = OsStr::new("report.tar.gz");
+        assert_eq!(numbered(name, 0), "report.tar.gz");
+        assert_eq!(numbered(name, 2), "report.tar (2).gz");
+        assert_eq!(numbered(OsStr::new("README"), 1), "README (1)");
+    }
+
+    #[test]
+    fn colliding_names_get_numbered() {
+        let mut names = FlatNames::default();
+        let name = OsStr::new("app.log");
+        assert_eq!(names.assign(Path::new("a/app.log"), name), "app.log");
+        assert_eq!(names.assign(Path::new("b/app.log"), name), "app (1).log");
+        assert_eq!(names.assign(Path::new("c/app.log"), name), "app (2).log");
+        // 同一源文件再次出现时沿用已分配的名称
+        assert_eq!(names.assign(Path::new("b/app.log"), name), "app (1).log");
+    }
+
+    #[test]
+    fn peek_does_not_claim() {
+        let mut names = FlatNames::default();
+        let name = OsStr::new("app.log");
+        names.assign(Path::new("a/app.log"), name);
+        assert_eq!(names.peek(Path::new("b/app.log"), name), "app (1).log");
+        assert_eq!(names.peek(Path::new("b/app.log"), name), "app (1).log");
+        assert_eq!(names.assign(Path::new("c/app.log"), name), "app (1).log");
+    }
+
+    #[test]
+    fn released_names_are_reused() {
+        let mut names = FlatNames::default();
+        let name = OsStr::new("app.log");
+        names.assign(Path::new("a/app.log"), name);
+        names.assign(Path::new("b/app.log"), name);
+        assert_eq!(names.release(Path::new("a/app.log")), vec!["app.log"]);
+        assert_eq!(names.assign(Path::new("c/app.log"), name), "app.log");
+    }
+
+    #[test]
+    fn releasing_directory_releases_its_files() {
+        let mut names = FlatNames::default();
+        names.assign(Path::new("a/x/one.txt"), OsStr::new("one.txt"));
+        names.assign(Path::new("a/two.txt"), OsStr::new("two.txt"));
+        names.assign(Path::new("b/three.txt"), OsStr::new("three.txt"));
+        let mut released = names.release(Path::new("a"));
+        released.sort();
+        assert_eq!(released, vec!["one.txt", "two.txt"]);
+        assert!(names.release(Path::new("missing")).is_empty());
+    }
+}
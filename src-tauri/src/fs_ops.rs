@@ -0,0 +1,898 @@
+use std::{
+    borrow::Cow,
+    future::Future,
+    hash::{DefaultHasher, Hasher},
+    io,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+use tokio::{
+    fs::File,
+    io::{
+        AsyncBufRead, AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter,
+        ReadBuf, SeekFrom,
+    },
+};
+
+/// 文件内容的摘要，用于确认复制结果与源文件一致。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Digest {
+    pub len: u64,
+    pub hash: u64,
+}
+
+/// 处理器访问文件系统的抽象层。
+///
+/// 处理器只通过此 trait 操作文件系统，真实运行时使用 [`TokioFs`]，
+/// 也可以注入其他实现（例如内存实现）来断言处理器发出的操作而无需触碰磁盘。
+pub trait FsOps: Send + Sync {
+    fn create_dir_all(&self, path: &Path) -> impl Future<Output = io::Result<()>> + Send;
+    /// 复制文件内容，每写入一块后以已复制的字节数调用 `progress`。
+    ///
+    /// `progress` 返回 `Break` 时中止复制并删除不完整的目标文件，返回 `Interrupted` 错误。
+    fn copy(
+        &self,
+        from: &Path,
+        to: &Path,
+        buffer_size: usize,
+        progress: &mut (dyn FnMut(u64) -> ControlFlow<()> + Send),
+    ) -> impl Future<Output = io::Result<u64>> + Send;
+    /// 就地更新已存在的目标文件：按 `block_size` 分块比较源文件与目标文件，只写入内容不同的块，
+    /// 再把目标截断到源文件的长度。每比较一块后以已处理的字节数调用 `progress`。
+    ///
+    /// 返回实际写入的字节数。出错或 `progress` 返回 `Break` 时中止并删除已部分更新的目标文件。
+    fn delta_copy(
+        &self,
+        from: &Path,
+        to: &Path,
+        block_size: usize,
+        progress: &mut (dyn FnMut(u64) -> ControlFlow<()> + Send),
+    ) -> impl Future<Output = io::Result<u64>> + Send;
+    /// 以写时复制方式克隆文件，`to` 已存在时被替换。
+    ///
+    /// 平台或文件系统不支持时返回错误，可用 [`is_reflink_unsupported`] 判断。
+    fn reflink(&self, from: &Path, to: &Path) -> impl Future<Output = io::Result<()>> + Send;
+    fn remove_file(&self, path: &Path) -> impl Future<Output = io::Result<()>> + Send;
+    /// 将文件或目录移到系统回收站。
+    fn trash(&self, path: &Path) -> impl Future<Output = io::Result<()>> + Send;
+    fn rename(&self, from: &Path, to: &Path) -> impl Future<Output = io::Result<()>> + Send;
+    fn hard_link(
+        &self,
+        original: &Path,
+        link: &Path,
+    ) -> impl Future<Output = io::Result<()>> + Send;
+    /// 创建与 `like` 权限相同的命名管道，非 Unix 平台返回 `Unsupported`。
+    fn create_fifo(&self, like: &Path, path: &Path) -> impl Future<Output = io::Result<()>> + Send;
+    /// 删除空目录或目录链接本身（不影响链接指向的内容）。
+    fn remove_dir(&self, path: &Path) -> impl Future<Output = io::Result<()>> + Send;
+    fn symlink_file(
+        &self,
+        original: &Path,
+        link: &Path,
+    ) -> impl Future<Output = io::Result<()>> + Send;
+    fn symlink_dir(
+        &self,
+        original: &Path,
+        link: &Path,
+    ) -> impl Future<Output = io::Result<()>> + Send;
+    /// 创建 Windows 目录联接，其他平台返回 `Unsupported`。
+    fn junction(&self, original: &Path, link: &Path)
+        -> impl Future<Output = io::Result<()>> + Send;
+    /// 设置或清除文件的只读属性。
+    fn set_readonly(
+        &self,
+        path: &Path,
+        readonly: bool,
+    ) -> impl Future<Output = io::Result<()>> + Send;
+    fn read_link(&self, path: &Path) -> impl Future<Output = io::Result<PathBuf>> + Send;
+    /// 列出目录中的直接子项。
+    fn read_dir(&self, path: &Path) -> impl Future<Output = io::Result<Vec<PathBuf>>> + Send;
+    /// 读取整个文件计算摘要。
+    fn digest(&self, path: &Path) -> impl Future<Output = io::Result<Digest>> + Send;
+    /// 读取文件开头最多 `limit` 字节。
+    fn read_head(
+        &self,
+        path: &Path,
+        limit: u64,
+    ) -> impl Future<Output = io::Result<Vec<u8>>> + Send;
+    /// 文件的大小（字节）。
+    fn len(&self, path: &Path) -> impl Future<Output = io::Result<u64>> + Send;
+    /// 文件的最后修改时间。
+    fn modified(&self, path: &Path) -> impl Future<Output = io::Result<SystemTime>> + Send;
+
+    fn exists(&self, path: &Path) -> bool;
+    fn is_file(&self, path: &Path) -> bool;
+    fn is_dir(&self, path: &Path) -> bool;
+    fn is_symlink(&self, path: &Path) -> bool;
+    /// 文件是否只读，无法读取元数据时返回 `false`。
+    fn is_readonly(&self, path: &Path) -> bool;
+    /// 路径所在文件系统的标识，见 [`volume_id`]。
+    fn volume_id(&self, path: &Path) -> io::Result<u64>;
+}
+
+/// 读满 `buffer`，到达文件末尾时返回实际读到的字节数。
+async fn read_block<R: AsyncRead + Unpin>(file: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..]).await? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
+/// 在 `copy_buf` 每消费一块数据后报告已复制的字节数，`progress` 要求中止时让下一次读取失败。
+struct Progressed<'a, R> {
+    inner: R,
+    copied: u64,
+    cancelled: bool,
+    progress: &'a mut (dyn FnMut(u64) -> ControlFlow<()> + Send),
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncRead for Progressed<'_, R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let available = std::task::ready!(self.as_mut().poll_fill_buf(cx))?;
+        let len = available.len().min(buf.remaining());
+        buf.put_slice(&available[..len]);
+        self.consume(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> AsyncBufRead for Progressed<'_, R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.cancelled {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "复制已取消",
+            )));
+        }
+        Pin::new(&mut this.inner).poll_fill_buf(cx)
+    }
+
+    fn consume(mut self: Pin<&mut Self>, amt: usize) {
+        Pin::new(&mut self.inner).consume(amt);
+        if amt == 0 {
+            return;
+        }
+        self.copied += amt as u64;
+        let copied = self.copied;
+        if (self.progress)(copied).is_break() {
+            self.cancelled = true;
+        }
+    }
+}
+
+/// 按块比较 `source` 与已存在的 `to`，只写入不同的块，见 [`FsOps::delta_copy`]。
+///
+/// 出错或被取消时目标已部分更新，删除目标文件而不是留下新旧混合的内容。
+async fn patch_in_place<R: AsyncRead + Unpin>(
+    mut source: R,
+    to: &Path,
+    permissions: std::fs::Permissions,
+    block_size: usize,
+    progress: &mut (dyn FnMut(u64) -> ControlFlow<()> + Send),
+) -> io::Result<u64> {
+    let mut target = tokio::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(to)
+        .await
+        .map_err(|e| explain(to, e))?;
+    let block_size = block_size.max(1);
+    let result = async {
+        let (mut wanted, mut existing) = (vec![0; block_size], vec![0; block_size]);
+        let (mut offset, mut written) = (0, 0);
+        loop {
+            let read = read_block(&mut source, &mut wanted).await?;
+            if read == 0 {
+                break;
+            }
+            let current = read_block(&mut target, &mut existing[..read]).await?;
+            if current != read || wanted[..read] != existing[..read] {
+                target.seek(SeekFrom::Start(offset)).await?;
+                target.write_all(&wanted[..read]).await?;
+                written += read as u64;
+            }
+            offset += read as u64;
+            if progress(offset).is_break() {
+                return Err(io::Error::new(io::ErrorKind::Interrupted, "复制已取消"));
+            }
+        }
+        target.set_len(offset).await?;
+        target.flush().await?;
+        tokio::fs::set_permissions(to, permissions).await?;
+        Ok(written)
+    }
+    .await;
+    if result.is_err() {
+        drop(target);
+        let _ = tokio::fs::remove_file(to).await;
+    }
+    result
+}
+
+/// 基于 `tokio::fs` 的真实文件系统实现。
+///
+/// 所有可能耗时的操作都通过 `tokio::fs` 或 `spawn_blocking`
+/// 在阻塞线程池中执行，不占用运行事件循环的异步工作线程。
+/// `exists` 等同步探测只是一次 `stat`，保持同步以便在条件表达式中使用。
+pub struct TokioFs;
+
+impl FsOps for TokioFs {
+    async fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let path = long_path(path);
+        tokio::fs::create_dir_all(&path)
+            .await
+            .map_err(|e| explain(&path, e))
+    }
+
+    /// 通过指定容量的 `BufReader`/`BufWriter` 以 `copy_buf` 复制文件内容，
+    /// 并像 `tokio::fs::copy` 一样复制权限。
+    ///
+    /// 复制失败或被取消时删除写了一半的目标文件。
+    async fn copy(
+        &self,
+        from: &Path,
+        to: &Path,
+        buffer_size: usize,
+        progress: &mut (dyn FnMut(u64) -> ControlFlow<()> + Send),
+    ) -> io::Result<u64> {
+        let (from, to) = (long_path(from), long_path(to));
+        let source = File::open(&from).await.map_err(|e| explain(&from, e))?;
+        let permissions = source.metadata().await?.permissions();
+        let target = File::create(&to).await.map_err(|e| explain(&to, e))?;
+        let buffer_size = buffer_size.max(1);
+        let mut reader = Progressed {
+            inner: BufReader::with_capacity(buffer_size, source),
+            copied: 0,
+            cancelled: false,
+            progress,
+        };
+        let mut writer = BufWriter::with_capacity(buffer_size, target);
+        let result = async {
+            let copied = tokio::io::copy_buf(&mut reader, &mut writer).await?;
+            writer.flush().await?;
+            tokio::fs::set_permissions(&to, permissions).await?;
+            Ok(copied)
+        }
+        .await;
+        if result.is_err() {
+            drop(writer);
+            let _ = tokio::fs::remove_file(&to).await;
+        }
+        result
+    }
+
+    async fn delta_copy(
+        &self,
+        from: &Path,
+        to: &Path,
+        block_size: usize,
+        progress: &mut (dyn FnMut(u64) -> ControlFlow<()> + Send),
+    ) -> io::Result<u64> {
+        let (from, to) = (long_path(from), long_path(to));
+        let source = File::open(&from).await.map_err(|e| explain(&from, e))?;
+        let permissions = source.metadata().await?.permissions();
+        patch_in_place(source, &to, permissions, block_size, progress).await
+    }
+
+    async fn reflink(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (long_path(from).into_owned(), long_path(to).into_owned());
+        tokio::task::spawn_blocking(move || clone_file(&from, &to).map_err(|e| explain(&to, e)))
+            .await?
+    }
+
+    async fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let path = long_path(path);
+        tokio::fs::remove_file(&path)
+            .await
+            .map_err(|e| explain(&path, e))
+    }
+
+    async fn trash(&self, path: &Path) -> io::Result<()> {
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        {
+            let path = path.to_path_buf();
+            tokio::task::spawn_blocking(move || trash::delete(path).map_err(io::Error::other))
+                .await?
+        }
+        #[cfg(any(target_os = "android", target_os = "ios"))]
+        {
+            let _ = path;
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "当前平台不支持回收站",
+            ))
+        }
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (long_path(from), long_path(to));
+        tokio::fs::rename(&from, &to)
+            .await
+            .map_err(|e| explain(&to, e))
+    }
+
+    async fn hard_link(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let (original, link) = (long_path(original), long_path(link));
+        tokio::fs::hard_link(&original, &link)
+            .await
+            .map_err(|e| explain(&link, e))
+    }
+
+    async fn create_fifo(&self, like: &Path, path: &Path) -> io::Result<()> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::{ffi::OsStrExt, fs::PermissionsExt};
+
+            let mode = tokio::fs::metadata(like).await?.permissions().mode() & 0o7777;
+            let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            // SAFETY: `c_path` 是以 0 结尾的有效字符串。
+            if unsafe { libc::mkfifo(c_path.as_ptr(), mode as libc::mode_t) } != 0 {
+                return Err(explain(path, io::Error::last_os_error()));
+            }
+            Ok(())
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (like, path);
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "当前平台不支持命名管道",
+            ))
+        }
+    }
+
+    async fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let path = long_path(path);
+        tokio::fs::remove_dir(&path)
+            .await
+            .map_err(|e| explain(&path, e))
+    }
+
+    // 链接指向的路径原样写入链接，只为链接本身的路径添加前缀
+    async fn symlink_file(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let link = long_path(link);
+        tokio::fs::symlink_file(original, &link)
+            .await
+            .map_err(|e| explain(&link, e))
+    }
+
+    async fn symlink_dir(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let link = long_path(link);
+        tokio::fs::symlink_dir(original, &link)
+            .await
+            .map_err(|e| explain(&link, e))
+    }
+
+    async fn junction(&self, original: &Path, link: &Path) -> io::Result<()> {
+        #[cfg(windows)]
+        {
+            let (original, link) = (original.to_path_buf(), long_path(link).into_owned());
+            tokio::task::spawn_blocking(move || {
+                junction::create(original, &link).map_err(|e| explain(&link, e))
+            })
+            .await?
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = (original, link);
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "目录联接仅在 Windows 上可用",
+            ))
+        }
+    }
+
+    async fn set_readonly(&self, path: &Path, readonly: bool) -> io::Result<()> {
+        let path = long_path(path);
+        let mut permissions = tokio::fs::metadata(&path).await?.permissions();
+        if permissions.readonly() != readonly {
+            // 在 Unix 上这会清除/设置所有写权限位，对镜像文件而言足够
+            permissions.set_readonly(readonly);
+            tokio::fs::set_permissions(&path, permissions).await?;
+        }
+        Ok(())
+    }
+
+    async fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        tokio::fs::read_link(long_path(path)).await
+    }
+
+    async fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut entries = tokio::fs::read_dir(long_path(path))
+            .await
+            .map_err(|e| explain(path, e))?;
+        let mut paths = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            paths.push(path.join(entry.file_name()));
+        }
+        Ok(paths)
+    }
+
+    async fn digest(&self, path: &Path) -> io::Result<Digest> {
+        let path = long_path(path);
+        let mut file = File::open(&path).await.map_err(|e| explain(&path, e))?;
+        // 摘要只在同一进程内比较，因此不要求哈希算法跨版本稳定
+        let mut hasher = DefaultHasher::new();
+        let mut buffer = vec![0; 64 * 1024];
+        let mut len = 0;
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            hasher.write(&buffer[..read]);
+            len += read as u64;
+        }
+        Ok(Digest {
+            len,
+            hash: hasher.finish(),
+        })
+    }
+
+    async fn read_head(&self, path: &Path, limit: u64) -> io::Result<Vec<u8>> {
+        let path = long_path(path);
+        let file = File::open(&path).await.map_err(|e| explain(&path, e))?;
+        let mut head = Vec::new();
+        file.take(limit).read_to_end(&mut head).await?;
+        Ok(head)
+    }
+
+    async fn len(&self, path: &Path) -> io::Result<u64> {
+        Ok(tokio::fs::metadata(long_path(path)).await?.len())
+    }
+
+    async fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        tokio::fs::metadata(long_path(path)).await?.modified()
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        long_path(path).exists()
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        long_path(path).is_file()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        long_path(path).is_dir()
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        long_path(path).is_symlink()
+    }
+
+    fn is_readonly(&self, path: &Path) -> bool {
+        std::fs::metadata(long_path(path)).is_ok_and(|metadata| metadata.permissions().readonly())
+    }
+
+    fn volume_id(&self, path: &Path) -> io::Result<u64> {
+        volume_id(path)
+    }
+}
+
+/// 通过 `FICLONE` 让目标共享源文件的数据块，Btrfs、XFS 等写时复制文件系统支持。
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn clone_file(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let src = std::fs::File::open(from)?;
+    let dst = std::fs::File::create(to)?;
+    // SAFETY: 两个文件描述符在调用期间都保持打开
+    if unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) } == 0 {
+        return Ok(());
+    }
+    let err = io::Error::last_os_error();
+    drop(dst);
+    let _ = std::fs::remove_file(to);
+    Err(err)
+}
+
+/// 通过 `clonefile` 克隆文件，APFS 支持。`clonefile` 要求目标不存在。
+#[cfg(target_os = "macos")]
+fn clone_file(from: &Path, to: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let src = CString::new(from.as_os_str().as_bytes())?;
+    let dst = CString::new(to.as_os_str().as_bytes())?;
+    match std::fs::remove_file(to) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    // SAFETY: 两个参数都是以 NUL 结尾的有效字符串
+    if unsafe { libc::clonefile(src.as_ptr(), dst.as_ptr(), 0) } == 0 {
+        return Ok(());
+    }
+    Err(io::Error::last_os_error())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn clone_file(from: &Path, to: &Path) -> io::Result<()> {
+    let _ = (from, to);
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "当前平台不支持写时复制",
+    ))
+}
+
+/// 路径（跟随软链接）是否为命名管道，非 Unix 平台总是返回 `false`。
+pub fn is_fifo(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::FileTypeExt;
+
+        std::fs::metadata(path).is_ok_and(|metadata| metadata.file_type().is_fifo())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// 两个已存在的路径是否位于同一文件系统。
+pub fn same_filesystem(a: &Path, b: &Path) -> io::Result<bool> {
+    Ok(volume_id(a)? == volume_id(b)?)
+}
+
+/// 已存在的路径所在文件系统的标识：Unix 上为设备号，Windows 上由所在卷的挂载路径得出。
+pub fn volume_id(path: &Path) -> io::Result<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        Ok(std::fs::metadata(path)?.dev())
+    }
+    #[cfg(windows)]
+    {
+        let volume = volume_path(path)?;
+        let mut hasher = DefaultHasher::new();
+        hasher.write(volume.to_string_lossy().to_ascii_lowercase().as_bytes());
+        Ok(hasher.finish())
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        let _ = path;
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "当前平台无法判断路径所在的文件系统",
+        ))
+    }
+}
+
+/// 路径所在卷的挂载路径（如 `C:\` 或挂载到文件夹的卷的路径）。
+#[cfg(windows)]
+fn volume_path(path: &Path) -> io::Result<std::ffi::OsString> {
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use windows_sys::Win32::Storage::FileSystem::GetVolumePathNameW;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    let mut buf = vec![0u16; wide.len().max(MAX_PATH) + 1];
+    // SAFETY: `wide` 以 0 结尾，`buf` 的长度与传入的容量一致。
+    if unsafe { GetVolumePathNameW(wide.as_ptr(), buf.as_mut_ptr(), buf.len() as u32) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let len = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    Ok(std::ffi::OsString::from_wide(&buf[..len]))
+}
+
+/// 错误是否表示平台或文件系统不支持写时复制（包括源和目标位于不同的文件系统）。
+pub fn is_reflink_unsupported(err: &io::Error) -> bool {
+    if err.kind() == io::ErrorKind::Unsupported {
+        return true;
+    }
+    #[cfg(unix)]
+    {
+        const CODES: [i32; 5] = [
+            libc::EOPNOTSUPP,
+            libc::ENOTSUP,
+            libc::EXDEV,
+            libc::EINVAL,
+            libc::ENOTTY,
+        ];
+        err.raw_os_error().is_some_and(|code| CODES.contains(&code))
+    }
+    #[cfg(not(unix))]
+    {
+        false
+    }
+}
+
+/// 超过此长度的路径在 Windows 上需要使用扩展长度形式。
+#[cfg(windows)]
+const MAX_PATH: usize = 260;
+
+/// Windows 上为超过 `MAX_PATH` 的绝对路径添加 `\\?\` 前缀，其他情况原样返回。
+///
+/// 扩展长度路径不做任何解析，因此会先把路径重新拼接为只含反斜杠的规范形式。
+#[cfg(windows)]
+fn long_path(path: &Path) -> Cow<'_, Path> {
+    use std::path::{Component, Prefix};
+
+    if path.as_os_str().len() < MAX_PATH || !path.is_absolute() {
+        return Cow::Borrowed(path);
+    }
+    let mut components = path.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return Cow::Borrowed(path);
+    };
+    let mut extended = match prefix.kind() {
+        Prefix::Disk(letter) => PathBuf::from(format!(r"\\?\{}:\", letter as char)),
+        Prefix::UNC(server, share) => {
+            let mut unc = PathBuf::from(r"\\?\UNC\");
+            unc.push(server);
+            unc.push(share);
+            unc
+        }
+        // 已经是扩展长度路径或设备路径
+        _ => return Cow::Borrowed(path),
+    };
+    for component in components {
+        match component {
+            Component::RootDir | Component::CurDir => {}
+            Component::ParentDir => {
+                extended.pop();
+            }
+            Component::Normal(name) => extended.push(name),
+            Component::Prefix(_) => unreachable!("前缀只会出现在路径开头"),
+        }
+    }
+    Cow::Owned(extended)
+}
+
+#[cfg(not(windows))]
+fn long_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+/// 路径过长导致的失败换成带有提示的错误，其他错误原样返回。
+fn explain(path: &Path, err: io::Error) -> io::Error {
+    // ERROR_FILENAME_EXCED_RANGE
+    if cfg!(windows) && err.raw_os_error() == Some(206) {
+        return io::Error::new(
+            err.kind(),
+            format!(
+                "路径过长: {:?}。请在系统中启用长路径支持（LongPathsEnabled）后重试",
+                path
+            ),
+        );
+    }
+    err
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(windows)]
+    #[test]
+    fn short_paths_are_unchanged() {
+        let path = Path::new(r"C:\data\a.txt");
+        assert!(matches!(long_path(path), Cow::Borrowed(p) if p == path));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_disk_paths_get_extended_prefix() {
+        let name = "d".repeat(100);
+        let path = PathBuf::from(format!(r"C:\{name}\{name}\..\{name}\.\a.txt"));
+        let expected = PathBuf::from(format!(r"\\?\C:\{name}\{name}\a.txt"));
+        assert_eq!(long_path(&path), expected);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_unc_paths_get_extended_prefix() {
+        let name = "d".repeat(150);
+        let path = PathBuf::from(format!(r"\\server\share\{name}\{name}\a.txt"));
+        let expected = PathBuf::from(format!(r"\\?\UNC\server\share\{name}\{name}\a.txt"));
+        assert_eq!(long_path(&path), expected);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn extended_paths_are_unchanged() {
+        let path = PathBuf::from(format!(r"\\?\C:\{}\a.txt", "d".repeat(300)));
+        assert!(matches!(long_path(&path), Cow::Borrowed(_)));
+    }
+
+    #[cfg(windows)]
+    #[tokio::test]
+    async fn operations_work_beyond_max_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let deep = (0..6).fold(dir.path().to_path_buf(), |path, i| {
+            path.join(format!("{}{}", i, "d".repeat(60)))
+        });
+        assert!(deep.as_os_str().len() > MAX_PATH);
+        let source = dir.path().join("a.txt");
+        std::fs::write(&source, "data").unwrap();
+        let target = deep.join("a.txt");
+
+        TokioFs.create_dir_all(&deep).await.unwrap();
+        let copied = TokioFs
+            .copy(&source, &target, 1024, &mut |_| ControlFlow::Continue(()))
+            .await
+            .unwrap();
+        assert_eq!(copied, 4);
+        assert!(TokioFs.is_file(&target));
+        assert_eq!(TokioFs.len(&target).await.unwrap(), 4);
+        TokioFs.remove_file(&target).await.unwrap();
+        assert!(!TokioFs.exists(&target));
+        TokioFs.remove_dir(&deep).await.unwrap();
+        assert!(!TokioFs.exists(&deep));
+    }
+
+    /// 目标文件系统支持写时复制时克隆内容，否则返回可识别的不支持错误。
+    #[tokio::test]
+    async fn reflink_clones_or_reports_unsupported() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a.txt");
+        let target = dir.path().join("b.txt");
+        std::fs::write(&source, "data").unwrap();
+        match TokioFs.reflink(&source, &target).await {
+            Ok(()) => {
+                assert_eq!(std::fs::read(&target).unwrap(), b"data");
+                // 克隆后两个文件互不影响
+                std::fs::write(&target, "changed").unwrap();
+                assert_eq!(std::fs::read(&source).unwrap(), b"data");
+            }
+            Err(e) => {
+                assert!(is_reflink_unsupported(&e), "{:?}", e);
+                assert!(!target.exists());
+            }
+        }
+    }
+
+    #[test]
+    fn unsupported_kind_is_reflink_unsupported() {
+        let err = io::Error::from(io::ErrorKind::Unsupported);
+        assert!(is_reflink_unsupported(&err));
+        assert!(!is_reflink_unsupported(&io::Error::from(
+            io::ErrorKind::NotFound
+        )));
+    }
+
+    #[test]
+    fn paths_in_same_directory_share_a_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let a = dir.path().join("a");
+        let b = dir.path().join("b");
+        std::fs::create_dir(&a).unwrap();
+        std::fs::write(&b, "").unwrap();
+        assert!(same_filesystem(&a, &b).unwrap());
+        assert_eq!(volume_id(&a).unwrap(), volume_id(dir.path()).unwrap());
+    }
+
+    #[test]
+    fn missing_path_has_no_filesystem() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        assert!(volume_id(&missing).is_err());
+        assert!(same_filesystem(dir.path(), &missing).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn different_mounts_are_different_filesystems() {
+        let dir = tempfile::tempdir().unwrap();
+        // procfs 总是单独挂载
+        assert!(!same_filesystem(dir.path(), Path::new("/proc/self")).unwrap());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn volume_id_ignores_path_case() {
+        let dir = tempfile::tempdir().unwrap();
+        let upper = PathBuf::from(dir.path().to_string_lossy().to_uppercase());
+        assert_eq!(volume_id(dir.path()).unwrap(), volume_id(&upper).unwrap());
+    }
+
+    /// 以 `old` 为已存在的目标内容增量复制 `new`，返回写入的字节数和复制后的目标内容。
+    async fn delta_copy(old: &[u8], new: &[u8], block_size: usize) -> (u64, Vec<u8>) {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a.txt");
+        let target = dir.path().join("b.txt");
+        std::fs::write(&source, new).unwrap();
+        std::fs::write(&target, old).unwrap();
+        let written = TokioFs
+            .delta_copy(&source, &target, block_size, &mut |_| {
+                ControlFlow::Continue(())
+            })
+            .await
+            .unwrap();
+        (written, std::fs::read(&target).unwrap())
+    }
+
+    #[tokio::test]
+    async fn delta_copy_rewrites_only_changed_blocks() {
+        let old = b"aaaabbbbccccdddd";
+        let new = b"aaaabbXbccccdddd";
+        assert_eq!(delta_copy(old, new, 4).await, (4, new.to_vec()));
+        assert_eq!(delta_copy(old, old, 4).await, (0, old.to_vec()));
+    }
+
+    #[tokio::test]
+    async fn delta_copy_handles_grown_shrunk_and_emptied_files() {
+        assert_eq!(
+            delta_copy(b"aaaa", b"aaaabbbbcc", 4).await,
+            (6, b"aaaabbbbcc".to_vec())
+        );
+        assert_eq!(
+            delta_copy(b"aaaabbbbcc", b"aaaab", 4).await,
+            (0, b"aaaab".to_vec())
+        );
+        assert_eq!(delta_copy(b"aaaabbbb", b"", 4).await, (0, Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn cancelled_delta_copy_removes_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a.txt");
+        let target = dir.path().join("b.txt");
+        std::fs::write(&source, "new data").unwrap();
+        std::fs::write(&target, "old data").unwrap();
+        let err = TokioFs
+            .delta_copy(&source, &target, 4, &mut |_| ControlFlow::Break(()))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+        assert!(!target.exists());
+    }
+
+    /// 读取时总是失败的源。
+    struct FailingReader;
+
+    impl AsyncRead for FailingReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            Poll::Ready(Err(io::Error::other("模拟的读取失败")))
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_delta_copy_removes_partially_patched_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("b.txt");
+        std::fs::write(&target, "old data old data").unwrap();
+        let permissions = std::fs::metadata(&target).unwrap().permissions();
+        // 第一块写入新内容后读取源失败
+        let source = std::io::Cursor::new(b"new!".to_vec()).chain(FailingReader);
+        let err = patch_in_place(source, &target, permissions, 4, &mut |_| {
+            ControlFlow::Continue(())
+        })
+        .await
+        .unwrap_err();
+        assert_eq!(err.to_string(), "模拟的读取失败");
+        assert!(!target.exists());
+    }
+
+    #[tokio::test]
+    async fn delta_copy_onto_missing_target_fails_without_creating_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("a.txt");
+        let target = dir.path().join("b.txt");
+        std::fs::write(&source, "data").unwrap();
+        let err = TokioFs
+            .delta_copy(&source, &target, 4, &mut |_| ControlFlow::Continue(()))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(!target.exists());
+    }
+}
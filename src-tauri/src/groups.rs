@@ -0,0 +1,174 @@
+//! 监视分组：同组的监视可以一起启动、停止和暂停。
+//!
+//! 分组由监视选项中的 `group` 指定。成员第一次启动时其配置被记录下来，
+//! 停止后仍然保留，因此 `start_group` 可以重新启动整组。
+//! 停止和暂停在同一次持有监视表锁期间处理所有成员；启动时为避免与等待中监视的检查任务死锁，
+//! 路径尚不存在的成员在释放锁后才加入等待列表。
+
+use std::collections::BTreeMap;
+
+use log::{error, info};
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+
+use crate::{
+    activate_locked,
+    config::WatchConfig,
+    events::{self, IdPayload},
+    pause::PauseMode,
+    pending, tray, AppState,
+};
+
+/// 分组操作对每个成员的结果。
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupResult {
+    pub group: String,
+    pub action: &'static str,
+    pub succeeded: Vec<String>,
+    /// 监视 id -> 失败原因。
+    pub failed: BTreeMap<String, String>,
+}
+
+impl GroupResult {
+    fn new(group: &str, action: &'static str) -> Self {
+        Self {
+            group: group.to_string(),
+            action,
+            ..Default::default()
+        }
+    }
+
+    fn record(&mut self, id: &str, result: Result<(), String>) {
+        match result {
+            Ok(()) => self.succeeded.push(id.to_string()),
+            Err(e) => {
+                self.failed.insert(id.to_string(), e);
+            }
+        }
+    }
+
+    /// 记录日志并向所有窗口发送 `group-action` 事件，分组事件不受按监视 id 的订阅限制。
+    fn finish(self, app: &tauri::AppHandle) -> Self {
+        info!(
+            "分组 '{}' 执行 {}: 成功 {} 个，失败 {} 个。",
+            self.group,
+            self.action,
+            self.succeeded.len(),
+            self.failed.len()
+        );
+        if let Err(e) = app.emit("group-action", self.clone()) {
+            error!("发送事件 'group-action' 失败: {:?}", e);
+        }
+        self
+    }
+}
+
+/// 记录分组成员的配置，同 id 的旧配置被替换。
+pub async fn register(state: &AppState, config: &WatchConfig) {
+    state
+        .groups
+        .lock()
+        .await
+        .insert(config.id.clone(), config.clone());
+}
+
+/// 分组中所有成员的配置，分组不存在时返回错误。
+async fn members(state: &AppState, name: &str) -> Result<Vec<WatchConfig>, String> {
+    let members: Vec<WatchConfig> = state
+        .groups
+        .lock()
+        .await
+        .values()
+        .filter(|config| config.options.group.as_deref() == Some(name))
+        .cloned()
+        .collect();
+    if members.is_empty() {
+        return Err(format!("分组 '{}' 不存在或没有成员。", name));
+    }
+    Ok(members)
+}
+
+pub async fn start(app: &tauri::AppHandle, name: &str) -> Result<GroupResult, String> {
+    let state = app.state::<AppState>();
+    let members = members(&state, name).await?;
+    let pending_ids: Vec<String> = state.pending.lock().await.keys().cloned().collect();
+    let mut result = GroupResult::new(name, "start");
+    let mut waiting = Vec::new();
+    let mut watchers = state.watcher.lock().await;
+    for config in members {
+        let id = config.id.clone();
+        if watchers.contains_key(&id) || pending_ids.contains(&id) {
+            result.record(&id, Ok(()));
+        } else if config.options.wait_for_paths && !(config.from.exists() && config.to.exists()) {
+            waiting.push(config);
+        } else {
+            let started = activate_locked(app, config, &mut watchers)
+                .await
+                .map_err(|e| format!("{:#}", e));
+            result.record(&id, started);
+        }
+    }
+    drop(watchers);
+    for config in waiting {
+        let id = config.id.clone();
+        pending::add(app, config).await;
+        result.record(&id, Ok(()));
+    }
+    tray::refresh(app).await;
+    Ok(result.finish(app))
+}
+
+pub async fn stop(app: &tauri::AppHandle, name: &str) -> Result<GroupResult, String> {
+    let state = app.state::<AppState>();
+    let members = members(&state, name).await?;
+    let mut result = GroupResult::new(name, "stop");
+    {
+        let mut pending = state.pending.lock().await;
+        for config in &members {
+            pending.remove(&config.id);
+        }
+    }
+    let removed: Vec<_> = {
+        let mut watchers = state.watcher.lock().await;
+        members
+            .iter()
+            .filter_map(|config| watchers.remove(&config.id))
+            .collect()
+    };
+    for watch in removed {
+        let id = watch.config.id.clone();
+        watch.stop().await;
+        result.record(&id, Ok(()));
+    }
+    tray::refresh(app).await;
+    Ok(result.finish(app))
+}
+
+pub async fn pause(
+    app: &tauri::AppHandle,
+    name: &str,
+    mode: PauseMode,
+) -> Result<GroupResult, String> {
+    let state = app.state::<AppState>();
+    let members = members(&state, name).await?;
+    let mut result = GroupResult::new(name, "pause");
+    let watchers = state.watcher.lock().await;
+    for config in &members {
+        let outcome = match watchers.get(&config.id) {
+            Some(watch) => {
+                watch.state.pause(mode);
+                let payload = IdPayload {
+                    id: config.id.clone(),
+                    name: watch.config.name(),
+                };
+                events::emit(app, &config.id, "watch-paused", payload);
+                Ok(())
+            }
+            None => Err("未在监视中".to_string()),
+        };
+        result.record(&config.id, outcome);
+    }
+    drop(watchers);
+    Ok(result.finish(app))
+}
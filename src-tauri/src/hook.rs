@@ -0,0 +1,143 @@
+//! 用户脚本钩子：对每个文件事件调用外部程序，由程序决定目标路径或跳过该文件。
+//!
+//! 程序以 `<事件类型> <源路径> <默认目标路径>` 为参数运行，事件类型为 `create`、`modify`、
+//! `rename` 或 `sync`（全量同步）。标准输出的第一行：
+//! - 为空：使用默认目标路径；
+//! - `SKIP`：跳过该文件；
+//! - 其他：目标路径，相对路径相对于目标目录解析，结果必须位于目标目录内。
+//!
+//! 程序以非零状态退出或超时视为处理失败。删除事件不调用钩子，而是删除钩子上次为该文件选择的目标。
+//! 选择结果只保存在内存中，与扁平化的名称分配一样，重启后未再次出现的源文件的删除不会传播到钩子选择的目标。
+//!
+//! 安全性：钩子以运行 auto-copy 的用户的权限执行任意程序，源目录中的文件名会原样作为参数传入。
+//! 只应配置自己信任的脚本；返回的路径被限制在目标目录内，但脚本本身可以做任何事。
+
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+
+/// 每个文件事件调用的外部程序。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct EventHook {
+    pub program: PathBuf,
+    /// 等待程序退出的最长时间（秒），超时后终止程序。
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    5
+}
+
+/// 钩子对一个文件的决定。
+#[derive(Debug, PartialEq, Eq)]
+pub enum HookDecision {
+    Default,
+    Skip,
+    Target(PathBuf),
+}
+
+/// 不创建控制台窗口，避免每次调用钩子时闪出命令行窗口。
+#[cfg(windows)]
+const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+
+/// 运行钩子并解析其输出，`to` 为监视的目标目录。
+pub async fn run(
+    hook: &EventHook,
+    kind: &str,
+    source: &Path,
+    default_target: &Path,
+    to: &Path,
+) -> anyhow::Result<HookDecision> {
+    let mut command = tokio::process::Command::new(&hook.program);
+    command
+        .arg(kind)
+        .arg(source)
+        .arg(default_target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(windows)]
+    command.creation_flags(CREATE_NO_WINDOW);
+    let timeout = Duration::from_secs(hook.timeout_secs.max(1));
+    let output = tokio::time::timeout(timeout, command.output())
+        .await
+        .map_err(|_| {
+            anyhow!(
+                "钩子 {:?} 超过 {} 秒未退出，已终止",
+                hook.program,
+                timeout.as_secs()
+            )
+        })?
+        .with_context(|| format!("无法运行钩子 {:?}", hook.program))?;
+    if !output.status.success() {
+        bail!(
+            "钩子 {:?} 以 {} 退出: {}",
+            hook.program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let stdout = String::from_utf8(output.stdout).context("钩子的输出不是 UTF-8")?;
+    match stdout.lines().next().unwrap_or_default().trim() {
+        "" => Ok(HookDecision::Default),
+        "SKIP" => Ok(HookDecision::Skip),
+        line => resolve(to, line).map(HookDecision::Target),
+    }
+}
+
+/// 将钩子输出的路径解析到目标目录内。
+fn resolve(to: &Path, line: &str) -> anyhow::Result<PathBuf> {
+    let path = Path::new(line);
+    if path.components().any(|c| c == Component::ParentDir) {
+        bail!("钩子返回的路径不能包含 '..': {}", line);
+    }
+    let target = to.join(path);
+    if !target.starts_with(to) || target == to {
+        bail!("钩子返回的路径不在目标目录 {:?} 内: {}", to, line);
+    }
+    Ok(target)
+}
+
+/// 钩子为本次运行中见过的源文件选择的目标。
+#[derive(Default)]
+pub struct HookTargets {
+    /// 相对源路径 -> 目标路径
+    by_source: HashMap<PathBuf, PathBuf>,
+}
+
+impl HookTargets {
+    pub fn get(&self, relative: &Path) -> Option<&PathBuf> {
+        self.by_source.get(relative)
+    }
+
+    pub fn set(&mut self, relative: &Path, target: PathBuf) {
+        self.by_source.insert(relative.to_path_buf(), target);
+    }
+
+    pub fn forget(&mut self, relative: &Path) {
+        self.by_source.remove(relative);
+    }
+
+    /// 移除源路径本身及其下所有文件的记录，返回它们的目标。
+    pub fn release(&mut self, relative: &Path) -> Vec<PathBuf> {
+        let released: Vec<PathBuf> = self
+            .by_source
+            .keys()
+            .filter(|source| source.starts_with(relative))
+            .cloned()
+            .collect();
+        released
+            .into_iter()
+            .filter_map(|source| self.by_source.remove(&source))
+            .collect()
+    }
+}
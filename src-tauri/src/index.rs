@@ -0,0 +1,113 @@
+//! 持久化的同步索引，记录每个已同步文件在同步时的大小和修改时间。
+//!
+//! 全量同步时跳过索引中未变化且目标仍存在的文件，
+//! 使重启后的重新同步只处理变化的部分。索引以 JSON 保存在应用数据目录中。
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexEntry {
+    pub len: u64,
+    /// 修改时间，自 UNIX 纪元起的毫秒数。
+    pub modified_ms: u64,
+}
+
+impl IndexEntry {
+    /// 无法获取修改时间时返回 `None`，这样的文件不会进入索引。
+    pub fn new(len: u64, modified: Option<SystemTime>) -> Option<Self> {
+        let modified_ms = modified?.duration_since(UNIX_EPOCH).ok()?.as_millis() as u64;
+        Some(Self { len, modified_ms })
+    }
+}
+
+/// 以相对源目录的路径为键的同步索引。
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SyncIndex {
+    entries: HashMap<PathBuf, IndexEntry>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl SyncIndex {
+    /// 从索引文件加载，文件不存在或已损坏时返回空索引。
+    pub fn load(file: &Path) -> Self {
+        match std::fs::read(file) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!("索引文件 {:?} 已损坏，将重新建立: {:?}", file, e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn is_unchanged(&self, relative: &Path, entry: IndexEntry) -> bool {
+        self.entries.get(relative) == Some(&entry)
+    }
+
+    pub fn record(&mut self, relative: PathBuf, entry: IndexEntry) {
+        if self.entries.insert(relative, entry) != Some(entry) {
+            self.dirty = true;
+        }
+    }
+
+    /// 移除路径本身以及其下所有文件的记录。
+    pub fn forget(&mut self, relative: &Path) {
+        let before = self.entries.len();
+        self.entries.retain(|path, _| !path.starts_with(relative));
+        self.dirty |= self.entries.len() != before;
+    }
+
+    /// 索引中记录的所有相对路径。
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.entries.keys().map(PathBuf::as_path)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.dirty = true;
+    }
+
+    /// 标记为有修改，下一次保存时写入完整的索引。
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// 有未保存的修改时序列化索引并清除修改标记。
+    pub fn take_snapshot(&mut self) -> Option<Vec<u8>> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        serde_json::to_vec(self).ok()
+    }
+}
+
+/// 监视的索引文件路径。应用数据目录不可用时返回 `None`，此时索引只保存在内存中。
+pub fn index_file(app: &tauri::AppHandle, id: &str) -> Option<PathBuf> {
+    let name: String = id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match app.path().app_data_dir() {
+        Ok(dir) => Some(dir.join("index").join(format!("{}.json", name))),
+        Err(e) => {
+            warn!("无法获取应用数据目录，索引不会被持久化: {:?}", e);
+            None
+        }
+    }
+}
@@ -1,177 +1,1649 @@
-use anyhow::anyhow;
+use anyhow::{anyhow, Context};
+use config::{WatchConfig, WatchLimitFallback, WatchMode, WatchOptions};
+use diagnostics::StateDump;
+use events::{IdPayload, PathsPayload, RenamePayload, Subscriptions, WatchLimitPayload};
+use evict::TargetCache;
+use file_processor::ProcessContext;
+use filter::{Filters, PathFilter};
+use fs_ops::TokioFs;
+use groups::GroupResult;
 use log::{error, info, warn};
-use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use manifest::ManifestEntry;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use pause::PauseMode;
+use presets::Preset;
+use profiles::{ActiveProfile, Profile};
+use queue::{EventReceiver, EventSender};
+use reload::ReloadSummary;
+use rename::Renamer;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf};
+use shutdown::LastRun;
+use stats::StatsSnapshot;
+use stats_feed::StatsFeed;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use sync::SyncSummary;
 use tauri::{
-    menu::{Menu, MenuItem},
     tray::{MouseButton, MouseButtonState, TrayIconBuilder},
-    Manager,
+    Emitter, Manager,
 };
+use tauri_plugin_dialog::DialogExt;
 use tauri_plugin_log::{Target, TargetKind};
-use tokio::{
-    spawn,
-    sync::{
-        mpsc::{channel, Receiver},
-        Mutex,
-    },
-};
+use tauri_plugin_opener::OpenerExt;
+use tokio::{spawn, sync::Mutex, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
+use watch_state::WatchState;
 
+mod allowlist;
+mod bundle;
+mod churn;
+mod config;
+mod content_filter;
+mod dedup;
+mod deferred;
+mod delayed_delete;
+mod diagnostics;
+mod error_history;
+mod event_loop;
+mod events;
+mod evict;
 mod file_processor;
 mod file_processor_copy;
 mod file_processor_link;
+mod file_processor_monitor;
+mod file_processor_move;
+mod filter;
+mod flatten;
+mod fs_ops;
+mod groups;
+mod hook;
+mod index;
+mod log_dedup;
+mod log_level;
+mod manifest;
+#[cfg(test)]
+mod mem_fs;
+mod metadata;
+mod mount;
+mod pause;
+mod pending;
+mod presets;
+mod profiles;
+mod progress;
+mod queue;
+mod rate_limit;
+mod reload;
+mod rename;
+mod shutdown;
+mod simulate;
+mod staging;
+mod stats;
+mod stats_feed;
+mod supervisor;
+mod sync;
+mod tray;
+mod walk;
+mod watch_state;
+
+/// 调试构建的日志目录，相对于进程的工作目录。
+#[cfg(debug_assertions)]
+const DEV_LOG_DIR: &str = "./logs";
+
+/// 与前端共用的 store 文件。
+const STORE_FILE: &str = "store.json";
+
+/// 日志目录的绝对路径，与日志插件实际写入的目录一致。
+///
+/// 调试构建写入工作目录下的 [`DEV_LOG_DIR`]；发布构建写入系统约定的应用日志目录，
+/// 如 macOS 的 `~/Library/Logs/<标识符>`、Windows 的 `%LOCALAPPDATA%\<标识符>\logs`、
+/// Linux 的 `$XDG_DATA_HOME/<标识符>/logs`。
+fn log_dir(app: &tauri::AppHandle) -> anyhow::Result<PathBuf> {
+    #[cfg(debug_assertions)]
+    {
+        let _ = app;
+        Ok(std::env::current_dir()?.join(DEV_LOG_DIR))
+    }
+    #[cfg(not(debug_assertions))]
+    {
+        Ok(app.path().app_log_dir()?)
+    }
+}
 
 struct AppState {
-    watcher: Mutex<HashMap<String, RecommendedWatcher>>,
+    watcher: Mutex<HashMap<String, ActiveWatch>>,
+    /// 为每个新建的监视分配唯一序号。
+    next_serial: AtomicU64,
+    /// 正在进行的全量同步，按监视 id 保存取消令牌。
+    syncs: Mutex<HashMap<String, CancellationToken>>,
+    subscriptions: Subscriptions,
+    /// 正在进行的源目录扫描，按扫描 id 保存取消令牌。
+    scans: Mutex<HashMap<String, CancellationToken>>,
+    /// 等待源路径或目标路径出现后再启动的监视。
+    pending: Mutex<HashMap<String, WatchConfig>>,
+    /// 启动过的分组成员的配置，按监视 id 保存，成员停止后仍保留以便整组重新启动。
+    groups: Mutex<HashMap<String, WatchConfig>>,
+    stats_feed: StatsFeed,
+    /// 当前激活的配置组，提供新建监视的默认值。
+    profile: Mutex<Option<ActiveProfile>>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+/// 一个正在运行的监视。
+struct ActiveWatch {
+    /// 仅为保持监视存活而持有，drop 时停止监视并关闭事件通道。
+    /// 事件循环 panic 后会被重建的监视器替换。
+    _watcher: WatchHandle,
+    config: WatchConfig,
+    state: Arc<WatchState>,
+    /// 区分同一 id 先后创建的不同监视，避免旧的事件循环误停新的监视。
+    serial: u64,
+    /// 取消后事件循环完成收尾（提交暂存文件、保存索引）并退出。
+    shutdown: CancellationToken,
+    /// 运行事件循环的监督任务。
+    task: JoinHandle<()>,
+    /// 监视创建的时刻，事件循环 panic 后重建监视时重置。
+    created_at: SystemTime,
+}
+
+/// 等待事件循环完成收尾的最长时间，超时后任务继续在后台完成。
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl ActiveWatch {
+    /// 创建时刻，自 UNIX 纪元起的毫秒数。
+    fn created_at_ms(&self) -> u64 {
+        self.created_at
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+
+    /// 自创建以来运行的时长，系统时间被回拨时为零。
+    fn uptime(&self) -> Duration {
+        self.created_at.elapsed().unwrap_or_default()
+    }
+
+    /// 通知事件循环停止并等待其退出。
+    ///
+    /// 调用方应先把监视从监视表中移除；监视器随后被 drop，作为停止事件循环的后备手段。
+    async fn stop(self) {
+        if !stop_task(&self.shutdown, self.task, STOP_TIMEOUT).await {
+            warn!("监视 '{}' 的事件循环未能及时退出。", self.config.name());
+        }
+    }
+}
+
+/// 取消 `shutdown` 并等待 `task` 退出，超过 `timeout` 仍未退出时返回 `false`。
+async fn stop_task(shutdown: &CancellationToken, task: JoinHandle<()>, timeout: Duration) -> bool {
+    shutdown.cancel();
+    tokio::time::timeout(timeout, task).await.is_ok()
+}
+
+/// 保持事件来源存活的句柄。
+///
+/// 事件模式下持有系统监视器；轮询模式下没有监视器，只持有事件通道的发送端，
+/// 使事件循环在监视被停止前不会因通道关闭而退出。
+struct WatchHandle {
+    _watcher: Option<RecommendedWatcher>,
+    _sender: Option<EventSender>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CopyType {
     Copy,
     Link,
+    /// 复制到目标后删除源文件。
+    Move,
+    /// 只比较源和目标，报告差异而不写入目标。
+    Monitor,
 }
 
+/// 未指定复制类型或选项时使用当前激活的配置组中的默认值，没有时使用复制和内置默认选项。
 #[tauri::command]
 async fn watch(
+    app: tauri::AppHandle,
     id: String,
     from: &str,
     to: &str,
+    copy_type: Option<CopyType>,
+    options: Option<WatchOptions>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let defaults = state
+        .profile
+        .lock()
+        .await
+        .as_ref()
+        .map(|active| active.profile.clone())
+        .unwrap_or_default();
+    let config = WatchConfig {
+        id,
+        from: PathBuf::from(from),
+        to: PathBuf::from(to),
+        copy_type: copy_type
+            .or(defaults.default_copy_type)
+            .unwrap_or(CopyType::Copy),
+        options: options.or(defaults.default_options).unwrap_or_default(),
+    };
+    start_watch(&app, config, &state).await
+}
+
+/// `watch_many` 的结果。
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct WatchManyResult {
+    /// 已启动或已加入等待列表的监视 id。
+    succeeded: Vec<String>,
+    /// 监视 id -> 失败原因。
+    failed: BTreeMap<String, String>,
+    /// 写入 store 失败的原因，此时已启动的监视仍在运行，只是不会被保存。
+    store_error: Option<String>,
+}
+
+/// 一次添加多个监视：只获取一次监视表锁，并在最后一次性把成功的监视写入 store。
+///
+/// 单个监视失败不影响其他监视，已成功启动的监视不会回滚。
+#[tauri::command]
+async fn watch_many(
+    app: tauri::AppHandle,
+    configs: Vec<WatchConfig>,
+    state: tauri::State<'_, AppState>,
+) -> Result<WatchManyResult, String> {
+    let pending_ids: Vec<String> = state.pending.lock().await.keys().cloned().collect();
+    let mut result = WatchManyResult::default();
+    let mut added = Vec::new();
+    let mut waiting = Vec::new();
+    let mut watchers = state.watcher.lock().await;
+    for config in configs {
+        let id = config.id.clone();
+        let duplicate = watchers.contains_key(&id)
+            || pending_ids.contains(&id)
+            || waiting.iter().any(|waiting: &WatchConfig| waiting.id == id);
+        if duplicate {
+            let error = format!("路径 '{}' 已在监视中。", config.from.display());
+            result.failed.insert(id, error);
+        } else if config.options.wait_for_paths && !(config.from.exists() && config.to.exists()) {
+            waiting.push(config);
+        } else {
+            let stored = config.clone();
+            match activate_locked(&app, config, &mut watchers).await {
+                Ok(()) => {
+                    result.succeeded.push(id);
+                    added.push(stored);
+                }
+                Err(e) => {
+                    let error =
+                        format!("启动对路径 '{}' 的监视失败: {:#}", stored.from.display(), e);
+                    result.failed.insert(id, error);
+                }
+            }
+        }
+    }
+    drop(watchers);
+    for config in waiting {
+        result.succeeded.push(config.id.clone());
+        added.push(config.clone());
+        pending::add(&app, config).await;
+    }
+    tray::refresh(&app).await;
+    if let Err(e) = reload::store_watches(&app, &added) {
+        warn!("无法保存批量添加的监视: {:#}", e);
+        result.store_error = Some(format!("{:#}", e));
+    }
+    info!(
+        "批量添加监视: 成功 {} 个，失败 {} 个。",
+        result.succeeded.len(),
+        result.failed.len()
+    );
+    Ok(result)
+}
+
+/// 保存监视模板，之后可用 `create_from_preset` 以不同的路径创建监视。
+#[tauri::command]
+async fn save_preset(
+    app: tauri::AppHandle,
+    name: &str,
     copy_type: CopyType,
+    options: Option<WatchOptions>,
+) -> Result<(), String> {
+    let preset = Preset {
+        copy_type,
+        options: options.unwrap_or_default(),
+    };
+    presets::save(&app, name, preset).map_err(|e| format!("保存模板 '{}' 失败: {:#}", name, e))?;
+    info!("已保存模板 '{}'。", name);
+    Ok(())
+}
+
+/// 列出所有已保存的模板，按名称排序。
+#[tauri::command]
+async fn list_presets(app: tauri::AppHandle) -> Result<BTreeMap<String, Preset>, String> {
+    presets::list(&app).map_err(|e| format!("读取模板失败: {:#}", e))
+}
+
+#[tauri::command]
+async fn delete_preset(app: tauri::AppHandle, name: &str) -> Result<(), String> {
+    match presets::delete(&app, name) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("模板 '{}' 不存在。", name)),
+        Err(e) => Err(format!("删除模板 '{}' 失败: {:#}", name, e)),
+    }
+}
+
+/// 保存全局设置的配置组，同名配置组会被覆盖。
+#[tauri::command]
+async fn save_profile(app: tauri::AppHandle, name: &str, profile: Profile) -> Result<(), String> {
+    profiles::save(&app, name, profile)
+        .map_err(|e| format!("保存配置组 '{}' 失败: {:#}", name, e))?;
+    info!("已保存配置组 '{}'。", name);
+    Ok(())
+}
+
+/// 列出所有已保存的配置组，按名称排序。
+#[tauri::command]
+async fn list_profiles(app: tauri::AppHandle) -> Result<BTreeMap<String, Profile>, String> {
+    profiles::list(&app).map_err(|e| format!("读取配置组失败: {:#}", e))
+}
+
+#[tauri::command]
+async fn delete_profile(app: tauri::AppHandle, name: &str) -> Result<(), String> {
+    match profiles::delete(&app, name) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!("配置组 '{}' 不存在。", name)),
+        Err(e) => Err(format!("删除配置组 '{}' 失败: {:#}", name, e)),
+    }
+}
+
+/// 激活配置组并应用其中的全局设置，之后新建的监视使用其中的默认值。
+#[tauri::command]
+async fn activate_profile(app: tauri::AppHandle, name: &str) -> Result<ActiveProfile, String> {
+    profiles::activate(&app, name)
+        .await
+        .map_err(|e| format!("激活配置组 '{}' 失败: {:#}", name, e))
+}
+
+#[tauri::command]
+async fn get_active_profile(
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<ActiveProfile>, String> {
+    Ok(state.profile.lock().await.clone())
+}
+
+/// 以模板中的复制类型和选项为路径 `from` -> `to` 创建监视。
+///
+/// 模板中的显示名称不会被使用，新监视默认以源路径命名。
+#[tauri::command]
+async fn create_from_preset(
+    app: tauri::AppHandle,
+    name: &str,
+    id: String,
+    from: &str,
+    to: &str,
     state: tauri::State<'_, AppState>,
 ) -> Result<(), String> {
+    let preset = presets::get(&app, name).map_err(|e| format!("{:#}", e))?;
+    let config = WatchConfig {
+        id,
+        from: PathBuf::from(from),
+        to: PathBuf::from(to),
+        copy_type: preset.copy_type,
+        options: WatchOptions {
+            name: None,
+            ..preset.options
+        },
+    };
+    start_watch(&app, config, &state).await
+}
+
+/// 检查 id 未被占用后启动监视，或在路径尚不存在时加入等待列表。
+async fn start_watch(
+    app: &tauri::AppHandle,
+    config: WatchConfig,
+    state: &AppState,
+) -> Result<(), String> {
+    if state.watcher.lock().await.contains_key(&config.id)
+        || state.pending.lock().await.contains_key(&config.id)
+    {
+        return Err(format!("路径 '{}' 已在监视中。", config.from.display()));
+    }
+    // 路径暂时不存在（例如驱动器尚未挂载）时等待路径出现后再启动
+    if config.options.wait_for_paths && !(config.from.exists() && config.to.exists()) {
+        pending::add(app, config).await;
+        return Ok(());
+    }
+    let from = config.from.clone();
+    activate_watch(app, config)
+        .await
+        .map_err(|e| format!("启动对路径 '{}' 的监视失败: {:#}", from.display(), e))
+}
+
+/// 为配置创建并启动监视器，加入监视表。
+async fn activate_watch(app: &tauri::AppHandle, config: WatchConfig) -> anyhow::Result<()> {
+    let state = app.state::<AppState>();
     let mut watcher_guard = state.watcher.lock().await;
-    if watcher_guard.contains_key(&id) {
-        return Err(format!("路径 '{}' 已在监视中。", from));
+    activate_locked(app, config, &mut watcher_guard).await?;
+    drop(watcher_guard);
+    tray::refresh(app).await;
+    Ok(())
+}
+
+/// 判断源目录与目标目录是否位于同一文件系统，据此选择复制方式，并记录源目录所在的文件系统。
+///
+/// 跨文件系统时写时复制和移动模式的重命名都不可用：关闭该监视的写时复制尝试，
+/// 移动模式下提示每个文件都需要完整复制后再删除源文件。
+fn detect_filesystem(config: &WatchConfig, watch_state: &WatchState) {
+    match fs_ops::volume_id(&config.from) {
+        Ok(volume) => watch_state.set_source_volume(volume),
+        Err(e) => warn!(
+            "无法获取 '{}' 的源目录所在的文件系统，卷被卸载时无法察觉: {:?}",
+            config.name(),
+            e
+        ),
     }
-    // 为新路径创建并启动监视器
-    match start_watching_path(from, to, copy_type).await {
-        Ok(wather) => {
-            watcher_guard.insert(id, wather);
-            Ok(())
+    let same = match fs_ops::same_filesystem(&config.from, &config.to) {
+        Ok(same) => same,
+        Err(e) => {
+            warn!(
+                "无法判断 '{}' 的源和目标是否位于同一文件系统: {:?}",
+                config.name(),
+                e
+            );
+            return;
         }
-        Err(e) => Err(format!("启动对路径 '{}' 的监视失败: {}", from, e)),
+    };
+    watch_state.set_same_filesystem(same);
+    if same {
+        return;
+    }
+    info!("监视 '{}' 的源和目标位于不同的文件系统。", config.name());
+    if config.options.use_reflink {
+        watch_state.set_reflink_unsupported();
+    }
+    if config.copy_type == CopyType::Move {
+        warn!(
+            "监视 '{}' 跨文件系统使用移动模式，每个文件都需要完整复制后再删除源文件，速度较慢。",
+            config.name()
+        );
     }
 }
 
+/// 与 [`activate_watch`] 相同，但使用调用方已持有的监视表锁，也不刷新托盘菜单。
+async fn activate_locked(
+    app: &tauri::AppHandle,
+    mut config: WatchConfig,
+    watcher_guard: &mut HashMap<String, ActiveWatch>,
+) -> anyhow::Result<()> {
+    let state = app.state::<AppState>();
+    if watcher_guard.contains_key(&config.id) {
+        return Err(anyhow!("id '{}' 已在监视中。", config.id));
+    }
+    let renamer = config
+        .options
+        .rename_rule
+        .as_ref()
+        .map(Renamer::new)
+        .transpose()?;
+    let serial = state.next_serial.fetch_add(1, Ordering::Relaxed);
+    let watch_state = Arc::new(WatchState::new(
+        config.copy_type,
+        renamer,
+        index::index_file(app, &config.id),
+        config
+            .options
+            .manifest
+            .then(|| config.to.join(manifest::MANIFEST_FILE)),
+        config.options.max_ops_per_sec,
+    ));
+    watch_state.set_modified_after(config.options.modified_after());
+    detect_filesystem(&config, &watch_state);
+    if let Some(cap) = config.options.max_target_size {
+        let to = config.to.clone();
+        let cache = tokio::task::spawn_blocking(move || TargetCache::scan(&to, cap)).await?;
+        watch_state.set_target_cache(cache);
+    }
+    if let Some(last_run) = shutdown::load(app).get(&config.id) {
+        if let Some(ms) = last_run.stats.last_activity_ms {
+            watch_state.stats().restore_last_activity(ms);
+        }
+        // 恢复上次退出时的暂停状态
+        if let Some(mode) = last_run.paused {
+            watch_state.pause(mode);
+            info!("id '{}' 恢复为上次退出时的暂停状态。", config.id);
+        }
+    }
+    let shutdown = CancellationToken::new();
+    let (watcher, task) = start_watching_path(
+        app.clone(),
+        &mut config,
+        serial,
+        watch_state.clone(),
+        shutdown.clone(),
+    )
+    .await?;
+    if config.options.group.is_some() {
+        groups::register(&state, &config).await;
+    }
+    watcher_guard.insert(
+        config.id.clone(),
+        ActiveWatch {
+            _watcher: watcher,
+            config,
+            state: watch_state,
+            serial,
+            shutdown,
+            task,
+            created_at: SystemTime::now(),
+        },
+    );
+    Ok(())
+}
+
 #[tauri::command]
-async fn stop_watching(id: &str, state: tauri::State<'_, AppState>) -> Result<(), String> {
-    let mut watcher_guard = state.watcher.lock().await;
-    if watcher_guard.remove(id).is_some() {
-        // 当 RecommendedWatcher 从 HashMap 中移除并被 drop 时，
-        // 其内部的发送端 (tx) 会被 drop，导致接收端 (rx) 的 recv() 方法返回 None，
-        // 从而使关联的异步任务优雅地停止。
-        // notify crate 的 RecommendedWatcher 在 Drop 时也会清理其监视的路径。
+async fn stop_watching(
+    app: tauri::AppHandle,
+    id: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    if state.pending.lock().await.remove(id).is_some() {
+        info!("已取消等待路径出现的监视 '{}'。", id);
+        return Ok(());
+    }
+    let removed = state.watcher.lock().await.remove(id);
+    if let Some(watch) = removed {
+        // 等待事件循环完成收尾后再返回
+        watch.stop().await;
         info!("已成功停止对id '{}' 的监视。", id);
+        tray::refresh(&app).await;
         Ok(())
     } else {
         Err(format!("id '{}' 未在监视中或无法停止。", id))
     }
 }
 
-fn setup_watcher_channel() -> notify::Result<(RecommendedWatcher, Receiver<notify::Result<Event>>)>
-{
-    let (tx, rx) = channel(200);
-    let watcher = RecommendedWatcher::new(
-        move |res| {
-            if tx.try_send(res).is_err() {
-                error!("发送事件错误: 通道可能已满或已关闭。");
+/// 从 store 重新加载前端保存的监视列表，只停止、启动或修改有变化的监视。
+#[tauri::command]
+async fn reload_config(app: tauri::AppHandle) -> Result<ReloadSummary, String> {
+    reload::reload(&app)
+        .await
+        .map_err(|e| format!("重新加载监视配置失败: {:#}", e))
+}
+
+/// 启动分组中所有未运行的成员。
+#[tauri::command]
+async fn start_group(app: tauri::AppHandle, name: &str) -> Result<GroupResult, String> {
+    groups::start(&app, name).await
+}
+
+/// 停止分组中所有正在运行的成员，等待各自的事件循环完成收尾。
+#[tauri::command]
+async fn stop_group(app: tauri::AppHandle, name: &str) -> Result<GroupResult, String> {
+    groups::stop(&app, name).await
+}
+
+/// 暂停分组中所有正在运行的成员，`buffer` 的含义与 `pause_watching` 相同。
+#[tauri::command]
+async fn pause_group(
+    app: tauri::AppHandle,
+    name: &str,
+    buffer: bool,
+) -> Result<GroupResult, String> {
+    let mode = if buffer {
+        PauseMode::Buffer
+    } else {
+        PauseMode::Drop
+    };
+    groups::pause(&app, name, mode).await
+}
+
+/// 对正在运行的监视立即执行一次全量同步，完成后返回同步结果。
+#[tauri::command]
+async fn force_resync(
+    app: tauri::AppHandle,
+    id: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<SyncSummary, String> {
+    let (config, watch_state) = match state.watcher.lock().await.get(id) {
+        Some(watch) => (watch.config.clone(), watch.state.clone()),
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    let filter = PathFilter::new(&config.options.filters).map_err(|e| format!("{:#}", e))?;
+    sync::run_full_sync(&app, &config, &filter, &watch_state)
+        .await
+        .map_err(|e| format!("全量同步 '{}' 失败: {:#}", config.from.display(), e))
+}
+
+/// 修改正在运行的监视的复制类型，之后的事件按新类型处理。
+///
+/// `convert_existing` 为真时在后台将目标中已有的条目转换为新类型，见 [`sync::convert_existing`]。
+/// 修改后发送 `watch-config-changed` 事件。
+#[tauri::command]
+async fn set_copy_type(
+    app: tauri::AppHandle,
+    id: &str,
+    copy_type: CopyType,
+    convert_existing: Option<bool>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let (config, watch_state) = match state.watcher.lock().await.get_mut(id) {
+        Some(watch) => {
+            watch.config.copy_type = copy_type;
+            watch.state.set_copy_type(copy_type);
+            (watch.config.clone(), watch.state.clone())
+        }
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    info!("id '{}' 的复制类型已改为 {:?}。", id, copy_type);
+    events::emit(&app, id, "watch-config-changed", config.clone());
+    if convert_existing.unwrap_or(false) {
+        let filter = PathFilter::new(&config.options.filters).map_err(|e| format!("{:#}", e))?;
+        tauri::async_runtime::spawn(async move {
+            sync::convert_existing(&app, &config, &filter, &watch_state).await;
+        });
+    }
+    Ok(())
+}
+
+/// 清空监视的同步索引，下一次全量同步将重新处理所有文件。
+#[tauri::command]
+async fn invalidate_index(id: &str, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    let watch_state = match state.watcher.lock().await.get(id) {
+        Some(watch) => watch.state.clone(),
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    watch_state.with_index(|index| index.clear());
+    watch_state.save().await;
+    info!("已清空 id '{}' 的同步索引。", id);
+    Ok(())
+}
+
+/// 取消监视正在进行的全量同步（初始同步或 `force_resync`）。
+#[tauri::command]
+async fn cancel_sync(id: &str, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    match state.syncs.lock().await.get(id) {
+        Some(cancel) => {
+            cancel.cancel();
+            info!("已请求取消 id '{}' 的全量同步。", id);
+            Ok(())
+        }
+        None => Err(format!("id '{}' 没有正在进行的全量同步。", id)),
+    }
+}
+
+/// 修改只同步较新文件的时间阈值，之后的事件和全量同步按新阈值过滤。
+///
+/// `modified_after_ms` 为空时取消阈值。修改后发送 `watch-config-changed` 事件。
+#[tauri::command]
+async fn set_modified_after(
+    app: tauri::AppHandle,
+    id: &str,
+    modified_after_ms: Option<u64>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let config = match state.watcher.lock().await.get_mut(id) {
+        Some(watch) => {
+            watch.config.options.modified_after_ms = modified_after_ms;
+            watch
+                .state
+                .set_modified_after(watch.config.options.modified_after());
+            watch.config.clone()
+        }
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    info!("id '{}' 的修改时间阈值已改为 {:?}。", id, modified_after_ms);
+    events::emit(&app, id, "watch-config-changed", config);
+    Ok(())
+}
+
+/// 在不停止监视的情况下改变它写入的目标目录，例如原目标正在整理时临时写到别处。
+///
+/// 新目标必须已存在且可写。`resync` 为真时切换后对新目标执行一次全量同步。
+/// 已写入原目标的文件不会被移动或删除，开启清单时清单仍保存在原目标中。
+/// 改写不写入 store，重新加载配置或重启应用后恢复为保存的目标目录。
+#[tauri::command]
+async fn redirect_target(
+    app: tauri::AppHandle,
+    id: &str,
+    new_to: PathBuf,
+    resync: Option<bool>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let from = match state.watcher.lock().await.get(id) {
+        Some(watch) => watch.config.from.clone(),
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    check_target(&from, &new_to)
+        .await
+        .map_err(|e| format!("{:#}", e))?;
+    let config = match state.watcher.lock().await.get_mut(id) {
+        Some(watch) => {
+            watch.config.to = new_to.clone();
+            watch.state.redirect(new_to, resync.unwrap_or(false));
+            watch.config.clone()
+        }
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    info!("id '{}' 的目标目录将改为 {:?}。", id, config.to);
+    events::emit(&app, id, "watch-config-changed", config);
+    Ok(())
+}
+
+/// 检查目录能否作为 `from` 的目标：已存在、不在源目录内、在允许的根目录内且可写。
+async fn check_target(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if !to.is_dir() {
+        return Err(anyhow!("目标路径 '{}' 不存在或不是目录。", to.display()));
+    }
+    if to.starts_with(from) {
+        return Err(anyhow!(
+            "目标路径 '{}' 不能是源路径 '{}' 的子目录。",
+            to.display(),
+            from.display()
+        ));
+    }
+    allowlist::check(to).await?;
+    let probe = to.join(format!(".auto-copy-probe-{}", std::process::id()));
+    tokio::fs::write(&probe, b"")
+        .await
+        .with_context(|| format!("目标路径 '{}' 不可写", to.display()))?;
+    if let Err(e) = tokio::fs::remove_file(&probe).await {
+        warn!("无法删除可写性检查文件 {:?}: {:?}", probe, e);
+    }
+    Ok(())
+}
+
+/// 设置监视的备注并写回 store，为空时清除备注。备注不影响监视的运行。
+#[tauri::command]
+async fn set_notes(
+    app: tauri::AppHandle,
+    id: &str,
+    notes: Option<String>,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let notes = notes.filter(|notes| !notes.trim().is_empty());
+    let config = match state.watcher.lock().await.get_mut(id) {
+        Some(watch) => {
+            watch.config.options.notes = notes;
+            watch.config.clone()
+        }
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    let stored = reload::store_notes(&app, id, &config.options)
+        .map_err(|e| format!("无法保存 id '{}' 的备注: {:#}", id, e))?;
+    if !stored {
+        warn!("store 中没有 id '{}' 的监视，备注只在本次运行中有效。", id);
+    }
+    info!("id '{}' 的备注已更新。", id);
+    events::emit(&app, id, "watch-config-changed", config);
+    Ok(())
+}
+
+/// 在不重建监视的情况下修改它的 id，同时修改 store 中保存的条目。
+///
+/// `new_id` 已被运行中、等待中或 store 中的监视使用，或者监视正在全量同步时拒绝修改。
+/// 窗口对原 id 的订阅改为订阅新 id，成功后发送 `watch-renamed` 事件。
+#[tauri::command]
+async fn rename_watch(
+    app: tauri::AppHandle,
+    old_id: &str,
+    new_id: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    if new_id.trim().is_empty() {
+        return Err("新 id 不能为空。".to_string());
+    }
+    let mut watchers = state.watcher.lock().await;
+    if !watchers.contains_key(old_id) {
+        return Err(format!("id '{}' 未在监视中。", old_id));
+    }
+    if watchers.contains_key(new_id) || state.pending.lock().await.contains_key(new_id) {
+        return Err(format!("id '{}' 已被其他监视使用。", new_id));
+    }
+    if state.syncs.lock().await.contains_key(old_id) {
+        return Err(format!("id '{}' 正在全量同步，请稍后再修改。", old_id));
+    }
+    let stored = reload::store_rename(&app, old_id, new_id)
+        .map_err(|e| format!("无法修改 id '{}': {:#}", old_id, e))?;
+    if !stored {
+        warn!(
+            "store 中没有 id '{}' 的监视，新 id 只在本次运行中有效。",
+            old_id
+        );
+    }
+    let Some(mut watch) = watchers.remove(old_id) else {
+        return Err(format!("id '{}' 未在监视中。", old_id));
+    };
+    watch.config.id = new_id.to_string();
+    let old_index = watch
+        .state
+        .rename(new_id.to_string(), index::index_file(&app, new_id));
+    let name = watch.config.name();
+    watchers.insert(new_id.to_string(), watch);
+    drop(watchers);
+    if let Some(old_index) = old_index {
+        if let Err(e) = tokio::fs::remove_file(&old_index).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("删除原索引文件 {:?} 失败: {:?}", old_index, e);
             }
-        },
-        notify::Config::default(),
-    )?;
+        }
+    }
+    {
+        let mut groups = state.groups.lock().await;
+        if let Some(mut config) = groups.remove(old_id) {
+            config.id = new_id.to_string();
+            groups.insert(new_id.to_string(), config);
+        }
+    }
+    for ids in state
+        .subscriptions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .values_mut()
+    {
+        if ids.remove(old_id) {
+            ids.insert(new_id.to_string());
+        }
+    }
+    info!("id '{}' 已改为 '{}'。", old_id, new_id);
+    let payload = RenamePayload {
+        old_id: old_id.to_string(),
+        id: new_id.to_string(),
+        name,
+    };
+    events::emit(&app, new_id, "watch-renamed", payload);
+    tray::refresh(&app).await;
+    Ok(())
+}
 
-    Ok((watcher, rx))
+/// 撤销监视所有等待执行的删除，返回被撤销的源路径，目标中对应的文件保持不变。
+#[tauri::command]
+async fn undo_delete(
+    app: tauri::AppHandle,
+    id: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<PathBuf>, String> {
+    let (paths, name) = match state.watcher.lock().await.get(id) {
+        Some(watch) => (
+            watch
+                .state
+                .with_delayed_deletes(|deletes| deletes.take_all()),
+            watch.config.name(),
+        ),
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    if !paths.is_empty() {
+        info!("已撤销监视 '{}' 的 {} 个删除。", name, paths.len());
+        let payload = PathsPayload {
+            id: id.to_string(),
+            name,
+            paths: paths.clone(),
+        };
+        events::emit(&app, id, "delete-undone", payload);
+    }
+    Ok(paths)
 }
 
-async fn start_watching_path(
+#[tauri::command]
+async fn get_notes(id: &str, state: tauri::State<'_, AppState>) -> Result<Option<String>, String> {
+    match state.watcher.lock().await.get(id) {
+        Some(watch) => Ok(watch.config.options.notes.clone()),
+        None => Err(format!("id '{}' 未在监视中。", id)),
+    }
+}
+
+/// 在 `duration_secs` 秒内不向前端报告该监视的错误，错误仍以 debug 级别记录。
+#[tauri::command]
+async fn mute_errors(
+    id: &str,
+    duration_secs: u64,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    match state.watcher.lock().await.get(id) {
+        Some(watch) => {
+            watch.state.mute_errors(Duration::from_secs(duration_secs));
+            info!("id '{}' 的错误通知已静音 {} 秒。", id, duration_secs);
+            Ok(())
+        }
+        None => Err(format!("id '{}' 未在监视中。", id)),
+    }
+}
+
+/// 暂停处理监视的事件，发送 `watch-paused` 事件。
+///
+/// `buffer` 为真时暂停期间的事件会被缓存，恢复后按顺序重放；否则直接丢弃。
+#[tauri::command]
+async fn pause_watching(
+    app: tauri::AppHandle,
+    id: &str,
+    buffer: bool,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let name = match state.watcher.lock().await.get(id) {
+        Some(watch) => {
+            let mode = if buffer {
+                PauseMode::Buffer
+            } else {
+                PauseMode::Drop
+            };
+            watch.state.pause(mode);
+            watch.config.name()
+        }
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    info!("id '{}' 已暂停，缓存事件: {}。", id, buffer);
+    let payload = IdPayload {
+        id: id.to_string(),
+        name,
+    };
+    events::emit(&app, id, "watch-paused", payload);
+    Ok(())
+}
+
+/// 恢复已暂停的监视，发送 `watch-resumed` 事件。
+#[tauri::command]
+async fn resume_watching(
+    app: tauri::AppHandle,
+    id: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<(), String> {
+    let name = match state.watcher.lock().await.get(id) {
+        Some(watch) if watch.state.resume() => watch.config.name(),
+        Some(_) => return Err(format!("id '{}' 未暂停。", id)),
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    info!("id '{}' 已恢复。", id);
+    let payload = IdPayload {
+        id: id.to_string(),
+        name,
+    };
+    events::emit(&app, id, "watch-resumed", payload);
+    Ok(())
+}
+
+/// 计算源路径 `source_path` 在监视 `id` 下对应的目标路径，不执行任何文件操作。
+///
+/// 会应用重命名和扁平化规则；扁平化时返回的是该文件此刻会得到的名称。
+#[tauri::command]
+async fn resolve_target(
+    id: &str,
+    source_path: PathBuf,
+    state: tauri::State<'_, AppState>,
+) -> Result<PathBuf, String> {
+    let (config, watch_state) = match state.watcher.lock().await.get(id) {
+        Some(watch) => (watch.config.clone(), watch.state.clone()),
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    if !source_path.starts_with(&config.from) {
+        return Err(format!(
+            "路径 '{}' 不在监视的源目录 '{}' 中。",
+            source_path.display(),
+            config.from.display()
+        ));
+    }
+    let filter = PathFilter::new(&config.options.filters).map_err(|e| format!("{:#}", e))?;
+    let ctx = ProcessContext {
+        fs: &TokioFs,
+        from: &config.from,
+        to: &config.to,
+        options: &config.options,
+        filter: &filter,
+        state: &watch_state,
+        progress: None,
+    };
+    ctx.preview_target(&source_path)
+        .map_err(|e| format!("无法计算目标路径: {:#}", e))
+}
+
+/// 在监视 `id` 的 `path` 上模拟一个 `kind` 类型的事件，返回处理器会执行的文件操作。
+///
+/// 默认只演练不写入磁盘，`apply` 为 `true` 时才真正执行。
+#[tauri::command]
+async fn simulate_event(
+    id: &str,
+    kind: simulate::SimulatedKind,
+    path: PathBuf,
+    apply: Option<bool>,
+    state: tauri::State<'_, AppState>,
+) -> Result<simulate::Simulation, String> {
+    let (config, watch_state) = match state.watcher.lock().await.get(id) {
+        Some(watch) => (watch.config.clone(), watch.state.clone()),
+        None => return Err(format!("id '{}' 未在监视中。", id)),
+    };
+    if !path.starts_with(&config.from) {
+        return Err(format!(
+            "路径 '{}' 不在监视的源目录 '{}' 中。",
+            path.display(),
+            config.from.display()
+        ));
+    }
+    simulate::simulate(&config, &watch_state, kind, path, apply.unwrap_or(false))
+        .await
+        .map_err(|e| format!("模拟事件失败: {:#}", e))
+}
+
+/// 读取所有监视上次退出时保存的统计和暂停状态。
+#[tauri::command]
+async fn get_last_run(app: tauri::AppHandle) -> BTreeMap<String, LastRun> {
+    shutdown::load(&app)
+}
+
+/// 读取监视最近一次成功同步或删除的时间，自 UNIX 纪元起的毫秒数。
+///
+/// 未在监视中的 id 返回上次退出时保存的时间，从未有过活动时返回 `None`。
+#[tauri::command]
+async fn get_last_activity(
+    id: &str,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, AppState>,
+) -> Result<Option<u64>, String> {
+    if let Some(watch) = state.watcher.lock().await.get(id) {
+        return Ok(watch.state.stats().snapshot().last_activity_ms);
+    }
+    match shutdown::load(&app).get(id) {
+        Some(last_run) => Ok(last_run.stats.last_activity_ms),
+        None => Err(format!("未找到 id '{}' 的监视记录。", id)),
+    }
+}
+
+/// 读取监视自创建（或上次自动重建）以来运行的毫秒数。
+#[tauri::command]
+async fn get_watch_uptime(id: &str, state: tauri::State<'_, AppState>) -> Result<u64, String> {
+    match state.watcher.lock().await.get(id) {
+        Some(watch) => Ok(watch.uptime().as_millis() as u64),
+        None => Err(format!("id '{}' 未在监视中。", id)),
+    }
+}
+
+/// 读取监视最近的错误，新的在前，最多返回 `limit` 条（默认全部保留的记录）。
+#[tauri::command]
+async fn get_error_history(
+    id: &str,
+    limit: Option<usize>,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<error_history::ErrorRecord>, String> {
+    match state.watcher.lock().await.get(id) {
+        Some(watch) => Ok(watch.state.with_error_history(|history| {
+            history.recent(limit.unwrap_or(error_history::MAX_ENTRIES))
+        })),
+        None => Err(format!("id '{}' 未在监视中。", id)),
+    }
+}
+
+/// 读取监视当前的清单，包含尚未写回磁盘的修改。
+#[tauri::command]
+async fn get_manifest(
+    id: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<BTreeMap<PathBuf, ManifestEntry>, String> {
+    match state.watcher.lock().await.get(id) {
+        Some(watch) => watch
+            .state
+            .manifest()
+            .map(|manifest| manifest.entries)
+            .ok_or_else(|| format!("id '{}' 未开启清单。", id)),
+        None => Err(format!("id '{}' 未在监视中。", id)),
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct WatchInfo {
+    name: String,
+    #[serde(flatten)]
+    config: WatchConfig,
+    /// 源和目标是否位于同一文件系统，无法判断时为 `None`。
+    same_filesystem: Option<bool>,
+    /// 监视创建的时刻，自 UNIX 纪元起的毫秒数。
+    created_at_ms: u64,
+}
+
+/// 返回所有监视及应用状态的快照，用于排查问题。
+#[tauri::command]
+async fn dump_state(app: tauri::AppHandle) -> StateDump {
+    diagnostics::dump(&app).await
+}
+
+/// 列出所有正在运行的监视，按名称排序。
+#[tauri::command]
+async fn list_watches(state: tauri::State<'_, AppState>) -> Result<Vec<WatchInfo>, String> {
+    let mut watches: Vec<WatchInfo> = state
+        .watcher
+        .lock()
+        .await
+        .values()
+        .map(|watch| WatchInfo {
+            name: watch.config.name(),
+            config: watch.config.clone(),
+            same_filesystem: watch.state.same_filesystem(),
+            created_at_ms: watch.created_at_ms(),
+        })
+        .collect();
+    watches.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(watches)
+}
+
+/// 返回源目录包含 `path` 的所有正在运行的监视 id，按 id 排序，监视重叠时全部返回。
+///
+/// 比较前路径和源目录都解析为规范路径，不存在的路径按字面处理。
+#[tauri::command]
+async fn find_watch_for_path(
+    path: &str,
+    state: tauri::State<'_, AppState>,
+) -> Result<Vec<String>, String> {
+    let roots: Vec<(String, PathBuf)> = state
+        .watcher
+        .lock()
+        .await
+        .iter()
+        .map(|(id, watch)| (id.clone(), watch.config.from.clone()))
+        .collect();
+    let path = PathBuf::from(path);
+    // 解析路径需要访问文件系统，在阻塞线程中进行
+    tokio::task::spawn_blocking(move || watches_containing(&path, roots))
+        .await
+        .map_err(|e| format!("查找监视失败: {:?}", e))
+}
+
+/// `roots` 中源目录包含 `path` 的监视 id，按 id 排序。会访问文件系统。
+fn watches_containing(path: &Path, roots: Vec<(String, PathBuf)>) -> Vec<String> {
+    let path = allowlist::resolve(path);
+    let mut ids: Vec<String> = roots
+        .into_iter()
+        .filter(|(_, from)| {
+            let from = std::fs::canonicalize(from).unwrap_or_else(|_| allowlist::resolve(from));
+            path.starts_with(from)
+        })
+        .map(|(id, _)| id)
+        .collect();
+    ids.sort();
+    ids
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct GlobalStats {
+    active_watches: usize,
+    #[serde(flatten)]
+    totals: StatsSnapshot,
+}
+
+/// 汇总所有正在运行的监视的统计。
+#[tauri::command]
+async fn get_global_stats(state: tauri::State<'_, AppState>) -> Result<GlobalStats, String> {
+    let watchers = state.watcher.lock().await;
+    let mut totals = StatsSnapshot::default();
+    for watch in watchers.values() {
+        totals += watch.state.stats().snapshot();
+    }
+    Ok(GlobalStats {
+        active_watches: watchers.len(),
+        totals,
+    })
+}
+
+/// 设置 `stats-snapshot` 事件的推送间隔（毫秒），为空时停止推送。
+#[tauri::command]
+fn set_stats_snapshot_interval(interval_ms: Option<u64>, state: tauri::State<'_, AppState>) {
+    state
+        .stats_feed
+        .set_interval(interval_ms.map(Duration::from_millis));
+    info!("统计快照的推送间隔已改为 {:?} 毫秒。", interval_ms);
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SupportedModes {
+    copy_types: Vec<CopyType>,
+    /// 扩展属性（Unix）或备用数据流（Windows）。
+    xattrs: bool,
+    hardlinks: bool,
+    junctions: bool,
+    ownership: bool,
+    /// 目录软链接，Windows 上可能还需要开发者模式或管理员权限。
+    dir_symlinks: bool,
+    /// 平台支持写时复制，实际是否可用取决于目标文件系统，不支持时对应监视自动改用普通复制。
+    reflink: bool,
+}
+
+/// 返回可用的复制类型，以及当前平台上可用的可选功能。
+#[tauri::command]
+fn get_supported_modes() -> SupportedModes {
+    SupportedModes {
+        copy_types: vec![
+            CopyType::Copy,
+            CopyType::Link,
+            CopyType::Move,
+            CopyType::Monitor,
+        ],
+        xattrs: cfg!(any(unix, windows)),
+        hardlinks: cfg!(unix),
+        junctions: cfg!(windows),
+        ownership: cfg!(unix),
+        dir_symlinks: true,
+        reflink: cfg!(any(
+            target_os = "linux",
+            target_os = "android",
+            target_os = "macos"
+        )),
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BackendInfo {
+    version: &'static str,
+    notify_backend: String,
+    os: &'static str,
+    arch: &'static str,
+    active_watches: usize,
+    log_dir: Option<PathBuf>,
+}
+
+async fn backend_info(app: &tauri::AppHandle) -> BackendInfo {
+    BackendInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        notify_backend: format!("{:?}", RecommendedWatcher::kind()),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        active_watches: app.state::<AppState>().watcher.lock().await.len(),
+        log_dir: log_dir(app).ok(),
+    }
+}
+
+/// 返回后端版本和运行环境信息，用于关于对话框和错误报告。
+#[tauri::command]
+async fn get_backend_info(app: tauri::AppHandle) -> Result<BackendInfo, String> {
+    Ok(backend_info(&app).await)
+}
+
+/// 将最近的日志、`dump_state` 的快照和后端信息打包为 zip 文件，返回写入的路径。
+///
+/// 未指定 `out_path` 时弹出保存对话框，用户取消时返回 `None`。
+/// `redact_paths` 为 `true` 时将内容中的用户主目录替换为 `~`。
+#[tauri::command]
+async fn export_diagnostics(
+    app: tauri::AppHandle,
+    out_path: Option<PathBuf>,
+    redact_paths: Option<bool>,
+) -> Result<Option<PathBuf>, String> {
+    let out_path = match out_path {
+        Some(path) => path,
+        None => {
+            let (tx, rx) = tokio::sync::oneshot::channel();
+            app.dialog()
+                .file()
+                .set_file_name("auto-copy-diagnostics.zip")
+                .add_filter("Zip", &["zip"])
+                .save_file(move |path| {
+                    let _ = tx.send(path);
+                });
+            match rx.await.ok().flatten() {
+                Some(path) => path
+                    .into_path()
+                    .map_err(|e| format!("无效的保存路径: {}", e))?,
+                None => return Ok(None),
+            }
+        }
+    };
+    bundle::export(&app, &out_path, redact_paths.unwrap_or(false))
+        .await
+        .map_err(|e| format!("导出诊断包失败: {:#}", e))?;
+    Ok(Some(out_path))
+}
+
+/// 在系统文件管理器中打开日志目录。
+#[tauri::command]
+fn open_logs_folder(app: tauri::AppHandle) -> Result<(), String> {
+    let dir = log_dir(&app).map_err(|e| format!("无法确定日志目录: {:#}", e))?;
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("无法创建日志目录 '{}': {}", dir.display(), e))?;
+    app.opener()
+        .open_path(dir.to_string_lossy(), None::<&str>)
+        .map_err(|e| format!("打开日志目录 '{}' 失败: {}", dir.display(), e))
+}
+
+/// 让窗口只接收指定监视的事件。
+#[tauri::command]
+fn subscribe(window_label: String, ids: Vec<String>, state: tauri::State<'_, AppState>) {
+    state
+        .subscriptions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(window_label, ids.into_iter().collect());
+}
+
+/// 取消窗口的订阅，恢复接收所有监视的事件。
+#[tauri::command]
+fn unsubscribe(window_label: &str, state: tauri::State<'_, AppState>) {
+    state
+        .subscriptions
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .remove(window_label);
+}
+
+/// 默认的临时日志级别持续时间。
+const LOG_LEVEL_REVERT: Duration = Duration::from_secs(10 * 60);
+
+/// 在运行时修改日志级别（`error`、`warn`、`info`、`debug`、`trace` 或 `off`）。
+///
+/// 修改在 `duration_secs` 秒后自动恢复，默认 10 分钟；为 0 时永久生效。
+#[tauri::command]
+fn set_log_level(level: &str, duration_secs: Option<u64>) -> Result<(), String> {
+    let level: log::LevelFilter = level
+        .parse()
+        .map_err(|_| format!("无效的日志级别 '{}'。", level))?;
+    let revert_after = match duration_secs {
+        Some(0) => None,
+        Some(secs) => Some(Duration::from_secs(secs)),
+        None => Some(LOG_LEVEL_REVERT),
+    };
+    log_level::set(level, revert_after);
+    Ok(())
+}
+
+/// 开启或关闭详细日志。开启后不再合并重复的日志。
+#[tauri::command]
+fn set_verbose_logging(verbose: bool) {
+    log_dedup::set_verbose(verbose);
+    info!("详细日志已{}。", if verbose { "开启" } else { "关闭" });
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct SourceEstimate {
+    files: u64,
+    bytes: u64,
+}
+
+/// 统计源目录中符合过滤规则的文件数量和总大小。
+///
+/// 扫描可以通过 `cancel_scan` 使用相同的 `scan_id` 取消。
+#[tauri::command]
+async fn estimate_source(
+    scan_id: String,
     from: &str,
-    to: &str,
-    copy_type: CopyType,
-) -> anyhow::Result<RecommendedWatcher> {
-    let from_path = PathBuf::from(from);
-    let to_path = PathBuf::from(to);
+    filters: Option<Filters>,
+    state: tauri::State<'_, AppState>,
+) -> Result<SourceEstimate, String> {
+    let filter = PathFilter::new(&filters.unwrap_or_default()).map_err(|e| format!("{:#}", e))?;
+    let cancel = CancellationToken::new();
+    {
+        let mut scans = state.scans.lock().await;
+        if scans.contains_key(&scan_id) {
+            return Err(format!("扫描 '{}' 已在进行中。", scan_id));
+        }
+        scans.insert(scan_id.clone(), cancel.clone());
+    }
+    let result = walk::walk_files(Path::new(from), &filter, &cancel).await;
+    state.scans.lock().await.remove(&scan_id);
+    let files = result.map_err(|e| format!("扫描源路径 '{}' 失败: {:#}", from, e))?;
+    Ok(SourceEstimate {
+        files: files.len() as u64,
+        bytes: files.iter().map(|file| file.len).sum(),
+    })
+}
 
+#[tauri::command]
+async fn cancel_scan(scan_id: &str, state: tauri::State<'_, AppState>) -> Result<(), String> {
+    match state.scans.lock().await.get(scan_id) {
+        Some(cancel) => {
+            cancel.cancel();
+            info!("已取消扫描 '{}'。", scan_id);
+            Ok(())
+        }
+        None => Err(format!("扫描 '{}' 不存在或已结束。", scan_id)),
+    }
+}
+
+/// 按监视模式创建事件来源，返回其句柄和事件通道。
+fn open_source(config: &WatchConfig) -> anyhow::Result<(WatchHandle, EventReceiver)> {
+    let (tx, rx) = queue::bounded(
+        config.options.max_queued_events,
+        config.options.queue_overflow,
+    );
+    if config.options.mode == WatchMode::Poll {
+        let handle = WatchHandle {
+            _watcher: None,
+            _sender: Some(tx),
+        };
+        return Ok((handle, rx));
+    }
+    let mut watcher = RecommendedWatcher::new(move |res| tx.send(res), notify::Config::default())?;
+    // 尝试监视路径。如果失败，错误将被传播。
+    watcher
+        .watch(&config.from, RecursiveMode::Recursive)
+        .map_err(|e| classify_watch_error(&config.from, e))?;
+    let handle = WatchHandle {
+        _watcher: Some(watcher),
+        _sender: None,
+    };
+    Ok((handle, rx))
+}
+
+/// 启动监视。文件监视数量达到上限且允许回退时，`config` 会被改为轮询模式。
+async fn start_watching_path(
+    app: tauri::AppHandle,
+    config: &mut WatchConfig,
+    serial: u64,
+    watch_state: Arc<WatchState>,
+    shutdown: CancellationToken,
+) -> anyhow::Result<(WatchHandle, JoinHandle<()>)> {
     // 检查源路径是否存在
-    if !from_path.exists() {
-        return Err(anyhow!("源路径 '{}' 不存在。请确保路径正确。", from));
+    if !config.from.exists() {
+        return Err(anyhow!(
+            "源路径 '{}' 不存在。请确保路径正确。",
+            config.from.display()
+        ));
     }
     // 检查目标路径是否存在
-    if !to_path.exists() {
-        return Err(anyhow!("目标路径 '{}' 不存在。请确保路径正确。", to));
+    if !config.to.exists() {
+        return Err(anyhow!(
+            "目标路径 '{}' 不存在。请确保路径正确。",
+            config.to.display()
+        ));
     }
+    let mut resolved = resolve_paths(config).await?;
     // 检查目标路径是否是源路径的子目录
-    if to_path.starts_with(&from_path) {
+    if resolved.to.starts_with(&resolved.from) {
         return Err(anyhow!(
             "目标路径 '{}' 不能是源路径 '{}' 的子目录。",
-            &to,
-            &from
+            config.to.display(),
+            config.from.display()
         ));
     }
 
-    let (mut watcher, mut rx) = setup_watcher_channel()?;
+    allowlist::check(&config.from).await?;
+    allowlist::check(&config.to).await?;
 
-    // 尝试监视路径。如果失败，错误将被传播。
-    watcher.watch(&from_path, RecursiveMode::Recursive)?;
-    // 生成一个新任务来处理事件。
-    // `watcher` 实例被移动到此任务中以使其保持活动状态。
-    spawn(async move {
-        info!("路径 {:?} 的事件处理循环已启动。", from_path);
-        while let Some(res) = rx.recv().await {
-            match res {
-                Ok(event) => file_processor::process(copy_type, event, &from_path, &to_path).await,
-                Err(e) => error!("监视路径 {:?} 时出错: {:?}", from_path, e),
+    let filter = PathFilter::new(&config.options.filters)?;
+
+    let (watcher, rx) = match open_source(&resolved) {
+        Err(e) if e.downcast_ref::<WatchLimitReached>().is_some() => {
+            let polling = config.options.watch_limit_fallback == WatchLimitFallback::Poll;
+            let payload = WatchLimitPayload {
+                id: config.id.clone(),
+                name: config.name(),
+                polling,
+            };
+            events::emit(&app, &config.id, "watch-limit-reached", payload);
+            if !polling {
+                return Err(e);
             }
+            warn!(
+                "监视 '{}' 已达到系统的文件监视数量上限，改用轮询模式。",
+                config.name()
+            );
+            config.options.mode = WatchMode::Poll;
+            resolved.options.mode = WatchMode::Poll;
+            open_source(&resolved)?
         }
-        info!("路径 {:?} 的事件处理循环已停止。", from_path);
-        // watcher 在任务作用域结束时隐式删除。
-    });
+        result => result?,
+    };
+    // 生成一个新任务来处理事件，事件循环 panic 时由其自动重建监视。
+    let task = spawn(supervisor::supervise(
+        app,
+        resolved,
+        serial,
+        filter,
+        watch_state,
+        rx,
+        shutdown,
+    ));
 
     // 返回 Ok，表示监视器已成功初始化并且事件循环已生成。
-    Ok(watcher)
+    Ok((watcher, task))
+}
+
+/// 开启 `canonicalize_paths` 时返回源和目标都解析为真实路径的配置副本，否则原样复制。
+///
+/// 系统监视器报告的是真实路径（例如 macOS 上 `/var` 实为 `/private/var`，Windows 上 `subst`
+/// 映射的驱动器），监视器和事件循环使用副本计算相对路径；用户输入的路径仍保存在原配置中，
+/// 用于显示和保存。
+async fn resolve_paths(config: &WatchConfig) -> anyhow::Result<WatchConfig> {
+    let mut resolved = config.clone();
+    if !config.options.canonicalize_paths {
+        return Ok(resolved);
+    }
+    resolved.from = tokio::fs::canonicalize(&config.from)
+        .await
+        .with_context(|| format!("无法解析源路径 '{}' 的真实路径", config.from.display()))?;
+    resolved.to = tokio::fs::canonicalize(&config.to)
+        .await
+        .with_context(|| format!("无法解析目标路径 '{}' 的真实路径", config.to.display()))?;
+    if resolved.from != config.from || resolved.to != config.to {
+        info!(
+            "监视 '{}' 使用真实路径: {:?} -> {:?}",
+            config.name(),
+            resolved.from,
+            resolved.to
+        );
+    }
+    Ok(resolved)
+}
+
+/// 系统的文件监视数量已达到上限。
+///
+/// notify 只把添加监视时的 `ENOSPC` 报告为 `MaxFilesWatch`，磁盘空间不足仍是普通的 IO 错误。
+#[derive(Debug)]
+struct WatchLimitReached;
+
+impl std::fmt::Display for WatchLimitReached {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "已达到系统的文件监视数量上限。{}", WATCH_LIMIT_HINT)
+    }
+}
+
+impl std::error::Error for WatchLimitReached {}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+const WATCH_LIMIT_HINT: &str = "可执行 `sudo sysctl fs.inotify.max_user_watches=524288` 提高上限（写入 /etc/sysctl.conf 后重启仍生效），或将该监视的 watchLimitFallback 设为 Poll 改用轮询。";
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+const WATCH_LIMIT_HINT: &str = "可将该监视的 watchLimitFallback 设为 Poll 改用轮询。";
+
+/// 将权限不足或监视数量达到上限导致的监视失败转换为带有修复提示的错误。
+fn classify_watch_error(from: &Path, err: notify::Error) -> anyhow::Error {
+    match &err.kind {
+        notify::ErrorKind::MaxFilesWatch => anyhow::Error::new(WatchLimitReached)
+            .context(format!("无法监视源路径 '{}'", from.display())),
+        notify::ErrorKind::Io(e) if e.kind() == std::io::ErrorKind::PermissionDenied => anyhow!(
+            "没有权限监视源路径 '{}'。{}",
+            from.display(),
+            PERMISSION_HINT
+        ),
+        _ => err.into(),
+    }
+}
+
+#[cfg(target_os = "macos")]
+const PERMISSION_HINT: &str = "请在“系统设置 > 隐私与安全性 > 完全磁盘访问权限”中允许 auto-copy。";
+#[cfg(windows)]
+const PERMISSION_HINT: &str = "请检查该目录的安全设置，或以管理员身份运行 auto-copy。";
+#[cfg(not(any(target_os = "macos", windows)))]
+const PERMISSION_HINT: &str = "请检查当前用户是否有读取该目录的权限。";
+
+/// 将第二个实例命令行中的路径转交给前端，相对路径按第二个实例的工作目录解析。
+fn hand_off_paths(app: &tauri::AppHandle, args: &[String], cwd: &str) {
+    let paths: Vec<PathBuf> = args
+        .iter()
+        .skip(1)
+        .filter(|arg| !arg.starts_with('-'))
+        .map(|arg| Path::new(cwd).join(arg))
+        .collect();
+    if paths.is_empty() {
+        return;
+    }
+    info!("收到另一个实例传入的路径: {:?}", paths);
+    if let Err(e) = app.emit("open-paths", paths) {
+        error!("发送事件 'open-paths' 失败: {:?}", e);
+    }
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
-        .plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
-            let _ = app
-                .get_webview_window("main")
-                .expect("no main window")
-                .set_focus();
+        .plugin(tauri_plugin_single_instance::init(|app, args, cwd| {
+            // 再次启动时不启动新的后端，而是显示已运行实例的窗口（可能已隐藏到托盘）
+            if let Some(window) = app.get_webview_window("main") {
+                let _ = window.show();
+                let _ = window.unminimize();
+                let _ = window.set_focus();
+            }
+            hand_off_paths(app, &args, &cwd);
         }))
-        .plugin(
-            tauri_plugin_log::Builder::new()
-                .targets([
-                    Target::new(TargetKind::Stdout),
-                    Target::new(TargetKind::Folder {
-                        path: "./logs".into(),
-                        file_name: Some("tauri".into()),
-                    }),
-                    Target::new(TargetKind::Webview),
-                ])
-                .build(),
-        )
         .manage(AppState {
             watcher: Default::default(),
+            next_serial: Default::default(),
+            syncs: Default::default(),
+            subscriptions: Default::default(),
+            scans: Default::default(),
+            pending: Default::default(),
+            groups: Default::default(),
+            stats_feed: Default::default(),
+            profile: Default::default(),
         })
         .plugin(tauri_plugin_store::Builder::new().build())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
         .setup(|app| {
-            let quit_i = MenuItem::with_id(app, "quit", "退出", true, None::<&str>)?;
-            let menu = Menu::with_items(app, &[&quit_i])?;
-            let _tray = TrayIconBuilder::new()
+            // 日志目录需要通过应用句柄解析，因此日志插件在此注册
+            let dir = log_dir(app.handle())?;
+            std::fs::create_dir_all(&dir)?;
+            app.handle().plugin(
+                tauri_plugin_log::Builder::new()
+                    // 实际级别由 `set_log_level` 通过全局最大级别控制
+                    .level(log::LevelFilter::Trace)
+                    .targets([
+                        Target::new(TargetKind::Stdout),
+                        Target::new(TargetKind::Folder {
+                            path: dir,
+                            file_name: Some("tauri".into()),
+                        }),
+                        Target::new(TargetKind::Webview),
+                    ])
+                    .build(),
+            )?;
+            allowlist::init(app.handle());
+            tauri::async_runtime::spawn(pending::run(app.handle().clone()));
+            tauri::async_runtime::spawn(stats_feed::run(app.handle().clone()));
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move { profiles::restore(&handle).await });
+            // 定期输出被合并日志的汇总
+            tauri::async_runtime::spawn(async {
+                let mut ticker = tokio::time::interval(log_dedup::WINDOW);
+                loop {
+                    ticker.tick().await;
+                    log_dedup::flush(false);
+                }
+            });
+            let menu = tray::build_menu(app.handle(), &[])?;
+            let _tray = TrayIconBuilder::with_id(tray::TRAY_ID)
                 .icon(app.default_window_icon().unwrap().clone())
                 .menu(&menu)
                 .show_menu_on_left_click(false)
-                .on_menu_event(|app, event| match event.id.as_ref() {
-                    "quit" => {
-                        app.exit(0);
-                    }
-                    _ => {
-                        warn!("menu item {:?} not handled", event.id);
-                    }
-                })
+                .on_menu_event(|app, event| tray::on_menu_event(app, event.id.as_ref()))
                 .on_tray_icon_event(|tray, event| {
                     if let tauri::tray::TrayIconEvent::Click {
                         button: MouseButton::Left,
@@ -190,19 +1662,193 @@ pub fn run() {
                 .build(app)?;
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![watch, stop_watching])
+        .invoke_handler(tauri::generate_handler![
+            watch,
+            watch_many,
+            stop_watching,
+            reload_config,
+            start_group,
+            stop_group,
+            pause_group,
+            save_preset,
+            list_presets,
+            delete_preset,
+            create_from_preset,
+            force_resync,
+            set_copy_type,
+            set_modified_after,
+            set_notes,
+            redirect_target,
+            rename_watch,
+            get_notes,
+            save_profile,
+            list_profiles,
+            delete_profile,
+            activate_profile,
+            get_active_profile,
+            undo_delete,
+            cancel_sync,
+            invalidate_index,
+            mute_errors,
+            pause_watching,
+            resume_watching,
+            estimate_source,
+            cancel_scan,
+            set_verbose_logging,
+            set_log_level,
+            get_backend_info,
+            export_diagnostics,
+            get_supported_modes,
+            open_logs_folder,
+            list_watches,
+            find_watch_for_path,
+            dump_state,
+            get_manifest,
+            resolve_target,
+            simulate_event,
+            get_global_stats,
+            set_stats_snapshot_interval,
+            get_last_run,
+            get_last_activity,
+            get_watch_uptime,
+            get_error_history,
+            subscribe,
+            unsubscribe
+        ])
         .build(tauri::generate_context!())
         .expect("运行Tauri应用程序时出错")
-        .run(|app, event| {
-            if let tauri::RunEvent::WindowEvent {
-                label,
-                event: tauri::WindowEvent::CloseRequested { api, .. },
-                ..
-            } = event
-            {
-                let window = app.get_webview_window(&label).expect("获取窗口失败");
-                window.hide().expect("隐藏窗口失败");
-                api.prevent_close();
+        .run(|app, event| match event {
+            tauri::RunEvent::WindowEvent { label, event, .. } => match event {
+                tauri::WindowEvent::CloseRequested { api, .. } => {
+                    let window = app.get_webview_window(&label).expect("获取窗口失败");
+                    window.hide().expect("隐藏窗口失败");
+                    api.prevent_close();
+                }
+                // 窗口销毁后清理其事件订阅
+                tauri::WindowEvent::Destroyed => {
+                    app.state::<AppState>()
+                        .subscriptions
+                        .lock()
+                        .unwrap_or_else(|e| e.into_inner())
+                        .remove(&label);
+                }
+                _ => {}
+            },
+            // 托盘菜单退出等所有退出路径都会经过这里
+            tauri::RunEvent::ExitRequested { api, code, .. } => {
+                shutdown::on_exit_requested(app, &api, code)
             }
+            _ => {}
         });
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn stop_waits_for_loop_to_finish_after_cancel() {
+        let shutdown = CancellationToken::new();
+        let flushed = Arc::new(AtomicBool::new(false));
+        let task = {
+            let shutdown = shutdown.clone();
+            let flushed = flushed.clone();
+            tokio::spawn(async move {
+                shutdown.cancelled().await;
+                // 模拟退出前的收尾工作
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                flushed.store(true, Ordering::SeqCst);
+            })
+        };
+        assert!(stop_task(&shutdown, task, Duration::from_secs(5)).await);
+        assert!(shutdown.is_cancelled());
+        assert!(flushed.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn stop_gives_up_on_loop_that_ignores_cancel() {
+        let shutdown = CancellationToken::new();
+        let task = tokio::spawn(tokio::time::sleep(Duration::from_secs(60)));
+        assert!(!stop_task(&shutdown, task, Duration::from_millis(50)).await);
+    }
+
+    #[tokio::test]
+    async fn redirect_target_must_be_writable_directory_outside_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("src"), dir.path().join("dst"));
+        std::fs::create_dir_all(from.join("inner")).unwrap();
+        std::fs::create_dir(&to).unwrap();
+
+        check_target(&from, &to).await.unwrap();
+        // 可写性检查文件已被删除
+        assert_eq!(std::fs::read_dir(&to).unwrap().count(), 0);
+        assert!(check_target(&from, &dir.path().join("missing"))
+            .await
+            .is_err());
+        assert!(check_target(&from, &from.join("inner")).await.is_err());
+    }
+
+    #[test]
+    fn overlapping_watches_containing_path_are_all_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let (outer, inner) = (dir.path().join("outer"), dir.path().join("outer/inner"));
+        std::fs::create_dir_all(&inner).unwrap();
+        let roots = vec![
+            ("b".to_string(), inner.clone()),
+            ("a".to_string(), outer.clone()),
+            ("c".to_string(), dir.path().join("other")),
+        ];
+        // 路径不存在时按字面比较
+        assert_eq!(
+            watches_containing(&inner.join("new/a.txt"), roots.clone()),
+            ["a", "b"]
+        );
+        assert_eq!(
+            watches_containing(&outer.join("inner/../a.txt"), roots.clone()),
+            ["a"]
+        );
+        assert!(watches_containing(&dir.path().join("x.txt"), roots).is_empty());
+    }
+
+    fn watch_config(from: PathBuf, to: PathBuf, canonicalize_paths: bool) -> WatchConfig {
+        WatchConfig {
+            id: "test".to_string(),
+            from,
+            to,
+            copy_type: CopyType::Copy,
+            options: WatchOptions {
+                canonicalize_paths,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn paths_are_resolved_only_when_enabled() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = std::fs::canonicalize(dir.path()).unwrap();
+        std::fs::create_dir_all(real.join("src/sub")).unwrap();
+        std::fs::create_dir(real.join("dst")).unwrap();
+        let (from, to) = (real.join("src/sub/.."), real.join("./dst"));
+
+        let kept = resolve_paths(&watch_config(from.clone(), to.clone(), false))
+            .await
+            .unwrap();
+        assert_eq!((kept.from, kept.to), (from.clone(), to.clone()));
+
+        let resolved = resolve_paths(&watch_config(from, to, true)).await.unwrap();
+        assert_eq!(
+            (resolved.from, resolved.to),
+            (real.join("src"), real.join("dst"))
+        );
+    }
+
+    #[tokio::test]
+    async fn unresolvable_path_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = watch_config(dir.path().join("missing"), dir.path().to_path_buf(), true);
+        assert!(resolve_paths(&config).await.is_err());
+    }
+}
@@ -0,0 +1,98 @@
+//! 合并短时间内重复出现的同类日志。
+//!
+//! 事件风暴时处理器会输出成千上万条“已成功复制文件”之类的日志，淹没真正的问题。
+//! 同一类日志在一个时间窗口内只输出第一条，其余计数后合并为一行汇总。
+//! 错误日志不经过这里；开启详细日志后不做合并。
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        LazyLock, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use log::info;
+
+/// 合并日志的时间窗口。
+pub const WINDOW: Duration = Duration::from_secs(2);
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+static BURSTS: LazyLock<Mutex<HashMap<&'static str, Burst>>> = LazyLock::new(Default::default);
+
+struct Burst {
+    started: Instant,
+    suppressed: u64,
+}
+
+/// 开启后所有日志都原样输出。
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+    if verbose {
+        flush(true);
+    }
+}
+
+/// 判断 `key` 类别的这条日志是否应当输出，不输出时计入合并数量。
+pub fn should_log(key: &'static str) -> bool {
+    if VERBOSE.load(Ordering::Relaxed) {
+        return true;
+    }
+    let mut bursts = BURSTS.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    match bursts.get_mut(key) {
+        Some(burst) if now.duration_since(burst.started) < WINDOW => {
+            burst.suppressed += 1;
+            false
+        }
+        Some(burst) => {
+            report(key, burst.suppressed);
+            *burst = Burst {
+                started: now,
+                suppressed: 0,
+            };
+            true
+        }
+        None => {
+            bursts.insert(
+                key,
+                Burst {
+                    started: now,
+                    suppressed: 0,
+                },
+            );
+            true
+        }
+    }
+}
+
+/// 输出已过期时间窗口的汇总行。`all` 为 true 时不论是否过期全部输出。
+pub fn flush(all: bool) {
+    let mut bursts = BURSTS.lock().unwrap_or_else(|e| e.into_inner());
+    bursts.retain(|key, burst| {
+        if all || burst.started.elapsed() >= WINDOW {
+            report(key, burst.suppressed);
+            false
+        } else {
+            true
+        }
+    });
+}
+
+fn report(key: &str, suppressed: u64) {
+    if suppressed > 0 {
+        info!("{}: 另有 {} 条同类日志已合并", key, suppressed);
+    }
+}
+
+/// 以 `$key` 为类别输出可合并的 info 日志。
+macro_rules! info_collapsed {
+    ($key:expr, $($arg:tt)+) => {
+        if $crate::log_dedup::should_log($key) {
+            log::info!($($arg)+);
+        }
+    };
+}
+
+pub(crate) use info_collapsed;
@@ -0,0 +1,48 @@
+//! 运行时调整日志级别。
+//!
+//! 日志插件以最详细的级别构建，实际输出由 `log` 的全局最大级别控制，
+//! 因此修改全局级别即可在不重启的情况下提高或降低日志的详细程度。
+//! 临时修改会在到期后恢复为修改前的级别，期间再次临时修改不会覆盖最初的级别。
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use log::{info, LevelFilter};
+
+/// 修改的序号，到期的恢复任务只在期间没有新的修改时生效。
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+/// 临时修改前的级别，没有进行中的临时修改时为 `None`。
+static BASELINE: Mutex<Option<LevelFilter>> = Mutex::new(None);
+
+/// 将日志级别设为 `level`，`revert_after` 不为空时到期后恢复为修改前的级别。
+pub fn set(level: LevelFilter, revert_after: Option<Duration>) {
+    let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+    let mut baseline = BASELINE.lock().unwrap_or_else(|e| e.into_inner());
+    match revert_after {
+        Some(duration) => {
+            let previous = *baseline.get_or_insert_with(log::max_level);
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(duration).await;
+                revert(generation, previous);
+            });
+        }
+        None => *baseline = None,
+    }
+    log::set_max_level(level);
+    info!("日志级别已改为 {}，恢复时间: {:?}。", level, revert_after);
+}
+
+fn revert(generation: u64, previous: LevelFilter) {
+    let mut baseline = BASELINE.lock().unwrap_or_else(|e| e.into_inner());
+    if GENERATION.load(Ordering::SeqCst) != generation {
+        return;
+    }
+    *baseline = None;
+    log::set_max_level(previous);
+    info!("临时日志级别已到期，恢复为 {}。", previous);
+}
@@ -0,0 +1,95 @@
+//! 目标目录中的清单文件。
+//!
+//! 开启后目标目录下的 [`MANIFEST_FILE`] 记录每个已同步文件在同步时的大小、修改时间和
+//! SHA-256 摘要，供外部工具校验目标内容。清单与同步索引一起定期写回磁盘，
+//! 不会在每个文件同步后立即写入。摘要根据同步成功后的源文件计算，
+//! 因此移动模式下源文件已不存在，清单中不会留下记录。
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+
+use crate::index::IndexEntry;
+
+/// 清单文件在目标目录下的名称。
+pub const MANIFEST_FILE: &str = ".auto-copy-manifest.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    pub len: u64,
+    /// 修改时间，自 UNIX 纪元起的毫秒数。
+    pub modified_ms: u64,
+    /// 小写十六进制的 SHA-256 摘要。
+    pub sha256: String,
+}
+
+/// 以相对源目录的路径为键的清单，按路径排序写出。
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct Manifest {
+    pub entries: BTreeMap<PathBuf, ManifestEntry>,
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl Manifest {
+    /// 从清单文件加载，文件不存在或已损坏时返回空清单。
+    pub fn load(file: &Path) -> Self {
+        match std::fs::read(file) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!("清单文件 {:?} 已损坏，将重新建立: {:?}", file, e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn record(&mut self, relative: PathBuf, entry: ManifestEntry) {
+        if self.entries.get(&relative) != Some(&entry) {
+            self.entries.insert(relative, entry);
+            self.dirty = true;
+        }
+    }
+
+    /// 移除路径本身以及其下所有文件的记录。
+    pub fn forget(&mut self, relative: &Path) {
+        let before = self.entries.len();
+        self.entries.retain(|path, _| !path.starts_with(relative));
+        self.dirty |= self.entries.len() != before;
+    }
+
+    /// 有未保存的修改时序列化清单并清除修改标记。
+    pub fn take_snapshot(&mut self) -> Option<Vec<u8>> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        serde_json::to_vec_pretty(self).ok()
+    }
+}
+
+/// 读取文件计算清单记录。
+pub async fn entry_for(path: &Path, index_entry: IndexEntry) -> io::Result<ManifestEntry> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(ManifestEntry {
+        len: index_entry.len,
+        modified_ms: index_entry.modified_ms,
+        sha256: format!("{:x}", hasher.finalize()),
+    })
+}
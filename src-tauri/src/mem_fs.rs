@@ -0,0 +1,649 @@
+//! 测试用的内存文件系统，记录处理器发出的每个修改操作，不触碰磁盘。
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    hash::{DefaultHasher, Hasher},
+    io,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, SystemTime},
+};
+
+use crate::fs_ops::{Digest, FsOps};
+
+/// [`MemFs`] 上执行过的修改操作。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    CreateDirAll(PathBuf),
+    Copy(PathBuf, PathBuf),
+    DeltaCopy(PathBuf, PathBuf),
+    Reflink(PathBuf, PathBuf),
+    RemoveFile(PathBuf),
+    Trash(PathBuf),
+    Rename(PathBuf, PathBuf),
+    HardLink(PathBuf, PathBuf),
+    CreateFifo(PathBuf),
+    RemoveDir(PathBuf),
+    SymlinkFile(PathBuf, PathBuf),
+    SymlinkDir(PathBuf, PathBuf),
+    Junction(PathBuf, PathBuf),
+    SetReadonly(PathBuf, bool),
+}
+
+#[derive(Debug, Clone)]
+struct MemFile {
+    data: Vec<u8>,
+    modified: SystemTime,
+    readonly: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Failure {
+    Kind(io::ErrorKind),
+    Os(i32),
+}
+
+#[derive(Default)]
+struct Inner {
+    files: HashMap<PathBuf, MemFile>,
+    dirs: BTreeSet<PathBuf>,
+    /// 链接路径 -> (指向的路径, 是否为目录链接)。
+    links: BTreeMap<PathBuf, (PathBuf, bool)>,
+    ops: Vec<Op>,
+    /// 操作名称 -> 该操作返回的错误，用于模拟不支持或失败的操作。
+    failures: HashMap<&'static str, Failure>,
+    /// 自动分配修改时间用的时钟，保证后写入的文件更新。
+    clock: u64,
+    /// 为真时 `copy` 只写入一半内容，模拟不可靠的介质。
+    short_copies: bool,
+    /// `volume_id` 对所有路径返回的文件系统标识。
+    volume: u64,
+}
+
+/// 以路径为键保存文件内容、目录和链接的内存文件系统。
+///
+/// 删除只读文件会像 Windows 一样返回 `PermissionDenied`；
+/// 写入文件要求父目录已存在，以便断言处理器先创建了目录。
+#[derive(Default)]
+pub struct MemFs {
+    inner: Mutex<Inner>,
+}
+
+impl MemFs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 创建目录及其所有上级目录，不记录为操作。
+    pub fn add_dir(&self, path: impl AsRef<Path>) {
+        self.lock().add_dir(path.as_ref());
+    }
+
+    /// 写入文件并创建其上级目录，不记录为操作。
+    pub fn add_file(&self, path: impl AsRef<Path>, data: impl Into<Vec<u8>>) {
+        let mut inner = self.lock();
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            inner.add_dir(parent);
+        }
+        let modified = inner.tick();
+        inner.files.insert(
+            path.to_path_buf(),
+            MemFile {
+                data: data.into(),
+                modified,
+                readonly: false,
+            },
+        );
+    }
+
+    /// 创建指向 `original` 的链接，不记录为操作。
+    pub fn add_symlink(&self, link: impl AsRef<Path>, original: impl AsRef<Path>, dir: bool) {
+        let mut inner = self.lock();
+        let link = link.as_ref();
+        if let Some(parent) = link.parent() {
+            inner.add_dir(parent);
+        }
+        inner
+            .links
+            .insert(link.to_path_buf(), (original.as_ref().to_path_buf(), dir));
+    }
+
+    pub fn set_modified(&self, path: impl AsRef<Path>, modified: SystemTime) {
+        if let Some(file) = self.lock().files.get_mut(path.as_ref()) {
+            file.modified = modified;
+        }
+    }
+
+    pub fn set_readonly_flag(&self, path: impl AsRef<Path>, readonly: bool) {
+        if let Some(file) = self.lock().files.get_mut(path.as_ref()) {
+            file.readonly = readonly;
+        }
+    }
+
+    /// 之后的 `copy` 只写入源文件的前一半内容但仍报告成功。
+    pub fn short_copies(&self) {
+        self.lock().short_copies = true;
+    }
+
+    /// 让名为 `op` 的操作（与 [`FsOps`] 的方法同名）之后都以 `kind` 失败。
+    pub fn fail(&self, op: &'static str, kind: io::ErrorKind) {
+        self.lock().failures.insert(op, Failure::Kind(kind));
+    }
+
+    /// 与 [`MemFs::fail`] 相同，但返回带系统错误码 `code` 的错误。
+    pub fn fail_os(&self, op: &'static str, code: i32) {
+        self.lock().failures.insert(op, Failure::Os(code));
+    }
+
+    pub fn read(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        let inner = self.lock();
+        let path = inner.resolve(path.as_ref());
+        inner.files.get(&path).map(|file| file.data.clone())
+    }
+
+    pub fn readonly(&self, path: impl AsRef<Path>) -> bool {
+        self.lock()
+            .files
+            .get(path.as_ref())
+            .is_some_and(|file| file.readonly)
+    }
+
+    /// 到目前为止执行过的修改操作。
+    pub fn ops(&self) -> Vec<Op> {
+        self.lock().ops.clone()
+    }
+
+    /// 记录操作，配置了失败时返回对应的错误。
+    /// 之后 `volume_id` 对所有路径返回 `volume`，模拟源目录所在的卷被卸载或替换。
+    pub fn set_volume(&self, volume: u64) {
+        self.lock().volume = volume;
+    }
+
+    fn record(&self, name: &'static str, op: Op) -> io::Result<MutexGuard<'_, Inner>> {
+        let mut inner = self.lock();
+        inner.check_failure(name)?;
+        inner.ops.push(op);
+        Ok(inner)
+    }
+}
+
+impl Inner {
+    /// 通过 [`MemFs::fail`] 为名为 `name` 的操作设置了错误时返回该错误。
+    fn check_failure(&self, name: &'static str) -> io::Result<()> {
+        match self.failures.get(name) {
+            Some(Failure::Kind(kind)) => {
+                Err(io::Error::new(*kind, format!("模拟的 {} 失败", name)))
+            }
+            Some(Failure::Os(code)) => Err(io::Error::from_raw_os_error(*code)),
+            None => Ok(()),
+        }
+    }
+
+    fn tick(&mut self) -> SystemTime {
+        self.clock += 1;
+        SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 + self.clock)
+    }
+
+    fn add_dir(&mut self, path: &Path) {
+        for ancestor in path.ancestors() {
+            if ancestor.as_os_str().is_empty() {
+                break;
+            }
+            self.dirs.insert(ancestor.to_path_buf());
+        }
+    }
+
+    /// 跟随路径本身及其上级目录中的链接得到实际路径，链接指向相对路径时相对链接所在目录解析。
+    fn resolve(&self, path: &Path) -> PathBuf {
+        let mut path = path.to_path_buf();
+        for _ in 0..8 {
+            let Some((link, original)) = path
+                .ancestors()
+                .find_map(|ancestor| Some((ancestor, &self.links.get(ancestor)?.0)))
+            else {
+                break;
+            };
+            let original = match link.parent() {
+                Some(parent) if original.is_relative() => parent.join(original),
+                _ => original.clone(),
+            };
+            path = match path.strip_prefix(link) {
+                Ok(rest) if !rest.as_os_str().is_empty() => original.join(rest),
+                _ => original,
+            };
+        }
+        path
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.files.contains_key(&self.resolve(path))
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.dirs.contains(&self.resolve(path))
+    }
+
+    fn file(&self, path: &Path) -> io::Result<&MemFile> {
+        self.files
+            .get(&self.resolve(path))
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn require_parent(&self, path: &Path) -> io::Result<()> {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() && !self.is_dir(parent) => {
+                Err(not_found(parent))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn write(&mut self, path: &Path, data: Vec<u8>) -> io::Result<()> {
+        self.require_parent(path)?;
+        if self.dirs.contains(path) {
+            return Err(io::Error::new(io::ErrorKind::IsADirectory, "目标是目录"));
+        }
+        if self.files.get(path).is_some_and(|file| file.readonly) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "文件只读"));
+        }
+        let modified = self.tick();
+        self.files.insert(
+            path.to_path_buf(),
+            MemFile {
+                data,
+                modified,
+                readonly: false,
+            },
+        );
+        Ok(())
+    }
+
+    /// 删除路径及其下的所有条目。
+    fn remove_tree(&mut self, path: &Path) {
+        self.files.retain(|file, _| !file.starts_with(path));
+        self.dirs.retain(|dir| !dir.starts_with(path));
+        self.links.retain(|link, _| !link.starts_with(path));
+    }
+
+    fn children(&self, path: &Path) -> Vec<PathBuf> {
+        let is_child = |entry: &&PathBuf| entry.parent() == Some(path);
+        let mut children: HashSet<PathBuf> = HashSet::new();
+        children.extend(self.files.keys().filter(is_child).cloned());
+        children.extend(self.dirs.iter().filter(is_child).cloned());
+        children.extend(self.links.keys().filter(is_child).cloned());
+        let mut children: Vec<PathBuf> = children.into_iter().collect();
+        children.sort();
+        children
+    }
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{:?} 不存在", path))
+}
+
+fn owned(from: &Path, to: &Path) -> (PathBuf, PathBuf) {
+    (from.to_path_buf(), to.to_path_buf())
+}
+
+impl FsOps for MemFs {
+    async fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut inner = self.record("create_dir_all", Op::CreateDirAll(path.to_path_buf()))?;
+        if inner.files.contains_key(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "已存在同名文件",
+            ));
+        }
+        inner.add_dir(path);
+        Ok(())
+    }
+
+    async fn copy(
+        &self,
+        from: &Path,
+        to: &Path,
+        _buffer_size: usize,
+        progress: &mut (dyn FnMut(u64) -> ControlFlow<()> + Send),
+    ) -> io::Result<u64> {
+        let (a, b) = owned(from, to);
+        let mut inner = self.record("copy", Op::Copy(a, b))?;
+        let mut data = inner.file(from)?.data.clone();
+        let len = data.len() as u64;
+        if progress(len).is_break() {
+            return Err(io::Error::new(io::ErrorKind::Interrupted, "复制已取消"));
+        }
+        if inner.short_copies {
+            data.truncate(data.len() / 2);
+        }
+        inner.write(to, data)?;
+        Ok(len)
+    }
+
+    async fn delta_copy(
+        &self,
+        from: &Path,
+        to: &Path,
+        _block_size: usize,
+        progress: &mut (dyn FnMut(u64) -> ControlFlow<()> + Send),
+    ) -> io::Result<u64> {
+        let (a, b) = owned(from, to);
+        let mut inner = self.record("delta_copy", Op::DeltaCopy(a, b))?;
+        let data = inner.file(from)?.data.clone();
+        let old = inner.file(to)?.data.clone();
+        let changed = data
+            .iter()
+            .enumerate()
+            .filter(|(i, byte)| old.get(*i) != Some(byte))
+            .count() as u64;
+        let _ = progress(data.len() as u64);
+        inner.write(to, data)?;
+        Ok(changed)
+    }
+
+    async fn reflink(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (a, b) = owned(from, to);
+        let mut inner = self.record("reflink", Op::Reflink(a, b))?;
+        let data = inner.file(from)?.data.clone();
+        inner.write(to, data)
+    }
+
+    async fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut inner = self.record("remove_file", Op::RemoveFile(path.to_path_buf()))?;
+        if inner.links.remove(path).is_some() {
+            return Ok(());
+        }
+        match inner.files.get(path) {
+            Some(file) if file.readonly => {
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, "文件只读"))
+            }
+            Some(_) => {
+                inner.files.remove(path);
+                Ok(())
+            }
+            None => Err(not_found(path)),
+        }
+    }
+
+    async fn trash(&self, path: &Path) -> io::Result<()> {
+        let mut inner = self.record("trash", Op::Trash(path.to_path_buf()))?;
+        inner.remove_tree(path);
+        Ok(())
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (a, b) = owned(from, to);
+        let mut inner = self.record("rename", Op::Rename(a, b))?;
+        inner.require_parent(to)?;
+        let moved = |path: &Path| to.join(path.strip_prefix(from).unwrap_or(path));
+        let exists = inner.files.contains_key(from)
+            || inner.dirs.contains(from)
+            || inner.links.contains_key(from);
+        if !exists {
+            return Err(not_found(from));
+        }
+        let files: Vec<_> = inner
+            .files
+            .iter()
+            .filter(|(path, _)| path.starts_with(from))
+            .map(|(path, file)| (path.clone(), file.clone()))
+            .collect();
+        let dirs: Vec<_> = inner
+            .dirs
+            .iter()
+            .filter(|dir| dir.starts_with(from))
+            .cloned()
+            .collect();
+        let links: Vec<_> = inner
+            .links
+            .iter()
+            .filter(|(link, _)| link.starts_with(from))
+            .map(|(link, original)| (link.clone(), original.clone()))
+            .collect();
+        inner.remove_tree(from);
+        inner.files.remove(to);
+        for (path, file) in files {
+            inner.files.insert(moved(&path), file);
+        }
+        for dir in dirs {
+            inner.dirs.insert(moved(&dir));
+        }
+        for (link, original) in links {
+            inner.links.insert(moved(&link), original);
+        }
+        Ok(())
+    }
+
+    async fn hard_link(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let (a, b) = owned(original, link);
+        let mut inner = self.record("hard_link", Op::HardLink(a, b))?;
+        let data = inner.file(original)?.data.clone();
+        inner.write(link, data)
+    }
+
+    async fn create_fifo(&self, _like: &Path, path: &Path) -> io::Result<()> {
+        drop(self.record("create_fifo", Op::CreateFifo(path.to_path_buf()))?);
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "内存文件系统不支持命名管道",
+        ))
+    }
+
+    async fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let mut inner = self.record("remove_dir", Op::RemoveDir(path.to_path_buf()))?;
+        if inner.links.remove(path).is_some() {
+            return Ok(());
+        }
+        if !inner.dirs.contains(path) {
+            return Err(not_found(path));
+        }
+        if !inner.children(path).is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::DirectoryNotEmpty,
+                "目录不为空",
+            ));
+        }
+        inner.dirs.remove(path);
+        Ok(())
+    }
+
+    async fn symlink_file(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let (a, b) = owned(original, link);
+        let mut inner = self.record("symlink_file", Op::SymlinkFile(a, b))?;
+        inner.require_parent(link)?;
+        inner
+            .links
+            .insert(link.to_path_buf(), (original.to_path_buf(), false));
+        Ok(())
+    }
+
+    async fn symlink_dir(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let (a, b) = owned(original, link);
+        let mut inner = self.record("symlink_dir", Op::SymlinkDir(a, b))?;
+        inner.require_parent(link)?;
+        inner
+            .links
+            .insert(link.to_path_buf(), (original.to_path_buf(), true));
+        Ok(())
+    }
+
+    async fn junction(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let (a, b) = owned(original, link);
+        let mut inner = self.record("junction", Op::Junction(a, b))?;
+        inner.require_parent(link)?;
+        inner
+            .links
+            .insert(link.to_path_buf(), (original.to_path_buf(), true));
+        Ok(())
+    }
+
+    async fn set_readonly(&self, path: &Path, readonly: bool) -> io::Result<()> {
+        let mut inner = self.record(
+            "set_readonly",
+            Op::SetReadonly(path.to_path_buf(), readonly),
+        )?;
+        let resolved = inner.resolve(path);
+        let file = inner
+            .files
+            .get_mut(&resolved)
+            .ok_or_else(|| not_found(path))?;
+        file.readonly = readonly;
+        Ok(())
+    }
+
+    async fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        self.lock()
+            .links
+            .get(path)
+            .map(|(original, _)| original.clone())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "不是链接"))
+    }
+
+    async fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let inner = self.lock();
+        if !inner.is_dir(path) {
+            return Err(not_found(path));
+        }
+        Ok(inner.children(&inner.resolve(path)))
+    }
+
+    async fn digest(&self, path: &Path) -> io::Result<Digest> {
+        let inner = self.lock();
+        let data = &inner.file(path)?.data;
+        let mut hasher = DefaultHasher::new();
+        hasher.write(data);
+        Ok(Digest {
+            len: data.len() as u64,
+            hash: hasher.finish(),
+        })
+    }
+
+    async fn read_head(&self, path: &Path, limit: u64) -> io::Result<Vec<u8>> {
+        let inner = self.lock();
+        let data = &inner.file(path)?.data;
+        Ok(data[..data.len().min(limit as usize)].to_vec())
+    }
+
+    async fn len(&self, path: &Path) -> io::Result<u64> {
+        Ok(self.lock().file(path)?.data.len() as u64)
+    }
+
+    async fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        Ok(self.lock().file(path)?.modified)
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let inner = self.lock();
+        inner.is_file(path) || inner.is_dir(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.lock().is_file(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.lock().is_dir(path)
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        self.lock().links.contains_key(path)
+    }
+
+    fn is_readonly(&self, path: &Path) -> bool {
+        let inner = self.lock();
+        inner.file(path).is_ok_and(|file| file.readonly)
+    }
+
+    fn volume_id(&self, _path: &Path) -> io::Result<u64> {
+        let inner = self.lock();
+        inner.check_failure("volume_id")?;
+        Ok(inner.volume)
+    }
+}
+
+/// 测试中源目录的路径。
+pub const SRC: &str = "/src";
+/// 测试中目标目录的路径。
+pub const DST: &str = "/dst";
+
+/// 运行处理器所需的监视。
+///
+/// 默认在 [`MemFs`] 上运行，源目录为 [`SRC`]，目标目录为 [`DST`]，两者都已存在。
+pub struct TestWatch<F = MemFs> {
+    pub fs: F,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub options: crate::config::WatchOptions,
+    pub filter: crate::filter::PathFilter,
+    pub state: crate::watch_state::WatchState,
+}
+
+impl TestWatch {
+    pub fn new(options: crate::config::WatchOptions) -> Self {
+        let fs = MemFs::new();
+        fs.add_dir(SRC);
+        fs.add_dir(DST);
+        TestWatch::with_fs(fs, SRC, DST, options)
+    }
+}
+
+impl<F: FsOps> TestWatch<F> {
+    /// 在 `fs` 上以 `from` 为源目录、`to` 为目标目录运行处理器，例如在临时目录中使用真实文件系统。
+    pub fn with_fs(
+        fs: F,
+        from: impl Into<PathBuf>,
+        to: impl Into<PathBuf>,
+        options: crate::config::WatchOptions,
+    ) -> Self {
+        let renamer = options
+            .rename_rule
+            .as_ref()
+            .map(crate::rename::Renamer::new)
+            .transpose()
+            .expect("测试中的重命名规则有效");
+        let filter =
+            crate::filter::PathFilter::new(&options.filters).expect("测试中的过滤规则有效");
+        let state = crate::watch_state::WatchState::new(
+            crate::CopyType::Copy,
+            renamer,
+            None,
+            None,
+            options.max_ops_per_sec,
+        );
+        Self {
+            fs,
+            from: from.into(),
+            to: to.into(),
+            options,
+            filter,
+            state,
+        }
+    }
+
+    pub fn ctx(&self) -> crate::file_processor::ProcessContext<'_, F> {
+        crate::file_processor::ProcessContext {
+            fs: &self.fs,
+            from: &self.from,
+            to: &self.to,
+            options: &self.options,
+            filter: &self.filter,
+            state: &self.state,
+            progress: None,
+        }
+    }
+}
+
+/// 源目录下的路径。
+pub fn src(relative: &str) -> PathBuf {
+    Path::new(SRC).join(relative)
+}
+
+/// 目标目录下的路径。
+pub fn dst(relative: &str) -> PathBuf {
+    Path::new(DST).join(relative)
+}
@@ -0,0 +1,297 @@
+//! 复制完成后对目标文件元数据的处理。
+
+use std::path::Path;
+
+use log::warn;
+
+/// 将源文件的 uid/gid 应用到目标文件。
+///
+/// 缺少权限（通常是非 root 运行）时只记录警告，不视为复制失败。
+#[cfg(unix)]
+pub async fn preserve_ownership(source: &Path, target: &Path) -> anyhow::Result<()> {
+    let (source, target) = (source.to_path_buf(), target.to_path_buf());
+    tokio::task::spawn_blocking(move || preserve_ownership_blocking(&source, &target)).await?
+}
+
+#[cfg(unix)]
+fn preserve_ownership_blocking(source: &Path, target: &Path) -> anyhow::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(source)?;
+    match std::os::unix::fs::chown(target, Some(metadata.uid()), Some(metadata.gid())) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => {
+            warn!("没有权限保留文件所有者: {:?}, {:?}", target, e);
+            Ok(())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 将目标文件的权限位设为 `mode`，覆盖复制时保留的源文件权限。
+#[cfg(unix)]
+pub async fn force_mode(target: &Path, mode: u32) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    tokio::fs::set_permissions(target, std::fs::Permissions::from_mode(mode & 0o7777)).await?;
+    Ok(())
+}
+
+/// 清除目标文件权限中在 `mask` 里置位的位，权限不变时不做修改。
+#[cfg(unix)]
+pub async fn mask_mode(target: &Path, mask: u32) -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = tokio::fs::metadata(target).await?.permissions().mode() & 0o7777;
+    let masked = mode & !(mask & 0o7777);
+    if masked != mode {
+        tokio::fs::set_permissions(target, std::fs::Permissions::from_mode(masked)).await?;
+    }
+    Ok(())
+}
+
+/// 将源目录的修改时间应用到目标目录。
+pub async fn copy_dir_mtime(source: &Path, target: &Path) -> anyhow::Result<()> {
+    let (source, target) = (source.to_path_buf(), target.to_path_buf());
+    tokio::task::spawn_blocking(move || {
+        let modified = std::fs::metadata(&source)?.modified()?;
+        open_dir(&target)?.set_modified(modified)?;
+        Ok(())
+    })
+    .await?
+}
+
+#[cfg(windows)]
+fn open_dir(path: &Path) -> std::io::Result<std::fs::File> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_FLAG_BACKUP_SEMANTICS, FILE_WRITE_ATTRIBUTES,
+    };
+
+    // 打开目录句柄需要 FILE_FLAG_BACKUP_SEMANTICS
+    std::fs::OpenOptions::new()
+        .access_mode(FILE_WRITE_ATTRIBUTES)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)
+}
+
+#[cfg(not(windows))]
+fn open_dir(path: &Path) -> std::io::Result<std::fs::File> {
+    std::fs::File::open(path)
+}
+
+/// 源文件有多个硬链接时返回其 (设备号, inode)，用于识别指向同一文件的不同路径。
+///
+/// 仅在 Unix 上可用，其他平台总是返回 `None`。
+#[cfg(unix)]
+pub async fn shared_inode(path: &Path) -> std::io::Result<Option<(u64, u64)>> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = tokio::fs::metadata(path).await?;
+    Ok((metadata.nlink() > 1).then(|| (metadata.dev(), metadata.ino())))
+}
+
+#[cfg(not(unix))]
+pub async fn shared_inode(path: &Path) -> std::io::Result<Option<(u64, u64)>> {
+    let _ = path;
+    Ok(None)
+}
+
+/// 将源文件的扩展属性（Unix xattr）或备用数据流（Windows NTFS）复制到目标文件。
+///
+/// 文件系统不支持时只记录警告；单个属性复制失败（例如没有权限写入 `security.*`）跳过该属性。
+pub async fn copy_xattrs(source: &Path, target: &Path) -> anyhow::Result<()> {
+    let (source, target) = (source.to_path_buf(), target.to_path_buf());
+    tokio::task::spawn_blocking(move || copy_xattrs_blocking(&source, &target)).await?
+}
+
+#[cfg(unix)]
+fn copy_xattrs_blocking(source: &Path, target: &Path) -> anyhow::Result<()> {
+    let names = match xattr::list(source) {
+        Ok(names) => names,
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => {
+            warn!("文件系统不支持扩展属性: {:?}", source);
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    for name in names {
+        let Some(value) = xattr::get(source, &name)? else {
+            continue;
+        };
+        if let Err(e) = xattr::set(target, &name, &value) {
+            warn!("复制扩展属性 {:?} 到 {:?} 失败: {:?}", name, target, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn copy_xattrs_blocking(source: &Path, target: &Path) -> anyhow::Result<()> {
+    for stream in windows_streams::list(source)? {
+        let mut from = source.as_os_str().to_os_string();
+        from.push(&stream);
+        let mut to = target.as_os_str().to_os_string();
+        to.push(&stream);
+        if let Err(e) = std::fs::copy(&from, &to) {
+            warn!("复制备用数据流 {:?} 到 {:?} 失败: {:?}", stream, target, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn copy_xattrs_blocking(source: &Path, _target: &Path) -> anyhow::Result<()> {
+    warn!("当前平台不支持复制扩展属性: {:?}", source);
+    Ok(())
+}
+
+#[cfg(windows)]
+mod windows_streams {
+    use std::{ffi::OsString, io, os::windows::ffi::OsStrExt, path::Path};
+
+    use windows_sys::Win32::{
+        Foundation::{ERROR_HANDLE_EOF, INVALID_HANDLE_VALUE},
+        Storage::FileSystem::{
+            FindClose, FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard,
+            WIN32_FIND_STREAM_DATA,
+        },
+    };
+
+    /// 列出文件的备用数据流名称（形如 `:Zone.Identifier`），不包括默认的数据流。
+    pub fn list(path: &Path) -> io::Result<Vec<OsString>> {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+        // SAFETY: WIN32_FIND_STREAM_DATA 是纯数据结构，全零是合法值。
+        let mut data: WIN32_FIND_STREAM_DATA = unsafe { std::mem::zeroed() };
+        // SAFETY: `wide` 以 0 结尾，`data` 的类型与 FindStreamInfoStandard 要求的一致。
+        let handle = unsafe {
+            FindFirstStreamW(
+                wide.as_ptr(),
+                FindStreamInfoStandard,
+                (&mut data as *mut WIN32_FIND_STREAM_DATA).cast(),
+                0,
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            let err = io::Error::last_os_error();
+            return if err.raw_os_error() == Some(ERROR_HANDLE_EOF as i32) {
+                Ok(Vec::new())
+            } else {
+                Err(err)
+            };
+        }
+        let mut streams = Vec::new();
+        loop {
+            let len = data
+                .cStreamName
+                .iter()
+                .position(|&c| c == 0)
+                .unwrap_or(data.cStreamName.len());
+            let name = String::from_utf16_lossy(&data.cStreamName[..len]);
+            // 流名称的格式为 ":名称:$DATA"，默认数据流为 "::$DATA"
+            if let Some(stream) = name.strip_suffix(":$DATA") {
+                if !stream.is_empty() && stream != ":" {
+                    streams.push(OsString::from(stream));
+                }
+            }
+            // SAFETY: `handle` 是有效的查找句柄，`data` 的类型正确。
+            if unsafe { FindNextStreamW(handle, (&mut data as *mut WIN32_FIND_STREAM_DATA).cast()) }
+                == 0
+            {
+                break;
+            }
+        }
+        // SAFETY: `handle` 是 FindFirstStreamW 返回的有效句柄，只关闭一次。
+        unsafe { FindClose(handle) };
+        Ok(streams)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(unix)]
+    use std::os::unix::fs::MetadataExt;
+
+    use super::*;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn ownership_is_copied_to_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, target) = (dir.path().join("source"), dir.path().join("target"));
+        std::fs::write(&source, "data").unwrap();
+        std::fs::write(&target, "data").unwrap();
+        // 只有 root 能把文件交给其他用户，其他情况下源文件保持当前用户所有
+        let (uid, gid) = if unsafe { libc::geteuid() } == 0 {
+            std::os::unix::fs::chown(&source, Some(1234), Some(1234)).unwrap();
+            (1234, 1234)
+        } else {
+            let metadata = std::fs::metadata(&source).unwrap();
+            (metadata.uid(), metadata.gid())
+        };
+        preserve_ownership(&source, &target).await.unwrap();
+        let metadata = std::fs::metadata(&target).unwrap();
+        assert_eq!((metadata.uid(), metadata.gid()), (uid, gid));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn missing_privileges_only_warn() {
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("target");
+        std::fs::write(&target, "data").unwrap();
+        let before = std::fs::metadata(&target).unwrap().uid();
+        // 根目录属于 root，非 root 用户无法把文件交给 root
+        preserve_ownership(Path::new("/"), &target).await.unwrap();
+        assert_eq!(std::fs::metadata(&target).unwrap().uid(), before);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn xattr_survives_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, target) = (dir.path().join("source"), dir.path().join("target"));
+        std::fs::write(&source, "data").unwrap();
+        std::fs::write(&target, "data").unwrap();
+        if let Err(e) = xattr::set(&source, "user.auto-copy.test", b"tag") {
+            // 临时目录所在的文件系统不支持用户扩展属性时无法验证
+            eprintln!("跳过: 无法设置扩展属性: {:?}", e);
+            return;
+        }
+        copy_xattrs(&source, &target).await.unwrap();
+        assert_eq!(
+            xattr::get(&target, "user.auto-copy.test").unwrap(),
+            Some(b"tag".to_vec())
+        );
+    }
+
+    #[cfg(windows)]
+    #[tokio::test]
+    async fn alternate_data_stream_survives_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, target) = (dir.path().join("source"), dir.path().join("target"));
+        std::fs::write(&source, "data").unwrap();
+        std::fs::write(&target, "data").unwrap();
+        let stream = |path: &Path| {
+            let mut path = path.as_os_str().to_os_string();
+            path.push(":auto-copy.test");
+            std::path::PathBuf::from(path)
+        };
+        std::fs::write(stream(&source), "tag").unwrap();
+        copy_xattrs(&source, &target).await.unwrap();
+        assert_eq!(std::fs::read(stream(&target)).unwrap(), b"tag");
+    }
+
+    #[tokio::test]
+    async fn file_without_attributes_copies_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let (source, target) = (dir.path().join("source"), dir.path().join("target"));
+        std::fs::write(&source, "data").unwrap();
+        std::fs::write(&target, "data").unwrap();
+        copy_xattrs(&source, &target).await.unwrap();
+        assert_eq!(std::fs::read(&target).unwrap(), b"data");
+    }
+}
@@ -0,0 +1,78 @@
+//! 发现源目录所在的卷被卸载，避免把“源目录变空”当作大量删除同步到目标。
+//!
+//! 源目录本身是挂载点时，卷卸载后它仍然存在，只是变成了底层文件系统上的空目录，
+//! 轮询和对账会把其中的所有文件都当作已删除。监视启动时记录源目录所在文件系统的标识，
+//! 之后标识改变或源目录无法访问即视为卷已卸载。
+
+use std::{io, path::Path, time::Duration};
+
+use crate::{fs_ops::FsOps, watch_state::WatchState};
+
+/// 卷被卸载期间检查是否已重新挂载的间隔。
+pub const REMOUNT_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 源目录所在的卷是否已被卸载。启动时未能记录文件系统标识时总是返回 `false`。
+pub fn is_unmounted<F: FsOps>(fs: &F, from: &Path, state: &WatchState) -> bool {
+    let Some(expected) = state.source_volume() else {
+        return false;
+    };
+    match fs.volume_id(from) {
+        Ok(volume) => volume != expected,
+        // 源目录本身被删除由源目录丢失的逻辑处理
+        Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mem_fs::MemFs, CopyType};
+
+    fn state(volume: Option<u64>) -> WatchState {
+        let state = WatchState::new(CopyType::Copy, None, None, None, None);
+        if let Some(volume) = volume {
+            state.set_source_volume(volume);
+        }
+        state
+    }
+
+    #[test]
+    fn same_volume_is_mounted() {
+        let fs = MemFs::new();
+        fs.set_volume(7);
+        assert!(!is_unmounted(&fs, Path::new("/src"), &state(Some(7))));
+    }
+
+    #[test]
+    fn changed_volume_is_unmounted_until_remounted() {
+        let fs = MemFs::new();
+        let state = state(Some(7));
+        // 卸载后源目录留在底层的文件系统上
+        fs.set_volume(1);
+        assert!(is_unmounted(&fs, Path::new("/src"), &state));
+        fs.set_volume(7);
+        assert!(!is_unmounted(&fs, Path::new("/src"), &state));
+    }
+
+    #[test]
+    fn inaccessible_source_is_unmounted() {
+        let fs = MemFs::new();
+        fs.fail("volume_id", io::ErrorKind::PermissionDenied);
+        assert!(is_unmounted(&fs, Path::new("/src"), &state(Some(0))));
+    }
+
+    #[test]
+    fn missing_source_is_left_to_source_lost_handling() {
+        let fs = MemFs::new();
+        fs.fail("volume_id", io::ErrorKind::NotFound);
+        assert!(!is_unmounted(&fs, Path::new("/src"), &state(Some(0))));
+    }
+
+    #[test]
+    fn unknown_source_volume_is_never_unmounted() {
+        let fs = MemFs::new();
+        fs.fail("volume_id", io::ErrorKind::PermissionDenied);
+        assert!(!is_unmounted(&fs, Path::new("/src"), &state(None)));
+    }
+}
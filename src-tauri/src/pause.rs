@@ -0,0 +1,190 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// 缓冲模式下暂停期间最多保留的事件数。
+pub const BUFFER_CAPACITY: usize = 10_000;
+
+/// 暂停期间如何对待收到的事件。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseMode {
+    /// 丢弃事件，恢复后不会补上暂停期间的变化。
+    Drop,
+    /// 缓存事件，恢复后按顺序重放。
+    Buffer,
+}
+
+/// 缓冲区已满时的处理方式。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PauseOverflow {
+    /// 丢弃所有缓存的事件，恢复时改为执行一次全量同步。
+    #[default]
+    Resync,
+    /// 丢弃最早的事件，恢复时只重放保留下来的事件。
+    DropOldest,
+}
+
+/// 暂停期间缓存的事件，由事件循环独占。
+#[derive(Default)]
+pub struct PauseBuffer {
+    events: VecDeque<notify::Event>,
+    /// 缓冲区是否已溢出并按 [`PauseOverflow::Resync`] 丢弃了事件。
+    overflowed: bool,
+}
+
+impl PauseBuffer {
+    pub fn push(&mut self, event: notify::Event, overflow: PauseOverflow) {
+        if self.overflowed {
+            return;
+        }
+        if self.events.len() >= BUFFER_CAPACITY {
+            match overflow {
+                PauseOverflow::Resync => {
+                    self.events.clear();
+                    self.overflowed = true;
+                    return;
+                }
+                PauseOverflow::DropOldest => {
+                    self.events.pop_front();
+                }
+            }
+        }
+        self.events.push_back(event);
+    }
+
+    /// 丢弃所有缓存的事件，取出时改为执行全量同步。
+    pub fn force_resync(&mut self) {
+        self.events.clear();
+        self.overflowed = true;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty() && !self.overflowed
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// 取出所有缓存的事件；缓冲区曾溢出时返回 `None`，调用方应执行全量同步。
+    pub fn take(&mut self) -> Option<VecDeque<notify::Event>> {
+        let events = std::mem::take(&mut self.events);
+        if std::mem::take(&mut self.overflowed) {
+            return None;
+        }
+        Some(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::{
+        event::{CreateKind, DataChange, ModifyKind, RemoveKind},
+        Event, EventKind,
+    };
+
+    use super::*;
+    use crate::{
+        config::WatchOptions,
+        file_processor,
+        mem_fs::{dst, src, TestWatch},
+        CopyType,
+    };
+
+    fn event(kind: EventKind, path: std::path::PathBuf) -> Event {
+        Event::new(kind).add_path(path)
+    }
+
+    fn numbered(n: usize) -> Event {
+        event(
+            EventKind::Create(CreateKind::File),
+            src(&format!("{}.txt", n)),
+        )
+    }
+
+    #[tokio::test]
+    async fn changes_made_while_paused_apply_after_resume() {
+        let watch = TestWatch::new(WatchOptions::default());
+        let mut buffer = PauseBuffer::default();
+        watch.state.pause(PauseMode::Buffer);
+        assert_eq!(watch.state.pause_mode(), Some(PauseMode::Buffer));
+
+        watch.fs.add_file(src("a.txt"), "v1");
+        buffer.push(
+            event(EventKind::Create(CreateKind::File), src("a.txt")),
+            PauseOverflow::Resync,
+        );
+        watch.fs.add_file(src("a.txt"), "v2");
+        buffer.push(
+            event(
+                EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+                src("a.txt"),
+            ),
+            PauseOverflow::Resync,
+        );
+        watch.fs.add_file(src("b.txt"), "b");
+        buffer.push(
+            event(EventKind::Create(CreateKind::File), src("b.txt")),
+            PauseOverflow::Resync,
+        );
+        watch.fs.add_file(dst("old.txt"), "old");
+        buffer.push(
+            event(EventKind::Remove(RemoveKind::File), src("old.txt")),
+            PauseOverflow::Resync,
+        );
+        assert!(watch.fs.ops().is_empty());
+        assert_eq!(buffer.len(), 4);
+
+        assert!(watch.state.resume());
+        assert!(!watch.state.resume());
+        assert_eq!(watch.state.pause_mode(), None);
+        let events = buffer.take().expect("缓冲区未溢出");
+        assert!(buffer.is_empty());
+        let ctx = watch.ctx();
+        for event in &events {
+            file_processor::process(&ctx, CopyType::Copy, event)
+                .await
+                .unwrap();
+        }
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"v2".to_vec()));
+        assert_eq!(watch.fs.read(dst("b.txt")), Some(b"b".to_vec()));
+        assert_eq!(watch.fs.read(dst("old.txt")), None);
+    }
+
+    #[test]
+    fn resync_overflow_discards_buffer_and_requests_full_sync() {
+        let mut buffer = PauseBuffer::default();
+        for n in 0..=BUFFER_CAPACITY {
+            buffer.push(numbered(n), PauseOverflow::Resync);
+        }
+        assert_eq!(buffer.len(), 0);
+        assert!(!buffer.is_empty());
+        // 溢出后继续收到的事件同样被丢弃
+        buffer.push(numbered(0), PauseOverflow::Resync);
+        assert_eq!(buffer.len(), 0);
+        assert!(buffer.take().is_none());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn drop_oldest_overflow_keeps_most_recent_events() {
+        let mut buffer = PauseBuffer::default();
+        for n in 0..BUFFER_CAPACITY + 2 {
+            buffer.push(numbered(n), PauseOverflow::DropOldest);
+        }
+        let events = buffer.take().expect("DropOldest 不会要求全量同步");
+        assert_eq!(events.len(), BUFFER_CAPACITY);
+        assert_eq!(events.front().unwrap().paths, vec![src("2.txt")]);
+        let last = format!("{}.txt", BUFFER_CAPACITY + 1);
+        assert_eq!(events.back().unwrap().paths, vec![src(&last)]);
+    }
+
+    #[test]
+    fn force_resync_discards_buffered_events() {
+        let mut buffer = PauseBuffer::default();
+        buffer.push(numbered(0), PauseOverflow::DropOldest);
+        buffer.force_resync();
+        assert!(buffer.take().is_none());
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+//! 等待路径出现的监视。
+//!
+//! 开启 `wait_for_paths` 的监视在源路径或目标路径不存在时（例如外接或网络驱动器尚未挂载）
+//! 先进入等待表，后台任务定期检查，两个路径都出现后再启动。
+
+use std::time::Duration;
+
+use log::{info, warn};
+use tauri::Manager;
+use tokio::time::{interval, MissedTickBehavior};
+
+use crate::{
+    activate_watch,
+    config::WatchConfig,
+    events::{self, ErrorPayload, IdPayload},
+    AppState,
+};
+
+/// 检查等待中的监视的间隔。
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 将监视加入等待表并发送 `watch-pending` 事件。
+pub async fn add(app: &tauri::AppHandle, config: WatchConfig) {
+    info!(
+        "监视 '{}' 的路径暂不可用，等待其出现: {:?} -> {:?}",
+        config.name(),
+        config.from,
+        config.to
+    );
+    let (id, name) = (config.id.clone(), config.name());
+    app.state::<AppState>()
+        .pending
+        .lock()
+        .await
+        .insert(id.clone(), config);
+    events::emit(
+        app,
+        &id,
+        "watch-pending",
+        IdPayload {
+            id: id.clone(),
+            name,
+        },
+    );
+}
+
+/// 定期启动路径已经出现的等待中的监视。
+pub async fn run(app: tauri::AppHandle) {
+    let mut ticker = interval(CHECK_INTERVAL);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        ticker.tick().await;
+        let ready: Vec<WatchConfig> = {
+            let state = app.state::<AppState>();
+            let mut pending = state.pending.lock().await;
+            let ids: Vec<String> = pending
+                .iter()
+                .filter(|(_, config)| config.from.exists() && config.to.exists())
+                .map(|(id, _)| id.clone())
+                .collect();
+            ids.iter().filter_map(|id| pending.remove(id)).collect()
+        };
+        for config in ready {
+            let (id, name) = (config.id.clone(), config.name());
+            match activate_watch(&app, config).await {
+                Ok(()) => {
+                    info!("监视 '{}' 的路径已出现，监视已启动。", name);
+                    events::emit(
+                        &app,
+                        &id,
+                        "watch-activated",
+                        IdPayload {
+                            id: id.clone(),
+                            name,
+                        },
+                    );
+                }
+                Err(e) => {
+                    warn!("启动等待中的监视 '{}' 失败: {:#}", name, e);
+                    let payload = ErrorPayload {
+                        id: id.clone(),
+                        name,
+                        path: None,
+                        message: format!("{:#}", e),
+                    };
+                    events::emit(&app, &id, "watch-error", payload);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,59 @@
+//! 可复用的监视模板，保存在前端同样使用的 store 文件中。
+
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use tauri_plugin_store::StoreExt;
+
+use crate::{config::WatchOptions, CopyType, STORE_FILE};
+
+/// 模板在 store 中的键，值为名称 -> 模板。
+const PRESETS_KEY: &str = "presets";
+
+/// 除源路径和目标路径外的监视配置。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Preset {
+    pub copy_type: CopyType,
+    #[serde(default)]
+    pub options: WatchOptions,
+}
+
+pub fn list(app: &tauri::AppHandle) -> anyhow::Result<BTreeMap<String, Preset>> {
+    let store = app.store(STORE_FILE)?;
+    match store.get(PRESETS_KEY) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+pub fn get(app: &tauri::AppHandle, name: &str) -> anyhow::Result<Preset> {
+    list(app)?
+        .remove(name)
+        .ok_or_else(|| anyhow!("模板 '{}' 不存在", name))
+}
+
+/// 保存模板，同名模板会被覆盖。
+pub fn save(app: &tauri::AppHandle, name: &str, preset: Preset) -> anyhow::Result<()> {
+    let mut presets = list(app)?;
+    presets.insert(name.to_string(), preset);
+    write(app, &presets)
+}
+
+/// 删除模板，返回模板是否存在。
+pub fn delete(app: &tauri::AppHandle, name: &str) -> anyhow::Result<bool> {
+    let mut presets = list(app)?;
+    if presets.remove(name).is_none() {
+        return Ok(false);
+    }
+    write(app, &presets)?;
+    Ok(true)
+}
+
+fn write(app: &tauri::AppHandle, presets: &BTreeMap<String, Preset>) -> anyhow::Result<()> {
+    let store = app.store(STORE_FILE)?;
+    store.set(PRESETS_KEY, serde_json::to_value(presets)?);
+    store.save()?;
+    Ok(())
+}
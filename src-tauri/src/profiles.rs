@@ -0,0 +1,154 @@
+//! 全局设置的命名配置组（例如家里和公司），保存在前端同样使用的 store 文件中，可整体切换。
+//!
+//! 激活配置组时立即应用其中的全局设置，并作为之后新建的监视的默认值；已在运行的监视不受影响。
+//! 当前激活的配置组也保存在 store 中，下次启动时恢复。
+
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use log::{info, warn, LevelFilter};
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+use tauri_plugin_store::StoreExt;
+
+use crate::{config::WatchOptions, log_level, AppState, CopyType, STORE_FILE};
+
+/// 配置组在 store 中的键，值为名称 -> 配置组。
+const PROFILES_KEY: &str = "profiles";
+/// 当前激活的配置组名称在 store 中的键。
+const ACTIVE_PROFILE_KEY: &str = "activeProfile";
+
+/// 一组全局设置，为空的项保持不变或使用内置的默认值。
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Profile {
+    /// 日志级别（`error`、`warn`、`info`、`debug`、`trace` 或 `off`），为空时不修改。
+    pub log_level: Option<String>,
+    /// `stats-snapshot` 事件的推送间隔（毫秒），为空时停止推送。
+    pub stats_snapshot_interval_ms: Option<u64>,
+    /// 新建监视未指定复制类型时使用的类型，为空时使用复制。
+    pub default_copy_type: Option<CopyType>,
+    /// 新建监视未指定选项时使用的选项（包括 `max_ops_per_sec` 等限速设置）。
+    pub default_options: Option<WatchOptions>,
+}
+
+/// 当前激活的配置组。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveProfile {
+    pub name: String,
+    pub profile: Profile,
+}
+
+pub fn list(app: &tauri::AppHandle) -> anyhow::Result<BTreeMap<String, Profile>> {
+    let store = app.store(STORE_FILE)?;
+    match store.get(PROFILES_KEY) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+/// 保存配置组，同名配置组会被覆盖。日志级别无效时拒绝保存。
+///
+/// 覆盖的是当前激活的配置组时不会自动重新应用，需要再次激活。
+pub fn save(app: &tauri::AppHandle, name: &str, profile: Profile) -> anyhow::Result<()> {
+    parse_level(&profile)?;
+    let mut profiles = list(app)?;
+    profiles.insert(name.to_string(), profile);
+    write(app, &profiles)
+}
+
+/// 删除配置组，返回配置组是否存在。删除当前激活的配置组不会撤销已应用的设置。
+pub fn delete(app: &tauri::AppHandle, name: &str) -> anyhow::Result<bool> {
+    let mut profiles = list(app)?;
+    if profiles.remove(name).is_none() {
+        return Ok(false);
+    }
+    write(app, &profiles)?;
+    Ok(true)
+}
+
+/// 激活配置组：应用其中的全局设置，记录到 store 并发送 `profile-changed` 事件。
+pub async fn activate(app: &tauri::AppHandle, name: &str) -> anyhow::Result<ActiveProfile> {
+    let profile = list(app)?
+        .remove(name)
+        .ok_or_else(|| anyhow!("配置组 '{}' 不存在", name))?;
+    let active = apply(app, name, profile).await?;
+    let store = app.store(STORE_FILE)?;
+    store.set(ACTIVE_PROFILE_KEY, name);
+    store.save()?;
+    info!("已激活配置组 '{}'。", name);
+    if let Err(e) = app.emit("profile-changed", active.clone()) {
+        warn!("发送事件 'profile-changed' 失败: {:?}", e);
+    }
+    Ok(active)
+}
+
+/// 启动时恢复上次激活的配置组，配置组已不存在时只记录警告。
+pub async fn restore(app: &tauri::AppHandle) {
+    let Ok(store) = app.store(STORE_FILE) else {
+        return;
+    };
+    let Some(name) = store
+        .get(ACTIVE_PROFILE_KEY)
+        .and_then(|value| value.as_str().map(str::to_string))
+    else {
+        return;
+    };
+    let profile = match list(app).map(|mut profiles| profiles.remove(&name)) {
+        Ok(Some(profile)) => profile,
+        Ok(None) => {
+            warn!("上次激活的配置组 '{}' 已不存在。", name);
+            return;
+        }
+        Err(e) => {
+            warn!("读取配置组失败: {:#}", e);
+            return;
+        }
+    };
+    match apply(app, &name, profile).await {
+        Ok(_) => info!("已恢复配置组 '{}'。", name),
+        Err(e) => warn!("恢复配置组 '{}' 失败: {:#}", name, e),
+    }
+}
+
+async fn apply(
+    app: &tauri::AppHandle,
+    name: &str,
+    profile: Profile,
+) -> anyhow::Result<ActiveProfile> {
+    if let Some(level) = parse_level(&profile)? {
+        log_level::set(level, None);
+    }
+    let state = app.state::<AppState>();
+    state.stats_feed.set_interval(
+        profile
+            .stats_snapshot_interval_ms
+            .map(std::time::Duration::from_millis),
+    );
+    let active = ActiveProfile {
+        name: name.to_string(),
+        profile,
+    };
+    *state.profile.lock().await = Some(active.clone());
+    Ok(active)
+}
+
+fn parse_level(profile: &Profile) -> anyhow::Result<Option<LevelFilter>> {
+    profile
+        .log_level
+        .as_deref()
+        .map(|level| {
+            level
+                .parse()
+                .map_err(|_| anyhow!("无效的日志级别 '{}'", level))
+        })
+        .transpose()
+}
+
+fn write(app: &tauri::AppHandle, profiles: &BTreeMap<String, Profile>) -> anyhow::Result<()> {
+    let store = app.store(STORE_FILE)?;
+    store.set(PROFILES_KEY, serde_json::to_value(profiles)?);
+    store.save()?;
+    Ok(())
+}
@@ -0,0 +1,154 @@
+//! 大文件复制和大目录删除过程中的进度报告。
+
+use std::{
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+
+use crate::{events, evict::TargetEvicted};
+
+/// 达到此大小的文件在复制过程中发送 `file-progress` 事件。
+pub const LARGE_FILE_THRESHOLD: u64 = 64 * 1024 * 1024;
+/// 同一文件两次进度事件之间的最短间隔。
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// `file-progress` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FileProgress {
+    pub watch_id: String,
+    pub path: PathBuf,
+    pub bytes_copied: u64,
+    pub bytes_total: u64,
+}
+
+/// `delete-progress` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteProgress {
+    pub watch_id: String,
+    pub path: PathBuf,
+    pub entries_removed: u64,
+    pub finished: bool,
+}
+
+/// 报告某个监视的大文件复制和大目录删除进度，并在复制的块、删除的批次之间检查取消令牌。
+pub struct ProgressReporter<'a> {
+    pub app: &'a tauri::AppHandle,
+    pub watch_id: &'a str,
+    /// 全量同步时为 `cancel_sync` 使用的令牌，处理实时事件时为监视的停止令牌。
+    pub cancel: Option<&'a CancellationToken>,
+}
+
+impl<'a> ProgressReporter<'a> {
+    /// 发送 `target-evicted` 事件。
+    pub fn evicted(&self, path: PathBuf, len: u64) {
+        let payload = TargetEvicted {
+            watch_id: self.watch_id.to_string(),
+            path,
+            len,
+        };
+        events::emit(self.app, self.watch_id, "target-evicted", payload);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_some_and(|cancel| cancel.is_cancelled())
+    }
+
+    /// 开始跟踪一个目录树的删除，只删除一批以内条目的目录不发送进度。
+    pub fn track_delete(&'a self, root: &Path) -> DeleteTracker<'a> {
+        DeleteTracker {
+            reporter: self,
+            progress: DeleteProgress {
+                watch_id: self.watch_id.to_string(),
+                path: root.to_path_buf(),
+                entries_removed: 0,
+                finished: false,
+            },
+        }
+    }
+
+    /// 开始跟踪一个文件的复制，小于 [`LARGE_FILE_THRESHOLD`] 的文件不发送进度。
+    pub fn track(&'a self, path: &Path, total: u64) -> FileTracker<'a> {
+        FileTracker {
+            reporter: self,
+            progress: FileProgress {
+                watch_id: self.watch_id.to_string(),
+                path: path.to_path_buf(),
+                bytes_copied: 0,
+                bytes_total: total,
+            },
+            last_emit: None,
+        }
+    }
+}
+
+pub struct FileTracker<'a> {
+    reporter: &'a ProgressReporter<'a>,
+    progress: FileProgress,
+    last_emit: Option<Instant>,
+}
+
+impl FileTracker<'_> {
+    /// 每复制完一块后调用，返回 `Break` 表示同步已被取消、应中止复制。
+    pub fn update(&mut self, copied: u64) -> ControlFlow<()> {
+        if self.reporter.is_cancelled() {
+            return ControlFlow::Break(());
+        }
+        if self.progress.bytes_total < LARGE_FILE_THRESHOLD {
+            return ControlFlow::Continue(());
+        }
+        self.progress.bytes_copied = copied;
+        let finished = copied >= self.progress.bytes_total;
+        if finished
+            || self
+                .last_emit
+                .is_none_or(|at| at.elapsed() >= PROGRESS_INTERVAL)
+        {
+            events::emit(
+                self.reporter.app,
+                self.reporter.watch_id,
+                "file-progress",
+                self.progress.clone(),
+            );
+            self.last_emit = Some(Instant::now());
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+pub struct DeleteTracker<'a> {
+    reporter: &'a ProgressReporter<'a>,
+    progress: DeleteProgress,
+}
+
+impl DeleteTracker<'_> {
+    /// 每删除完一批后调用。
+    pub fn update(&mut self, removed: u64) {
+        self.progress.entries_removed = removed;
+        self.emit();
+    }
+
+    /// 删除完成后调用，之前发送过进度时再发送一次完成事件。
+    pub fn finish(&mut self, removed: u64) {
+        if self.progress.entries_removed == 0 {
+            return;
+        }
+        self.progress.entries_removed = removed;
+        self.progress.finished = true;
+        self.emit();
+    }
+
+    fn emit(&self) {
+        events::emit(
+            self.reporter.app,
+            self.reporter.watch_id,
+            "delete-progress",
+            self.progress.clone(),
+        );
+    }
+}
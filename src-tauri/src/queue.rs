@@ -0,0 +1,262 @@
+//! 系统监视器与事件循环之间的有界事件队列。
+//!
+//! 监视器的回调只把事件放入队列，不会阻塞；事件循环处理不过来、队列达到 `max_queued_events` 时
+//! 按 `queue_overflow` 处理超出容量的事件，避免事件风暴期间占用的内存无限增长。
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use notify::Event;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+/// 每个监视默认最多排队的事件数。
+pub const DEFAULT_CAPACITY: usize = 10_000;
+
+/// 队列已满时的处理方式。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QueueOverflow {
+    /// 丢弃新到达的事件。
+    #[default]
+    DropNewest,
+    /// 丢弃最早排队的事件，为新事件腾出位置。
+    DropOldest,
+    /// 丢弃所有排队的事件，改为执行一次基于差异的全量同步，适合大批量的变化。
+    FallbackToResync,
+}
+
+/// 从队列中取出的内容。
+pub enum Received {
+    Event(notify::Result<Event>),
+    /// 队列按 [`QueueOverflow::FallbackToResync`] 溢出，调用方应执行全量同步。
+    Resync,
+}
+
+struct Shared {
+    inner: Mutex<Inner>,
+    ready: Notify,
+    capacity: usize,
+    overflow: QueueOverflow,
+}
+
+#[derive(Default)]
+struct Inner {
+    events: VecDeque<notify::Result<Event>>,
+    /// 是否需要以全量同步代替被丢弃的事件。
+    resync: bool,
+    /// 本次溢出开始后是否还未通知事件循环。
+    overflow_started: bool,
+    /// 本次溢出中丢弃的事件数，溢出在队列清空后结束。
+    dropped: Option<u64>,
+    /// 发送端已被 drop。
+    closed: bool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 创建容量为 `capacity` 的队列。
+pub fn bounded(capacity: usize, overflow: QueueOverflow) -> (EventSender, EventReceiver) {
+    let shared = Arc::new(Shared {
+        inner: Mutex::default(),
+        ready: Notify::new(),
+        capacity: capacity.max(1),
+        overflow,
+    });
+    (EventSender(shared.clone()), EventReceiver(shared))
+}
+
+pub struct EventSender(Arc<Shared>);
+
+impl EventSender {
+    /// 放入事件，队列已满时按溢出策略处理，不会阻塞。
+    pub fn send(&self, event: notify::Result<Event>) {
+        let shared = &self.0;
+        {
+            let mut inner = shared.lock();
+            if inner.resync {
+                // 待执行的全量同步会覆盖这个事件
+                *inner.dropped.get_or_insert(0) += 1;
+                return;
+            }
+            if inner.events.len() >= shared.capacity {
+                if inner.dropped.is_none() {
+                    inner.overflow_started = true;
+                }
+                *inner.dropped.get_or_insert(0) += 1;
+                match shared.overflow {
+                    QueueOverflow::DropNewest => return,
+                    QueueOverflow::DropOldest => {
+                        inner.events.pop_front();
+                    }
+                    QueueOverflow::FallbackToResync => {
+                        let discarded = inner.events.len() as u64;
+                        inner.events.clear();
+                        inner.resync = true;
+                        *inner.dropped.get_or_insert(0) += discarded;
+                        drop(inner);
+                        shared.ready.notify_one();
+                        return;
+                    }
+                }
+            }
+            inner.events.push_back(event);
+        }
+        shared.ready.notify_one();
+    }
+}
+
+impl Drop for EventSender {
+    fn drop(&mut self) {
+        self.0.lock().closed = true;
+        self.0.ready.notify_one();
+    }
+}
+
+pub struct EventReceiver(Arc<Shared>);
+
+impl EventReceiver {
+    /// 取出下一项，队列为空时等待；发送端已被 drop 且队列为空时返回 `None`。
+    ///
+    /// 可在 `select!` 中使用：每次调用都先检查队列，被取消的等待不会丢失事件。
+    pub async fn recv(&mut self) -> Option<Received> {
+        loop {
+            {
+                let mut inner = self.0.lock();
+                if std::mem::take(&mut inner.resync) {
+                    return Some(Received::Resync);
+                }
+                if let Some(event) = inner.events.pop_front() {
+                    return Some(Received::Event(event));
+                }
+                if inner.closed {
+                    return None;
+                }
+            }
+            self.0.ready.notified().await;
+        }
+    }
+
+    /// 溢出刚开始时返回 `true`，每次溢出只返回一次。
+    pub fn take_overflow_started(&self) -> bool {
+        std::mem::take(&mut self.0.lock().overflow_started)
+    }
+
+    /// 队列已清空、溢出结束时返回本次溢出中丢弃的事件数。
+    pub fn take_overflow_ended(&self) -> Option<u64> {
+        let mut inner = self.0.lock();
+        if !inner.events.is_empty() || inner.resync {
+            return None;
+        }
+        inner.dropped.take()
+    }
+
+    pub fn overflow(&self) -> QueueOverflow {
+        self.0.overflow
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn event(n: usize) -> notify::Result<Event> {
+        Ok(Event::default().add_path(PathBuf::from(n.to_string())))
+    }
+
+    /// 依次放入 `count` 个事件，再关闭发送端取出全部内容；全量同步记为 `None`。
+    async fn send_and_drain(
+        capacity: usize,
+        overflow: QueueOverflow,
+        count: usize,
+    ) -> (EventReceiver, Vec<Option<String>>) {
+        let (sender, mut receiver) = bounded(capacity, overflow);
+        for n in 0..count {
+            sender.send(event(n));
+        }
+        drop(sender);
+        let mut received = Vec::new();
+        while let Some(item) = receiver.recv().await {
+            received.push(match item {
+                Received::Event(event) => Some(event.unwrap().paths[0].display().to_string()),
+                Received::Resync => None,
+            });
+        }
+        (receiver, received)
+    }
+
+    fn names(names: &[&str]) -> Vec<Option<String>> {
+        names.iter().map(|name| Some(name.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn events_within_capacity_are_not_dropped() {
+        let (receiver, received) = send_and_drain(5, QueueOverflow::DropNewest, 5).await;
+        assert_eq!(received, names(&["0", "1", "2", "3", "4"]));
+        assert!(!receiver.take_overflow_started());
+        assert_eq!(receiver.take_overflow_ended(), None);
+    }
+
+    #[tokio::test]
+    async fn drop_newest_keeps_earliest_events() {
+        let (receiver, received) = send_and_drain(3, QueueOverflow::DropNewest, 5).await;
+        assert_eq!(received, names(&["0", "1", "2"]));
+        assert!(receiver.take_overflow_started());
+        assert!(!receiver.take_overflow_started());
+        assert_eq!(receiver.take_overflow_ended(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn drop_oldest_keeps_latest_events() {
+        let (receiver, received) = send_and_drain(3, QueueOverflow::DropOldest, 5).await;
+        assert_eq!(received, names(&["2", "3", "4"]));
+        assert!(receiver.take_overflow_started());
+        assert_eq!(receiver.take_overflow_ended(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn fallback_to_resync_replaces_all_queued_events() {
+        let (receiver, received) = send_and_drain(3, QueueOverflow::FallbackToResync, 5).await;
+        assert_eq!(received, vec![None]);
+        assert!(receiver.take_overflow_started());
+        // 清空的 3 个排队事件和溢出时及之后到达的 2 个事件
+        assert_eq!(receiver.take_overflow_ended(), Some(5));
+    }
+
+    #[tokio::test]
+    async fn overflow_ends_only_after_queue_drains() {
+        let (sender, mut receiver) = bounded(2, QueueOverflow::DropNewest);
+        for n in 0..3 {
+            sender.send(event(n));
+        }
+        assert!(receiver.take_overflow_started());
+        assert_eq!(receiver.take_overflow_ended(), None);
+        receiver.recv().await.unwrap();
+        receiver.recv().await.unwrap();
+        assert_eq!(receiver.take_overflow_ended(), Some(1));
+
+        // 下一次溢出重新通知
+        for n in 0..3 {
+            sender.send(event(n));
+        }
+        assert!(receiver.take_overflow_started());
+        assert_eq!(receiver.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn zero_capacity_keeps_one_event() {
+        let (_, received) = send_and_drain(0, QueueOverflow::DropNewest, 2).await;
+        assert_eq!(received, names(&["0"]));
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// 限制每秒操作次数的令牌桶，最多允许积累一秒的突发量。
+pub struct RateLimiter {
+    per_sec: f64,
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    /// 可能为负，表示已被等待中的操作预订的令牌。
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(per_sec: u32) -> Self {
+        let per_sec = f64::from(per_sec.max(1));
+        Self {
+            per_sec,
+            bucket: Mutex::new(Bucket {
+                tokens: per_sec,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// 取得一个令牌，令牌不足时等待到轮到自己为止。
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.last).as_secs_f64() * self.per_sec;
+            bucket.tokens = (bucket.tokens + refill).min(self.per_sec) - 1.0;
+            bucket.last = now;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / self.per_sec)
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn burst_up_to_rate_does_not_wait() {
+        let limiter = RateLimiter::new(50);
+        let start = Instant::now();
+        for _ in 0..50 {
+            limiter.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn operations_beyond_burst_are_paced() {
+        let limiter = RateLimiter::new(20);
+        for _ in 0..20 {
+            limiter.acquire().await;
+        }
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.acquire().await;
+        }
+        // 5 个令牌按每秒 20 个补充约需 250ms
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn zero_rate_is_treated_as_one_per_second() {
+        assert_eq!(RateLimiter::new(0).per_sec, 1.0);
+    }
+}
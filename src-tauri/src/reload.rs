@@ -0,0 +1,299 @@
+//! 从 store 重新加载前端保存的监视配置，只对有变化的监视执行操作。
+//!
+//! 前端把监视列表保存在 [`WATCHES_KEY`] 下。重新加载时与正在运行的监视比较：
+//! 已删除或被禁用的监视停止，新增的启用监视启动，只有复制类型或备注变化的监视就地修改，
+//! 其他配置变化的监视重新创建，未变化的监视保持运行、不会重建系统监视。
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anyhow::anyhow;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+use tauri_plugin_store::StoreExt;
+
+use crate::{
+    config::{WatchConfig, WatchOptions},
+    events, start_watch, tray, AppState, CopyType, STORE_FILE,
+};
+
+/// 前端保存监视列表的键。
+const WATCHES_KEY: &str = "copyConfs";
+
+/// 前端保存的一项监视配置。
+#[derive(Deserialize, Debug)]
+struct StoredWatch {
+    id: String,
+    from: PathBuf,
+    to: PathBuf,
+    #[serde(rename = "type")]
+    copy_type: CopyType,
+    #[serde(default)]
+    enable: bool,
+    /// 前端未保存选项时沿用正在运行的监视的选项。
+    #[serde(default)]
+    options: Option<WatchOptions>,
+}
+
+/// 对单个监视执行的操作。
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReloadAction {
+    Started,
+    Stopped,
+    Restarted,
+    /// 只有复制类型或备注变化，已就地修改。
+    Updated,
+    Unchanged,
+    Failed,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ReloadActionPayload {
+    watch_id: String,
+    action: ReloadAction,
+    error: Option<String>,
+}
+
+/// 重新加载的结果，按操作列出监视 id。
+#[derive(Serialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ReloadSummary {
+    pub started: Vec<String>,
+    pub stopped: Vec<String>,
+    pub restarted: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+    /// 监视 id -> 失败原因。
+    pub failed: BTreeMap<String, String>,
+}
+
+impl ReloadSummary {
+    /// 记录操作并向前端发送 `watch-reload-action` 事件。
+    fn record(&mut self, app: &tauri::AppHandle, id: &str, result: Result<ReloadAction, String>) {
+        let (action, error) = match result {
+            Ok(action) => (action, None),
+            Err(e) => (ReloadAction::Failed, Some(e)),
+        };
+        match (action, &error) {
+            (ReloadAction::Started, _) => self.started.push(id.to_string()),
+            (ReloadAction::Stopped, _) => self.stopped.push(id.to_string()),
+            (ReloadAction::Restarted, _) => self.restarted.push(id.to_string()),
+            (ReloadAction::Updated, _) => self.updated.push(id.to_string()),
+            (ReloadAction::Unchanged, _) => self.unchanged.push(id.to_string()),
+            (ReloadAction::Failed, error) => {
+                warn!("重新加载监视 '{}' 失败: {:?}", id, error);
+                let error = error.clone().unwrap_or_default();
+                self.failed.insert(id.to_string(), error);
+            }
+        }
+        let payload = ReloadActionPayload {
+            watch_id: id.to_string(),
+            action,
+            error,
+        };
+        events::emit(app, id, "watch-reload-action", payload);
+    }
+}
+
+/// 从磁盘重新读取 store 中的监视列表并应用到正在运行的监视。
+pub async fn reload(app: &tauri::AppHandle) -> anyhow::Result<ReloadSummary> {
+    let store = app.store(STORE_FILE)?;
+    store.reload()?;
+    let stored: Vec<StoredWatch> = match store.get(WATCHES_KEY) {
+        Some(value) => serde_json::from_value(value)?,
+        None => Vec::new(),
+    };
+    let mut desired: BTreeMap<String, StoredWatch> = stored
+        .into_iter()
+        .filter(|watch| watch.enable)
+        .map(|watch| (watch.id.clone(), watch))
+        .collect();
+
+    let state = app.state::<AppState>();
+    let mut summary = ReloadSummary::default();
+    state
+        .pending
+        .lock()
+        .await
+        .retain(|id, _| desired.contains_key(id));
+    let running: Vec<String> = state.watcher.lock().await.keys().cloned().collect();
+    for id in running {
+        if desired.contains_key(&id) {
+            continue;
+        }
+        let removed = state.watcher.lock().await.remove(&id);
+        if let Some(watch) = removed {
+            watch.stop().await;
+            summary.record(app, &id, Ok(ReloadAction::Stopped));
+        }
+    }
+
+    let pending: Vec<String> = state.pending.lock().await.keys().cloned().collect();
+    for id in pending {
+        if desired.remove(&id).is_some() {
+            summary.record(app, &id, Ok(ReloadAction::Unchanged));
+        }
+    }
+    for (id, stored) in desired {
+        let result = apply(app, &state, stored).await;
+        summary.record(app, &id, result);
+    }
+    tray::refresh(app).await;
+    info!("已重新加载监视配置: {:?}", summary);
+    Ok(summary)
+}
+
+/// 将监视的备注写回 store 中同 id 的条目，没有该条目时返回 `false`。
+///
+/// 条目保存了选项时只修改其中的备注，运行期间改变的其他选项（例如回退到轮询）不会被写回；
+/// 没有保存选项时写入 `options` 的全部内容，避免之后重新加载时被当作默认选项。
+pub fn store_notes(
+    app: &tauri::AppHandle,
+    id: &str,
+    options: &WatchOptions,
+) -> anyhow::Result<bool> {
+    let store = app.store(STORE_FILE)?;
+    let Some(mut watches) = store.get(WATCHES_KEY) else {
+        return Ok(false);
+    };
+    let entry = watches.as_array_mut().and_then(|watches| {
+        watches
+            .iter_mut()
+            .find(|watch| watch.get("id").and_then(serde_json::Value::as_str) == Some(id))
+    });
+    let Some(serde_json::Value::Object(entry)) = entry else {
+        return Ok(false);
+    };
+    match entry.get_mut("options") {
+        Some(serde_json::Value::Object(stored)) => {
+            stored.insert("notes".to_string(), serde_json::to_value(&options.notes)?);
+        }
+        _ => {
+            entry.insert("options".to_string(), serde_json::to_value(options)?);
+        }
+    }
+    store.set(WATCHES_KEY, watches);
+    store.save()?;
+    Ok(true)
+}
+
+/// 把 store 中 id 为 `old_id` 的条目改为 `new_id`，没有该条目时返回 `false`。
+///
+/// 已有 id 为 `new_id` 的条目时返回错误，store 保持不变。
+pub fn store_rename(app: &tauri::AppHandle, old_id: &str, new_id: &str) -> anyhow::Result<bool> {
+    let store = app.store(STORE_FILE)?;
+    let Some(mut watches) = store.get(WATCHES_KEY) else {
+        return Ok(false);
+    };
+    let Some(entries) = watches.as_array_mut() else {
+        return Ok(false);
+    };
+    let has_id = |watch: &serde_json::Value, id: &str| {
+        watch.get("id").and_then(serde_json::Value::as_str) == Some(id)
+    };
+    if entries.iter().any(|watch| has_id(watch, new_id)) {
+        return Err(anyhow!("store 中已有 id 为 '{}' 的监视。", new_id));
+    }
+    let Some(serde_json::Value::Object(entry)) =
+        entries.iter_mut().find(|watch| has_id(watch, old_id))
+    else {
+        return Ok(false);
+    };
+    entry.insert("id".to_string(), serde_json::Value::from(new_id));
+    store.set(WATCHES_KEY, watches);
+    store.save()?;
+    Ok(true)
+}
+
+/// 把启用的监视写入 store，已有同 id 的条目时替换其中的路径、复制类型和选项，只写入一次。
+pub fn store_watches(app: &tauri::AppHandle, configs: &[WatchConfig]) -> anyhow::Result<()> {
+    if configs.is_empty() {
+        return Ok(());
+    }
+    let store = app.store(STORE_FILE)?;
+    let mut watches = match store.get(WATCHES_KEY) {
+        Some(serde_json::Value::Array(watches)) => watches,
+        _ => Vec::new(),
+    };
+    for config in configs {
+        let stored = serde_json::json!({
+            "id": config.id,
+            "from": config.from,
+            "to": config.to,
+            "type": config.copy_type,
+            "enable": true,
+            "options": config.options,
+        });
+        let existing = watches
+            .iter_mut()
+            .find(|watch| watch.get("id").and_then(serde_json::Value::as_str) == Some(&config.id));
+        match (existing, stored) {
+            (Some(serde_json::Value::Object(entry)), serde_json::Value::Object(stored)) => {
+                entry.extend(stored)
+            }
+            (_, stored) => watches.push(stored),
+        }
+    }
+    store.set(WATCHES_KEY, watches);
+    store.save()?;
+    Ok(())
+}
+
+/// 将一项启用的配置应用到同 id 的监视上。
+async fn apply(
+    app: &tauri::AppHandle,
+    state: &AppState,
+    stored: StoredWatch,
+) -> Result<ReloadAction, String> {
+    let mut watchers = state.watcher.lock().await;
+    let Some(watch) = watchers.get_mut(&stored.id) else {
+        drop(watchers);
+        let config = WatchConfig {
+            id: stored.id,
+            from: stored.from,
+            to: stored.to,
+            copy_type: stored.copy_type,
+            options: stored.options.unwrap_or_default(),
+        };
+        start_watch(app, config, state).await?;
+        return Ok(ReloadAction::Started);
+    };
+    let config = WatchConfig {
+        id: stored.id,
+        from: stored.from,
+        to: stored.to,
+        copy_type: stored.copy_type,
+        options: stored
+            .options
+            .unwrap_or_else(|| watch.config.options.clone()),
+    };
+    // 备注不影响监视的行为，变化时不需要重建
+    let mut running_options = watch.config.options.clone();
+    running_options.notes = config.options.notes.clone();
+    let same_options =
+        serde_json::to_value(&config.options).ok() == serde_json::to_value(&running_options).ok();
+    if config.from == watch.config.from && config.to == watch.config.to && same_options {
+        let same_notes = config.options.notes == watch.config.options.notes;
+        if config.copy_type == watch.config.copy_type && same_notes {
+            return Ok(ReloadAction::Unchanged);
+        }
+        if config.copy_type != watch.config.copy_type {
+            watch.config.copy_type = config.copy_type;
+            watch.state.set_copy_type(config.copy_type);
+            info!(
+                "id '{}' 的复制类型已改为 {:?}。",
+                config.id, config.copy_type
+            );
+        }
+        watch.config.options.notes = config.options.notes;
+        return Ok(ReloadAction::Updated);
+    }
+    let removed = watchers.remove(&config.id);
+    drop(watchers);
+    if let Some(watch) = removed {
+        watch.stop().await;
+    }
+    start_watch(app, config, state).await?;
+    Ok(ReloadAction::Restarted)
+}
@@ -0,0 +1,86 @@
+use std::ffi::{OsStr, OsString};
+
+use anyhow::Context;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// 用户配置的重命名规则：文件名中第一处匹配 `from` 的部分替换为 `to`。
+///
+/// `to` 可以用 `$1`、`${name}` 引用捕获组，例如 `\.md$` -> `.html`。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RenameRule {
+    pub from: String,
+    pub to: String,
+}
+
+/// 编译后的 [`RenameRule`]。
+pub struct Renamer {
+    from: Regex,
+    to: String,
+}
+
+impl Renamer {
+    pub fn new(rule: &RenameRule) -> anyhow::Result<Self> {
+        let from =
+            Regex::new(&rule.from).with_context(|| format!("无效的重命名规则 '{}'", rule.from))?;
+        Ok(Self {
+            from,
+            to: rule.to.clone(),
+        })
+    }
+
+    /// 对文件名应用规则。非 UTF-8 的文件名和替换后为空的文件名保持不变。
+    pub fn apply(&self, file_name: &OsStr) -> OsString {
+        let Some(name) = file_name.to_str() else {
+            return file_name.to_os_string();
+        };
+        let renamed = self.from.replace(name, self.to.as_str());
+        if renamed.is_empty() {
+            return file_name.to_os_string();
+        }
+        renamed.into_owned().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renamer(from: &str, to: &str) -> Renamer {
+        Renamer::new(&RenameRule {
+            from: from.to_string(),
+            to: to.to_string(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn swaps_extension() {
+        let renamer = renamer(r"\.md$", ".html");
+        assert_eq!(renamer.apply(OsStr::new("notes.md")), "notes.html");
+        assert_eq!(renamer.apply(OsStr::new("notes.mdx")), "notes.mdx");
+    }
+
+    #[test]
+    fn expands_capture_groups() {
+        let renamer = renamer(r"^draft-(?<name>.+)$", "${name}");
+        assert_eq!(renamer.apply(OsStr::new("draft-report.txt")), "report.txt");
+    }
+
+    #[test]
+    fn empty_result_keeps_the_name() {
+        let renamer = renamer(r"^.*$", "");
+        assert_eq!(renamer.apply(OsStr::new("a.txt")), "a.txt");
+    }
+
+    #[test]
+    fn invalid_regex_is_rejected() {
+        let err = Renamer::new(&RenameRule {
+            from: "(".to_string(),
+            to: String::new(),
+        })
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("无效的重命名规则"));
+    }
+}
@@ -0,0 +1,107 @@
+//! 退出前的收尾工作。
+//!
+//! 退出请求会先被拦截，写回每个监视的索引和清单，并把统计和暂停状态保存到 store，
+//! 下次启动时前端可以显示上次运行的情况，同 id 的监视也会恢复暂停状态。
+//! 收尾超过 [`SHUTDOWN_TIMEOUT`] 时放弃等待直接退出。
+
+use std::{
+    collections::BTreeMap,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+use tauri_plugin_store::StoreExt;
+
+use crate::{pause::PauseMode, stats::StatsSnapshot, AppState, STORE_FILE};
+
+/// 上次运行状态在 store 中的键，值为监视 id -> [`LastRun`]。
+const LAST_RUN_KEY: &str = "lastRun";
+/// 收尾工作的最长等待时间。
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 收尾完成后再次发出的退出请求不再拦截。
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// 监视在上次退出时的状态。
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LastRun {
+    pub name: String,
+    pub stats: StatsSnapshot,
+    pub paused: Option<PauseMode>,
+    /// 退出时间，自 UNIX 纪元起的毫秒数。
+    pub stopped_at_ms: u64,
+}
+
+/// 处理 `RunEvent::ExitRequested`：首次请求时阻止退出，收尾后再以同样的退出码退出。
+pub fn on_exit_requested(app: &tauri::AppHandle, api: &tauri::ExitRequestApi, code: Option<i32>) {
+    if SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    api.prevent_exit();
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, persist(&app))
+            .await
+            .is_err()
+        {
+            warn!("退出前的收尾工作超时，直接退出。");
+        }
+        app.exit(code.unwrap_or(0));
+    });
+}
+
+/// 写回所有监视的索引和清单，并保存它们的统计和暂停状态。
+async fn persist(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    let watches: Vec<_> = state
+        .watcher
+        .lock()
+        .await
+        .iter()
+        .map(|(id, watch)| (id.clone(), watch.config.name(), watch.state.clone()))
+        .collect();
+    let stopped_at_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64);
+    let mut last_runs = load(app);
+    for (id, name, watch_state) in watches {
+        watch_state.save().await;
+        let last_run = LastRun {
+            name,
+            stats: watch_state.stats().snapshot(),
+            paused: watch_state.pause_mode(),
+            stopped_at_ms,
+        };
+        last_runs.insert(id, last_run);
+    }
+    if let Err(e) = save(app, &last_runs) {
+        warn!("保存上次运行状态失败: {:#}", e);
+        return;
+    }
+    info!("已保存 {} 个监视的运行状态。", last_runs.len());
+}
+
+/// 读取所有监视上次退出时的状态，读取失败时返回空表。
+pub fn load(app: &tauri::AppHandle) -> BTreeMap<String, LastRun> {
+    let value = match app.store(STORE_FILE) {
+        Ok(store) => store.get(LAST_RUN_KEY),
+        Err(e) => {
+            warn!("无法打开 store: {:?}", e);
+            None
+        }
+    };
+    value
+        .and_then(|value| serde_json::from_value(value).ok())
+        .unwrap_or_default()
+}
+
+fn save(app: &tauri::AppHandle, last_runs: &BTreeMap<String, LastRun>) -> anyhow::Result<()> {
+    let store = app.store(STORE_FILE)?;
+    store.set(LAST_RUN_KEY, serde_json::to_value(last_runs)?);
+    store.save()?;
+    Ok(())
+}
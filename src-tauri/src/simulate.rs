@@ -0,0 +1,522 @@
+//! 构造模拟事件并交给处理器，演练监视的过滤规则和各项策略会执行哪些文件操作。
+//!
+//! 默认不写入磁盘：[`RecordingFs`] 照常读取文件系统，写操作只记录下来不执行。
+//! 演练使用独立的监视状态，不影响正在运行的监视的索引、扁平化名称和暂存区。
+
+use std::{
+    collections::HashMap,
+    io,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use notify::event::{CreateKind, DataChange, EventKind, ModifyKind, RemoveKind, RenameMode};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::WatchConfig,
+    file_processor::{self, ProcessContext},
+    filter::PathFilter,
+    fs_ops::{Digest, FsOps, TokioFs},
+    rename::Renamer,
+    watch_state::WatchState,
+};
+
+/// 可模拟的事件类型。
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SimulatedKind {
+    Create,
+    CreateFolder,
+    Modify,
+    Remove,
+    RemoveFolder,
+    RenameFrom,
+    RenameTo,
+}
+
+impl SimulatedKind {
+    fn event_kind(self) -> EventKind {
+        match self {
+            Self::Create => EventKind::Create(CreateKind::File),
+            Self::CreateFolder => EventKind::Create(CreateKind::Folder),
+            Self::Modify => EventKind::Modify(ModifyKind::Data(DataChange::Content)),
+            Self::Remove => EventKind::Remove(RemoveKind::File),
+            Self::RemoveFolder => EventKind::Remove(RemoveKind::Folder),
+            Self::RenameFrom => EventKind::Modify(ModifyKind::Name(RenameMode::From)),
+            Self::RenameTo => EventKind::Modify(ModifyKind::Name(RenameMode::To)),
+        }
+    }
+}
+
+/// 处理器发出的一项写操作。
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum SimulatedOp {
+    CreateDir { path: PathBuf },
+    Copy { from: PathBuf, to: PathBuf },
+    DeltaCopy { from: PathBuf, to: PathBuf },
+    Reflink { from: PathBuf, to: PathBuf },
+    RemoveFile { path: PathBuf },
+    RemoveDir { path: PathBuf },
+    CreateFifo { path: PathBuf },
+    Trash { path: PathBuf },
+    Rename { from: PathBuf, to: PathBuf },
+    HardLink { original: PathBuf, link: PathBuf },
+    SymlinkFile { original: PathBuf, link: PathBuf },
+    SymlinkDir { original: PathBuf, link: PathBuf },
+    Junction { original: PathBuf, link: PathBuf },
+    SetReadonly { path: PathBuf, readonly: bool },
+}
+
+/// 模拟的结果。
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Simulation {
+    /// 是否真正执行了操作。
+    pub applied: bool,
+    /// 按顺序列出的写操作，为空表示事件被过滤或无需处理。
+    pub operations: Vec<SimulatedOp>,
+    /// 处理器返回的错误。
+    pub error: Option<String>,
+}
+
+/// 记录所有写操作的文件系统，`apply` 为 `false` 时不执行写操作。
+///
+/// 读操作始终访问真实文件系统，因此演练中后续的判断看不到前面记录的写操作的结果；
+/// 只有复制、重命名后的目标读取大小和摘要时会转而读取对应的源文件。
+struct RecordingFs {
+    apply: bool,
+    ops: Mutex<Vec<SimulatedOp>>,
+    /// 演练中写入的目标路径 -> 内容来源。
+    written: Mutex<HashMap<PathBuf, PathBuf>>,
+}
+
+impl RecordingFs {
+    fn new(apply: bool) -> Self {
+        Self {
+            apply,
+            ops: Mutex::default(),
+            written: Mutex::default(),
+        }
+    }
+
+    /// 记录操作，返回是否需要真正执行。
+    fn record(&self, op: SimulatedOp) -> bool {
+        self.ops.lock().unwrap_or_else(|e| e.into_inner()).push(op);
+        self.apply
+    }
+
+    fn mark_written(&self, from: &Path, to: &Path) {
+        let from = self.source_of(from);
+        self.written
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(to.to_path_buf(), from);
+    }
+
+    /// 演练时读取 `path` 应当读取的真实路径。
+    fn source_of(&self, path: &Path) -> PathBuf {
+        if self.apply {
+            return path.to_path_buf();
+        }
+        self.written
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    fn into_ops(self) -> Vec<SimulatedOp> {
+        self.ops.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl FsOps for RecordingFs {
+    async fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let path = path.to_path_buf();
+        if self.record(SimulatedOp::CreateDir { path: path.clone() }) {
+            return TokioFs.create_dir_all(&path).await;
+        }
+        Ok(())
+    }
+
+    async fn copy(
+        &self,
+        from: &Path,
+        to: &Path,
+        buffer_size: usize,
+        progress: &mut (dyn FnMut(u64) -> ControlFlow<()> + Send),
+    ) -> io::Result<u64> {
+        let op = SimulatedOp::Copy {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.copy(from, to, buffer_size, progress).await;
+        }
+        self.mark_written(from, to);
+        TokioFs.len(&self.source_of(from)).await
+    }
+
+    async fn delta_copy(
+        &self,
+        from: &Path,
+        to: &Path,
+        block_size: usize,
+        progress: &mut (dyn FnMut(u64) -> ControlFlow<()> + Send),
+    ) -> io::Result<u64> {
+        let op = SimulatedOp::DeltaCopy {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.delta_copy(from, to, block_size, progress).await;
+        }
+        self.mark_written(from, to);
+        TokioFs.len(&self.source_of(from)).await
+    }
+
+    async fn reflink(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let op = SimulatedOp::Reflink {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.reflink(from, to).await;
+        }
+        self.mark_written(from, to);
+        Ok(())
+    }
+
+    async fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let op = SimulatedOp::RemoveFile {
+            path: path.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.remove_file(path).await;
+        }
+        Ok(())
+    }
+
+    async fn trash(&self, path: &Path) -> io::Result<()> {
+        let op = SimulatedOp::Trash {
+            path: path.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.trash(path).await;
+        }
+        Ok(())
+    }
+
+    async fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let op = SimulatedOp::Rename {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.rename(from, to).await;
+        }
+        self.mark_written(from, to);
+        Ok(())
+    }
+
+    async fn hard_link(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let op = SimulatedOp::HardLink {
+            original: original.to_path_buf(),
+            link: link.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.hard_link(original, link).await;
+        }
+        self.mark_written(original, link);
+        Ok(())
+    }
+
+    async fn create_fifo(&self, like: &Path, path: &Path) -> io::Result<()> {
+        let op = SimulatedOp::CreateFifo {
+            path: path.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.create_fifo(like, path).await;
+        }
+        Ok(())
+    }
+
+    async fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        let op = SimulatedOp::RemoveDir {
+            path: path.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.remove_dir(path).await;
+        }
+        Ok(())
+    }
+
+    async fn symlink_file(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let op = SimulatedOp::SymlinkFile {
+            original: original.to_path_buf(),
+            link: link.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.symlink_file(original, link).await;
+        }
+        Ok(())
+    }
+
+    async fn symlink_dir(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let op = SimulatedOp::SymlinkDir {
+            original: original.to_path_buf(),
+            link: link.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.symlink_dir(original, link).await;
+        }
+        Ok(())
+    }
+
+    async fn junction(&self, original: &Path, link: &Path) -> io::Result<()> {
+        let op = SimulatedOp::Junction {
+            original: original.to_path_buf(),
+            link: link.to_path_buf(),
+        };
+        if self.record(op) {
+            return TokioFs.junction(original, link).await;
+        }
+        Ok(())
+    }
+
+    async fn set_readonly(&self, path: &Path, readonly: bool) -> io::Result<()> {
+        let op = SimulatedOp::SetReadonly {
+            path: path.to_path_buf(),
+            readonly,
+        };
+        if self.record(op) {
+            return TokioFs.set_readonly(path, readonly).await;
+        }
+        Ok(())
+    }
+
+    async fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        TokioFs.read_link(path).await
+    }
+
+    async fn read_dir(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        TokioFs.read_dir(path).await
+    }
+
+    async fn digest(&self, path: &Path) -> io::Result<Digest> {
+        TokioFs.digest(&self.source_of(path)).await
+    }
+
+    async fn read_head(&self, path: &Path, limit: u64) -> io::Result<Vec<u8>> {
+        TokioFs.read_head(&self.source_of(path), limit).await
+    }
+
+    async fn len(&self, path: &Path) -> io::Result<u64> {
+        TokioFs.len(&self.source_of(path)).await
+    }
+
+    async fn modified(&self, path: &Path) -> io::Result<SystemTime> {
+        TokioFs.modified(path).await
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        TokioFs.exists(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        TokioFs.is_file(path)
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        TokioFs.is_dir(path)
+    }
+
+    fn is_symlink(&self, path: &Path) -> bool {
+        TokioFs.is_symlink(path)
+    }
+
+    fn is_readonly(&self, path: &Path) -> bool {
+        TokioFs.is_readonly(path)
+    }
+
+    fn volume_id(&self, path: &Path) -> io::Result<u64> {
+        TokioFs.volume_id(path)
+    }
+}
+
+/// 将 `path` 上的模拟事件交给监视的处理器。
+///
+/// `apply` 为 `false` 时只记录操作；为 `true` 时使用正在运行的监视的状态真正执行。
+/// 演练时关闭复制扩展属性和所有者，它们不经过 [`FsOps`]，无法只记录不执行。
+/// 配置的钩子照常运行，钩子程序自身的操作不会被记录。
+pub async fn simulate(
+    config: &WatchConfig,
+    live_state: &Arc<WatchState>,
+    kind: SimulatedKind,
+    path: PathBuf,
+    apply: bool,
+) -> anyhow::Result<Simulation> {
+    let filter = PathFilter::new(&config.options.filters)?;
+    let mut options = config.options.clone();
+    let state = if apply {
+        live_state.clone()
+    } else {
+        options.copy_xattrs = false;
+        options.preserve_ownership = false;
+        options.force_mode = None;
+        options.permission_mask = None;
+        Arc::new(dry_run_state(config, live_state)?)
+    };
+    let fs = RecordingFs::new(apply);
+    let ctx = ProcessContext {
+        fs: &fs,
+        from: &config.from,
+        to: &config.to,
+        options: &options,
+        filter: &filter,
+        state: &state,
+        progress: None,
+    };
+    let event = notify::Event::new(kind.event_kind()).add_path(path);
+    let result = file_processor::process(&ctx, state.copy_type(), &event).await;
+    Ok(Simulation {
+        applied: apply,
+        operations: fs.into_ops(),
+        error: result.err().map(|e| format!("{:#}", e)),
+    })
+}
+
+/// 演练用的独立状态：不加载清单、不限速，其他判断依据与正在运行的监视一致。
+fn dry_run_state(config: &WatchConfig, live_state: &WatchState) -> anyhow::Result<WatchState> {
+    let renamer = config
+        .options
+        .rename_rule
+        .as_ref()
+        .map(Renamer::new)
+        .transpose()?;
+    let state = WatchState::new(live_state.copy_type(), renamer, None, None, None);
+    state.with_index(|index| *index = live_state.with_index(|live| live.clone()));
+    state.set_modified_after(config.options.modified_after());
+    if let Some(same) = live_state.same_filesystem() {
+        state.set_same_filesystem(same);
+    }
+    if live_state.reflink_unsupported() {
+        state.set_reflink_unsupported();
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{config::WatchOptions, CopyType};
+
+    struct Sandbox {
+        _dir: tempfile::TempDir,
+        config: WatchConfig,
+        state: Arc<WatchState>,
+    }
+
+    impl Sandbox {
+        fn new(options: WatchOptions) -> Self {
+            let dir = tempfile::tempdir().unwrap();
+            let from = dir.path().join("src");
+            let to = dir.path().join("dst");
+            fs::create_dir_all(&from).unwrap();
+            fs::create_dir_all(&to).unwrap();
+            let config = WatchConfig {
+                id: "test".to_string(),
+                from,
+                to,
+                copy_type: CopyType::Copy,
+                options,
+            };
+            let state = Arc::new(WatchState::new(CopyType::Copy, None, None, None, None));
+            Self {
+                _dir: dir,
+                config,
+                state,
+            }
+        }
+
+        async fn run(&self, kind: SimulatedKind, name: &str, apply: bool) -> Simulation {
+            let path = self.config.from.join(name);
+            simulate(&self.config, &self.state, kind, path, apply)
+                .await
+                .unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn dry_run_records_copy_without_writing() {
+        let sandbox = Sandbox::new(WatchOptions::default());
+        fs::write(sandbox.config.from.join("a.txt"), "data").unwrap();
+        let simulation = sandbox.run(SimulatedKind::Create, "a.txt", false).await;
+        assert!(!simulation.applied);
+        assert_eq!(simulation.error, None);
+        assert_eq!(
+            simulation.operations,
+            vec![SimulatedOp::Copy {
+                from: sandbox.config.from.join("a.txt"),
+                to: sandbox.config.to.join("a.txt"),
+            }]
+        );
+        assert!(!sandbox.config.to.join("a.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn dry_run_remove_keeps_target() {
+        let sandbox = Sandbox::new(WatchOptions::default());
+        let target = sandbox.config.to.join("a.txt");
+        fs::write(&target, "data").unwrap();
+        let simulation = sandbox.run(SimulatedKind::Remove, "a.txt", false).await;
+        assert_eq!(
+            simulation.operations,
+            vec![SimulatedOp::RemoveFile {
+                path: target.clone()
+            }]
+        );
+        assert!(target.exists());
+    }
+
+    #[tokio::test]
+    async fn filtered_path_results_in_no_operations() {
+        let mut options = WatchOptions::default();
+        options.filters.exclude = vec!["*.log".to_string()];
+        let sandbox = Sandbox::new(options);
+        fs::write(sandbox.config.from.join("a.log"), "data").unwrap();
+        fs::write(sandbox.config.from.join("b.tmp"), "data").unwrap();
+        for name in ["a.log", "b.tmp"] {
+            let simulation = sandbox.run(SimulatedKind::Create, name, false).await;
+            assert!(simulation.operations.is_empty(), "{}", name);
+            assert_eq!(simulation.error, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn applied_simulation_performs_operations() {
+        let sandbox = Sandbox::new(WatchOptions::default());
+        fs::write(sandbox.config.from.join("a.txt"), "data").unwrap();
+        let simulation = sandbox.run(SimulatedKind::Create, "a.txt", true).await;
+        assert!(simulation.applied);
+        assert_eq!(simulation.operations.len(), 1);
+        assert_eq!(fs::read(sandbox.config.to.join("a.txt")).unwrap(), b"data");
+    }
+
+    #[tokio::test]
+    async fn processor_errors_are_reported_not_returned() {
+        let sandbox = Sandbox::new(WatchOptions::default());
+        fs::create_dir_all(sandbox.config.to.join("a.txt")).unwrap();
+        fs::write(sandbox.config.from.join("a.txt"), "data").unwrap();
+        let simulation = sandbox.run(SimulatedKind::Create, "a.txt", false).await;
+        assert!(simulation.error.is_some());
+        assert!(sandbox.config.to.join("a.txt").is_dir());
+    }
+}
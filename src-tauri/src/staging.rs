@@ -0,0 +1,100 @@
+//! 暂存目录。
+//!
+//! 开启后复制的文件先写入目标目录下的暂存目录，连续一段时间没有新文件写入后
+//! 再统一移动到最终位置，目标目录的使用者不会看到同步到一半的状态。
+//! 暂存目录与最终位置在同一卷上，因此移动是原子的重命名。
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// 暂存目录在目标目录下的名称。
+pub const STAGING_DIR: &str = ".auto-copy-staging";
+
+/// 等待提交的暂存文件。
+#[derive(Default)]
+pub struct Staging {
+    /// 最终路径 -> 暂存路径
+    pending: HashMap<PathBuf, PathBuf>,
+    last_change: Option<Instant>,
+}
+
+impl Staging {
+    /// 最终路径 `target` 对应的暂存路径。
+    pub fn staging_path(to: &Path, target: &Path) -> anyhow::Result<PathBuf> {
+        Ok(to.join(STAGING_DIR).join(target.strip_prefix(to)?))
+    }
+
+    pub fn add(&mut self, target: PathBuf, staged: PathBuf) {
+        self.pending.insert(target, staged);
+        self.last_change = Some(Instant::now());
+    }
+
+    /// 放弃尚未提交的暂存文件，返回其暂存路径。
+    pub fn discard(&mut self, target: &Path) -> Option<PathBuf> {
+        self.pending.remove(target)
+    }
+
+    /// 自最后一次暂存起已安静 `quiet` 时取出所有待提交的文件，`quiet` 为空时立即取出。
+    pub fn take(&mut self, quiet: Option<Duration>) -> Vec<(PathBuf, PathBuf)> {
+        let ready = match (quiet, self.last_change) {
+            (Some(quiet), Some(last_change)) => last_change.elapsed() >= quiet,
+            _ => true,
+        };
+        if !ready {
+            return Vec::new();
+        }
+        self.pending.drain().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn staging_path_mirrors_target_under_staging_dir() {
+        let to = Path::new("/dst");
+        assert_eq!(
+            Staging::staging_path(to, &to.join("a/b.txt")).unwrap(),
+            to.join(STAGING_DIR).join("a/b.txt")
+        );
+        assert!(Staging::staging_path(to, Path::new("/other/b.txt")).is_err());
+    }
+
+    #[test]
+    fn files_are_held_until_quiet() {
+        let mut staging = Staging::default();
+        staging.add(PathBuf::from("/dst/a"), PathBuf::from("/dst/.s/a"));
+        assert!(staging.take(Some(Duration::from_secs(3600))).is_empty());
+        assert_eq!(
+            staging.take(Some(Duration::ZERO)),
+            vec![(PathBuf::from("/dst/a"), PathBuf::from("/dst/.s/a"))]
+        );
+        assert!(staging.take(None).is_empty());
+    }
+
+    #[test]
+    fn new_file_restarts_quiet_period() {
+        let mut staging = Staging::default();
+        staging.add(PathBuf::from("/dst/a"), PathBuf::from("/dst/.s/a"));
+        std::thread::sleep(Duration::from_millis(30));
+        staging.add(PathBuf::from("/dst/b"), PathBuf::from("/dst/.s/b"));
+        assert!(staging.take(Some(Duration::from_millis(25))).is_empty());
+        std::thread::sleep(Duration::from_millis(30));
+        assert_eq!(staging.take(Some(Duration::from_millis(25))).len(), 2);
+    }
+
+    #[test]
+    fn discarded_file_is_not_committed() {
+        let mut staging = Staging::default();
+        staging.add(PathBuf::from("/dst/a"), PathBuf::from("/dst/.s/a"));
+        assert_eq!(
+            staging.discard(Path::new("/dst/a")),
+            Some(PathBuf::from("/dst/.s/a"))
+        );
+        assert!(staging.take(None).is_empty());
+    }
+}
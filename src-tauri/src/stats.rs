@@ -0,0 +1,83 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// 单个监视的累计计数，命令读取时不需要加锁。
+#[derive(Default)]
+pub struct WatchStats {
+    files_synced: AtomicU64,
+    files_deleted: AtomicU64,
+    errors: AtomicU64,
+    bytes_synced: AtomicU64,
+    /// 最近一次成功同步或删除的时间，自 UNIX 纪元起的毫秒数，0 表示没有。
+    last_activity_ms: AtomicU64,
+}
+
+impl WatchStats {
+    pub fn record_synced(&self, bytes: u64) {
+        self.files_synced.fetch_add(1, Ordering::Relaxed);
+        self.bytes_synced.fetch_add(bytes, Ordering::Relaxed);
+        self.touch();
+    }
+
+    pub fn record_deleted(&self) {
+        self.files_deleted.fetch_add(1, Ordering::Relaxed);
+        self.touch();
+    }
+
+    /// 恢复上次运行时保存的最近活动时间，本次运行已有活动时不覆盖。
+    pub fn restore_last_activity(&self, last_activity_ms: u64) {
+        self.last_activity_ms
+            .fetch_max(last_activity_ms, Ordering::Relaxed);
+    }
+
+    pub fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            files_synced: self.files_synced.load(Ordering::Relaxed),
+            files_deleted: self.files_deleted.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            bytes_synced: self.bytes_synced.load(Ordering::Relaxed),
+            last_activity_ms: match self.last_activity_ms.load(Ordering::Relaxed) {
+                0 => None,
+                ms => Some(ms),
+            },
+        }
+    }
+
+    fn touch(&self) {
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        self.last_activity_ms.fetch_max(now_ms, Ordering::Relaxed);
+    }
+}
+
+/// [`WatchStats`] 在某一时刻的值。
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsSnapshot {
+    pub files_synced: u64,
+    pub files_deleted: u64,
+    pub errors: u64,
+    pub bytes_synced: u64,
+    /// 最近一次成功同步或删除的时间，自 UNIX 纪元起的毫秒数。
+    #[serde(default)]
+    pub last_activity_ms: Option<u64>,
+}
+
+impl std::ops::AddAssign for StatsSnapshot {
+    fn add_assign(&mut self, other: Self) {
+        self.files_synced += other.files_synced;
+        self.files_deleted += other.files_deleted;
+        self.errors += other.errors;
+        self.bytes_synced += other.bytes_synced;
+        self.last_activity_ms = self.last_activity_ms.max(other.last_activity_ms);
+    }
+}
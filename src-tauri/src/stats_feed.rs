@@ -0,0 +1,114 @@
+//! 定期向前端推送所有监视的统计，供实时面板使用，不需要前端轮询。
+//!
+//! 默认关闭；通过 `set_stats_snapshot_interval` 设置间隔后每隔一段时间发送一次
+//! `stats-snapshot` 事件，之后打开的窗口在下一次推送时即可拿到完整状态。
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::error;
+use serde::Serialize;
+use tauri::{Emitter, Manager};
+use tokio::sync::Notify;
+
+use crate::{diagnostics::QueueDepths, pause::PauseMode, stats::StatsSnapshot, AppState};
+
+/// 推送的最短间隔，避免过于频繁地获取监视表锁。
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 推送间隔的设置。
+#[derive(Default)]
+pub struct StatsFeed {
+    /// 为 `None` 时不推送。
+    interval: Mutex<Option<Duration>>,
+    changed: Notify,
+}
+
+impl StatsFeed {
+    pub fn set_interval(&self, interval: Option<Duration>) {
+        *self.interval.lock().unwrap_or_else(|e| e.into_inner()) =
+            interval.map(|interval| interval.max(MIN_INTERVAL));
+        self.changed.notify_one();
+    }
+
+    fn interval(&self) -> Option<Duration> {
+        *self.interval.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// 单个监视在 `stats-snapshot` 中的状态。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct WatchSnapshot {
+    pub name: String,
+    pub stats: StatsSnapshot,
+    pub queue_depths: QueueDepths,
+    pub paused: Option<PauseMode>,
+    pub syncing: bool,
+}
+
+/// `stats-snapshot` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsSnapshotPayload {
+    /// 生成快照的时间，自 UNIX 纪元起的毫秒数。
+    pub at_ms: u64,
+    /// 监视 id -> 状态。
+    pub watches: BTreeMap<String, WatchSnapshot>,
+    /// 等待路径出现的监视 id。
+    pub pending: BTreeSet<String>,
+}
+
+/// 按设置的间隔推送快照，间隔为空时等待设置改变。
+pub async fn run(app: tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    let feed = &state.stats_feed;
+    loop {
+        let changed = feed.changed.notified();
+        match feed.interval() {
+            Some(interval) => {
+                tokio::select! {
+                    _ = tokio::time::sleep(interval) => emit(&app).await,
+                    _ = changed => {}
+                }
+            }
+            None => changed.await,
+        }
+    }
+}
+
+/// 所有监视的统计汇总到一个事件中发送给所有窗口，不受按监视 id 的订阅限制。
+async fn emit(app: &tauri::AppHandle) {
+    let state = app.state::<AppState>();
+    let syncing: BTreeSet<String> = state.syncs.lock().await.keys().cloned().collect();
+    let watches = state
+        .watcher
+        .lock()
+        .await
+        .iter()
+        .map(|(id, watch)| {
+            let snapshot = WatchSnapshot {
+                name: watch.config.name(),
+                stats: watch.state.stats().snapshot(),
+                queue_depths: watch.state.queue_depths(),
+                paused: watch.state.pause_mode(),
+                syncing: syncing.contains(id),
+            };
+            (id.clone(), snapshot)
+        })
+        .collect();
+    let pending = state.pending.lock().await.keys().cloned().collect();
+    let payload = StatsSnapshotPayload {
+        at_ms: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64),
+        watches,
+        pending,
+    };
+    if let Err(e) = app.emit("stats-snapshot", payload) {
+        error!("发送事件 'stats-snapshot' 失败: {:?}", e);
+    }
+}
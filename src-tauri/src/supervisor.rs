@@ -0,0 +1,226 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::anyhow;
+use log::{error, info};
+use tauri::Manager;
+use tokio::{select, spawn, time::sleep};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::WatchConfig,
+    event_loop::{self, EventLoop},
+    events::{self, ErrorPayload, RestartPayload},
+    filter::PathFilter,
+    open_source,
+    queue::EventReceiver,
+    watch_state::WatchState,
+    AppState,
+};
+
+/// 事件循环 panic 后最多自动重建监视的次数。
+const MAX_RESTARTS: u32 = 3;
+/// 第一次重建前的等待时间，之后每次翻倍。
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+/// 重建后的事件循环运行超过这个时间才 panic 时，不与之前的失败累计为连续失败。
+const HEALTHY_UPTIME: Duration = Duration::from_secs(60);
+
+/// 事件循环连续失败的次数，决定重建前的等待时间和何时放弃。
+#[derive(Default)]
+struct RestartCounter {
+    restarts: u32,
+}
+
+impl RestartCounter {
+    /// 记录一次运行 `uptime` 后发生的 panic，返回重建前的等待时间。
+    ///
+    /// 运行超过 [`HEALTHY_UPTIME`] 时先清零计数；连续失败已超过 [`MAX_RESTARTS`] 次时返回 `None`。
+    fn on_panic(&mut self, uptime: Duration) -> Option<Duration> {
+        if uptime >= HEALTHY_UPTIME {
+            self.restarts = 0;
+        }
+        if self.restarts >= MAX_RESTARTS {
+            return None;
+        }
+        self.restarts += 1;
+        Some(RESTART_BACKOFF * 2u32.pow(self.restarts - 1))
+    }
+
+    /// 本轮连续失败中的重建次数，从 1 开始。
+    fn attempt(&self) -> u32 {
+        self.restarts
+    }
+}
+
+/// 运行监视的事件循环，并在其 panic 时重建监视。
+///
+/// 重建会创建新的系统监视器替换监视表中的旧监视器，连续失败超过
+/// [`MAX_RESTARTS`] 次后放弃，并将监视从表中移除。重建后正常运行超过 [`HEALTHY_UPTIME`]
+/// 的监视再次 panic 时重新计数，长期运行中偶发的失败不会累计到上限。
+pub async fn supervise(
+    app: tauri::AppHandle,
+    mut config: WatchConfig,
+    serial: u64,
+    filter: PathFilter,
+    state: Arc<WatchState>,
+    mut rx: EventReceiver,
+    shutdown: CancellationToken,
+) {
+    let mut restarts = RestartCounter::default();
+    loop {
+        let event_loop = EventLoop::new(
+            app.clone(),
+            config.clone(),
+            serial,
+            filter.clone(),
+            state.clone(),
+            shutdown.clone(),
+        );
+        let started = Instant::now();
+        let panic = match spawn(event_loop.run(rx)).await {
+            Ok(()) => return,
+            Err(e) => e,
+        };
+        // 运行期间 `rename_watch` 可能改变了 id，以监视表中的为准
+        if let Some(id) = current_id(&app, serial).await {
+            config.id = id;
+        }
+        error!(
+            "监视 '{}' 的事件处理循环异常退出: {:?}",
+            config.name(),
+            panic
+        );
+        let Some(backoff) = restarts.on_panic(started.elapsed()) else {
+            give_up(
+                &app,
+                &config,
+                serial,
+                "事件处理循环多次异常退出，已停止监视",
+            )
+            .await;
+            return;
+        };
+        select! {
+            () = sleep(backoff) => {}
+            () = shutdown.cancelled() => return,
+        }
+        rx = match rewatch(&app, &config, serial).await {
+            Ok(Some(rx)) => rx,
+            // 等待期间监视已被停止或替换
+            Ok(None) => return,
+            Err(e) => {
+                give_up(&app, &config, serial, &format!("重建监视失败: {:#}", e)).await;
+                return;
+            }
+        };
+        info!(
+            "已重建监视 '{}'（第 {} 次）。",
+            config.name(),
+            restarts.attempt()
+        );
+        events::emit(
+            &app,
+            &config.id,
+            "watch-restarted",
+            RestartPayload {
+                id: config.id.clone(),
+                name: config.name(),
+                attempt: restarts.attempt(),
+            },
+        );
+    }
+}
+
+/// 监视表中序号为 `serial` 的监视当前的 id。
+async fn current_id(app: &tauri::AppHandle, serial: u64) -> Option<String> {
+    let state = app.state::<AppState>();
+    let watchers = state.watcher.lock().await;
+    watchers
+        .iter()
+        .find(|(_, watch)| watch.serial == serial)
+        .map(|(id, _)| id.clone())
+}
+
+/// 为监视表中序号为 `serial` 的监视重新创建事件来源，返回新的事件通道。
+///
+/// 监视已不在表中时返回 `None`。
+async fn rewatch(
+    app: &tauri::AppHandle,
+    config: &WatchConfig,
+    serial: u64,
+) -> anyhow::Result<Option<EventReceiver>> {
+    let state = app.state::<AppState>();
+    let mut watchers = state.watcher.lock().await;
+    let Some(watch) = watchers
+        .get_mut(&config.id)
+        .filter(|watch| watch.serial == serial)
+    else {
+        return Ok(None);
+    };
+    if !config.from.exists() {
+        return Err(anyhow!("源路径 '{}' 不存在。", config.from.display()));
+    }
+    let (watcher, rx) = open_source(config)?;
+    watch._watcher = watcher;
+    watch.created_at = SystemTime::now();
+    Ok(Some(rx))
+}
+
+async fn give_up(app: &tauri::AppHandle, config: &WatchConfig, serial: u64, message: &str) {
+    error!("监视 '{}': {}", config.name(), message);
+    if event_loop::remove_watch(app, &config.id, serial).await {
+        events::emit(
+            app,
+            &config.id,
+            "watch-error",
+            ErrorPayload {
+                id: config.id.clone(),
+                name: config.name(),
+                path: None,
+                message: message.to_string(),
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consecutive_panics_back_off_then_give_up() {
+        let mut restarts = RestartCounter::default();
+        let quick = Duration::from_millis(10);
+        assert_eq!(restarts.on_panic(quick), Some(RESTART_BACKOFF));
+        assert_eq!(restarts.on_panic(quick), Some(RESTART_BACKOFF * 2));
+        assert_eq!(restarts.on_panic(quick), Some(RESTART_BACKOFF * 4));
+        assert_eq!(restarts.attempt(), MAX_RESTARTS);
+        assert_eq!(restarts.on_panic(quick), None);
+    }
+
+    #[test]
+    fn panic_after_healthy_run_starts_a_new_count() {
+        let mut restarts = RestartCounter::default();
+        let quick = Duration::from_millis(10);
+        for _ in 0..MAX_RESTARTS {
+            restarts.on_panic(quick).unwrap();
+        }
+        // 长期运行后的偶发 panic 不累计，等待时间也从头开始
+        assert_eq!(restarts.on_panic(HEALTHY_UPTIME), Some(RESTART_BACKOFF));
+        assert_eq!(restarts.attempt(), 1);
+        assert_eq!(restarts.on_panic(quick), Some(RESTART_BACKOFF * 2));
+    }
+
+    #[test]
+    fn unrelated_panics_over_a_long_run_never_give_up() {
+        let mut restarts = RestartCounter::default();
+        for _ in 0..10 {
+            assert_eq!(
+                restarts.on_panic(HEALTHY_UPTIME * 24),
+                Some(RESTART_BACKOFF)
+            );
+        }
+    }
+}
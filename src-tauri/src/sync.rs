@@ -0,0 +1,586 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use log::{error, info, warn};
+use notify::{
+    event::{CreateKind, RemoveKind},
+    Event, EventKind,
+};
+use serde::Serialize;
+use tauri::Manager;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    config::WatchConfig,
+    events,
+    file_processor::{self, ProcessContext},
+    file_processor_monitor,
+    filter::PathFilter,
+    fs_ops::TokioFs,
+    index::IndexEntry,
+    metadata,
+    progress::ProgressReporter,
+    walk::{self, Cancelled, WalkEntry},
+    watch_state::WatchState,
+    AppState, CopyType,
+};
+
+/// 两次进度事件之间的最短间隔。
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// `sync-progress` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncProgress {
+    pub watch_id: String,
+    pub files_done: u64,
+    pub files_total: u64,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+    pub current_path: Option<PathBuf>,
+}
+
+/// 一次全量同步的结果。
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncSummary {
+    pub files_synced: u64,
+    /// 索引显示未变化而跳过的文件。
+    pub files_skipped: u64,
+    pub files_failed: u64,
+    pub bytes_synced: u64,
+    /// 同步是否被 `cancel_sync` 提前终止。
+    pub cancelled: bool,
+}
+
+/// `sync-cancelled` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncCancelled {
+    pub watch_id: String,
+    pub summary: SyncSummary,
+}
+
+/// `reconcile-finished` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconcileFinished {
+    pub watch_id: String,
+    pub summary: SyncSummary,
+}
+
+/// 定期对账：用一次全量同步修正实时事件遗漏的变化，完成后发送 `reconcile-finished`。
+///
+/// 已有全量同步在进行时跳过本次对账。
+pub async fn reconcile(
+    app: &tauri::AppHandle,
+    config: &WatchConfig,
+    filter: &PathFilter,
+    watch_state: &WatchState,
+) {
+    match run_full_sync(app, config, filter, watch_state).await {
+        Ok(summary) => {
+            info!("id '{}' 的定期对账完成: {:?}", config.id, summary);
+            let payload = ReconcileFinished {
+                watch_id: config.id.clone(),
+                summary,
+            };
+            events::emit(app, &config.id, "reconcile-finished", payload);
+        }
+        Err(e) => info!("id '{}' 的定期对账未执行: {:#}", config.id, e),
+    }
+}
+
+/// `copy-type-converted` 事件的负载。
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ConvertFinished {
+    pub watch_id: String,
+    pub copy_type: CopyType,
+    /// 转换失败时为 `None`，原因见 `error`。
+    pub summary: Option<SyncSummary>,
+    pub error: Option<String>,
+}
+
+/// 修改复制类型后将目标中已有的条目转换为新类型：清空同步索引后执行一次全量同步，
+/// 复制模式把软链接替换为文件，链接模式把文件替换为软链接。
+///
+/// 进度通过 `sync-progress` 报告，结束后发送 `copy-type-converted`。
+pub async fn convert_existing(
+    app: &tauri::AppHandle,
+    config: &WatchConfig,
+    filter: &PathFilter,
+    watch_state: &WatchState,
+) {
+    watch_state.with_index(|index| index.clear());
+    let result = run_full_sync(app, config, filter, watch_state).await;
+    let payload = convert_finished(config, result);
+    events::emit(app, &config.id, "copy-type-converted", payload);
+}
+
+/// 由转换时全量同步的结果生成 `copy-type-converted` 事件的负载。
+fn convert_finished(config: &WatchConfig, result: anyhow::Result<SyncSummary>) -> ConvertFinished {
+    match result {
+        Ok(summary) => {
+            info!(
+                "id '{}' 的已有文件已转换为 {:?}: {:?}",
+                config.id, config.copy_type, summary
+            );
+            ConvertFinished {
+                watch_id: config.id.clone(),
+                copy_type: config.copy_type,
+                summary: Some(summary),
+                error: None,
+            }
+        }
+        Err(e) => {
+            error!("转换 '{}' 的已有文件失败: {:?}", config.id, e);
+            ConvertFinished {
+                watch_id: config.id.clone(),
+                copy_type: config.copy_type,
+                summary: None,
+                error: Some(format!("{:#}", e)),
+            }
+        }
+    }
+}
+
+/// 为监视注册取消令牌后执行全量同步，结束后注销。
+///
+/// 同一监视同时只允许一个全量同步，可通过 `cancel_sync` 取消。
+pub async fn run_full_sync(
+    app: &tauri::AppHandle,
+    config: &WatchConfig,
+    filter: &PathFilter,
+    watch_state: &WatchState,
+) -> anyhow::Result<SyncSummary> {
+    let state = app.state::<AppState>();
+    let cancel = CancellationToken::new();
+    {
+        let mut syncs = state.syncs.lock().await;
+        if syncs.contains_key(&config.id) {
+            return Err(anyhow!("id '{}' 的全量同步已在进行中。", config.id));
+        }
+        syncs.insert(config.id.clone(), cancel.clone());
+    }
+    let result = full_sync(app, config, filter, watch_state, &cancel).await;
+    state.syncs.lock().await.remove(&config.id);
+    watch_state.save().await;
+    let summary = match result {
+        Err(e) if e.is::<Cancelled>() => SyncSummary {
+            cancelled: true,
+            ..Default::default()
+        },
+        result => result?,
+    };
+    if summary.cancelled {
+        info!("id '{}' 的全量同步已取消: {:?}", config.id, summary);
+        let payload = SyncCancelled {
+            watch_id: config.id.clone(),
+            summary: summary.clone(),
+        };
+        events::emit(app, &config.id, "sync-cancelled", payload);
+    }
+    Ok(summary)
+}
+
+/// 将源目录中的所有文件同步到目标目录。
+///
+/// 先遍历一次得到文件总数和总大小，再逐个同步，期间节流发送 `sync-progress` 事件。
+/// 单个文件失败只记录日志并计数，不中断整个同步。每个文件之间检查一次取消令牌。
+/// 同步索引显示未变化且目标仍存在（复制模式下大小也一致）的文件，
+/// 以及修改时间早于 `modified_after` 的文件会被跳过。
+async fn full_sync(
+    app: &tauri::AppHandle,
+    config: &WatchConfig,
+    filter: &PathFilter,
+    watch_state: &WatchState,
+    cancel: &CancellationToken,
+) -> anyhow::Result<SyncSummary> {
+    let reporter = ProgressReporter {
+        app,
+        watch_id: &config.id,
+        cancel: Some(cancel),
+    };
+    let ctx = ProcessContext {
+        fs: &TokioFs,
+        from: &config.from,
+        to: &config.to,
+        options: &config.options,
+        filter,
+        state: watch_state,
+        progress: Some(&reporter),
+    };
+    let files = walk::walk_files(&config.from, filter, cancel).await?;
+    let mut progress = SyncProgress {
+        watch_id: config.id.clone(),
+        files_done: 0,
+        files_total: files.len() as u64,
+        bytes_done: 0,
+        bytes_total: files.iter().map(|file| file.len).sum(),
+        current_path: None,
+    };
+    let dirs = synced_dirs(&config.from, &files);
+    let monitor = watch_state.copy_type() == CopyType::Monitor;
+    let expected: HashSet<PathBuf> = if monitor {
+        files
+            .iter()
+            .filter_map(|file| ctx.preview_target(&file.path).ok())
+            .collect()
+    } else {
+        HashSet::new()
+    };
+    let mut summary = SyncSummary::default();
+    let mut last_emit: Option<Instant> = None;
+    info!(
+        "开始全量同步 {:?}: {} 个文件, {} 字节",
+        config.from, progress.files_total, progress.bytes_total
+    );
+    for file in files {
+        if cancel.is_cancelled() {
+            summary.cancelled = true;
+            return Ok(summary);
+        }
+        let relative = file.path.strip_prefix(&config.from)?.to_path_buf();
+        let entry = IndexEntry::new(file.len, file.modified);
+        let indexed = entry.is_some_and(|entry| {
+            watch_state.with_index(|index| index.is_unchanged(&relative, entry))
+        });
+        let unchanged = indexed
+            && match ctx.target_path(&file.path) {
+                Ok(target) => target_matches(watch_state.copy_type(), &target, file.len).await,
+                Err(_) => false,
+            };
+        // 读取内容失败时不跳过，由同步本身报告错误
+        let skipped = unchanged
+            || watch_state.is_too_old(file.modified)
+            || !file_processor::content_matches(&ctx, &file.path)
+                .await
+                .unwrap_or(true);
+        if skipped {
+            summary.files_skipped += 1;
+        } else {
+            match file_processor::sync_file(&ctx, watch_state.copy_type(), &file.path).await {
+                Ok(()) => {
+                    summary.files_synced += 1;
+                    summary.bytes_synced += file.len;
+                    watch_state.stats().record_synced(file.len);
+                    watch_state
+                        .record_manifest(&relative, &file.path, entry)
+                        .await;
+                    if let Some(entry) = entry {
+                        watch_state.with_index(|index| index.record(relative, entry));
+                    }
+                }
+                // 复制大文件时被取消，不完整的目标已删除
+                Err(_) if cancel.is_cancelled() => {
+                    summary.cancelled = true;
+                    return Ok(summary);
+                }
+                Err(e) => {
+                    summary.files_failed += 1;
+                    watch_state.stats().record_error();
+                    watch_state
+                        .with_error_history(|history| history.record(Some(file.path.clone()), &e));
+                    error!("全量同步文件 {:?} 失败: {:?}", file.path, e);
+                }
+            }
+        }
+        progress.files_done += 1;
+        progress.bytes_done += file.len;
+        progress.current_path = Some(file.path);
+        if last_emit.is_none_or(|at| at.elapsed() >= PROGRESS_INTERVAL) {
+            events::emit(app, &config.id, "sync-progress", progress.clone());
+            last_emit = Some(Instant::now());
+        }
+    }
+    // 保证前端总能收到完成时的进度
+    events::emit(app, &config.id, "sync-progress", progress);
+    if monitor {
+        file_processor_monitor::report_extras(&ctx, &expected, cancel).await;
+    } else if config.options.preserve_dir_mtimes {
+        restore_dir_mtimes(&ctx, &dirs).await;
+    }
+    info!("全量同步 {:?} 完成: {:?}", config.from, summary);
+    Ok(summary)
+}
+
+/// 包含同步文件的源目录（含源目录本身）。
+fn synced_dirs(from: &Path, files: &[WalkEntry]) -> BTreeSet<PathBuf> {
+    let mut dirs = BTreeSet::new();
+    for file in files {
+        for dir in file.path.ancestors().skip(1) {
+            if !dir.starts_with(from) || !dirs.insert(dir.to_path_buf()) {
+                break;
+            }
+        }
+    }
+    dirs
+}
+
+/// 目录写入新条目后修改时间会更新，因此在所有文件同步后从最深的目录开始恢复。
+async fn restore_dir_mtimes(ctx: &ProcessContext<'_, TokioFs>, dirs: &BTreeSet<PathBuf>) {
+    // 子目录按字典序排在父目录之后，逆序遍历即先处理子目录
+    for dir in dirs.iter().rev() {
+        let Some(target) = ctx.target_dir(dir) else {
+            continue;
+        };
+        if let Err(e) = metadata::copy_dir_mtime(dir, &target).await {
+            warn!("无法恢复目录的修改时间: {:?}, {:?}", target, e);
+        }
+    }
+}
+
+/// 轮询模式下扫描一次源目录，为与同步索引相比有变化的路径生成事件。
+///
+/// 新增或修改的文件生成创建事件；索引中有记录但已不存在的文件生成删除事件。
+/// 事件交给事件循环按普通事件处理，处理成功后由其更新索引。
+pub async fn poll_changes(
+    config: &WatchConfig,
+    filter: &PathFilter,
+    watch_state: &WatchState,
+) -> anyhow::Result<Vec<Event>> {
+    let ctx = ProcessContext {
+        fs: &TokioFs,
+        from: &config.from,
+        to: &config.to,
+        options: &config.options,
+        filter,
+        state: watch_state,
+        progress: None,
+    };
+    let files = walk::walk_files(&config.from, filter, &CancellationToken::new()).await?;
+    let mut seen = HashSet::new();
+    let mut events = Vec::new();
+    for file in files {
+        let relative = file.path.strip_prefix(&config.from)?.to_path_buf();
+        let indexed = IndexEntry::new(file.len, file.modified).is_some_and(|entry| {
+            watch_state.with_index(|index| index.is_unchanged(&relative, entry))
+        });
+        let unchanged = indexed
+            && match ctx.preview_target(&file.path) {
+                Ok(target) => target_matches(watch_state.copy_type(), &target, file.len).await,
+                Err(_) => false,
+            };
+        if !unchanged {
+            events.push(Event::new(EventKind::Create(CreateKind::File)).add_path(file.path));
+        }
+        seen.insert(relative);
+    }
+    let removed: Vec<PathBuf> = watch_state.with_index(|index| {
+        index
+            .paths()
+            .filter(|relative| !seen.contains(*relative))
+            .map(|relative| config.from.join(relative))
+            .collect()
+    });
+    events.extend(
+        removed
+            .into_iter()
+            .map(|path| Event::new(EventKind::Remove(RemoveKind::File)).add_path(path)),
+    );
+    Ok(events)
+}
+
+/// 目标是否仍与同步时一致：存在，且复制和移动模式下大小与源文件相同。
+///
+/// 校验模式下总是重新比较。
+async fn target_matches(copy_type: CopyType, target: &Path, len: u64) -> bool {
+    match copy_type {
+        CopyType::Monitor => false,
+        CopyType::Copy | CopyType::Move => tokio::fs::metadata(target)
+            .await
+            .is_ok_and(|metadata| metadata.len() == len),
+        CopyType::Link => tokio::fs::try_exists(target).await.unwrap_or(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::{
+        config::{self, WatchOptions},
+        fs_ops::FsOps,
+        mem_fs::{dst, src, TestWatch},
+    };
+
+    fn mtime(path: &Path) -> SystemTime {
+        std::fs::metadata(path).unwrap().modified().unwrap()
+    }
+
+    /// 在临时目录中创建源目录树，按全量同步的顺序复制文件后恢复目录的修改时间。
+    async fn sync_tree(options: WatchOptions) -> (tempfile::TempDir, PathBuf, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+        for file in ["a.txt", "sub/b.txt", "sub/deep/c.txt"] {
+            let path = from.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, file).unwrap();
+        }
+        // 保证目标目录的写入时间晚于源目录
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        std::fs::create_dir(&to).unwrap();
+
+        let watch = TestWatch::with_fs(TokioFs, from.clone(), to.clone(), options);
+        let ctx = watch.ctx();
+        let files = walk::walk_files(&from, ctx.filter, &CancellationToken::new())
+            .await
+            .unwrap();
+        let dirs = synced_dirs(&from, &files);
+        for file in &files {
+            let event = Event::new(EventKind::Create(CreateKind::File)).add_path(file.path.clone());
+            file_processor::process(&ctx, CopyType::Copy, &event)
+                .await
+                .unwrap();
+        }
+        restore_dir_mtimes(&ctx, &dirs).await;
+        (dir, from, to)
+    }
+
+    fn watch_config(copy_type: CopyType) -> WatchConfig {
+        WatchConfig {
+            id: "test".to_string(),
+            from: "/src".into(),
+            to: "/dst".into(),
+            copy_type,
+            options: WatchOptions::default(),
+        }
+    }
+
+    #[test]
+    fn conversion_result_is_reported() {
+        let summary = SyncSummary {
+            files_synced: 2,
+            ..Default::default()
+        };
+        let done = convert_finished(&watch_config(CopyType::Link), Ok(summary));
+        assert_eq!(done.copy_type, CopyType::Link);
+        assert_eq!(done.summary.map(|summary| summary.files_synced), Some(2));
+        assert!(done.error.is_none());
+
+        let failed = convert_finished(
+            &watch_config(CopyType::Copy),
+            Err(anyhow!("源目录不存在").context("全量同步失败")),
+        );
+        assert!(failed.summary.is_none());
+        assert_eq!(failed.error.as_deref(), Some("全量同步失败: 源目录不存在"));
+    }
+
+    #[tokio::test]
+    async fn converting_replaces_existing_entries_with_new_type() {
+        let watch = TestWatch::new(WatchOptions::default());
+        watch.fs.add_file(src("a.txt"), "a");
+        watch.fs.add_symlink(dst("a.txt"), src("a.txt"), false);
+        file_processor::sync_file(&watch.ctx(), CopyType::Copy, &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(!watch.fs.is_symlink(&dst("a.txt")));
+        assert_eq!(watch.fs.read(dst("a.txt")), Some(b"a".to_vec()));
+
+        file_processor::sync_file(&watch.ctx(), CopyType::Link, &src("a.txt"))
+            .await
+            .unwrap();
+        assert!(watch.fs.is_symlink(&dst("a.txt")));
+    }
+
+    #[test]
+    fn synced_dirs_are_ancestors_of_files_within_source() {
+        let from = Path::new("/from");
+        let files: Vec<WalkEntry> = ["/from/x/y/a.txt", "/from/x/b.txt", "/from/z/c.txt"]
+            .into_iter()
+            .map(|path| WalkEntry {
+                path: path.into(),
+                len: 0,
+                modified: None,
+            })
+            .collect();
+        let dirs: Vec<PathBuf> = synced_dirs(from, &files).into_iter().collect();
+        assert_eq!(
+            dirs,
+            ["/from", "/from/x", "/from/x/y", "/from/z"].map(PathBuf::from)
+        );
+    }
+
+    #[tokio::test]
+    async fn target_dir_mtimes_match_source_after_sync() {
+        let (_dir, from, to) = sync_tree(WatchOptions {
+            preserve_dir_mtimes: true,
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(
+            std::fs::read(to.join("sub/deep/c.txt")).unwrap(),
+            b"sub/deep/c.txt"
+        );
+        for dir in ["", "sub", "sub/deep"] {
+            assert_eq!(mtime(&to.join(dir)), mtime(&from.join(dir)), "{:?}", dir);
+        }
+    }
+
+    #[tokio::test]
+    async fn flattened_target_dir_mtime_is_not_restored() {
+        let (_dir, from, to) = sync_tree(WatchOptions {
+            preserve_dir_mtimes: true,
+            flatten: true,
+            ..Default::default()
+        })
+        .await;
+        assert!(to.join("c.txt").is_file());
+        assert!(mtime(&to) > mtime(&from));
+    }
+
+    #[tokio::test]
+    async fn poll_finds_changes_missed_by_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+        std::fs::create_dir_all(&from).unwrap();
+        std::fs::create_dir_all(&to).unwrap();
+        for name in ["synced.txt", "missed.txt"] {
+            std::fs::write(from.join(name), name).unwrap();
+        }
+        std::fs::write(to.join("synced.txt"), "synced.txt").unwrap();
+        let config = WatchConfig {
+            id: "test".to_string(),
+            from: from.clone(),
+            to,
+            copy_type: CopyType::Copy,
+            options: WatchOptions {
+                mode: config::WatchMode::Hybrid,
+                ..Default::default()
+            },
+        };
+        let watch = TestWatch::with_fs(
+            TokioFs,
+            from.clone(),
+            config.to.clone(),
+            config.options.clone(),
+        );
+        let metadata = std::fs::metadata(from.join("synced.txt")).unwrap();
+        let entry = IndexEntry::new(metadata.len(), metadata.modified().ok()).unwrap();
+        watch.state.with_index(|index| {
+            index.record("synced.txt".into(), entry);
+            index.record("deleted.txt".into(), entry);
+        });
+
+        let events = poll_changes(&config, &watch.filter, &watch.state)
+            .await
+            .unwrap();
+        let found: Vec<(bool, PathBuf)> = events
+            .into_iter()
+            .map(|event| (event.kind.is_create(), event.paths[0].clone()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (true, from.join("missed.txt")),
+                (false, from.join("deleted.txt")),
+            ]
+        );
+    }
+}
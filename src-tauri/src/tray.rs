@@ -0,0 +1,121 @@
+//! 托盘菜单。
+//!
+//! 菜单中为每个正在运行的监视列出一个子菜单，监视增减时调用 [`refresh`] 重建菜单。
+
+use log::{error, info, warn};
+use tauri::{
+    menu::{IsMenuItem, Menu, MenuItem, PredefinedMenuItem, Submenu},
+    AppHandle, Manager,
+};
+
+use crate::{
+    events::{self, IdPayload},
+    AppState,
+};
+
+pub const TRAY_ID: &str = "main";
+
+const STOP_PREFIX: &str = "stop:";
+const SHOW_PREFIX: &str = "show:";
+
+/// 按给定的监视（id，名称）构建托盘菜单。
+pub fn build_menu(
+    app: &AppHandle,
+    watches: &[(String, String)],
+) -> tauri::Result<Menu<tauri::Wry>> {
+    let submenus = watches
+        .iter()
+        .map(|(id, name)| {
+            let show = MenuItem::with_id(
+                app,
+                format!("{SHOW_PREFIX}{id}"),
+                "显示",
+                true,
+                None::<&str>,
+            )?;
+            let stop = MenuItem::with_id(
+                app,
+                format!("{STOP_PREFIX}{id}"),
+                "停止",
+                true,
+                None::<&str>,
+            )?;
+            Submenu::with_items(app, name, true, &[&show, &stop])
+        })
+        .collect::<tauri::Result<Vec<_>>>()?;
+    let watch_items: Vec<&dyn IsMenuItem<tauri::Wry>> = submenus
+        .iter()
+        .map(|submenu| submenu as &dyn IsMenuItem<tauri::Wry>)
+        .collect();
+    let watches_menu = Submenu::with_items(app, "监视", !watches.is_empty(), &watch_items)?;
+    let separator = PredefinedMenuItem::separator(app)?;
+    let quit_i = MenuItem::with_id(app, "quit", "退出", true, None::<&str>)?;
+    Menu::with_items(app, &[&watches_menu, &separator, &quit_i])
+}
+
+/// 按当前的监视表重建托盘菜单。调用时不能持有监视表的锁。
+pub async fn refresh(app: &AppHandle) {
+    let mut watches: Vec<(String, String)> = app
+        .state::<AppState>()
+        .watcher
+        .lock()
+        .await
+        .iter()
+        .map(|(id, watch)| (id.clone(), watch.config.name()))
+        .collect();
+    watches.sort_by(|a, b| a.1.cmp(&b.1));
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    if let Err(e) = build_menu(app, &watches).and_then(|menu| tray.set_menu(Some(menu))) {
+        error!("更新托盘菜单失败: {:?}", e);
+    }
+}
+
+/// 处理托盘菜单的点击。
+pub fn on_menu_event(app: &AppHandle, item_id: &str) {
+    if item_id == "quit" {
+        app.exit(0);
+    } else if let Some(id) = item_id.strip_prefix(STOP_PREFIX) {
+        let app = app.clone();
+        let id = id.to_string();
+        tauri::async_runtime::spawn(async move {
+            let removed = app.state::<AppState>().watcher.lock().await.remove(&id);
+            if let Some(watch) = removed {
+                let name = watch.config.name();
+                watch.stop().await;
+                info!("已从托盘停止监视 '{}'。", name);
+                let payload = IdPayload {
+                    id: id.clone(),
+                    name,
+                };
+                events::emit(&app, &id, "watch-stopped", payload);
+                refresh(&app).await;
+            }
+        });
+    } else if let Some(id) = item_id.strip_prefix(SHOW_PREFIX) {
+        if let Some(window) = app.get_webview_window("main") {
+            let _ = window.show();
+            let _ = window.set_focus();
+        }
+        let app = app.clone();
+        let id = id.to_string();
+        tauri::async_runtime::spawn(async move {
+            let name = match app.state::<AppState>().watcher.lock().await.get(&id) {
+                Some(watch) => watch.config.name(),
+                None => return,
+            };
+            events::emit(
+                &app,
+                &id,
+                "focus-watch",
+                IdPayload {
+                    id: id.clone(),
+                    name,
+                },
+            );
+        });
+    } else {
+        warn!("menu item {:?} not handled", item_id);
+    }
+}
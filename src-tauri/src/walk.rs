@@ -0,0 +1,153 @@
+use std::{
+    collections::HashSet,
+    fmt,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use log::warn;
+use tokio_util::sync::CancellationToken;
+
+use crate::filter::PathFilter;
+
+/// 遍历时找到的一个文件。
+pub struct WalkEntry {
+    pub path: PathBuf,
+    pub len: u64,
+    pub modified: Option<SystemTime>,
+}
+
+/// 遍历或同步被取消时返回的错误。
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("操作已取消")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// 收集 `root` 下所有符合过滤规则的文件。
+///
+/// 被排除的目录不会进入，指向目录的软链接不会跟随。每处理完一个目录检查一次取消令牌。
+/// 按规范化路径记录已访问的目录，即使通过其他途径（如目录联接）形成环也只访问一次。
+pub async fn walk_files(
+    root: &Path,
+    filter: &PathFilter,
+    cancel: &CancellationToken,
+) -> anyhow::Result<Vec<WalkEntry>> {
+    let mut files = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    let mut visited = HashSet::new();
+    while let Some(dir) = pending.pop() {
+        if cancel.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        match tokio::fs::canonicalize(&dir).await {
+            Ok(canonical) => {
+                if !visited.insert(canonical) {
+                    warn!("目录已访问过，可能存在链接环，跳过: {:?}", dir);
+                    continue;
+                }
+            }
+            Err(e) => warn!("无法规范化目录路径 {:?}: {:?}", dir, e),
+        }
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(e) => {
+                warn!("无法读取目录 {:?}: {:?}", dir, e);
+                continue;
+            }
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let relative = path.strip_prefix(root)?;
+            if entry.file_type().await?.is_dir() {
+                if !filter.is_excluded(relative) {
+                    pending.push(path);
+                }
+                continue;
+            }
+            if !filter.matches(relative) {
+                continue;
+            }
+            match tokio::fs::metadata(&path).await {
+                Ok(metadata) if metadata.is_file() => files.push(WalkEntry {
+                    path,
+                    len: metadata.len(),
+                    modified: metadata.modified().ok(),
+                }),
+                Ok(_) => {}
+                Err(e) => warn!("无法读取文件信息 {:?}: {:?}", path, e),
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::Filters;
+
+    fn link_dir(original: &Path, link: &Path) {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(original, link).unwrap();
+        #[cfg(windows)]
+        junction::create(original, link).unwrap();
+    }
+
+    fn names(root: &Path, files: &[WalkEntry]) -> Vec<PathBuf> {
+        let mut names: Vec<PathBuf> = files
+            .iter()
+            .map(|file| file.path.strip_prefix(root).unwrap().to_path_buf())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn self_referential_link_does_not_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub/a.txt"), "a").unwrap();
+        link_dir(root, &root.join("sub/loop"));
+        let filter = PathFilter::new(&Filters::default()).unwrap();
+        let files = walk_files(root, &filter, &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(names(root, &files), vec![Path::new("sub").join("a.txt")]);
+    }
+
+    #[tokio::test]
+    async fn linked_root_is_walked_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = dir.path().join("data");
+        std::fs::create_dir_all(&data).unwrap();
+        std::fs::write(data.join("a.txt"), "a").unwrap();
+        let root = dir.path().join("root");
+        link_dir(&data, &root);
+        link_dir(&root, &data.join("back"));
+        let filter = PathFilter::new(&Filters::default()).unwrap();
+        let files = walk_files(&root, &filter, &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(names(&root, &files), vec![PathBuf::from("a.txt")]);
+    }
+
+    #[tokio::test]
+    async fn cancelled_walk_stops() {
+        let dir = tempfile::tempdir().unwrap();
+        let filter = PathFilter::new(&Filters::default()).unwrap();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let err = walk_files(dir.path(), &filter, &cancel)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.is::<Cancelled>());
+    }
+}
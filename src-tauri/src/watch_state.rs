@@ -0,0 +1,402 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::warn;
+use tokio::sync::Notify;
+
+use crate::{
+    delayed_delete::DelayedDeletes,
+    diagnostics::QueueDepths,
+    error_history::ErrorHistory,
+    evict::TargetCache,
+    flatten::FlatNames,
+    hook::HookTargets,
+    index::{IndexEntry, SyncIndex},
+    manifest::{self, Manifest},
+    pause::PauseMode,
+    rate_limit::RateLimiter,
+    rename::Renamer,
+    staging::Staging,
+    stats::WatchStats,
+    CopyType,
+};
+
+/// 命令与事件循环之间共享的单个监视的运行时状态。
+pub struct WatchState {
+    /// 当前生效的复制类型，可在运行期间通过 `set_copy_type` 修改。
+    copy_type: Mutex<CopyType>,
+    renamer: Option<Renamer>,
+    muted_until: Mutex<Option<Instant>>,
+    index: Mutex<SyncIndex>,
+    /// 索引文件的路径随监视 id 变化，`rename_watch` 后改为新 id 对应的文件。
+    index_file: Mutex<Option<PathBuf>>,
+    /// 未开启清单时为 `None`。
+    manifest: Option<(Mutex<Manifest>, PathBuf)>,
+    flat_names: Mutex<FlatNames>,
+    hook_targets: Mutex<HookTargets>,
+    staging: Mutex<Staging>,
+    /// 等待执行、可被 `undo_delete` 撤销的删除。
+    delayed_deletes: Mutex<DelayedDeletes>,
+    /// 源文件 (设备号, inode) -> 第一个复制出的目标文件，用于保留硬链接。
+    hardlinks: Mutex<HashMap<(u64, u64), PathBuf>>,
+    stats: WatchStats,
+    /// 最近的错误，供 `get_error_history` 读取。
+    error_history: Mutex<ErrorHistory>,
+    pause: Mutex<Option<PauseMode>>,
+    ops_limiter: Option<RateLimiter>,
+    modified_after: Mutex<Option<SystemTime>>,
+    resumed: Notify,
+    /// `redirect_target` 设置、等待事件循环应用的新目标目录，以及切换后是否全量同步。
+    redirect: Mutex<Option<(PathBuf, bool)>>,
+    redirected: Notify,
+    /// `rename_watch` 设置、等待事件循环应用的新 id。
+    rename: Mutex<Option<String>>,
+    renamed: Notify,
+    /// 写时复制失败并确认目标不支持后置位，之后直接使用普通复制。
+    reflink_unsupported: AtomicBool,
+    /// 未配置 `max_target_size` 时为 `None`。
+    target_cache: Mutex<Option<TargetCache>>,
+    /// 事件循环最近一次发布的队列长度。
+    queue_depths: Mutex<QueueDepths>,
+    /// 源目录与目标目录是否位于同一文件系统，无法判断时未设置。
+    same_filesystem: OnceLock<bool>,
+    /// 监视启动时源目录所在文件系统的标识，用于发现卷被卸载。
+    source_volume: OnceLock<u64>,
+}
+
+impl WatchState {
+    /// 创建状态并从 `index_file` 加载同步索引，从 `manifest_file` 加载清单。
+    ///
+    /// `max_ops_per_sec` 不为空时，每个文件操作前都要先取得令牌。
+    pub fn new(
+        copy_type: CopyType,
+        renamer: Option<Renamer>,
+        index_file: Option<PathBuf>,
+        manifest_file: Option<PathBuf>,
+        max_ops_per_sec: Option<u32>,
+    ) -> Self {
+        let index = index_file
+            .as_deref()
+            .map(SyncIndex::load)
+            .unwrap_or_default();
+        let manifest = manifest_file.map(|file| (Mutex::new(Manifest::load(&file)), file));
+        Self {
+            copy_type: Mutex::new(copy_type),
+            renamer,
+            muted_until: Mutex::default(),
+            index: Mutex::new(index),
+            index_file: Mutex::new(index_file),
+            manifest,
+            flat_names: Mutex::default(),
+            hook_targets: Mutex::default(),
+            staging: Mutex::default(),
+            delayed_deletes: Mutex::default(),
+            hardlinks: Mutex::default(),
+            stats: WatchStats::default(),
+            error_history: Mutex::default(),
+            pause: Mutex::default(),
+            ops_limiter: max_ops_per_sec.map(RateLimiter::new),
+            modified_after: Mutex::default(),
+            resumed: Notify::new(),
+            redirect: Mutex::default(),
+            redirected: Notify::new(),
+            rename: Mutex::default(),
+            renamed: Notify::new(),
+            reflink_unsupported: AtomicBool::new(false),
+            target_cache: Mutex::default(),
+            queue_depths: Mutex::default(),
+            same_filesystem: OnceLock::new(),
+            source_volume: OnceLock::new(),
+        }
+    }
+
+    pub fn copy_type(&self) -> CopyType {
+        *lock(&self.copy_type)
+    }
+
+    pub fn stats(&self) -> &WatchStats {
+        &self.stats
+    }
+
+    pub fn renamer(&self) -> Option<&Renamer> {
+        self.renamer.as_ref()
+    }
+
+    pub fn set_copy_type(&self, copy_type: CopyType) {
+        *lock(&self.copy_type) = copy_type;
+    }
+
+    pub fn with_index<R>(&self, f: impl FnOnce(&mut SyncIndex) -> R) -> R {
+        f(&mut lock(&self.index))
+    }
+
+    pub fn with_flat_names<R>(&self, f: impl FnOnce(&mut FlatNames) -> R) -> R {
+        f(&mut lock(&self.flat_names))
+    }
+
+    pub fn with_hook_targets<R>(&self, f: impl FnOnce(&mut HookTargets) -> R) -> R {
+        f(&mut lock(&self.hook_targets))
+    }
+
+    pub fn with_staging<R>(&self, f: impl FnOnce(&mut Staging) -> R) -> R {
+        f(&mut lock(&self.staging))
+    }
+
+    pub fn with_delayed_deletes<R>(&self, f: impl FnOnce(&mut DelayedDeletes) -> R) -> R {
+        f(&mut lock(&self.delayed_deletes))
+    }
+
+    pub fn with_error_history<R>(&self, f: impl FnOnce(&mut ErrorHistory) -> R) -> R {
+        f(&mut lock(&self.error_history))
+    }
+
+    pub fn with_hardlinks<R>(&self, f: impl FnOnce(&mut HashMap<(u64, u64), PathBuf>) -> R) -> R {
+        f(&mut lock(&self.hardlinks))
+    }
+
+    /// 同步成功后按源文件的当前状态更新清单，未开启清单或处于校验模式时什么都不做。
+    ///
+    /// `entry` 为 `None` 表示源文件已不存在，移除对应记录。
+    pub async fn record_manifest(&self, relative: &Path, path: &Path, entry: Option<IndexEntry>) {
+        let Some((manifest, _)) = &self.manifest else {
+            return;
+        };
+        if self.copy_type() == CopyType::Monitor {
+            return;
+        }
+        match entry {
+            Some(entry) => match manifest::entry_for(path, entry).await {
+                Ok(entry) => lock(manifest).record(relative.to_path_buf(), entry),
+                Err(e) => warn!("计算清单摘要失败 {:?}: {:?}", path, e),
+            },
+            None => lock(manifest).forget(relative),
+        }
+    }
+
+    /// 当前清单的副本，未开启清单时返回 `None`。
+    pub fn manifest(&self) -> Option<Manifest> {
+        self.manifest
+            .as_ref()
+            .map(|(manifest, _)| lock(manifest).clone())
+    }
+
+    /// 将有修改的同步索引和清单写入磁盘。
+    pub async fn save(&self) {
+        let index_file = lock(&self.index_file).clone();
+        if let Some(file) = &index_file {
+            let snapshot = lock(&self.index).take_snapshot();
+            if let Some(bytes) = snapshot {
+                write_file(file, &bytes, "索引").await;
+            }
+        }
+        if let Some((manifest, file)) = &self.manifest {
+            let snapshot = lock(manifest).take_snapshot();
+            if let Some(bytes) = snapshot {
+                write_file(file, &bytes, "清单").await;
+            }
+        }
+    }
+
+    /// 在 `duration` 内不再向前端报告错误。
+    pub fn mute_errors(&self, duration: Duration) {
+        *lock(&self.muted_until) = Some(Instant::now() + duration);
+    }
+
+    pub fn errors_muted(&self) -> bool {
+        lock(&self.muted_until).is_some_and(|until| Instant::now() < until)
+    }
+
+    /// 配置了每秒操作数上限时等待轮到下一个操作。
+    pub async fn throttle_op(&self) {
+        if let Some(limiter) = &self.ops_limiter {
+            limiter.acquire().await;
+        }
+    }
+
+    pub fn set_target_cache(&self, cache: TargetCache) {
+        *lock(&self.target_cache) = Some(cache);
+    }
+
+    /// 未配置大小上限时返回 `None`。
+    pub fn with_target_cache<R>(&self, f: impl FnOnce(&mut TargetCache) -> R) -> Option<R> {
+        lock(&self.target_cache).as_mut().map(f)
+    }
+
+    pub fn same_filesystem(&self) -> Option<bool> {
+        self.same_filesystem.get().copied()
+    }
+
+    pub fn set_same_filesystem(&self, same: bool) {
+        let _ = self.same_filesystem.set(same);
+    }
+
+    pub fn source_volume(&self) -> Option<u64> {
+        self.source_volume.get().copied()
+    }
+
+    pub fn set_source_volume(&self, volume: u64) {
+        let _ = self.source_volume.set(volume);
+    }
+
+    pub fn queue_depths(&self) -> QueueDepths {
+        *lock(&self.queue_depths)
+    }
+
+    pub fn set_queue_depths(&self, depths: QueueDepths) {
+        *lock(&self.queue_depths) = depths;
+    }
+
+    pub fn reflink_unsupported(&self) -> bool {
+        self.reflink_unsupported.load(Ordering::Relaxed)
+    }
+
+    pub fn set_reflink_unsupported(&self) {
+        self.reflink_unsupported.store(true, Ordering::Relaxed);
+    }
+
+    /// 早于此时刻修改的文件不会被同步。
+    pub fn modified_after(&self) -> Option<SystemTime> {
+        *lock(&self.modified_after)
+    }
+
+    pub fn set_modified_after(&self, modified_after: Option<SystemTime>) {
+        *lock(&self.modified_after) = modified_after;
+    }
+
+    /// 文件的修改时间是否早于 [`modified_after`](Self::modified_after)，修改时间未知时视为不早于。
+    pub fn is_too_old(&self, modified: Option<SystemTime>) -> bool {
+        self.modified_after()
+            .zip(modified)
+            .is_some_and(|(cutoff, modified)| modified < cutoff)
+    }
+
+    pub fn pause_mode(&self) -> Option<PauseMode> {
+        *lock(&self.pause)
+    }
+
+    pub fn pause(&self, mode: PauseMode) {
+        *lock(&self.pause) = Some(mode);
+    }
+
+    /// 取消暂停并通知事件循环重放缓存的事件，未暂停时返回 `false`。
+    pub fn resume(&self) -> bool {
+        let was_paused = lock(&self.pause).take().is_some();
+        if was_paused {
+            self.resumed.notify_one();
+        }
+        was_paused
+    }
+
+    /// 等待下一次 [`resume`](Self::resume)。
+    pub async fn resumed(&self) {
+        self.resumed.notified().await;
+    }
+
+    /// 请求事件循环改为写入 `to`，之前未应用的请求被替换。
+    pub fn redirect(&self, to: PathBuf, resync: bool) {
+        *lock(&self.redirect) = Some((to, resync));
+        self.redirected.notify_one();
+    }
+
+    pub fn take_redirect(&self) -> Option<(PathBuf, bool)> {
+        lock(&self.redirect).take()
+    }
+
+    /// 等待下一次 [`redirect`](Self::redirect)。
+    pub async fn redirected(&self) {
+        self.redirected.notified().await;
+    }
+
+    /// 请求事件循环改用新 id，并把索引改为保存到 `index_file`，返回原来的索引文件。
+    ///
+    /// 索引被标记为有修改，下一次保存时完整写入新文件。只在内存中保存索引时不改变索引文件。
+    pub fn rename(&self, id: String, index_file: Option<PathBuf>) -> Option<PathBuf> {
+        let old = {
+            let mut current = lock(&self.index_file);
+            match (current.as_mut(), index_file) {
+                (Some(current), Some(file)) => Some(std::mem::replace(current, file)),
+                _ => None,
+            }
+        };
+        if old.is_some() {
+            lock(&self.index).mark_dirty();
+        }
+        *lock(&self.rename) = Some(id);
+        self.renamed.notify_one();
+        old
+    }
+
+    pub fn take_rename(&self) -> Option<String> {
+        lock(&self.rename).take()
+    }
+
+    /// 等待下一次 [`rename`](Self::rename)。
+    pub async fn renamed(&self) {
+        self.renamed.notified().await;
+    }
+
+    /// 清除记录了目标中具体路径的状态，目标目录改变后这些路径不再有效。
+    pub fn forget_target_paths(&self) {
+        *lock(&self.hook_targets) = HookTargets::default();
+        lock(&self.hardlinks).clear();
+    }
+}
+
+/// 先写入临时文件再重命名，避免读取方看到写到一半的内容。
+async fn write_file(file: &Path, bytes: &[u8], what: &str) {
+    if let Some(parent) = file.parent() {
+        if let Err(e) = tokio::fs::create_dir_all(parent).await {
+            warn!("无法创建{}目录 {:?}: {:?}", what, parent, e);
+            return;
+        }
+    }
+    let temp = file.with_extension("json.tmp");
+    let result = match tokio::fs::write(&temp, bytes).await {
+        Ok(()) => tokio::fs::rename(&temp, file).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        warn!("保存{}文件 {:?} 失败: {:?}", what, file, e);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state() -> WatchState {
+        WatchState::new(CopyType::Copy, None, None, None, None)
+    }
+
+    #[tokio::test]
+    async fn latest_redirect_replaces_pending_one() {
+        let state = state();
+        state.redirect("/a".into(), true);
+        state.redirect("/b".into(), false);
+        // 请求先于等待到达时不会丢失通知
+        tokio::time::timeout(Duration::from_secs(5), state.redirected())
+            .await
+            .unwrap();
+        assert_eq!(state.take_redirect(), Some(("/b".into(), false)));
+        assert_eq!(state.take_redirect(), None);
+    }
+
+    #[test]
+    fn forgetting_target_paths_clears_hardlinks() {
+        let state = state();
+        state.with_hardlinks(|links| links.insert((1, 2), "/old/a.txt".into()));
+        state.forget_target_paths();
+        assert!(state.with_hardlinks(|links| links.is_empty()));
+    }
+}